@@ -0,0 +1,40 @@
+use art_int::{ActivationFunc, LayerTopology, Network};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// number of crossings in a 10x10 grid (the edges are IONodes)
+const CROSSINGS: usize = 64;
+
+/// Evaluates the inputs of every crossing with the default topology of the
+/// simulator, one by one and as one batch
+fn propagate_bench(c: &mut Criterion) {
+    let mut rng = ChaCha8Rng::from_seed(Default::default());
+    let network = Network::random(
+        &mut rng,
+        &[
+            LayerTopology::new(16),
+            LayerTopology::new(14),
+            LayerTopology::new(8),
+            LayerTopology::new(4).with_activation(ActivationFunc::SoftMax),
+        ],
+    );
+    let inputs: Vec<Vec<f32>> = (0..CROSSINGS)
+        .map(|_| (0..16).map(|_| rng.gen_range(0.0..1.0)).collect())
+        .collect();
+
+    let mut group = c.benchmark_group("propagate_bench");
+    group.bench_function("individual", |b| {
+        b.iter(|| {
+            inputs
+                .iter()
+                .map(|input| network.propagate(input.clone()))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("batch", |b| b.iter(|| network.propagate_batch(&inputs)));
+    group.finish()
+}
+
+criterion_group!(benches, propagate_bench);
+criterion_main!(benches);
@@ -1,4 +1,6 @@
 use rand::{prelude::ThreadRng, Rng};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 
 use crate::Network;
 
@@ -15,17 +17,66 @@ pub trait IndividualComponent {
     fn mutate(&mut self, coeff: f32, rng: &mut ThreadRng); 
 }
 
-pub fn crossover_sim_nns(sim_a: &Vec<Network>, sim_b: &Vec<Network>, rng: &mut ThreadRng) -> Vec<Network> {
-    sim_a.iter().zip(sim_b.iter()).map( | (nn_a, nn_b) | {
-        nn_a.crossover(nn_b, rng)
-    }).collect()
+/// The default limit for the absolute value of weights and biases
+/// (see [Network::sanitize])
+pub const DEFAULT_WEIGHT_LIMIT: f32 = 10.0;
+
+/// Is returned when the nns of two simulations can't be crossed over
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopologyMismatch {
+    /// the number of nns of both simulations
+    pub lens: (usize, usize),
+    /// the index of the first pair of nns with different topologies
+    /// (None, if the number of nns differs)
+    pub index: Option<usize>,
+}
+
+impl Display for TopologyMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(i) => write!(f, "Unable to cross over simulations: the topologies of nn {} differ", i),
+            None => write!(
+                f,
+                "Unable to cross over simulations with a different number of nns ({} and {})",
+                self.lens.0, self.lens.1
+            ),
+        }
+    }
+}
+
+impl Error for TopologyMismatch {}
+
+/// Performs crossover on the nns of two simulations
+///
+/// The resulting weights are sanitized with `weight_limit` (see [Network::sanitize]).
+/// Both simulations need the same number of nns with the same topologies, otherwise
+/// the child would be missing weights.
+pub fn crossover_sim_nns(sim_a: &Vec<Network>, sim_b: &Vec<Network>, rng: &mut ThreadRng, weight_limit: f32) -> Result<Vec<Network>, TopologyMismatch> {
+    let lens = (sim_a.len(), sim_b.len());
+    if lens.0 != lens.1 {
+        return Err(TopologyMismatch { lens, index: None });
+    }
+    if let Some(i) = sim_a.iter().zip(sim_b.iter()).position(| (nn_a, nn_b) | !nn_a.same_topology(nn_b)) {
+        return Err(TopologyMismatch { lens, index: Some(i) });
+    }
+    Ok(sim_a.iter().zip(sim_b.iter()).map( | (nn_a, nn_b) | {
+        let mut nn = nn_a.crossover(nn_b, rng);
+        nn.sanitize(weight_limit);
+        nn
+    }).collect())
 }
 
 /// Applies mutation with a chance
-pub fn mutate_sim_nns(rng: &mut ThreadRng, sim: &mut Vec<Network>, chance: f32, coeff: f32) {
+///
+/// The weights are sanitized afterwards (see [Network::sanitize]). Returns the
+/// number of weights and biases that had to be changed.
+pub fn mutate_sim_nns(rng: &mut ThreadRng, sim: &mut Vec<Network>, chance: f32, coeff: f32, weight_limit: f32) -> usize {
     if rng.gen_bool(chance.into()) {
-        sim.iter_mut().for_each(| nn | {
+        sim.iter_mut().map(| nn | {
             nn.mutate(coeff, rng);
-        });
+            nn.sanitize(weight_limit)
+        }).sum()
+    } else {
+        0
     }
 }
\ No newline at end of file
@@ -1,40 +1,61 @@
 use crate::*;
 
+/// The function applied to the weighted sums of the neurons of a layer
 #[derive(Clone, Debug, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ActivationFunc {
     ReLu,
+    /// Normalizes the outputs of the layer to probabilities that sum up to 1
     SoftMax,
+    /// Passes the weighted sums on unchanged
+    Identity,
 }
 
 impl ActivationFunc {
-    pub fn propagate(&self, neurons: &[Neuron], mut inputs: Vec<f32>) -> Vec<f32> {
+    pub fn propagate(&self, neurons: &[Neuron], inputs: Vec<f32>) -> Vec<f32> {
+        let mut outputs: Vec<f32> = neurons.iter().map( | n | {
+            let output = inputs
+                .iter()
+                .zip(&n.weights)
+                .map(|(input, weight)| input * weight)
+                .sum::<f32>();
+
+            n.bias + output
+        }).collect();
         match self {
             ActivationFunc::ReLu => {
-                neurons.iter().map( | n | {
-                    let output = inputs
-                        .iter()
-                        .zip(&n.weights)
-                        .map(|(input, weight)| input * weight)
-                        .sum::<f32>();
-
-                    (n.bias + output).max(0.0)
-                }).collect()
-            },
-            ActivationFunc::SoftMax =>  {
-                let mut sum: f32 = 0.0;
-                inputs.iter_mut().for_each( | value | {
-                    *value = value.exp();
-                    sum += *value;
-                });
-                inputs.iter_mut().for_each( | value | {
-                    *value /= sum;
-                });
-
-                inputs
+                outputs.iter_mut().for_each( | value | *value = value.max(0.0));
             },
+            ActivationFunc::SoftMax => softmax(&mut outputs, 1.0),
+            ActivationFunc::Identity => {},
         }
+        outputs
     }
 }
+/// Normalizes `values` to probabilities that sum up to 1
+///
+/// Higher temperatures make the probabilities more similar, lower ones favour the
+/// largest value. A temperature of 0 (or less) gives the largest value a
+/// probability of 1.
+pub fn softmax(values: &mut [f32], temperature: f32) {
+    // subtracting the maximum doesn't change the result, but keeps exp() from overflowing
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    if temperature <= 0.0 {
+        let argmax = values.iter().position(|value| *value == max);
+        values.iter_mut().enumerate().for_each( | (i, value) | {
+            *value = if Some(i) == argmax { 1.0 } else { 0.0 };
+        });
+        return;
+    }
+    let mut sum: f32 = 0.0;
+    values.iter_mut().for_each( | value | {
+        *value = ((*value - max) / temperature).exp();
+        sum += *value;
+    });
+    values.iter_mut().for_each( | value | {
+        *value /= sum;
+    });
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Layer {
     pub neurons: Vec<Neuron>,
@@ -43,9 +64,7 @@ pub struct Layer {
 
 impl Layer {
     pub fn new(neurons: Vec<Neuron>, activation: ActivationFunc) -> Self {
-        if activation != ActivationFunc::SoftMax {
-            assert!(!neurons.is_empty());
-        }
+        assert!(!neurons.is_empty());
 
         assert!(neurons
             .iter()
@@ -78,6 +97,52 @@ impl Layer {
     pub fn propagate(&self, inputs: Vec<f32>) -> Vec<f32> {
         self.activation.propagate(&self.neurons, inputs)
     }
+
+    /// Like [Layer::propagate], but SoftMax is applied with the given temperature (see [softmax])
+    pub fn propagate_with_temperature(&self, inputs: Vec<f32>, temperature: f32) -> Vec<f32> {
+        match self.activation {
+            ActivationFunc::SoftMax => {
+                let mut outputs = ActivationFunc::Identity.propagate(&self.neurons, inputs);
+                softmax(&mut outputs, temperature);
+                outputs
+            }
+            _ => self.propagate(inputs),
+        }
+    }
+
+    /// Propagates a batch of input vectors through this layer with one matrix
+    /// multiplication
+    ///
+    /// `inputs` holds the input vectors one after another (each has the length
+    /// of the weights of the neurons), the outputs are returned the same way,
+    /// each with one value per neuron.
+    pub fn propagate_batch(&self, inputs: &[f32]) -> Vec<f32> {
+        let input_len = self.neurons[0].weights.len();
+        let output_len = self.neurons.len();
+        assert_eq!(inputs.len() % input_len, 0, "the inputs don't fit the layer");
+        let batch_size = inputs.len() / input_len;
+        let mut outputs = vec![0.0; batch_size * output_len];
+        // the weights of a neuron are used for the whole batch before moving on
+        //  to the next neuron
+        for (j, neuron) in self.neurons.iter().enumerate() {
+            for (b, input) in inputs.chunks_exact(input_len).enumerate() {
+                outputs[b * output_len + j] = neuron.bias
+                    + input
+                        .iter()
+                        .zip(&neuron.weights)
+                        .map(|(input, weight)| input * weight)
+                        .sum::<f32>();
+            }
+        }
+        for output in outputs.chunks_exact_mut(output_len) {
+            match self.activation {
+                ActivationFunc::ReLu => output.iter_mut().for_each(|value| *value = value.max(0.0)),
+                ActivationFunc::SoftMax => softmax(output, 1.0),
+                ActivationFunc::Identity => {}
+            }
+        }
+        outputs
+    }
 }
 
 #[cfg(test)]
@@ -139,4 +204,54 @@ mod tests {
             approx::assert_relative_eq!(actual_weights.as_slice(), expected_weights.as_slice());
         }
     }
+
+    mod propagate {
+        use super::*;
+
+        #[test]
+        fn softmax_sums_to_one() {
+            let layer = Layer::new(vec![
+                Neuron::new(0.5, vec![1.0, -2.0]),
+                Neuron::new(0.0, vec![3.0, 0.5]),
+                Neuron::new(-1.0, vec![-0.5, 0.2]),
+            ], ActivationFunc::SoftMax);
+
+            let outputs = layer.propagate(vec![1.0, 2.0]);
+
+            assert_eq!(outputs.len(), 3);
+            assert!(outputs.iter().all(|p| *p > 0.0 && *p < 1.0));
+            approx::assert_relative_eq!(outputs.iter().sum::<f32>(), 1.0, epsilon = 1e-6);
+            // the order of the weighted sums (-3.0, 4.0, -1.1) is kept
+            assert!(outputs[1] > outputs[2] && outputs[2] > outputs[0]);
+        }
+
+        #[test]
+        fn softmax_temperature() {
+            let values = [0.5, 2.0, -1.0, 1.5];
+            let with_temperature = |temperature| {
+                let mut probabilities = values;
+                softmax(&mut probabilities, temperature);
+                probabilities
+            };
+
+            // no temperature picks the largest value
+            assert_eq!(with_temperature(0.0), [0.0, 1.0, 0.0, 0.0]);
+            assert!(with_temperature(0.01)[1] > 0.999);
+            // higher temperatures flatten the distribution, but keep the order
+            let normal = with_temperature(1.0);
+            let flat = with_temperature(10.0);
+            assert!(flat[1] < normal[1] && flat[2] > normal[2]);
+            assert!(flat[1] > flat[3] && flat[3] > flat[0] && flat[0] > flat[2]);
+            approx::assert_relative_eq!(flat.iter().sum::<f32>(), 1.0, epsilon = 1e-6);
+        }
+
+        #[test]
+        fn identity_keeps_weighted_sums() {
+            let layer = Layer::new(vec![Neuron::new(0.5, vec![1.0, -2.0])], ActivationFunc::Identity);
+
+            let outputs = layer.propagate(vec![1.0, 2.0]);
+
+            approx::assert_relative_eq!(outputs.as_slice(), [-2.5].as_slice());
+        }
+    }
 }
@@ -1,6 +1,6 @@
 use crate::ActivationFunc;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct LayerTopology {
     pub neurons: usize,
     pub activation: ActivationFunc
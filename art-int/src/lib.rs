@@ -5,6 +5,8 @@ use genetics::IndividualComponent;
 use rand::prelude::ThreadRng;
 use rand::{Rng, RngCore};
 use serde::{Serialize, Deserialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::iter::once;
 
 pub mod genetics;
@@ -12,11 +14,44 @@ pub mod layer;
 mod layer_topology;
 pub mod neuron;
 
+/// Loaded networks are checked (see [Network::check_layers]), as e.g. older
+/// saves end with an empty SoftMax layer, which isn't supported anymore
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedNetwork")]
 pub struct Network {
     pub layers: Vec<Layer>,
 }
 
+/// A deserialized [Network] that hasn't been checked yet
+#[derive(Deserialize)]
+struct UncheckedNetwork {
+    layers: Vec<Layer>,
+}
+
+impl TryFrom<UncheckedNetwork> for Network {
+    type Error = InvalidNetworkError;
+
+    fn try_from(unchecked: UncheckedNetwork) -> Result<Self, Self::Error> {
+        let network = Network { layers: unchecked.layers };
+        network.check_layers()?;
+        Ok(network)
+    }
+}
+
+/// Is returned when the layers of a network don't fit together (see [Network::check_layers])
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidNetworkError {
+    pub msg: String,
+}
+
+impl Display for InvalidNetworkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid neural network: {}", self.msg)
+    }
+}
+
+impl Error for InvalidNetworkError {}
+
 impl Network {
     pub fn new(layers: Vec<Layer>) -> Self {
         Self { layers }
@@ -56,6 +91,115 @@ impl Network {
             .fold(inputs, |inputs, layer| layer.propagate(inputs))
     }
 
+    /// Evaluates many input vectors at once
+    ///
+    /// The whole batch is passed through each layer with one matrix
+    /// multiplication (see [Layer::propagate_batch]). The outputs are in the
+    /// same order as the inputs.
+    pub fn propagate_batch(&self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let output_len = match self.layers.last() {
+            Some(layer) => layer.neurons.len(),
+            None => return inputs.to_vec(),
+        };
+        if inputs.is_empty() {
+            return Vec::new();
+        }
+        let flat: Vec<f32> = inputs.iter().flatten().copied().collect();
+        self.layers
+            .iter()
+            .fold(flat, |inputs, layer| layer.propagate_batch(&inputs))
+            .chunks_exact(output_len)
+            .map(|output| output.to_vec())
+            .collect()
+    }
+
+    /// Like [Network::propagate], but a SoftMax output layer uses the given temperature
+    ///
+    /// The outputs can be used to sample the output instead of always choosing the
+    /// largest one (see [softmax])
+    pub fn propagate_with_temperature(&self, inputs: Vec<f32>, temperature: f32) -> Vec<f32> {
+        match self.layers.split_last() {
+            Some((last, hidden)) => {
+                let inputs = hidden.iter().fold(inputs, |inputs, layer| layer.propagate(inputs));
+                last.propagate_with_temperature(inputs, temperature)
+            }
+            None => inputs,
+        }
+    }
+
+    /// Reconstructs the [LayerTopology] the network was created from
+    ///
+    /// The width of the input is derived from the weights of the first layer,
+    /// its activation function is irrelevant and therefore always the default
+    pub fn topology(&self) -> Vec<LayerTopology> {
+        let inputs = self
+            .layers
+            .first()
+            .and_then(|layer| layer.neurons.first())
+            .map_or(0, |neuron| neuron.weights.len());
+        once(LayerTopology::new(inputs))
+            .chain(self.layers.iter().map(|layer| {
+                LayerTopology::new(layer.neurons.len()).with_activation(layer.activation)
+            }))
+            .collect()
+    }
+
+    /// Checks that the network has at least one layer, every layer has neurons
+    /// and every neuron has one weight per output of the previous layer
+    ///
+    /// [Network::new] doesn't check this, but the networks created by [Network::random]
+    /// and [Network::from_weights] are always valid
+    pub fn check_layers(&self) -> Result<(), InvalidNetworkError> {
+        if self.layers.is_empty() {
+            return Err(InvalidNetworkError {
+                msg: "the network has no layers".to_string(),
+            });
+        }
+        let mut inputs = None;
+        for (i, layer) in self.layers.iter().enumerate() {
+            if layer.neurons.is_empty() {
+                return Err(InvalidNetworkError {
+                    msg: format!("layer {} has no neurons", i),
+                });
+            }
+            let expected = inputs.unwrap_or(layer.neurons[0].weights.len());
+            if expected == 0 || layer.neurons.iter().any(|neuron| neuron.weights.len() != expected) {
+                return Err(InvalidNetworkError {
+                    msg: format!("every neuron of layer {} needs {} weights", i, expected.max(1)),
+                });
+            }
+            inputs = Some(layer.neurons.len());
+        }
+        Ok(())
+    }
+
+    /// The number of weights and biases in the network (see [Network::weights])
+    pub fn num_weights(&self) -> usize {
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.neurons.iter())
+            .map(|neuron| 1 + neuron.weights.len())
+            .sum()
+    }
+
+    /// Whether both networks have the same layers, neurons, weights and activation functions
+    ///
+    /// Only networks with the same topology can be crossed over
+    pub fn same_topology(&self, other: &Network) -> bool {
+        self.layers.len() == other.layers.len()
+            && self.layers.iter().zip(other.layers.iter()).all(|(this_layer, other_layer)| {
+                this_layer.activation == other_layer.activation
+                    && this_layer.neurons.len() == other_layer.neurons.len()
+                    && this_layer
+                        .neurons
+                        .iter()
+                        .zip(other_layer.neurons.iter())
+                        .all(|(this_neuron, other_neuron)| {
+                            this_neuron.weights.len() == other_neuron.weights.len()
+                        })
+            })
+    }
+
     pub fn weights(&self) -> impl Iterator<Item = f32> + '_ {
         self.layers
             .iter()
@@ -63,6 +207,30 @@ impl Network {
             .flat_map(|neuron| once(&neuron.bias).chain(&neuron.weights))
             .cloned()
     }
+
+    /// Replaces non-finite weights and biases with 0 and clamps the others
+    /// to `-limit..=limit`
+    ///
+    /// Returns the number of values that were changed
+    pub fn sanitize(&mut self, limit: f32) -> usize {
+        let mut changed = 0;
+        self.layers
+            .iter_mut()
+            .flat_map(|layer| layer.neurons.iter_mut())
+            .flat_map(|neuron| once(&mut neuron.bias).chain(neuron.weights.iter_mut()))
+            .for_each(|w_or_b| {
+                let sanitized = if w_or_b.is_finite() {
+                    w_or_b.clamp(-limit, limit)
+                } else {
+                    0.0
+                };
+                if sanitized != *w_or_b {
+                    *w_or_b = sanitized;
+                    changed += 1;
+                }
+            });
+        changed
+    }
 }
 
 /// Performs crossover on two neurons
@@ -81,13 +249,18 @@ fn crossover_neurons(n1: &Neuron, n2: &Neuron, rng: &mut ThreadRng) -> Neuron {
 
 impl IndividualComponent for Network {
     fn crossover(&self, other: &Self, rng: &mut ThreadRng) -> Self {
+        assert!(
+            self.same_topology(other),
+            "Unable to cross over networks with different topologies: {:?} and {:?}",
+            self.topology(),
+            other.topology()
+        );
         // operate on two layers in the same position at the same time
         let new_layers = self
             .layers
             .iter()
             .zip(other.layers.iter())
             .map(|(this_layer, other_layer)| {
-                assert_eq!(this_layer.activation, other_layer.activation);
                 // operate on two neurons in the same position at the same time
                 Layer::new(
                     this_layer
@@ -210,6 +383,150 @@ mod tests {
         }
     }
 
+    mod propagate_batch {
+        use super::*;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        #[test]
+        fn test() {
+            let mut rng = ChaCha8Rng::from_seed(Default::default());
+            let network = Network::random(
+                &mut rng,
+                &[
+                    LayerTopology::new(4),
+                    LayerTopology::new(5),
+                    LayerTopology::new(3).with_activation(ActivationFunc::SoftMax),
+                ],
+            );
+            let inputs = vec![
+                vec![0.1, 0.2, 0.3, 0.4],
+                vec![1.0, 0.0, -1.0, 0.5],
+                vec![0.0, 0.0, 0.0, 0.0],
+            ];
+
+            let actual = network.propagate_batch(&inputs);
+
+            assert_eq!(actual.len(), inputs.len());
+            for (actual, input) in actual.iter().zip(inputs) {
+                let expected = network.propagate(input);
+                approx::assert_relative_eq!(actual.as_slice(), expected.as_slice());
+            }
+            assert!(network.propagate_batch(&[]).is_empty());
+        }
+    }
+
+    mod topology {
+        use super::*;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        #[test]
+        fn test() {
+            let mut rng = ChaCha8Rng::from_seed(Default::default());
+            let layers = [
+                LayerTopology::new(16),
+                LayerTopology::new(8),
+                LayerTopology::new(4).with_activation(ActivationFunc::SoftMax),
+            ];
+
+            let network = Network::random(&mut rng, &layers);
+
+            assert_eq!(network.topology(), layers.to_vec());
+        }
+    }
+
+    mod sanitize {
+        use super::*;
+        use genetics::mutate_sim_nns;
+
+        #[test]
+        fn test() {
+            let network = Network::new(vec![Layer::new(
+                vec![Neuron::new(f32::NAN, vec![f32::INFINITY, -20.0, 0.5])],
+                ActivationFunc::ReLu,
+            )]);
+            let mut nns = vec![network];
+
+            let changed = mutate_sim_nns(&mut rand::thread_rng(), &mut nns, 1.0, 0.1, 10.0);
+
+            // the weights could have been pushed out of the range by the mutation as well
+            assert!(changed >= 3);
+            assert!(nns[0].weights().all(|w| w.is_finite() && w.abs() <= 10.0));
+        }
+    }
+
+    mod num_weights {
+        use super::*;
+
+        #[test]
+        fn test() {
+            let layers = &[LayerTopology::new(3), LayerTopology::new(2), LayerTopology::new(1)];
+            let network = Network::random(&mut rand::thread_rng(), layers);
+
+            // (3 weights + bias) * 2 neurons + (2 weights + bias) * 1 neuron
+            assert_eq!(network.num_weights(), 11);
+            assert_eq!(network.num_weights(), network.weights().count());
+        }
+    }
+
+    mod same_topology {
+        use super::*;
+
+        #[test]
+        fn test() {
+            let mut rng = rand::thread_rng();
+            let layers = [LayerTopology::new(3), LayerTopology::new(2), LayerTopology::new(1)];
+            let network = Network::random(&mut rng, &layers);
+
+            assert!(network.same_topology(&Network::random(&mut rng, &layers)));
+            let wider = [LayerTopology::new(3), LayerTopology::new(4), LayerTopology::new(1)];
+            assert!(!network.same_topology(&Network::random(&mut rng, &wider)));
+            let softmax = [
+                LayerTopology::new(3),
+                LayerTopology::new(2),
+                LayerTopology::new(1).with_activation(ActivationFunc::SoftMax),
+            ];
+            assert!(!network.same_topology(&Network::random(&mut rng, &softmax)));
+        }
+
+        #[test]
+        #[should_panic(expected = "different topologies")]
+        fn crossover_panics() {
+            let mut rng = rand::thread_rng();
+            let network = Network::random(&mut rng, &[LayerTopology::new(3), LayerTopology::new(2)]);
+            let other = Network::random(&mut rng, &[LayerTopology::new(2), LayerTopology::new(2)]);
+
+            network.crossover(&other, &mut rng);
+        }
+    }
+
+    mod crossover_sim_nns {
+        use super::*;
+        use genetics::{crossover_sim_nns, TopologyMismatch, DEFAULT_WEIGHT_LIMIT};
+
+        #[test]
+        fn test() {
+            let mut rng = rand::thread_rng();
+            let small = [LayerTopology::new(3), LayerTopology::new(2)];
+            let large = [LayerTopology::new(3), LayerTopology::new(4)];
+            let sim_a = vec![Network::random(&mut rng, &small), Network::random(&mut rng, &small)];
+            let sim_b = vec![Network::random(&mut rng, &small), Network::random(&mut rng, &large)];
+
+            let crossed = crossover_sim_nns(&sim_a, &sim_a, &mut rng, DEFAULT_WEIGHT_LIMIT).unwrap();
+            assert!(crossed.iter().zip(sim_a.iter()).all(|(child, parent)| child.same_topology(parent)));
+
+            assert_eq!(
+                crossover_sim_nns(&sim_a, &sim_b, &mut rng, DEFAULT_WEIGHT_LIMIT).unwrap_err(),
+                TopologyMismatch { lens: (2, 2), index: Some(1) }
+            );
+            assert_eq!(
+                crossover_sim_nns(&sim_a, &sim_a[..1].to_vec(), &mut rng, DEFAULT_WEIGHT_LIMIT).unwrap_err(),
+                TopologyMismatch { lens: (2, 1), index: None }
+            );
+        }
+    }
+
     mod weights {
         use super::*;
 
@@ -226,4 +543,34 @@ mod tests {
             approx::assert_relative_eq!(actual.as_slice(), expected.as_slice());
         }
     }
+
+    mod check_layers {
+        use super::*;
+
+        #[test]
+        fn loading_rejects_invalid_layers() {
+            let network = Network::new(vec![
+                Layer::new(vec![Neuron::new(0.1, vec![0.2, 0.3])], ActivationFunc::ReLu),
+                Layer::new(vec![Neuron::new(0.5, vec![0.6]), Neuron::new(0.7, vec![0.8])], ActivationFunc::SoftMax),
+            ]);
+            let json = serde_json::to_string(&network).unwrap();
+            let loaded: Network = serde_json::from_str(&json).unwrap();
+            assert!(loaded.same_topology(&network));
+
+            // older saves end with an empty SoftMax layer
+            let mut legacy: serde_json::Value = serde_json::from_str(&json).unwrap();
+            legacy["layers"]
+                .as_array_mut()
+                .unwrap()
+                .push(serde_json::json!({ "neurons": [], "activation": "SoftMax" }));
+            let err = serde_json::from_value::<Network>(legacy).unwrap_err();
+            assert!(err.to_string().contains("layer 2 has no neurons"));
+
+            assert!(serde_json::from_str::<Network>(r#"{ "layers": [] }"#).is_err());
+            let mut wrong_inputs = network.clone();
+            wrong_inputs.layers[1].neurons[0].weights.push(1.0);
+            assert!(wrong_inputs.check_layers().is_err());
+            assert!(serde_json::from_str::<Network>(&serde_json::to_string(&wrong_inputs).unwrap()).is_err());
+        }
+    }
 }
@@ -11,15 +11,18 @@ fn main() {
     //         Neuron::new(0.0, vec![0.1, 0.1, 0.1]),
     //         Neuron::new(0.0, vec![0., 0.5, 0.5]),
     //     ], art_int::ActivationFunc::ReLu),
-    //     Layer::new(vec![Neuron::new(1.0, vec![0.2, 0.2]), Neuron::new(1.0, vec![0.1, 0.3])], art_int::ActivationFunc::ReLu),
-    //     Layer::new(vec![], art_int::ActivationFunc::SoftMax)
+    //     Layer::new(vec![Neuron::new(1.0, vec![0.2, 0.2]), Neuron::new(1.0, vec![0.1, 0.3])], art_int::ActivationFunc::SoftMax),
     // ]);
     let n = Network::new(vec![
         Layer::new(vec![
             Neuron::new(0.0, vec![0.5, 2.0]),
             Neuron::new(0.0, vec![1.0, 0.5]),
         ], art_int::ActivationFunc::ReLu),
-        Layer::new(vec![], art_int::ActivationFunc::SoftMax)
+        // the output layer turns the values into probabilities
+        Layer::new(vec![
+            Neuron::new(0.0, vec![1.0, 0.0]),
+            Neuron::new(0.0, vec![0.0, 1.0]),
+        ], art_int::ActivationFunc::SoftMax),
     ]);
     for layer in &n.layers {
         println!("{:?}", layer.neurons);
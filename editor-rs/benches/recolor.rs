@@ -0,0 +1,59 @@
+use bevy::{
+    prelude::{Color, Mesh},
+    render::pipeline::PrimitiveTopology,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use editor_rs::{repaint_mesh, set_mesh_color};
+
+/// the number of nodes of a large street network
+const NODES: usize = 10_000;
+/// roughly the number of vertices of a tesselated crossing
+const VERTICES: usize = 64;
+
+fn meshes(color: Color) -> Vec<Mesh> {
+    let color: [f32; 4] = color.into();
+    (0..NODES)
+        .map(|_| {
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, vec![color; VERTICES]);
+            mesh
+        })
+        .collect()
+}
+
+/// Recolors 10k nodes, e.g. after the theme changed, by rewriting the colors
+/// of every mesh (as it was done before) and by skipping the meshes that
+/// already have the color (e.g. if both themes use the same color for streets)
+fn recolor_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("recolor_bench");
+    for (name, old_color) in [("changed color", Color::BLACK), ("same color", Color::WHITE)] {
+        group.bench_function(format!("{}, unconditional rewrite", name), |b| {
+            b.iter_batched(
+                || meshes(old_color),
+                |mut meshes| {
+                    for mesh in meshes.iter_mut() {
+                        set_mesh_color(mesh, Color::WHITE);
+                    }
+                    meshes
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function(format!("{}, checked", name), |b| {
+            b.iter_batched(
+                || meshes(old_color),
+                |mut meshes| {
+                    for mesh in meshes.iter_mut() {
+                        repaint_mesh(mesh, Color::WHITE);
+                    }
+                    meshes
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish()
+}
+
+criterion_group!(benches, recolor_bench);
+criterion_main!(benches);
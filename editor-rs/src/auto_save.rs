@@ -0,0 +1,172 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use bevy::{
+    core::{Time, Timer},
+    prelude::{Query, Res, ResMut, Transform, With},
+};
+use bevy_egui::{egui, EguiContext};
+use simulator::SimManager;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::{
+    file_io::{self, PendingLoad},
+    user_interface::{CameraView, FunnyNNBuilderCombi, SaveMeta, SAVE_FILE_NAME},
+    Camera, NodeType, SimulationID, StreetLinePosition,
+};
+
+/// the file in the current directory the street network is saved to automatically
+const AUTO_SAVE_FILE_NAME: &str = ".autosave.json";
+/// how often the street network is saved automatically (in seconds)
+const AUTO_SAVE_INTERVAL: f32 = 60.0;
+
+/// Saves the street network periodically, so it can be recovered if the editor crashes
+pub struct AutoSave {
+    timer: Timer,
+    /// true while the user hasn't decided whether to recover the auto-save
+    /// of the last session
+    ///
+    /// Nothing is auto-saved in the meantime, as it would overwrite the file
+    pub recovery_pending: bool,
+}
+
+impl Default for AutoSave {
+    fn default() -> Self {
+        AutoSave {
+            timer: Timer::from_seconds(AUTO_SAVE_INTERVAL, true),
+            recovery_pending: false,
+        }
+    }
+}
+
+fn auto_save_path() -> Option<PathBuf> {
+    Some(std::env::current_dir().ok()?.join(AUTO_SAVE_FILE_NAME))
+}
+
+/// when the file in the current directory was last written to
+fn modified(name: &str) -> Option<SystemTime> {
+    let path = std::env::current_dir().ok()?.join(name);
+    fs::metadata(path).ok()?.modified().ok()
+}
+
+/// the auto-save is only worth recovering if the street network was changed
+/// after it was saved manually
+fn should_recover(auto_saved: Option<SystemTime>, saved: Option<SystemTime>) -> bool {
+    match (auto_saved, saved) {
+        (Some(auto_saved), Some(saved)) => auto_saved > saved,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// deletes the auto-save, e.g. after the street network was saved
+pub fn remove_auto_save() {
+    if let Some(path) = auto_save_path().filter(|path| path.exists()) {
+        if let Err(err) = fs::remove_file(path) {
+            warn!("Unable to delete the auto-save: {}", err);
+        }
+    }
+}
+
+/// checks at startup if there is an auto-save of the last session to recover
+pub fn check_for_auto_save(mut auto_save: ResMut<AutoSave>) {
+    auto_save.recovery_pending =
+        should_recover(modified(AUTO_SAVE_FILE_NAME), modified(SAVE_FILE_NAME));
+}
+
+/// Offers to restore the auto-save of the last session
+///
+/// The auto-save is loaded like a file chosen with "Load"
+pub fn draw_recovery_window(
+    egui_context: ResMut<EguiContext>,
+    mut auto_save: ResMut<AutoSave>,
+    pending_load: Res<PendingLoad>,
+) {
+    if !auto_save.recovery_pending {
+        return;
+    }
+    let mut restore = false;
+    let mut discard = false;
+    egui::Window::new("Recover unsaved changes")
+        .resizable(false)
+        .collapsible(false)
+        .show(egui_context.ctx(), |ui| {
+            ui.label("The street network was changed after it was last saved.");
+            ui.label("Do you want to restore the automatically saved version?");
+            ui.horizontal(|ui| {
+                restore = ui.button("Restore").clicked();
+                discard = ui.button("Discard").clicked();
+            });
+        });
+    if restore {
+        if let Err(err) = file_io::request_load(AUTO_SAVE_FILE_NAME, &pending_load) {
+            error!("Unable to restore the auto-save: {}", err);
+        }
+    }
+    if discard {
+        remove_auto_save();
+    }
+    if restore || discard {
+        auto_save.recovery_pending = false;
+    }
+}
+
+/// Saves the street network to [AUTO_SAVE_FILE_NAME] every [AUTO_SAVE_INTERVAL]
+/// seconds while not simulating, if it was changed since the last save
+///
+/// Should run in a stage of its own, as the nodes are locked while saving
+pub fn auto_save(
+    time: Res<Time>,
+    mut auto_save: ResMut<AutoSave>,
+    mut sim_manager: ResMut<SimManager>,
+    save_meta: Res<SaveMeta>,
+    nodes: Query<(&Transform, Option<&StreetLinePosition>, &SimulationID), With<NodeType>>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    if auto_save.recovery_pending || !auto_save.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    // nothing to save if the street network wasn't edited since the last save
+    if sim_manager.is_simulating() || !sim_manager.builder_changed() {
+        return;
+    }
+    let report = sim_manager.simulation_report.as_ref().map(|report| report.get_best_nn());
+    let camera = camera.single().ok().map(CameraView::from_transform);
+    let save = FunnyNNBuilderCombi::new(sim_manager.sim_builder(), report, nodes.iter(), camera, save_meta.clone());
+    let path = match auto_save_path() {
+        Some(path) => path,
+        None => return,
+    };
+    match serde_json::to_string(&save) {
+        Ok(json) => match fs::write(path, json) {
+            Ok(_) => {
+                debug!("Auto-saved the street network to {}", AUTO_SAVE_FILE_NAME);
+                sim_manager.mark_builder_saved();
+            }
+            Err(err) => warn!("Unable to auto-save the street network: {}", err),
+        },
+        Err(err) => warn!("Unable to serialize the street network: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::should_recover;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn only_newer_auto_saves_are_recovered() {
+        let saved = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let later = saved + Duration::from_secs(60);
+        assert!(should_recover(Some(later), Some(saved)));
+        assert!(!should_recover(Some(saved), Some(later)));
+        // never saved manually
+        assert!(should_recover(Some(saved), None));
+        assert!(!should_recover(None, Some(saved)));
+        assert!(!should_recover(None, None));
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// A file that was read, the name tells how its content is formatted
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedFile {
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+impl LoadedFile {
+    /// the content of a text file (e.g. JSON)
+    pub fn text(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.contents)
+    }
+}
+
+/// The content of a file that was loaded, but not applied yet
+///
+/// In the browser, the file dialog doesn't block, so the file is only
+/// available a few frames after "Load" was clicked
+#[derive(Default, Clone)]
+pub struct PendingLoad(pub Arc<Mutex<Option<LoadedFile>>>);
+
+impl PendingLoad {
+    /// returns the loaded file (only once)
+    pub fn take(&self) -> Option<LoadedFile> {
+        self.0.lock().ok()?.take()
+    }
+    fn set(&self, file: LoadedFile) {
+        if let Ok(mut pending) = self.0.lock() {
+            *pending = Some(file);
+        }
+    }
+}
+
+/// true if the file `name` is saved in a binary format instead of JSON
+pub fn is_binary_file(name: &str) -> bool {
+    std::path::Path::new(name)
+        .extension()
+        .map_or(false, |extension| extension == "bin")
+}
+
+/// writes `contents` to the file `name` in the current directory
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(name: &str, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    let path = std::env::current_dir()?.join(name);
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// reads the file `name` in the current directory into `pending`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn request_load(name: &str, pending: &PendingLoad) -> Result<(), Box<dyn Error>> {
+    let path = std::env::current_dir()?.join(name);
+    pending.set(LoadedFile {
+        name: name.to_string(),
+        contents: std::fs::read(path)?,
+    });
+    Ok(())
+}
+
+/// today's date (UTC) as YYYY-MM-DD
+#[cfg(not(target_arch = "wasm32"))]
+pub fn current_date() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    date_from_days((secs / 86400) as i64)
+}
+
+/// the date that is `days` after 1970-01-01 as YYYY-MM-DD
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+#[cfg(not(target_arch = "wasm32"))]
+fn date_from_days(days: i64) -> String {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// today's date (UTC) as YYYY-MM-DD
+#[cfg(target_arch = "wasm32")]
+pub fn current_date() -> String {
+    let iso: String = js_sys::Date::new_0().to_iso_string().into();
+    iso.chars().take(10).collect()
+}
+
+/// converts errors thrown by javascript
+#[cfg(target_arch = "wasm32")]
+fn js_error(err: wasm_bindgen::JsValue) -> Box<dyn Error> {
+    format!("{:?}", err).into()
+}
+
+/// lets the browser download `contents` as a file called `name`
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(name: &str, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    use wasm_bindgen::JsCast;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or("No document to save the file from")?;
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(contents));
+    let mime_type = match is_binary_file(name) {
+        true => "application/octet-stream",
+        false => "application/json",
+    };
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(
+        &parts,
+        web_sys::BlobPropertyBag::new().type_(mime_type),
+    )
+    .map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+    // clicking a link with the download attribute saves the file
+    let link: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(|_| "Unable to create a download link")?;
+    link.set_href(&url);
+    link.set_download(name);
+    link.click();
+    web_sys::Url::revoke_object_url(&url).map_err(js_error)?;
+    Ok(())
+}
+
+/// opens a file dialog and reads the chosen file into `pending`
+///
+/// The file is read asynchronously, so it is only available a few frames later.
+/// `name` is ignored, as the user chooses the file
+#[cfg(target_arch = "wasm32")]
+pub fn request_load(_name: &str, pending: &PendingLoad) -> Result<(), Box<dyn Error>> {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or("No document to load the file from")?;
+    let input: web_sys::HtmlInputElement = document
+        .create_element("input")
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(|_| "Unable to create a file input")?;
+    input.set_type("file");
+    input.set_accept(".json,.bin");
+    let pending = pending.clone();
+    let chosen_input = input.clone();
+    let on_change = Closure::once(move |_event: web_sys::Event| {
+        let file = match chosen_input.files().and_then(|files| files.get(0)) {
+            Some(file) => file,
+            None => return,
+        };
+        let reader = match web_sys::FileReader::new() {
+            Ok(reader) => reader,
+            Err(err) => {
+                error!("Unable to read the file: {:?}", err);
+                return;
+            }
+        };
+        let name = file.name();
+        let loaded_reader = reader.clone();
+        let on_load = Closure::once(move |_event: web_sys::Event| {
+            match loaded_reader.result() {
+                Ok(result) => pending.set(LoadedFile {
+                    name,
+                    contents: js_sys::Uint8Array::new(&result).to_vec(),
+                }),
+                Err(err) => error!("Unable to read the file: {:?}", err),
+            }
+        });
+        reader.set_onload(Some(on_load.as_ref().unchecked_ref()));
+        // the closures are called by the browser later, so they must not be dropped
+        on_load.forget();
+        if let Err(err) = reader.read_as_array_buffer(&file) {
+            error!("Unable to read the file: {:?}", err);
+        }
+    });
+    input.set_onchange(Some(on_change.as_ref().unchecked_ref()));
+    on_change.forget();
+    input.click();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{date_from_days, is_binary_file, LoadedFile, PendingLoad};
+
+    #[test]
+    fn pending_load_is_taken_once() {
+        let pending = PendingLoad::default();
+        assert_eq!(pending.take(), None);
+        let file = LoadedFile {
+            name: "save.json".to_string(),
+            contents: b"{}".to_vec(),
+        };
+        // clones share the content, like the closures reading the file
+        pending.clone().set(file.clone());
+        assert_eq!(pending.take(), Some(file));
+        assert_eq!(pending.take(), None);
+    }
+
+    #[test]
+    fn binary_files_are_told_apart_by_extension() {
+        assert!(is_binary_file("StreetSimulation.bin"));
+        assert!(!is_binary_file("StreetSimulation.json"));
+        assert!(!is_binary_file("bin"));
+    }
+
+    #[test]
+    fn days_are_converted_to_dates() {
+        assert_eq!(date_from_days(0), "1970-01-01");
+        assert_eq!(date_from_days(11016), "2000-02-29");
+        assert_eq!(date_from_days(19723), "2024-01-01");
+    }
+}
@@ -6,7 +6,7 @@ use bevy::{
     math::{Vec2, Vec3},
     prelude::{
         Commands, Entity, EventReader, GlobalTransform, KeyCode, MouseButton, Or, Query, QuerySet,
-        Res, Transform, With,
+        Res, ResMut, Transform, With, Without,
     },
     window::Windows,
 };
@@ -16,13 +16,15 @@ use crate::{
     node_bundles::{InputCircle, OutputCircle},
     tool_systems::mouse_to_world_space,
     toolbar::ToolType,
-    Camera, NodeBuilderRef, NodeType, SimulationID, UIState, UnderCursor, CONNECTION_CIRCLE_RADIUS,
-    CROSSING_SIZE, IONODE_SIZE,
+    Camera, NodeBuilderRef, NodeType, SimulationID, StreetLinePosition, UIState, UnderCursor,
+    CONNECTION_CIRCLE_RADIUS, CROSSING_SIZE, IONODE_SIZE, STREET_THICKNESS,
 };
 
 const MIN_X: f32 = 300.0;
 const MAX_X: f32 = 100.0;
 const PAN_SPEED: f32 = 10.0;
+/// the space (in world coordinates) kept around the nodes when fitting them into view
+const FIT_TO_VIEW_MARGIN: f32 = 50.0;
 
 /// This is used to be able to connect different sides of a crossing with
 /// another. (The Circle you clicked on represents one side of the crossing)
@@ -71,10 +73,20 @@ pub fn mark_connector_under_cursor(
     });
 }
 
-pub fn get_shape_under_mouse<'a, T: Iterator<Item = (Entity, &'a Transform, &'a NodeType)>>(
+pub fn get_shape_under_mouse<
+    'a,
+    T: Iterator<
+        Item = (
+            Entity,
+            &'a Transform,
+            &'a NodeType,
+            Option<&'a StreetLinePosition>,
+        ),
+    >,
+>(
     m_pos: Vec2,
     windows: Res<Windows>,
-    shapes: T, // &Query<(Entity, &Transform, &NodeType)>,
+    shapes: T, // &Query<(Entity, &Transform, &NodeType, Option<&StreetLinePosition>)>,
     camera: &Query<&Transform, With<Camera>>,
 ) -> Option<(Entity, Transform, NodeType)> {
     if let Ok(camera_transform) = camera.single() {
@@ -83,31 +95,39 @@ pub fn get_shape_under_mouse<'a, T: Iterator<Item = (Entity, &'a Transform, &'a
         // get position of 0,0 of world coordinate system in screen coordinates
         let mouse_pos = mouse_to_world_space(camera_transform, m_pos, &windows);
         // dbg!(mouse_pos);
-        let min_dist_io = IONODE_SIZE * IONODE_SIZE;
-        let half_square_side_len = CROSSING_SIZE / 2.0;
-        let mut shapes_under_cursor = shapes.filter(|(_entity, transform, node_type)| {
+        // crossings and IONodes are drawn above the streets, so a street is
+        //  only returned if there is no other node under the cursor
+        let mut nearest_street: Option<(Entity, &Transform, &NodeType, f32)> = None;
+        for (entity, transform, node_type, line) in shapes {
             match node_type {
                 NodeType::CROSSING => {
-                    let position = Vec2::new(transform.translation.x, transform.translation.y);
-                    // is the mouse in the square?
-                    position.x - half_square_side_len <= mouse_pos.x
-                        && mouse_pos.x <= position.x + half_square_side_len
-                        && position.y - half_square_side_len <= mouse_pos.y
-                        && mouse_pos.y <= position.y + half_square_side_len
+                    if is_in_crossing(transform, mouse_pos) {
+                        return Some((entity, transform.clone(), node_type.clone()));
+                    }
                 }
                 NodeType::IONODE => {
-                    let position = Vec2::new(transform.translation.x, transform.translation.y);
-                    // calculate distance, squared to improve performance so does not need to be rooted
-                    let dist = (position - mouse_pos).length_squared();
-                    dist <= min_dist_io
+                    if is_in_io_node(transform, mouse_pos) {
+                        return Some((entity, transform.clone(), node_type.clone()));
+                    }
+                }
+                NodeType::STREET => {
+                    let line = match line {
+                        Some(l) => l,
+                        None => continue,
+                    };
+                    let dist = dist_to_segment(mouse_pos, line.0, line.1);
+                    // if streets overlap, the nearest one is chosen
+                    let is_nearer = match nearest_street {
+                        Some((_, _, _, min_dist)) => dist < min_dist,
+                        None => true,
+                    };
+                    if dist <= STREET_THICKNESS && is_nearer {
+                        nearest_street = Some((entity, transform, node_type, dist));
+                    }
                 }
-                NodeType::STREET => false, // streets can't be selected
             }
-        });
-        return match shapes_under_cursor.next() {
-            Some((e, t, n)) => Some((e, t.clone(), n.clone())),
-            None => None,
-        };
+        }
+        return nearest_street.map(|(e, t, n, _)| (e, t.clone(), n.clone()));
     }
     None
 }
@@ -126,24 +146,10 @@ pub fn get_shape_under_mouse_mut_ref<
         // get position of 0,0 of world coordinate system in screen coordinates
         let mouse_pos = mouse_to_world_space(camera_transform, m_pos, &windows);
         // dbg!(mouse_pos);
-        let min_dist_io = IONODE_SIZE * IONODE_SIZE;
-        let half_square_side_len = CROSSING_SIZE / 2.0;
         let mut shapes_under_cursor = shapes.filter(|(_entity, transform, node_type)| {
             match node_type {
-                NodeType::CROSSING => {
-                    let position = Vec2::new(transform.translation.x, transform.translation.y);
-                    // is the mouse in the square?
-                    position.x - half_square_side_len <= mouse_pos.x
-                        && mouse_pos.x <= position.x + half_square_side_len
-                        && position.y - half_square_side_len <= mouse_pos.y
-                        && mouse_pos.y <= position.y + half_square_side_len
-                }
-                NodeType::IONODE => {
-                    let position = Vec2::new(transform.translation.x, transform.translation.y);
-                    // calculate distance, squared to improve performance so does not need to be rooted
-                    let dist = (position - mouse_pos).length_squared();
-                    dist <= min_dist_io
-                }
+                NodeType::CROSSING => is_in_crossing(transform, mouse_pos),
+                NodeType::IONODE => is_in_io_node(transform, mouse_pos),
                 NodeType::STREET => false, // streets can't be selected
             }
         });
@@ -154,6 +160,37 @@ pub fn get_shape_under_mouse_mut_ref<
     }
     None
 }
+/// checks if `point` lies on the crossing drawn with `transform`
+///
+/// The crossing is a square with side length [CROSSING_SIZE] centered on its
+/// position (see [crate::node_bundles::node_render::crossing]), scaled with the
+/// render settings. Points on the edge are inside
+pub fn is_in_crossing(transform: &Transform, point: Vec2) -> bool {
+    let half_side_len = CROSSING_SIZE / 2.0 * transform.scale.x;
+    (point.x - transform.translation.x).abs() <= half_side_len
+        && (point.y - transform.translation.y).abs() <= half_side_len
+}
+/// checks if `point` lies on the IONode drawn with `transform`
+///
+/// The IONode is a circle with radius [IONODE_SIZE] (see
+/// [crate::node_bundles::node_render::io_node]). Points on the edge are inside
+pub fn is_in_io_node(transform: &Transform, point: Vec2) -> bool {
+    let position = Vec2::new(transform.translation.x, transform.translation.y);
+    // squared to avoid the square root
+    (position - point).length_squared() <= IONODE_SIZE * IONODE_SIZE
+}
+/// distance between the point `p` and the line segment from `a` to `b`
+pub fn dist_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let len_sqr = ab.length_squared();
+    if len_sqr == 0.0 {
+        return (p - a).length();
+    }
+    // position of the projection of p on the segment (0 = a, 1 = b)
+    let t = ((p - a).dot(ab) / len_sqr).clamp(0.0, 1.0);
+    (p - (a + ab * t)).length()
+}
+
 // used by the code that checks if a shape was clicked
 #[derive(Clone)]
 pub struct ShapeClicked<'a> {
@@ -232,11 +269,44 @@ pub fn mouse_panning(
     }
 }
 
+/// true if the modifier for the tool hotkeys is pressed
+fn tool_modifier_pressed(keyboard_input: &Res<Input<KeyCode>>) -> bool {
+    keyboard_input.pressed(KeyCode::LAlt) || keyboard_input.pressed(KeyCode::RAlt)
+}
+
+/// selects tools with Alt + key
+///
+/// The modifier is needed, as some of the keys are also used for panning
+pub fn tool_hotkeys(keyboard_input: Res<Input<KeyCode>>, mut uistate: ResMut<UIState>) {
+    if !tool_modifier_pressed(&keyboard_input) {
+        return;
+    }
+    let hotkeys = [
+        (KeyCode::P, ToolType::Pan),
+        (KeyCode::S, ToolType::Select),
+        (KeyCode::C, ToolType::AddCrossing),
+        (KeyCode::I, ToolType::AddIONode),
+        (KeyCode::R, ToolType::AddStreet),
+        (KeyCode::X, ToolType::DeleteNode),
+    ];
+    for (key, ttype) in hotkeys {
+        if keyboard_input.just_pressed(key) {
+            // does nothing if the toolbar is locked (e.g. while placing a street)
+            uistate.toolbar.select_tooltype(ttype);
+            return;
+        }
+    }
+}
+
 // pans canvas
 pub fn keyboard_movement(
     keyboard_input: Res<Input<KeyCode>>,
     mut camera: Query<&mut Transform, With<Camera>>,
 ) {
+    // the keys are used to select tools
+    if tool_modifier_pressed(&keyboard_input) {
+        return;
+    }
     let speed: f32 = PAN_SPEED;
     for mut transform in camera.iter_mut() {
         let s: Vec3 = transform.scale;
@@ -260,3 +330,98 @@ pub fn keyboard_movement(
         }
     }
 }
+
+/// Calculates the camera translation and scale needed to display everything
+/// between `min` and `max` (plus a margin) in the part of the window that isn't
+/// covered by the side panels
+pub fn fit_to_view_transform(min: Vec2, max: Vec2, window_size: Vec2) -> (Vec2, f32) {
+    let visible_size = Vec2::new(window_size.x - MIN_X - MAX_X, window_size.y);
+    let needed_size = max - min + Vec2::splat(2.0 * FIT_TO_VIEW_MARGIN);
+    let scale = (needed_size / visible_size).max_element();
+    // the visible area is not centered in the window, as the side panels
+    // have different widths
+    let panel_offset = Vec2::new((MIN_X - MAX_X) / 2.0, 0.0);
+    let center = (min + max) / 2.0;
+    (center - panel_offset * scale, scale)
+}
+
+/// moves and zooms the camera so that every node is visible when F is pressed
+pub fn fit_to_view(
+    keyboard_input: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+    nodes: Query<(&Transform, &NodeType), Without<Camera>>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F) {
+        return;
+    }
+    // streets lie between other nodes, so they don't need to be considered
+    let positions = nodes
+        .iter()
+        .filter(|(_, ntype)| **ntype != NodeType::STREET)
+        .map(|(transform, _)| Vec2::new(transform.translation.x, transform.translation.y));
+    let bounds = positions.fold(None, |bounds: Option<(Vec2, Vec2)>, pos| match bounds {
+        Some((min, max)) => Some((min.min(pos), max.max(pos))),
+        None => Some((pos, pos)),
+    });
+    // there is nothing to fit into view on an empty map
+    let (min, max) = match bounds {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    let (translation, scale) = fit_to_view_transform(min, max, get_primary_window_size(&windows));
+    for mut transform in camera.iter_mut() {
+        transform.translation.x = translation.x;
+        transform.translation.y = translation.y;
+        transform.scale = Vec3::new(scale, scale, 1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dist_to_segment, is_in_crossing, is_in_io_node};
+    use crate::{CROSSING_SIZE, IONODE_SIZE};
+    use bevy::{
+        math::{Vec2, Vec3},
+        prelude::Transform,
+    };
+
+    #[test]
+    fn crossing_hit_test_matches_square() {
+        let transform = Transform::from_xyz(10.0, 20.0, 0.0);
+        let half = CROSSING_SIZE / 2.0;
+        // the edges and corners belong to the crossing
+        assert!(is_in_crossing(&transform, Vec2::new(10.0 + half, 20.0)));
+        assert!(is_in_crossing(&transform, Vec2::new(10.0 - half, 20.0 - half)));
+        assert!(!is_in_crossing(&transform, Vec2::new(10.0 + half + 0.1, 20.0)));
+        assert!(!is_in_crossing(&transform, Vec2::new(10.0, 20.0 - half - 0.1)));
+        // scaled crossings are bigger
+        let mut scaled = transform.clone();
+        scaled.scale = Vec3::new(2.0, 2.0, 1.0);
+        assert!(is_in_crossing(&scaled, Vec2::new(10.0 + CROSSING_SIZE, 20.0)));
+    }
+
+    #[test]
+    fn io_node_hit_test_matches_circle() {
+        let transform = Transform::from_xyz(-5.0, 5.0, 0.0);
+        assert!(is_in_io_node(&transform, Vec2::new(-5.0 + IONODE_SIZE, 5.0)));
+        assert!(!is_in_io_node(&transform, Vec2::new(-5.0 + IONODE_SIZE + 0.1, 5.0)));
+        // the corner of the bounding square is outside of the circle
+        assert!(!is_in_io_node(&transform, Vec2::new(-5.0 + IONODE_SIZE, 5.0 + IONODE_SIZE)));
+    }
+
+    #[test]
+    fn point_to_segment_distance() {
+        let a = Vec2::new(0.0, 0.0);
+        let b = Vec2::new(10.0, 0.0);
+        // projection lies on the segment
+        assert_eq!(dist_to_segment(Vec2::new(5.0, 3.0), a, b), 3.0);
+        // projection lies behind the endpoints
+        assert_eq!(dist_to_segment(Vec2::new(-3.0, 4.0), a, b), 5.0);
+        assert_eq!(dist_to_segment(Vec2::new(13.0, -4.0), a, b), 5.0);
+        // point on the segment
+        assert_eq!(dist_to_segment(Vec2::new(7.0, 0.0), a, b), 0.0);
+        // degenerated segment
+        assert_eq!(dist_to_segment(Vec2::new(3.0, 4.0), a, a), 5.0);
+    }
+}
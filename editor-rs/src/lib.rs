@@ -1,4 +1,4 @@
-use std::sync::MutexGuard;
+use std::sync::RwLockWriteGuard;
 
 use bevy::prelude::*;
 use bevy::render::mesh::VertexAttributeValues;
@@ -6,15 +6,22 @@ use bevy_egui::{EguiPlugin, EguiContext};
 use bevy_prototype_lyon::prelude::*;
 use simulator::datastructs::IntMut;
 use simulator::debug::build_grid_sim;
-use simulator::nodes::{NodeBuilder, NodeBuilderTrait, InOut};
+use simulator::nodes::{Direction, NodeBuilder, NodeBuilderTrait, InOut};
 use simulator::{self, SimManager};
 use themes::*;
 use tool_systems::SelectedNode;
 use user_interface::{repaint_ui, update_sim_reports};
 use wasm_bindgen::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+mod auto_save;
+mod file_io;
 mod input;
 mod node_bundles;
+mod notifications;
+mod render_settings;
 mod simulation_display;
+#[cfg(not(target_arch = "wasm32"))]
+mod snapshot;
 mod themes;
 mod tool_systems;
 mod toolbar;
@@ -87,6 +94,19 @@ pub struct UIState {
     toolbar: toolbar::Toolbar,
     mode: UIMode,
     prev_mode: Option<UIMode>,
+    /// if set to true, new nodes are placed on a grid with the spacing [GRID_NODE_SPACING]
+    snap_to_grid: bool,
+    /// the path entered in the preferences to load a custom theme from
+    theme_path: String,
+    /// if set to true, cars are colored by their speed instead of a single color
+    car_heatmap: bool,
+    /// if set to true, the number of cars on each street is shown while simulating
+    street_car_labels: bool,
+    /// the path entered in the preferences to save snapshots to
+    snapshot_path: String,
+    /// if set to true, the street network is saved and loaded in a binary
+    /// format instead of JSON
+    binary_save: bool,
 }
 impl UIState {
     /// if there was a previous mode, switch to it
@@ -115,6 +135,9 @@ pub enum NodeType {
 const GRID_NODE_SPACING: usize = 100;
 const GRID_SIDE_LENGTH: usize = 3;
 const STREET_THICKNESS: f32 = 5.0;
+const GRID_LINE_THICKNESS: f32 = 1.0;
+/// how far the snapping grid is drawn in each direction from the origin
+const SNAP_GRID_EXTENT: f32 = 5000.0;
 // const STREET_SPACING: usize = 20;
 const CROSSING_SIZE: f32 = 20.0;
 const IONODE_SIZE: f32 = 20.0;
@@ -126,37 +149,58 @@ const CONNECTION_CIRCLE_DIST_FROM_MIDDLE: f32 = CROSSING_SIZE/2.0 + 10.0;
 const STREET_OFFSET: [f32; 2] = [CROSSING_SIZE/2.0, CROSSING_SIZE/4.0];
 const CAR_Z: f32 = 20.0;
 const CAR_SIZE: f32 = 1.5;
+const TRAFFIC_LIGHT_SIZE: f32 = 2.5;
 
 #[wasm_bindgen]
 pub fn run() {
+    // the theme used the last time the editor was open
+    let current_theme = CurrentTheme::load();
+    let theme = UITheme::from_enum(&current_theme);
     let mut app = App::build();
     app.add_plugins_with(DefaultPlugins, | group | { group.disable::<bevy::log::LogPlugin>() } )
         .add_plugin(EguiPlugin)
         .add_plugin(ShapePlugin)
         .init_resource::<UIState>()
         .init_resource::<AddStreetStage>()
+        .init_resource::<simulation_display::TrafficLightStates>()
+        .init_resource::<simulation_display::LiveStats>()
+        .init_resource::<simulation_display::SimBridge>()
+        .init_resource::<simulation_display::Replay>()
+        .init_resource::<simulation_display::LivePlayback>()
+        .init_resource::<user_interface::ReplaySettings>()
+        .init_resource::<render_settings::RenderSettings>()
+        .init_resource::<tool_systems::Selection>()
+        .init_resource::<file_io::PendingLoad>()
+        .init_resource::<user_interface::SaveMeta>()
+        .init_resource::<notifications::Notifications>()
         //app.add_plugins(bevy_webgl2::DefaultPlugins);
         // when building for Web, use WebGL2 rendering
         //#[cfg(target_arch = "wasm32")]
         //app.add_plugin(bevy_webgl2::WebGL2Plugin);
         .insert_resource(SimManager::new())
-        .add_startup_system(spawn_node_grid.system())
+        .add_system(spawn_node_grid.system())
         .add_startup_system(spawn_camera.system())
-        .insert_resource(UITheme::dracula()) // Theme
-        .insert_resource(CurrentTheme::DRACULA) // Theme
-        .insert_resource(ClearColor(UITheme::dracula().background))
+        .insert_resource(ClearColor(theme.background))
+        .insert_resource(theme) // Theme
+        .insert_resource(current_theme) // Theme
         .insert_resource(bevy::input::InputSystem)
-        .insert_resource(first_frame{ b: true })
         .add_system(user_interface::draw_user_interface.system())
         .add_system_to_stage(CoreStage::PreUpdate, mark_under_cursor.system())
         // .add_system(color_under_cursor.system())
         //.add_system(rotation_test.system())
         .add_system(input::keyboard_movement.system())
+        .add_system(input::fit_to_view.system())
+        .add_system(input::tool_hotkeys.system())
         .add_system_to_stage(CoreStage::PostUpdate, update_sim_reports.system())
         .add_system(input::mouse_panning.system())
+        .add_system(render_settings::apply_render_settings.system())
+        .add_system(render_settings::update_street_lanes.system())
         .add_system(recolor_nodes.system())
         .add_system(debug_status_updates.system())
-        .add_system(toggle_theme_on_startup.system())
+        .add_system(apply_egui_visuals.system())
+        .add_system(user_interface::update_window_title.system())
+        .add_system(notifications::draw_notifications.system())
+        .add_system(tool_systems::display_snap_grid.system())
         // .add_system(toolbarsystem.system())
         .add_system_set_to_stage(
             CoreStage::PostUpdate,
@@ -174,7 +218,8 @@ pub fn run() {
         .add_system_set(
             SystemSet::new()
                 .with_run_criteria(tool_systems::run_if_select.system())
-                .with_system(tool_systems::select_node.system()),
+                .with_system(tool_systems::select_node.system())
+                .with_system(tool_systems::delete_selected_nodes.system()),
         )
         .add_system_set(
             SystemSet::new()
@@ -196,39 +241,30 @@ pub fn run() {
         .add_system_set(
             SystemSet::new()
                 .with_run_criteria(simulation_display::run_if_simulating.system())
-                .with_system(simulation_display::display_cars.system()),
-        )
-        .run();
+                .with_system(simulation_display::display_cars.system())
+                .with_system(simulation_display::display_replay.system())
+                .with_system(simulation_display::display_street_car_counts.system())
+                .with_system(user_interface::draw_replay_window.system())
+                .with_system(simulation_display::display_traffic_lights.system()),
+        );
+    // there is no file system to save the snapshots to on the web
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_system(snapshot::save_snapshot.system());
+    // there is no file system to recover the auto-save from on the web
+    #[cfg(not(target_arch = "wasm32"))]
+    app.init_resource::<auto_save::AutoSave>()
+        .add_startup_system(auto_save::check_for_auto_save.system())
+        .add_system(auto_save::draw_recovery_window.system())
+        // the nodes are locked while saving, so no other system may use them
+        .add_system_to_stage(CoreStage::Last, auto_save::auto_save.system());
+    app.run();
 }
 
 
-struct first_frame{
-    b: bool
-}
-
-fn toggle_theme_on_startup(commands: Commands, egui_context: ResMut<EguiContext>, mut background: ResMut<ClearColor>, nodes: Query<Entity, With<NodeType>>,
-    mut theme: ResMut<UITheme>, mut current_theme: ResMut<CurrentTheme>, mut ff: ResMut<first_frame>) {
-    if ff.b {
-    let mut new_theme = CurrentTheme::LIGHT;
-    if new_theme != *current_theme {
-        *current_theme = new_theme;
-        *theme = UITheme::from_enum(&new_theme);
-    }
-    new_theme = CurrentTheme::DRACULA;
-    if new_theme != *current_theme {
-        *current_theme = new_theme;
-        *theme = UITheme::from_enum(&new_theme);
-    }
-    // repaint_ui(
-    //     commands,
-    //     Some(egui_context.ctx()),
-    //     &mut background,
-    //     &nodes,
-    //     theme,
-    //     );
-    background.0 = theme.background;
-    egui_context.ctx().set_visuals(theme.egui_visuals.clone());
-    ff.b = false;
+/// applies the visuals of the theme to egui when the theme is loaded or changed
+fn apply_egui_visuals(egui_context: ResMut<EguiContext>, theme: Res<UITheme>) {
+    if theme.is_changed() {
+        egui_context.ctx().set_visuals(theme.egui_visuals.clone());
     }
 }
 
@@ -236,6 +272,7 @@ fn debug_status_updates(sim_manager: Res<SimManager>) {
     let report = sim_manager.get_status_updates();
     if let Some(r) = report {
         let update: String = r
+            .cars
             .values()
             .map(|s| s.iter().map(|s| s.position.to_string() + ", "))
             .flatten()
@@ -254,7 +291,16 @@ fn spawn_camera(mut commands: Commands) {
 
 pub struct NeedsRecolor;
 
-/// recolors all nodes with the marker Component [NeedsRecolor]
+/// how many nodes are recolored at most per frame
+///
+/// When the theme changes, every node needs to be recolored. Spreading that
+/// over several frames keeps the editor responsive for large street networks
+const RECOLOR_BATCH_SIZE: usize = 1000;
+
+/// recolors the nodes with the marker Component [NeedsRecolor]
+///
+/// At most [RECOLOR_BATCH_SIZE] nodes are recolored per frame, the others keep
+/// their marker and are recolored in the following frames
 pub fn recolor_nodes(
     mut commands: Commands,
     to_recolor: Query<
@@ -264,7 +310,7 @@ pub fn recolor_nodes(
     theme: Res<UITheme>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    to_recolor.for_each(|(entity, mesh_handle, ntype, selected)| {
+    to_recolor.iter().take(RECOLOR_BATCH_SIZE).for_each(|(entity, mesh_handle, ntype, selected)| {
         // repaint the node
         let color = match selected.is_some() {
             true => theme.highlight,
@@ -290,7 +336,7 @@ fn mark_under_cursor(
         // previously marked nodes that need to be unmarked
         Query<Entity, (With<NodeType>, With<UnderCursor>)>,
         // candidates for selection
-        Query<(Entity, &Transform, &NodeType)>,
+        Query<(Entity, &Transform, &NodeType, Option<&StreetLinePosition>)>,
         // the camera
         Query<&Transform, With<Camera>>,
     )>,
@@ -329,13 +375,59 @@ pub fn color_under_cursor(
 ///  line positions seperatly
 pub struct StreetLinePosition(Vec2, Vec2);
 
+/// The number of lanes a street is currently drawn with, so it can be redrawn
+///  when the lanes of its NodeBuilder change
+pub struct StreetLanes(u8);
+
 /// Holds an IntMut (interior mutability) for a nodebuilder
 #[derive(Debug, Clone)]
 pub struct NodeBuilderRef(IntMut<NodeBuilder>);
 
 pub fn repaint_node(mesh_handle: &Handle<Mesh>, color: Color, meshes: &mut ResMut<Assets<Mesh>>) {
-    let mesh = meshes.get_mut(mesh_handle).unwrap();
-    let colors = mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR).unwrap();
+    // only borrow the mesh mutably if it has to be changed, as that marks it
+    // as modified and it would be uploaded to the GPU again
+    match meshes.get(mesh_handle) {
+        Some(mesh) if has_color(mesh, color) => return,
+        Some(_) => {}
+        None => {
+            warn!("Unable to repaint node, as its mesh doesn't exist");
+            return;
+        }
+    }
+    // the color was already checked above
+    if let Some(mesh) = meshes.get_mut(mesh_handle) {
+        set_mesh_color(mesh, color);
+    }
+}
+
+/// true if every vertex of the mesh already has the color `color`
+pub fn has_color(mesh: &Mesh, color: Color) -> bool {
+    let color: [f32; 4] = color.into();
+    match mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+        Some(VertexAttributeValues::Float4(colors)) => colors.iter().all(|c| *c == color),
+        _ => false,
+    }
+}
+
+/// Sets the color of every vertex of the mesh to `color`
+///
+/// Returns false if the mesh was left as it was, because it already had the color
+pub fn repaint_mesh(mesh: &mut Mesh, color: Color) -> bool {
+    !has_color(mesh, color) && set_mesh_color(mesh, color)
+}
+
+/// Sets the color of every vertex of the mesh to `color`, even if it already
+/// has it
+///
+/// Returns false if the mesh has no colors
+pub fn set_mesh_color(mesh: &mut Mesh, color: Color) -> bool {
+    let colors = match mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR) {
+        Some(colors) => colors,
+        None => {
+            warn!("Unable to repaint node, as its mesh has no colors");
+            return false;
+        }
+    };
     let values = match colors {
         VertexAttributeValues::Float4(colors) => colors
             .iter()
@@ -344,77 +436,77 @@ pub fn repaint_node(mesh_handle: &Handle<Mesh>, color: Color, meshes: &mut ResMu
         _ => vec![],
     };
     mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, values);
+    true
 }
 
-pub fn calculate_offset_from_crossing_in(street: &IntMut<NodeBuilder>, c_in: &MutexGuard<NodeBuilder>, c_out: &MutexGuard<NodeBuilder>) -> Vec2 {
-    let mut offset = Vec2::ZERO;
-    match &**c_in{
-        NodeBuilder::IONode(io_node) => {
-            // The offset can not be determined from an IONode, as the direction is unclear.
-            //  Therefor, use the output as reference
-            if let NodeBuilder::Crossing(crossing) = &**c_out{
-                let dir = crossing.get_direction_for_item(InOut::IN, street).expect("Crossing that is set as output doesn't have street as input");
-                match dir {
-                    simulator::nodes::Direction::N => {
-                        offset.x -= STREET_OFFSET[1];
-                        offset.y += STREET_OFFSET[0];
-                    },
-                    simulator::nodes::Direction::S => {
-                        offset.x += STREET_OFFSET[1];
-                        offset.y -= STREET_OFFSET[0];
-                    },
-                    simulator::nodes::Direction::E => {
-                        offset.x += STREET_OFFSET[0];
-                        offset.y += STREET_OFFSET[1];
-                    },
-                    simulator::nodes::Direction::W => {
-                        offset.x -= STREET_OFFSET[0];
-                        offset.y -= STREET_OFFSET[1];
-                    },
-                }
-            }
-        },
-        NodeBuilder::Crossing(crossing) => {
-            let dir = crossing.get_direction_for_item(InOut::OUT, street).expect("Crossing that is set as input doesn't have street as output");
-            match dir {
-                simulator::nodes::Direction::N => {
-                    offset.x += STREET_OFFSET[1];
-                    offset.y += STREET_OFFSET[0];
-                },
-                simulator::nodes::Direction::S => {
-                    offset.x -= STREET_OFFSET[1];
-                    offset.y -= STREET_OFFSET[0];
-                },
-                simulator::nodes::Direction::E => {
-                    offset.x += STREET_OFFSET[0];
-                    offset.y -= STREET_OFFSET[1];
-                },
-                simulator::nodes::Direction::W => {
-                    offset.x -= STREET_OFFSET[0];
-                    offset.y += STREET_OFFSET[1];
-                },
-            }
-        },
-        NodeBuilder::Street(_) => panic!("Street connected to street!"),
+/// The offset of a street connected to the side `dir` of a crossing, so
+/// the streets in both directions run parallel instead of on top of each other
+///
+/// `in_out` is the type of the connection from the view of the crossing. If a
+/// side holds several streets of the same type, they are spread over its half
+/// of the side, `index` is the position of the street among the `count` streets
+fn street_offset(dir: Direction, in_out: InOut, index: usize, count: usize) -> Vec2 {
+    let [along, across] = STREET_OFFSET;
+    // a single street is drawn at `across`
+    let across = across * 2.0 * (index + 1) as f32 / (count + 1) as f32;
+    match (in_out, dir) {
+        (InOut::OUT, Direction::N) => Vec2::new(across, along),
+        (InOut::OUT, Direction::S) => Vec2::new(-across, -along),
+        (InOut::OUT, Direction::E) => Vec2::new(along, -across),
+        (InOut::OUT, Direction::W) => Vec2::new(-along, across),
+        (InOut::IN, Direction::N) => Vec2::new(-across, along),
+        (InOut::IN, Direction::S) => Vec2::new(across, -along),
+        (InOut::IN, Direction::E) => Vec2::new(along, across),
+        (InOut::IN, Direction::W) => Vec2::new(-along, -across),
     }
-    offset
 }
 
-/// This function is for debugging purposes
-/// It spawns a grid of nodes
+/// Calculates the offset of a street going from `c_in` to `c_out`
 ///
-/// It also generates the graphics for it
+/// The side of the crossing the street starts at is used. If the street
+/// doesn't start at a crossing (or isn't connected to one of its sides),
+/// the crossing it ends at is used instead. Crossings don't need to have all
+/// four sides connected, and a side can hold several streets. Streets between two IONodes aren't offset.
+pub fn calculate_offset_from_crossing_in(street: &IntMut<NodeBuilder>, c_in: &RwLockWriteGuard<NodeBuilder>, c_out: &RwLockWriteGuard<NodeBuilder>) -> Vec2 {
+    let side_of = | node: &NodeBuilder, in_out: InOut | match node {
+        NodeBuilder::Crossing(crossing) => crossing
+            .get_direction_for_item(in_out, street)
+            .map(| dir | {
+                let side = crossing.connections.get_all(in_out, dir);
+                let index = side.iter().position(| c | c == street).unwrap_or(0);
+                street_offset(dir, in_out, index, side.len())
+            }),
+        NodeBuilder::IONode(_) => None,
+        NodeBuilder::Street(_) => panic!("Street connected to street!"),
+    };
+    side_of(c_in, InOut::OUT)
+        .or_else(|| side_of(c_out, InOut::IN))
+        .unwrap_or(Vec2::ZERO)
+}
+
+/// Spawns the grid that is shown when the editor starts, including its graphics
+///
+/// If the SimulatorBuilder can't be modified yet, this is retried in the next frame
 fn spawn_node_grid(
     mut commands: Commands,
     theme: Res<UITheme>,
     mut sim_manager: ResMut<SimManager>,
+    mut spawned: Local<bool>,
 ) {
+    if *spawned {
+        return;
+    }
     // for testing purposes
     let side_len = GRID_SIDE_LENGTH;
     let spacing = GRID_NODE_SPACING;
-    let new_builder = sim_manager
-        .modify_sim_builder()
-        .expect("Simulation is running while trying to construct grid");
+    let new_builder = match sim_manager.modify_sim_builder() {
+        Ok(builder) => builder,
+        Err(err) => {
+            warn!("Deferring the construction of the grid: {}", err);
+            return;
+        }
+    };
+    *spawned = true;
     *new_builder = build_grid_sim(side_len as u32, GRID_NODE_SPACING as f32);
     new_builder.with_delay(0).with_dt(2.0);
     info!("spawning node grid");
@@ -480,6 +572,8 @@ fn spawn_node_grid(
                 }
             }
         });
+    // the default grid doesn't need to be auto-saved
+    sim_manager.mark_builder_saved();
 }
 
 fn get_primary_window_size(windows: &Res<Windows>) -> Vec2 {
@@ -3,7 +3,7 @@ use bevy_prototype_lyon::entity::ShapeBundle;
 use simulator::nodes::Direction;
 use simulator::{datastructs::IntMut, nodes::NodeBuilder};
 
-use crate::{NodeBuilderRef, NodeType, SimulationID, StreetLinePosition, CROSSING_SIZE, CONNECTION_CIRCLE_DIST_FROM_MIDDLE};
+use crate::{NodeBuilderRef, NodeType, SimulationID, StreetLanes, StreetLinePosition, STREET_THICKNESS, CROSSING_SIZE, CONNECTION_CIRCLE_DIST_FROM_MIDDLE};
 
 #[derive(Bundle)]
 /// This is the way Crossings are saved in the frontend
@@ -61,6 +61,8 @@ pub struct StreetBundle {
     /// start and end (to my knowledge) positions can't be read from
     /// the ShapeBundle
     position: StreetLinePosition,
+    /// The number of lanes that is drawn
+    lanes: StreetLanes,
     /// a reference to the NodeBuilder
     node_builder_ref: NodeBuilderRef,
 }
@@ -74,16 +76,26 @@ impl StreetBundle {
         color: Color,
     ) -> StreetBundle {
         let nbr = NodeBuilderRef(node_builder.clone());
+        let lanes = street_lanes(node_builder);
         StreetBundle {
-            shape: node_render::street(start, end, color),
+            shape: node_render::street_with_lanes(start, end, color, STREET_THICKNESS, lanes),
             sim_id: SimulationID(id),
             node_type: NodeType::STREET,
             node_builder_ref: nbr,
             position: StreetLinePosition(start, end),
+            lanes: StreetLanes(lanes),
         }
     }
 }
 
+/// the number of lanes of a street (1 for the other nodes)
+pub fn street_lanes(node_builder: &IntMut<NodeBuilder>) -> u8 {
+    match &*node_builder.read() {
+        NodeBuilder::Street(street) => street.lanes,
+        _ => 1,
+    }
+}
+
 #[derive(Bundle)]
 /// This is the way IONodes are saved in the frontend
 ///
@@ -113,9 +125,9 @@ impl IONodeBundle {
         // IONodes should be rendered on top of streets
         shape.transform.translation.z = 1.0;
         IONodeBundle {
-            shape: node_render::io_node(pos, color),
+            shape,
             sim_id: SimulationID(id),
-            node_type: NodeType::CROSSING,
+            node_type: NodeType::IONODE,
             node_builder_ref: nbr,
         }
     }
@@ -132,13 +144,17 @@ pub mod node_render {
         shapes,
     };
 
-    use crate::{CONNECTION_CIRCLE_RADIUS, CROSSING_SIZE, IONODE_SIZE, STREET_THICKNESS};
+    use crate::{
+        CONNECTION_CIRCLE_RADIUS, CROSSING_SIZE, GRID_LINE_THICKNESS, IONODE_SIZE, STREET_THICKNESS,
+    };
 
     pub fn crossing(pos: Vec2, color: Color) -> ShapeBundle {
+        // centered, so the position is the middle of the crossing like for
+        //  the circles of the IONodes
         let rect = shapes::Rectangle {
             width: CROSSING_SIZE,
             height: CROSSING_SIZE,
-            ..shapes::Rectangle::default()
+            origin: shapes::RectangleOrigin::Center,
         };
         GeometryBuilder::build_as(
             &rect,
@@ -167,16 +183,67 @@ pub mod node_render {
         )
     }
     pub fn street(p1: Vec2, p2: Vec2, color: Color) -> ShapeBundle {
+        street_with_thickness(p1, p2, color, STREET_THICKNESS)
+    }
+    /// a street drawn with a line width other than [STREET_THICKNESS]
+    /// (see [crate::render_settings::RenderSettings])
+    pub fn street_with_thickness(p1: Vec2, p2: Vec2, color: Color, thickness: f32) -> ShapeBundle {
+        street_with_lanes(p1, p2, color, thickness, 1)
+    }
+    /// a street drawn as one line per lane (see [lane_lines])
+    ///
+    /// The lines of streets with several lanes are thinner, so they can be told apart
+    pub fn street_with_lanes(p1: Vec2, p2: Vec2, color: Color, thickness: f32, lanes: u8) -> ShapeBundle {
+        let line_width = match lanes {
+            0 | 1 => thickness,
+            _ => thickness * LANE_LINE_WIDTH,
+        };
+        let mut builder = GeometryBuilder::new();
+        for (start, end) in lane_lines(p1, p2, lanes, thickness) {
+            builder.add(&shapes::Line(start, end));
+        }
+        builder.build(
+            ShapeColors::outlined(color, color),
+            DrawMode::Outlined {
+                fill_options: FillOptions::default(),
+                outline_options: StrokeOptions::default().with_line_width(line_width),
+            },
+            Transform::default(),
+        )
+    }
+    /// the width of a lane line relative to the thickness of the street
+    const LANE_LINE_WIDTH: f32 = 0.5;
+    /// The start and end of the lines of each lane
+    ///
+    /// The lines are parallel to the line from `p1` to `p2` and `spacing` apart,
+    /// shifted perpendicular to it so they are centered around it
+    pub fn lane_lines(p1: Vec2, p2: Vec2, lanes: u8, spacing: f32) -> Vec<(Vec2, Vec2)> {
+        let lanes = lanes.max(1);
+        let dir = p2 - p1;
+        let len = dir.length();
+        if len == 0.0 {
+            return vec![(p1, p2); lanes as usize];
+        }
+        let normal = Vec2::new(-dir.y, dir.x) / len;
+        let middle = (lanes - 1) as f32 / 2.0;
+        (0..lanes)
+            .map(|i| {
+                let offset = normal * (i as f32 - middle) * spacing;
+                (p1 + offset, p2 + offset)
+            })
+            .collect()
+    }
+    pub fn grid_line(p1: Vec2, p2: Vec2, color: Color) -> ShapeBundle {
         let line = shapes::Line(p1, p2);
         GeometryBuilder::build_as(
             &line,
             ShapeColors::outlined(color, color),
-            //DrawMode::Fill(FillOptions::default()),
             DrawMode::Outlined {
                 fill_options: FillOptions::default(),
-                outline_options: StrokeOptions::default().with_line_width(STREET_THICKNESS),
+                outline_options: StrokeOptions::default().with_line_width(GRID_LINE_THICKNESS),
             },
-            Transform::default(), // Transform::from_xyz(calc_x(i), calc_y(i), 0.0)
+            // behind everything else
+            Transform::from_xyz(0., 0., -1.),
         )
     }
     pub fn connector(pos: Vec2, color: Color) -> ShapeBundle {
@@ -310,3 +377,42 @@ impl ConnectorCircleOut {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{node_render::lane_lines, IONodeBundle};
+    use crate::NodeType;
+    use bevy::{math::Vec2, prelude::Color};
+    use simulator::{
+        datastructs::IntMut,
+        nodes::{IONodeBuilder, NodeBuilder},
+    };
+
+    #[test]
+    fn io_node_bundle_is_an_io_node() {
+        let node = IntMut::new(NodeBuilder::IONode(IONodeBuilder::new()));
+        let bundle = IONodeBundle::new(0, &node, Vec2::ZERO, Color::WHITE);
+        assert!(bundle.node_type == NodeType::IONODE);
+        // drawn above the streets
+        assert_eq!(bundle.shape.transform.translation.z, 1.0);
+    }
+
+    #[test]
+    fn lanes_are_parallel_offset_lines() {
+        let (p1, p2) = (Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0));
+        let lines = lane_lines(p1, p2, 3, 2.0);
+        assert_eq!(
+            lines,
+            vec![
+                (Vec2::new(0.0, -2.0), Vec2::new(10.0, -2.0)),
+                (p1, p2),
+                (Vec2::new(0.0, 2.0), Vec2::new(10.0, 2.0)),
+            ]
+        );
+        // the offsets are perpendicular for vertical streets as well
+        let lines = lane_lines(p1, Vec2::new(0.0, 10.0), 2, 4.0);
+        assert_eq!(lines[0], (Vec2::new(2.0, 0.0), Vec2::new(2.0, 10.0)));
+        assert_eq!(lines[1], (Vec2::new(-2.0, 0.0), Vec2::new(-2.0, 10.0)));
+        assert_eq!(lane_lines(p1, p2, 1, 2.0), vec![(p1, p2)]);
+    }
+}
@@ -0,0 +1,85 @@
+use bevy::{
+    core::Time,
+    prelude::{Res, ResMut},
+};
+use bevy_egui::{
+    egui::{self, Align2},
+    EguiContext,
+};
+
+use crate::themes::UITheme;
+
+/// shown when the street network is edited while a simulation is running
+pub const EDIT_WHILE_SIMULATING: &str = "Cannot edit while simulating";
+/// how long a notification is shown (in seconds)
+const NOTIFICATION_DURATION: f32 = 3.0;
+
+/// Short messages shown in a corner of the window for a few seconds, e.g. to
+/// tell the user why a click didn't do anything
+#[derive(Default)]
+pub struct Notifications {
+    /// the text and the remaining time in seconds of each notification
+    shown: Vec<(String, f32)>,
+}
+
+impl Notifications {
+    /// shows `text`, if it is already shown it is shown for longer instead of twice
+    pub fn push(&mut self, text: &str) {
+        match self.shown.iter_mut().find(|(shown, _)| shown == text) {
+            Some((_, remaining)) => *remaining = NOTIFICATION_DURATION,
+            None => self.shown.push((text.to_string(), NOTIFICATION_DURATION)),
+        }
+    }
+    /// removes the notifications that were shown long enough
+    pub fn tick(&mut self, seconds: f32) {
+        self.shown.iter_mut().for_each(|(_, remaining)| *remaining -= seconds);
+        self.shown.retain(|(_, remaining)| *remaining > 0.0);
+    }
+    /// the texts that are currently shown, the oldest first
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        self.shown.iter().map(|(text, _)| text.as_str())
+    }
+}
+
+/// draws the notifications above the bottom right corner of the window
+pub fn draw_notifications(
+    egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
+    mut notifications: ResMut<Notifications>,
+    theme: Res<UITheme>,
+) {
+    // only write to the notifications if there are any, as they are checked every frame
+    if notifications.texts().next().is_none() {
+        return;
+    }
+    notifications.tick(time.delta_seconds());
+    egui::Area::new("notifications")
+        .anchor(Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+        .show(egui_context.ctx(), |ui| {
+            for text in notifications.texts() {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.colored_label(theme.text_color, text);
+                });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Notifications, NOTIFICATION_DURATION};
+
+    #[test]
+    fn notifications_disappear_and_are_not_repeated() {
+        let mut notifications = Notifications::default();
+        notifications.push("a");
+        notifications.tick(NOTIFICATION_DURATION / 2.0);
+        notifications.push("b");
+        // shown again, so it is shown for the full duration again
+        notifications.push("a");
+        assert_eq!(notifications.texts().collect::<Vec<_>>(), vec!["a", "b"]);
+        notifications.tick(NOTIFICATION_DURATION * 0.75);
+        assert_eq!(notifications.texts().count(), 2);
+        notifications.tick(NOTIFICATION_DURATION * 0.5);
+        assert_eq!(notifications.texts().count(), 0);
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::{
+    math::Vec3,
+    prelude::{Added, Commands, Entity, Query, Res, Transform},
+};
+
+use crate::{
+    node_bundles::{node_render, street_lanes}, themes::UITheme, NeedsRecolor, NodeBuilderRef,
+    NodeType, StreetLanes, StreetLinePosition, CAR_SIZE, CAR_Z, CROSSING_SIZE, STREET_THICKNESS,
+};
+
+/// Sizes and z positions used to draw the street network and the cars
+///
+/// They can be changed in the preferences, e.g. to scale the visualization
+/// for dense maps
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    /// the radius of a car
+    pub car_size: f32,
+    /// the z position of cars and traffic lights (they are drawn above
+    /// everything with a lower z)
+    pub car_z: f32,
+    /// the width of the lines representing streets
+    pub street_thickness: f32,
+    /// the side length of a crossing
+    pub crossing_size: f32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            car_size: CAR_SIZE,
+            car_z: CAR_Z,
+            street_thickness: STREET_THICKNESS,
+            crossing_size: CROSSING_SIZE,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// the scale of the crossing shapes, which are built with a side length of [CROSSING_SIZE]
+    pub fn crossing_scale(&self) -> Vec3 {
+        let scale = self.crossing_size / CROSSING_SIZE;
+        Vec3::new(scale, scale, 1.0)
+    }
+}
+
+/// Applies the [RenderSettings] to new nodes and to all nodes if the settings changed
+///
+/// Crossings are scaled, streets get a new shape with the right thickness
+pub fn apply_render_settings(
+    mut commands: Commands,
+    settings: Res<RenderSettings>,
+    theme: Res<UITheme>,
+    mut nodes: Query<(
+        Entity,
+        &NodeType,
+        &mut Transform,
+        Option<&StreetLinePosition>,
+        Option<&StreetLanes>,
+    )>,
+    new_nodes: Query<Entity, Added<NodeType>>,
+) {
+    let apply_to_all = settings.is_changed();
+    if !apply_to_all && new_nodes.iter().next().is_none() {
+        return;
+    }
+    nodes.for_each_mut(|(entity, node_type, mut transform, line, lanes)| {
+        if !apply_to_all && new_nodes.get(entity).is_err() {
+            return;
+        }
+        match node_type {
+            NodeType::CROSSING => transform.scale = settings.crossing_scale(),
+            NodeType::STREET => {
+                // new streets already have the default thickness
+                if !apply_to_all && settings.street_thickness == STREET_THICKNESS {
+                    return;
+                }
+                if let Some(line) = line {
+                    commands
+                        .entity(entity)
+                        .insert_bundle(node_render::street_with_lanes(
+                            line.0,
+                            line.1,
+                            theme.street,
+                            settings.street_thickness,
+                            lanes.map_or(1, |lanes| lanes.0),
+                        ))
+                        // the new shape has the default color, even if the street is selected
+                        .insert(NeedsRecolor);
+                }
+            }
+            NodeType::IONODE => {}
+        }
+    });
+}
+
+/// Redraws the streets whose number of lanes changed (e.g. with the lanes slider)
+pub fn update_street_lanes(
+    mut commands: Commands,
+    settings: Res<RenderSettings>,
+    theme: Res<UITheme>,
+    mut streets: Query<(Entity, &StreetLinePosition, &NodeBuilderRef, &mut StreetLanes)>,
+) {
+    streets.for_each_mut(|(entity, line, node_builder, mut lanes)| {
+        let current = street_lanes(&node_builder.0);
+        if current == lanes.0 {
+            return;
+        }
+        lanes.0 = current;
+        commands
+            .entity(entity)
+            .insert_bundle(node_render::street_with_lanes(
+                line.0,
+                line.1,
+                theme.street,
+                settings.street_thickness,
+                current,
+            ))
+            .insert(NeedsRecolor);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenderSettings;
+    use crate::CROSSING_SIZE;
+    use bevy::math::Vec3;
+
+    #[test]
+    fn crossing_scale_follows_size() {
+        let mut settings = RenderSettings::default();
+        assert_eq!(settings.crossing_scale(), Vec3::ONE);
+        settings.crossing_size = CROSSING_SIZE * 2.0;
+        // the z position isn't scaled, so the drawing order stays the same
+        assert_eq!(settings.crossing_scale(), Vec3::new(2.0, 2.0, 1.0));
+    }
+}
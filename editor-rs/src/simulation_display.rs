@@ -1,22 +1,166 @@
 use bevy::{
     ecs::schedule::ShouldRun,
     math::{Vec2, Vec3},
-    prelude::{Color, Commands, Query, Res, ResMut, Transform, Entity, DespawnRecursiveExt},
+    prelude::{Color, Commands, Local, Query, Res, ResMut, Transform, Entity, DespawnRecursiveExt, With},
+    window::Windows,
+};
+use std::collections::HashMap;
+use bevy_egui::{
+    egui::{Align2, Color32, Id, LayerId, Order, Pos2, TextStyle},
+    EguiContext,
 };
-use bevy_egui::egui::Color32;
 use bevy_prototype_lyon::{
     entity::ShapeBundle,
     prelude::{DrawMode, FillOptions, GeometryBuilder, ShapeColors},
     shapes,
 };
-use simulator::SimManager;
+use simulator::{
+    datastructs::MovableStatus,
+    nodes::{CrossingSnapshot, Direction, TrafficLightState},
+    Frame, Recorder, Replayer, SimManager, StatusUpdate,
+};
 
-use crate::{themes::UITheme, SimulationID, StreetLinePosition, UIState, CAR_SIZE, CAR_Z};
+use crate::{
+    get_primary_window_size, render_settings::RenderSettings, themes::UITheme,
+    tool_systems::world_to_screen_space, Camera, NodeType, SimulationID, StreetLinePosition,
+    UIState, TRAFFIC_LIGHT_SIZE,
+};
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
 pub struct CarID(u32);
 
+/// Marker for the indicators showing the traffic light state of a crossing
+pub struct TrafficLightIndicator;
+
+/// The last traffic light states received from the tracked simulation
+///
+/// the key is the id of the crossing
+#[derive(Default)]
+pub struct TrafficLightStates(HashMap<usize, TrafficLightState>);
+
+/// Statistics of the tracked simulation, taken from the last status update
+#[derive(Default)]
+pub struct LiveStats {
+    /// the cost and the CO2 emissions in tonnes
+    pub cost: [f64; 2],
+    /// the number of cars currently driving
+    pub car_count: usize,
+    /// the number of cars that reached their destination
+    pub absorbed_cars: usize,
+}
+
+/// The live state of the nodes of the tracked simulation, taken from the last status update
+///
+/// The simulation is built from the SimulatorBuilder, so the nodes have the
+/// same ids as the [NodeBuilderRef](crate::NodeBuilderRef)s
+#[derive(Default)]
+pub struct SimBridge {
+    /// the state of every crossing, the key is the id of the crossing
+    pub crossings: HashMap<usize, CrossingSnapshot>,
+    /// the number of cars on every node, nodes without cars are left out
+    pub cars: HashMap<usize, usize>,
+}
+
+impl SimBridge {
+    /// takes the node states from a status update of the tracked simulation
+    pub fn update(&mut self, update: &StatusUpdate) {
+        self.crossings = update.crossings.clone();
+        self.cars = update
+            .cars
+            .iter()
+            .map(|(id, cars)| (*id, cars.len()))
+            .collect();
+    }
+    /// the number of cars on the node with the given id (on all lanes of a street)
+    pub fn cars_on(&self, id: usize) -> usize {
+        self.cars.get(&id).copied().unwrap_or(0)
+    }
+    /// the state of the crossing with the given id, if it is part of the tracked simulation
+    pub fn crossing(&self, id: usize) -> Option<&CrossingSnapshot> {
+        self.crossings.get(&id)
+    }
+}
+
+/// The recorded run that is shown instead of the tracked simulation
+#[derive(Default)]
+pub struct Replay {
+    /// the recording and the current frame, None if nothing is replayed
+    pub replayer: Option<Replayer>,
+}
+
+/// the number of frames of the tracked simulation that are kept for stepping back
+const MAX_LIVE_FRAMES: usize = 2000;
+
+/// The frames received from the tracked simulation, so the display can be paused
+/// and stepped through
+///
+/// The simulation itself keeps running, stepping back only shows older frames
+#[derive(Default)]
+pub struct LivePlayback {
+    recorder: Recorder,
+    /// the index of the shown frame, None if the newest frame is shown
+    paused_at: Option<usize>,
+}
+
+impl LivePlayback {
+    /// keeps the frame of a status update of the tracked simulation
+    pub fn record(&mut self, update: &StatusUpdate) {
+        if self.recorder.frames().len() >= MAX_LIVE_FRAMES {
+            let dropped = MAX_LIVE_FRAMES / 2;
+            self.recorder.drop_oldest(dropped);
+            // the shown frame keeps its place, or the oldest frame is shown if it was dropped
+            self.paused_at = self.paused_at.map(|i| i.saturating_sub(dropped));
+        }
+        self.recorder.push(Frame {
+            cars: update.cars.clone(),
+            traffic_lights: update.traffic_lights.clone(),
+        });
+    }
+    /// drops all frames, e.g. when a new simulation is started
+    pub fn clear(&mut self) {
+        *self = LivePlayback::default();
+    }
+    /// true if no frame was received yet
+    pub fn is_empty(&self) -> bool {
+        self.recorder.frames().is_empty()
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+    /// keeps showing the newest frame until [LivePlayback::resume] is called
+    pub fn pause(&mut self) {
+        self.paused_at = Some(self.recorder.frames().len().saturating_sub(1));
+    }
+    /// shows the frames of the simulation as they arrive again
+    pub fn resume(&mut self) {
+        self.paused_at = None;
+    }
+    /// the frame that is shown while paused
+    pub fn current(&self) -> Option<&Frame> {
+        self.recorder.frames().get(self.paused_at?)
+    }
+    /// true if there is an older frame to show
+    pub fn can_step_back(&self) -> bool {
+        self.paused_at.map_or(false, |i| i > 0 && i < self.recorder.frames().len())
+    }
+    /// true if a newer frame was received while paused
+    pub fn can_step_forward(&self) -> bool {
+        self.paused_at.map_or(false, |i| i + 1 < self.recorder.frames().len())
+    }
+    /// shows the previous frame (only while paused)
+    pub fn step_back(&mut self) {
+        if self.can_step_back() {
+            self.paused_at = self.paused_at.map(|i| i - 1);
+        }
+    }
+    /// shows the next frame (only while paused)
+    pub fn step_forward(&mut self) {
+        if self.can_step_forward() {
+            self.paused_at = self.paused_at.map(|i| i + 1);
+        }
+    }
+}
 
 pub fn run_if_simulating(ui_state: Res<UIState>) -> ShouldRun {
     match ui_state.mode {
@@ -25,16 +169,39 @@ pub fn run_if_simulating(ui_state: Res<UIState>) -> ShouldRun {
     }
 }
 
-fn render_car(pos: Vec2, color: Color) -> ShapeBundle {
+fn render_car(pos: Vec2, color: Color, settings: &RenderSettings) -> ShapeBundle {
     let circle = shapes::Circle {
-        radius: CAR_SIZE,
+        radius: settings.car_size,
         ..shapes::Circle::default()
     };
     GeometryBuilder::build_as(
         &circle,
         ShapeColors::outlined(color, Color::WHITE),
         DrawMode::Fill(FillOptions::default()),
-        Transform::from_xyz(pos.x, pos.y, CAR_Z),
+        Transform::from_xyz(pos.x, pos.y, settings.car_z),
+    )
+}
+
+/// linearly interpolates between the colors `a` (t=0) and `b` (t=1)
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    Color::rgb(
+        a.r() + (b.r() - a.r()) * t,
+        a.g() + (b.g() - a.g()) * t,
+        a.b() + (b.b() - a.b()) * t,
+    )
+}
+
+fn render_traffic_light(pos: Vec2, color: Color, settings: &RenderSettings) -> ShapeBundle {
+    let circle = shapes::Circle {
+        radius: TRAFFIC_LIGHT_SIZE,
+        ..shapes::Circle::default()
+    };
+    GeometryBuilder::build_as(
+        &circle,
+        ShapeColors::outlined(color, color),
+        DrawMode::Fill(FillOptions::default()),
+        Transform::from_xyz(pos.x, pos.y, settings.car_z),
     )
 }
 
@@ -45,32 +212,387 @@ pub fn display_cars(
     sim_manager: ResMut<SimManager>,
     nodes: Query<(&SimulationID, &StreetLinePosition)>,
     mut cars: Query<(Entity, &CarID, &mut Transform)>,
+    mut traffic_lights: ResMut<TrafficLightStates>,
     theme: Res<UITheme>,
+    ui_state: Res<UIState>,
+    mut live_stats: ResMut<LiveStats>,
+    mut sim_bridge: ResMut<SimBridge>,
+    render_settings: Res<RenderSettings>,
+    replay: Res<Replay>,
+    mut live_playback: ResMut<LivePlayback>,
+    mut tracked: Local<Option<usize>>,
 ) {
-    if let Some(updates) = sim_manager.get_status_updates() {
-        cars.iter_mut().for_each(| (entity, _, _) | commands.entity(entity).despawn());
-        nodes.for_each(|(sim_id, line)| {
-            let id = sim_id.0;
-            let start = line.0;
-            let end = line.1;
-            // println!("start: {}, end: {}", start, end);
-            match updates.get(&id) {
-                Some(stati) => {
-                    stati.iter().for_each(|status| {
-                        let new_car_position = start + (end - start) * status.position;
-                        let new_car = render_car(new_car_position, theme.car_color);
-                        commands
-                            .spawn_bundle(new_car)
-                            .insert(CarID(status.movable_id));
-                        trace!("Generated new car at {}", new_car_position);
-                    });
-                }
-                None => {
-                    trace!("There is no MovableStatus for node with id {}", id)
-                }
-            }
-        });
+    // the display starts fresh, so no cars of the previously tracked simulation are left
+    let now_tracked = sim_manager.tracked_simulation();
+    if *tracked != now_tracked {
+        *tracked = now_tracked;
+        cars.iter_mut().for_each(|(entity, _, _)| commands.entity(entity).despawn());
+        *sim_bridge = SimBridge::default();
+        live_playback.clear();
+    }
+    if let Some(update) = sim_manager.get_status_updates() {
+        // the cars of the replay are drawn by [display_replay]
+        if replay.replayer.is_some() {
+            return;
+        }
+        live_playback.record(&update);
+        // while paused, the chosen frame is drawn by [display_replay]
+        if live_playback.is_paused() {
+            return;
+        }
+        sim_bridge.update(&update);
+        // the indicators are drawn by [display_traffic_lights]
+        traffic_lights.0 = update.traffic_lights;
+        *live_stats = LiveStats {
+            cost: update.cost,
+            car_count: update.car_count,
+            absorbed_cars: update.absorbed_cars,
+        };
+        draw_cars(&mut commands, &nodes, &mut cars, &update.cars, &theme, &ui_state, &render_settings);
     } else {
         // println!("No Updates");
     }
 }
+
+/// Shows the current frame of the replay or of the paused tracked simulation,
+/// whenever it changes
+pub fn display_replay(
+    mut commands: Commands,
+    replay: Res<Replay>,
+    live_playback: Res<LivePlayback>,
+    nodes: Query<(&SimulationID, &StreetLinePosition)>,
+    mut cars: Query<(Entity, &CarID, &mut Transform)>,
+    mut traffic_lights: ResMut<TrafficLightStates>,
+    theme: Res<UITheme>,
+    ui_state: Res<UIState>,
+    render_settings: Res<RenderSettings>,
+) {
+    if !replay.is_changed() && !live_playback.is_changed() {
+        return;
+    }
+    let frame = match &replay.replayer {
+        Some(replayer) => replayer.current(),
+        None if live_playback.is_paused() => live_playback.current(),
+        None => {
+            // the replay has stopped, the live simulation is shown with its next update
+            if replay.is_changed() {
+                cars.iter_mut().for_each(|(entity, _, _)| commands.entity(entity).despawn());
+            }
+            return;
+        }
+    };
+    if let Some(frame) = frame {
+        traffic_lights.0 = frame.traffic_lights.clone();
+        draw_cars(&mut commands, &nodes, &mut cars, &frame.cars, &theme, &ui_state, &render_settings);
+    }
+}
+
+/// replaces all displayed cars with the cars in `updates` (the key is the node id)
+fn draw_cars(
+    commands: &mut Commands,
+    nodes: &Query<(&SimulationID, &StreetLinePosition)>,
+    cars: &mut Query<(Entity, &CarID, &mut Transform)>,
+    updates: &HashMap<usize, Vec<MovableStatus>>,
+    theme: &UITheme,
+    ui_state: &UIState,
+    render_settings: &RenderSettings,
+) {
+    // in heatmap mode, the speeds are relative to the fastest car
+    let max_speed = updates
+        .values()
+        .flatten()
+        .map(|status| status.speed)
+        .fold(0.0, f32::max);
+    cars.iter_mut().for_each(| (entity, _, _) | commands.entity(entity).despawn());
+    nodes.for_each(|(sim_id, line)| {
+        let id = sim_id.0;
+        let start = line.0;
+        let end = line.1;
+        // println!("start: {}, end: {}", start, end);
+        match updates.get(&id) {
+            Some(stati) => {
+                stati.iter().for_each(|status| {
+                    let new_car_position = start + (end - start) * status.position;
+                    let color = match ui_state.car_heatmap && max_speed > 0.0 {
+                        // slow cars are red, fast ones green
+                        true => lerp_color(
+                            theme.traffic_light_red,
+                            theme.traffic_light_green,
+                            status.speed / max_speed,
+                        ),
+                        false => theme.car_color,
+                    };
+                    let new_car = render_car(new_car_position, color, render_settings);
+                    commands
+                        .spawn_bundle(new_car)
+                        .insert(CarID(status.movable_id));
+                    trace!("Generated new car at {}", new_car_position);
+                });
+            }
+            None => {
+                trace!("There is no MovableStatus for node with id {}", id)
+            }
+        }
+    });
+}
+
+/// labels closer than this (in pixels) overlap, so only the busier street is labeled
+const STREET_LABEL_MIN_DIST: f32 = 20.0;
+
+/// Picks the labels that can be shown without overlapping
+///
+/// The labels are the screen position and the number of cars, the labels of
+/// the busiest streets are kept first
+fn non_overlapping_labels(mut labels: Vec<(Vec2, usize)>, min_dist: f32) -> Vec<(Vec2, usize)> {
+    labels.sort_by(|a, b| b.1.cmp(&a.1));
+    let mut shown: Vec<(Vec2, usize)> = Vec::new();
+    for label in labels {
+        if shown.iter().all(|(pos, _)| (*pos - label.0).length_squared() >= min_dist * min_dist) {
+            shown.push(label);
+        }
+    }
+    shown
+}
+
+/// Shows the number of cars next to the middle of every street with cars on it,
+/// if enabled in the simulation settings
+pub fn display_street_car_counts(
+    egui_context: ResMut<EguiContext>,
+    ui_state: Res<UIState>,
+    sim_bridge: Res<SimBridge>,
+    theme: Res<UITheme>,
+    windows: Res<Windows>,
+    camera: Query<&Transform, With<Camera>>,
+    streets: Query<(&SimulationID, &StreetLinePosition)>,
+) {
+    if !ui_state.street_car_labels {
+        return;
+    }
+    let camera = match camera.single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window_size = get_primary_window_size(&windows);
+    let labels = streets
+        .iter()
+        .filter_map(|(id, line)| match sim_bridge.cars_on(id.0) {
+            0 => None,
+            cars => Some((world_to_screen_space(camera, (line.0 + line.1) / 2.0, window_size), cars)),
+        })
+        .collect();
+    // below the windows and panels of the ui, but above the streets
+    let painter = egui_context
+        .ctx()
+        .layer_painter(LayerId::new(Order::Background, Id::new("street_car_counts")));
+    for (pos, cars) in non_overlapping_labels(labels, STREET_LABEL_MIN_DIST) {
+        // egui counts the y coordinate from the top of the window
+        painter.text(
+            Pos2::new(pos.x, window_size.y - pos.y),
+            Align2::CENTER_CENTER,
+            cars,
+            TextStyle::Small,
+            theme.text_color,
+        );
+    }
+}
+
+/// Draws an indicator on each side of every crossing that is green if cars
+/// coming from this side are allowed to drive
+pub fn display_traffic_lights(
+    mut commands: Commands,
+    traffic_lights: Res<TrafficLightStates>,
+    crossings: Query<(&SimulationID, &Transform, &NodeType)>,
+    indicators: Query<Entity, With<TrafficLightIndicator>>,
+    theme: Res<UITheme>,
+    render_settings: Res<RenderSettings>,
+) {
+    if !traffic_lights.is_changed() && !render_settings.is_changed() {
+        return;
+    }
+    indicators.for_each(|entity| commands.entity(entity).despawn());
+    crossings.for_each(|(sim_id, transform, node_type)| {
+        if *node_type != NodeType::CROSSING {
+            return;
+        }
+        let state = match traffic_lights.0.get(&sim_id.0) {
+            Some(state) => state,
+            None => return,
+        };
+        let green_inputs = state.green_inputs();
+        let middle = Vec2::new(transform.translation.x, transform.translation.y);
+        let half_size = render_settings.crossing_size / 2.0;
+        for dir in [Direction::N, Direction::E, Direction::S, Direction::W] {
+            let offset = match dir {
+                Direction::N => Vec2::new(0.0, half_size),
+                Direction::E => Vec2::new(half_size, 0.0),
+                Direction::S => Vec2::new(0.0, -half_size),
+                Direction::W => Vec2::new(-half_size, 0.0),
+            };
+            let color = match green_inputs.contains(&dir) {
+                true => theme.traffic_light_green,
+                false => theme.traffic_light_red,
+            };
+            commands
+                .spawn_bundle(render_traffic_light(middle + offset, color, &render_settings))
+                .insert(TrafficLightIndicator);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{non_overlapping_labels, LivePlayback, SimBridge, MAX_LIVE_FRAMES};
+    use bevy::math::Vec2;
+    use simulator::{
+        controllers::FixedTimerController,
+        debug::build_grid_sim,
+        default_nn_topology,
+        nodes::{TrafficLightState, NN_OUTPUTS},
+        path::{MovableServer, PathAwareCar},
+        StatusUpdate,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn bridge_reports_crossing_phases() {
+        let mut builder = build_grid_sim(4, 10.0);
+        builder.with_dt(1.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim = builder.build(&mv_server).unwrap();
+        // every crossing switches to the next state after 10 seconds
+        sim.set_traffic_controllers(&FixedTimerController::new(vec![10.0; NN_OUTPUTS]).unwrap());
+        for _ in 0..15 {
+            sim.sim_iter();
+        }
+        let update = StatusUpdate {
+            cars: HashMap::new(),
+            traffic_lights: sim.get_traffic_light_states(),
+            crossings: sim.crossing_snapshots(),
+            cost: sim.calculate_sim_cost(),
+            car_count: sim.count_cars(),
+            absorbed_cars: sim.count_absorbed_cars(),
+        };
+        let mut bridge = SimBridge::default();
+        bridge.update(&update);
+        assert!(!bridge.crossings.is_empty());
+        for id in update.traffic_lights.keys() {
+            let crossing = bridge.crossing(*id).unwrap();
+            assert_eq!(crossing.traffic_light_state, TrafficLightState::S1);
+            assert_eq!(crossing.time_in_current_phase, 5.0);
+        }
+    }
+
+    #[test]
+    fn step_back_shows_previous_frame() {
+        let mut builder = build_grid_sim(3, 10.0);
+        builder.with_dt(1.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim = builder.build(&mv_server).unwrap();
+        sim.init_neural_networks_random(&default_nn_topology());
+        let mut playback = LivePlayback::default();
+        let mut sent = Vec::new();
+        for _ in 0..20 {
+            sim.sim_iter();
+            let update = StatusUpdate {
+                cars: sim.get_car_status(),
+                traffic_lights: sim.get_traffic_light_states(),
+                crossings: HashMap::new(),
+                cost: sim.calculate_sim_cost(),
+                car_count: sim.count_cars(),
+                absorbed_cars: sim.count_absorbed_cars(),
+            };
+            playback.record(&update);
+            sent.push(update);
+        }
+        assert!(!playback.can_step_back());
+        playback.pause();
+        assert_eq!(playback.current().unwrap().cars, sent[19].cars);
+        assert!(!playback.can_step_forward());
+        playback.step_back();
+        assert_eq!(playback.current().unwrap().cars, sent[18].cars);
+        assert_eq!(playback.current().unwrap().traffic_lights, sent[18].traffic_lights);
+        // frames received while paused can be stepped to
+        playback.record(&sent[0]);
+        playback.step_forward();
+        playback.step_forward();
+        assert_eq!(playback.current().unwrap().cars, sent[0].cars);
+        playback.resume();
+        assert!(playback.current().is_none());
+    }
+
+    #[test]
+    fn paused_playback_is_capped() {
+        let mut playback = LivePlayback::default();
+        let frame = |i: usize| {
+            let mut traffic_lights = HashMap::new();
+            traffic_lights.insert(i, TrafficLightState::S0);
+            StatusUpdate {
+                cars: HashMap::new(),
+                traffic_lights,
+                crossings: HashMap::new(),
+                cost: [0.0; 2],
+                car_count: 0,
+                absorbed_cars: 0,
+            }
+        };
+        for i in 0..MAX_LIVE_FRAMES {
+            playback.record(&frame(i));
+        }
+        playback.pause();
+        let shown = playback.current().unwrap().traffic_lights.clone();
+        // the oldest half is dropped, the shown frame keeps its place
+        playback.record(&frame(MAX_LIVE_FRAMES));
+        assert_eq!(playback.current().unwrap().traffic_lights, shown);
+        for i in MAX_LIVE_FRAMES + 1..3 * MAX_LIVE_FRAMES {
+            playback.record(&frame(i));
+        }
+        assert!(playback.recorder.frames().len() <= MAX_LIVE_FRAMES);
+        // the shown frame was dropped as well, so the oldest one is shown
+        assert!(playback.is_paused());
+        assert!(!playback.can_step_back());
+        assert_ne!(playback.current().unwrap().traffic_lights, shown);
+        playback.step_forward();
+        assert!(playback.current().is_some());
+    }
+
+    #[test]
+    fn bridge_counts_cars_on_nodes() {
+        let mut builder = build_grid_sim(3, 10.0);
+        builder.with_dt(1.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim = builder.build(&mv_server).unwrap();
+        sim.init_neural_networks_random(&default_nn_topology());
+        for _ in 0..100 {
+            sim.sim_iter();
+        }
+        let update = StatusUpdate {
+            cars: sim.get_car_status(),
+            traffic_lights: HashMap::new(),
+            crossings: HashMap::new(),
+            cost: sim.calculate_sim_cost(),
+            car_count: sim.count_cars(),
+            absorbed_cars: sim.count_absorbed_cars(),
+        };
+        let mut bridge = SimBridge::default();
+        bridge.update(&update);
+        assert!(update.car_count > 0);
+        assert_eq!(bridge.cars.values().sum::<usize>(), update.car_count);
+        assert_eq!(bridge.cars_on(usize::MAX), 0);
+    }
+
+    #[test]
+    fn overlapping_labels_keep_the_busiest_street() {
+        let labels = vec![
+            (Vec2::new(0.0, 0.0), 3),
+            (Vec2::new(5.0, 0.0), 7),
+            (Vec2::new(100.0, 0.0), 1),
+        ];
+        assert_eq!(
+            non_overlapping_labels(labels, 20.0),
+            vec![(Vec2::new(5.0, 0.0), 7), (Vec2::new(100.0, 0.0), 1)]
+        );
+    }
+}
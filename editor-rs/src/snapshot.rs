@@ -0,0 +1,191 @@
+use std::env;
+
+use bevy::{
+    input::Input,
+    math::{Vec2, Vec3},
+    prelude::{
+        Assets, Color, GlobalTransform, Handle, KeyCode, Mesh, Query, Res, Transform, Visible,
+        With,
+    },
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_graph::base::MainPass,
+    },
+    window::Windows,
+};
+use image::{Rgba, RgbaImage};
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+use crate::{get_primary_window_size, themes::UITheme, Camera, UIState};
+
+/// saves a snapshot of the street network when pressed
+const SNAPSHOT_KEY: KeyCode = KeyCode::F12;
+/// the file in the current directory the snapshot is saved to if no path is set
+const DEFAULT_SNAPSHOT_FILE: &str = "StreetNetwork.png";
+
+/// converts a position in world space to the pixel it is drawn at in an
+/// image of the size `size` that shows what the camera sees
+///
+/// This is the inverse of [crate::tool_systems::mouse_to_world_space], except
+/// that the y axis of images points down
+pub fn world_to_pixel(cam: &Transform, size: Vec2, pos: Vec2) -> Vec2 {
+    let screen = (pos - Vec2::new(cam.translation.x, cam.translation.y)) / cam.scale.x + size / 2.0;
+    Vec2::new(screen.x, size.y - screen.y)
+}
+
+/// blends `color` over the pixel at `(x, y)`
+fn blend(image: &mut RgbaImage, x: u32, y: u32, color: [f32; 4]) {
+    let pixel = image.get_pixel_mut(x, y);
+    let alpha = color[3].clamp(0.0, 1.0);
+    for c in 0..3 {
+        let old = pixel.0[c] as f32 / 255.0;
+        let new = color[c].clamp(0.0, 1.0) * alpha + old * (1.0 - alpha);
+        pixel.0[c] = (new * 255.0).round() as u8;
+    }
+}
+
+/// fills the triangle with the corners `a`, `b`, `c` (in pixel coordinates)
+///
+/// A pixel is filled if its center lies in the triangle
+pub fn fill_triangle(image: &mut RgbaImage, [a, b, c]: [Vec2; 3], color: [f32; 4]) {
+    // twice the signed area, used to normalize the edge functions
+    let edge = |p: Vec2, q: Vec2, r: Vec2| (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x);
+    let area = edge(a, b, c);
+    if area == 0.0 {
+        return;
+    }
+    let min = a.min(b).min(c).max(Vec2::ZERO);
+    let max = a
+        .max(b)
+        .max(c)
+        .min(Vec2::new(image.width() as f32 - 1.0, image.height() as f32 - 1.0));
+    if min.x > max.x || min.y > max.y {
+        return;
+    }
+    for y in min.y as u32..=max.y as u32 {
+        for x in min.x as u32..=max.x as u32 {
+            let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            // the point is inside if it is on the same side of all edges
+            let inside = [edge(b, c, p), edge(c, a, p), edge(a, b, p)]
+                .iter()
+                .all(|e| e / area >= 0.0);
+            if inside {
+                blend(image, x, y, color);
+            }
+        }
+    }
+}
+
+/// draws all triangles of `mesh`, moved to its place in the world
+fn draw_mesh(
+    image: &mut RgbaImage,
+    mesh: &Mesh,
+    transform: &GlobalTransform,
+    cam: &Transform,
+    size: Vec2,
+) {
+    let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float3(positions)) => positions,
+        _ => return,
+    };
+    let colors = match mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+        Some(VertexAttributeValues::Float4(colors)) => colors,
+        _ => return,
+    };
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        Some(Indices::U32(indices)) => indices.iter().map(|i| *i as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+    let to_pixel = |i: usize| {
+        let world = transform.mul_vec3(Vec3::from(positions[i]));
+        world_to_pixel(cam, size, Vec2::new(world.x, world.y))
+    };
+    for triangle in indices.chunks_exact(3) {
+        // the shapes have a single color
+        fill_triangle(
+            image,
+            [to_pixel(triangle[0]), to_pixel(triangle[1]), to_pixel(triangle[2])],
+            colors[triangle[0]],
+        );
+    }
+}
+
+/// Saves what the camera sees (without the ui) as a PNG when [SNAPSHOT_KEY] is pressed
+///
+/// The shapes are drawn from their meshes, as Bevy can't capture the window
+/// content. The file is saved to the path set in the preferences
+pub fn save_snapshot(
+    keyboard_input: Res<Input<KeyCode>>,
+    ui_state: Res<UIState>,
+    windows: Res<Windows>,
+    theme: Res<UITheme>,
+    meshes: Res<Assets<Mesh>>,
+    camera: Query<&Transform, With<Camera>>,
+    shapes: Query<(&Handle<Mesh>, &GlobalTransform, &Visible), With<MainPass>>,
+) {
+    if !keyboard_input.just_pressed(SNAPSHOT_KEY) {
+        return;
+    }
+    let cam = match camera.single() {
+        Ok(cam) => cam,
+        Err(_) => return,
+    };
+    let size = get_primary_window_size(&windows);
+    let background: [f32; 4] = theme.background.into();
+    let background = Rgba(background.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+    let mut image = RgbaImage::from_pixel(size.x as u32, size.y as u32, background);
+    // shapes further back are drawn first
+    let mut shapes: Vec<(&Handle<Mesh>, &GlobalTransform)> = shapes
+        .iter()
+        .filter(|(_mesh, _transform, visible)| visible.is_visible)
+        .map(|(mesh, transform, _visible)| (mesh, transform))
+        .collect();
+    shapes.sort_by(|(_, a), (_, b)| a.translation.z.partial_cmp(&b.translation.z).unwrap());
+    for (mesh_handle, transform) in shapes {
+        if let Some(mesh) = meshes.get(mesh_handle) {
+            draw_mesh(&mut image, mesh, transform, cam, size);
+        }
+    }
+    let path = match ui_state.snapshot_path.is_empty() {
+        true => env::current_dir().unwrap_or_default().join(DEFAULT_SNAPSHOT_FILE),
+        false => ui_state.snapshot_path.clone().into(),
+    };
+    match image.save(&path) {
+        Ok(_) => info!("Saved snapshot to {}", path.display()),
+        Err(err) => warn!("Unable to save snapshot to {}: {}", path.display(), err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill_triangle, world_to_pixel};
+    use bevy::{math::Vec2, prelude::Transform};
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn camera_center_is_image_center() {
+        let mut cam = Transform::from_xyz(100.0, 50.0, 0.0);
+        let size = Vec2::new(200.0, 100.0);
+        assert_eq!(world_to_pixel(&cam, size, Vec2::new(100.0, 50.0)), size / 2.0);
+        // the y axis is flipped
+        assert_eq!(world_to_pixel(&cam, size, Vec2::new(100.0, 60.0)), Vec2::new(100.0, 40.0));
+        // zooming out shows more of the world
+        cam.scale = bevy::math::Vec3::new(2.0, 2.0, 1.0);
+        assert_eq!(world_to_pixel(&cam, size, Vec2::new(120.0, 50.0)), Vec2::new(110.0, 50.0));
+    }
+
+    #[test]
+    fn triangle_fills_covered_pixels() {
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        // the lower left half, in both windings
+        let corners = [Vec2::new(0.0, 0.0), Vec2::new(0.0, 4.0), Vec2::new(4.0, 4.0)];
+        fill_triangle(&mut image, corners, [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(image.get_pixel(0, 3).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(3, 0).0, [0, 0, 0, 255]);
+        let [a, b, c] = corners;
+        fill_triangle(&mut image, [a, c, b], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(image.get_pixel(0, 3).0, [255, 0, 0, 255]);
+    }
+}
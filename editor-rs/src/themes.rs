@@ -3,6 +3,14 @@ use bevy_egui::egui::Visuals;
 use bevy_egui::egui::Color32;
 use bevy_egui::egui::style;
 use bevy_egui::egui::Stroke;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// the file the editor settings are saved to
+const CONFIG_FILE: &str = "editor_config.json";
 
 /// This struct stores information about the visual style of the application
 ///
@@ -19,14 +27,98 @@ pub struct UITheme {
     pub connector_out: Color,
     pub placing_street: Color,
     pub car_color: Color,
+    pub traffic_light_green: Color,
+    pub traffic_light_red: Color,
+    pub grid: Color,
     pub egui_visuals: Visuals,
     pub text_color: Color32,
 }
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum CurrentTheme {
     LIGHT,
     DRACULA,
+    /// a theme loaded from a file (see [UITheme::from_file])
+    Custom(ThemeColors),
+}
+impl Default for CurrentTheme {
+    fn default() -> Self {
+        CurrentTheme::DRACULA
+    }
+}
+
+/// The colors of a user defined theme
+///
+/// Every color is given as `[r, g, b]` with values from 0 to 255. All
+/// fields have to be present.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeColors {
+    pub background: [u8; 3],
+    pub io_node: [u8; 3],
+    pub street: [u8; 3],
+    pub crossing: [u8; 3],
+    pub highlight: [u8; 3],
+    pub connector_in: [u8; 3],
+    pub connector_out: [u8; 3],
+    pub placing_street: [u8; 3],
+    pub car_color: [u8; 3],
+    pub traffic_light_green: [u8; 3],
+    pub traffic_light_red: [u8; 3],
+    pub grid: [u8; 3],
+    pub text: [u8; 3],
+}
+
+impl ThemeColors {
+    /// parses the colors from JSON
+    pub fn from_json(json: &str) -> Result<ThemeColors, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+    /// reads the colors from a JSON file
+    pub fn from_file(path: &str) -> Result<ThemeColors, Box<dyn Error>> {
+        Ok(ThemeColors::from_json(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// converts a color given as `[r, g, b]` to a bevy [Color]
+fn to_color([r, g, b]: [u8; 3]) -> Color {
+    Color::rgb_u8(r, g, b)
+}
+
+/// The settings of the editor that are kept between sessions
+#[derive(Serialize, Deserialize)]
+struct EditorConfig {
+    theme: CurrentTheme,
+}
+
+impl CurrentTheme {
+    /// loads the theme used last time from the config file
+    ///
+    /// If the file is missing or corrupt, the default theme is returned
+    pub fn load() -> CurrentTheme {
+        let load = || -> Result<CurrentTheme, Box<dyn Error>> {
+            let config: EditorConfig = serde_json::from_str(&fs::read_to_string(CONFIG_FILE)?)?;
+            Ok(config.theme)
+        };
+        match load() {
+            Ok(theme) => theme,
+            Err(err) => {
+                info!("Could not load theme from {}, using the default ({})", CONFIG_FILE, err);
+                CurrentTheme::default()
+            }
+        }
+    }
+    /// saves the theme to the config file, so it is used the next time the editor starts
+    pub fn save(&self) {
+        let save = || -> Result<(), Box<dyn Error>> {
+            let config = EditorConfig { theme: *self };
+            fs::write(CONFIG_FILE, serde_json::to_string_pretty(&config)?)?;
+            Ok(())
+        };
+        if let Err(err) = save() {
+            warn!("Could not save theme to {}: {}", CONFIG_FILE, err);
+        }
+    }
 }
 
 impl UITheme {
@@ -87,6 +179,9 @@ impl UITheme {
             connector_out: Color::rgb(240.0/255.0, 100.0/255.0, 0.0/255.0),
             placing_street: Color::rgb(160.0/255.0, 100.0/255.0, 100.0/255.0),
             car_color: Color::rgb(80.0/255.0, 180.0/255.0, 80.0/255.0),
+            traffic_light_green: Color::rgb(40.0/255.0, 160.0/255.0, 60.0/255.0),
+            traffic_light_red: Color::rgb(200.0/255.0, 50.0/255.0, 50.0/255.0),
+            grid: Color::rgb(200.0/255.0, 200.0/255.0, 200.0/255.0),
             egui_visuals: visuals,
             text_color: Color32::from_rgb(0,0,0)
         }
@@ -148,6 +243,9 @@ impl UITheme {
             connector_out: Color::rgb(80.0/255.0, 250.0/255.0, 123.0/255.0),
             placing_street: Color::rgb(255.0/255.0, 85.0/255.0, 85.0/255.0),
             car_color: Color::rgb(80.0/255.0, 180.0/255.0, 100.0/255.0),
+            traffic_light_green: Color::rgb(80.0/255.0, 250.0/255.0, 123.0/255.0),
+            traffic_light_red: Color::rgb(255.0/255.0, 85.0/255.0, 85.0/255.0),
+            grid: Color::rgb(55.0/255.0, 58.0/255.0, 74.0/255.0),
             egui_visuals: visuals,
             text_color,
 
@@ -173,10 +271,49 @@ impl UITheme {
             //egui_visuals: Visuals::dark().visuals_mut().override_text_color = from_rgb(r: 248, g: 248, b: 24),
         }
     }
+    /// creates a theme with user defined colors
+    ///
+    /// The egui visuals are based on the light or dark preset depending on
+    /// the brightness of the background
+    pub fn from_colors(colors: &ThemeColors) -> UITheme {
+        let [r, g, b] = colors.background;
+        let bg_color = Color32::from_rgb(r, g, b);
+        let [r, g, b] = colors.text;
+        let text_color = Color32::from_rgb(r, g, b);
+        let brightness = (colors.background.iter().map(|c| *c as u32).sum::<u32>() / 3) as u8;
+        let mut visuals = match brightness > 127 {
+            true => Visuals::light(),
+            false => Visuals::dark(),
+        };
+        visuals.override_text_color = Some(text_color);
+        visuals.extreme_bg_color = bg_color;
+        visuals.code_bg_color = bg_color;
+        UITheme {
+            background: to_color(colors.background),
+            io_node: to_color(colors.io_node),
+            street: to_color(colors.street),
+            crossing: to_color(colors.crossing),
+            highlight: to_color(colors.highlight),
+            connector_in: to_color(colors.connector_in),
+            connector_out: to_color(colors.connector_out),
+            placing_street: to_color(colors.placing_street),
+            car_color: to_color(colors.car_color),
+            traffic_light_green: to_color(colors.traffic_light_green),
+            traffic_light_red: to_color(colors.traffic_light_red),
+            grid: to_color(colors.grid),
+            egui_visuals: visuals,
+            text_color,
+        }
+    }
+    /// loads a theme with user defined colors from a JSON file (see [ThemeColors])
+    pub fn from_file(path: &str) -> Result<UITheme, Box<dyn Error>> {
+        Ok(UITheme::from_colors(&ThemeColors::from_file(path)?))
+    }
     pub fn from_enum(theme: &CurrentTheme) -> UITheme {
         match theme {
             CurrentTheme::LIGHT => UITheme::light(),
             CurrentTheme::DRACULA => UITheme::dracula(),
+            CurrentTheme::Custom(colors) => UITheme::from_colors(colors),
         }
     }
 }
@@ -185,3 +322,39 @@ impl Default for UITheme {
         UITheme::dracula()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ThemeColors, UITheme};
+    use bevy::prelude::Color;
+    use bevy_egui::egui::Color32;
+
+    const SAMPLE_THEME: &str = r#"{
+        "background": [10, 20, 30],
+        "io_node": [200, 100, 0],
+        "street": [255, 255, 255],
+        "crossing": [200, 100, 0],
+        "highlight": [255, 0, 0],
+        "connector_in": [0, 255, 0],
+        "connector_out": [0, 0, 255],
+        "placing_street": [255, 0, 0],
+        "car_color": [0, 200, 0],
+        "traffic_light_green": [0, 255, 0],
+        "traffic_light_red": [255, 0, 0],
+        "grid": [50, 50, 50],
+        "text": [240, 240, 240]
+    }"#;
+
+    #[test]
+    fn custom_theme_from_json() {
+        let colors = ThemeColors::from_json(SAMPLE_THEME).unwrap();
+        assert_eq!(colors.background, [10, 20, 30]);
+        let theme = UITheme::from_colors(&colors);
+        assert_eq!(theme.background, Color::rgb_u8(10, 20, 30));
+        assert_eq!(theme.connector_out, Color::rgb_u8(0, 0, 255));
+        assert_eq!(theme.text_color, Color32::from_rgb(240, 240, 240));
+        // all fields are required
+        let incomplete = SAMPLE_THEME.replace(r#""grid": [50, 50, 50],"#, "");
+        assert!(ThemeColors::from_json(&incomplete).is_err());
+    }
+}
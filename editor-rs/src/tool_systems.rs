@@ -1,6 +1,6 @@
 use bevy::{
     ecs::schedule::ShouldRun,
-    input::{mouse::MouseMotion, Input},
+    input::{keyboard::KeyCode, mouse::MouseMotion, Input},
     math::{vec3, Vec2},
     prelude::{
         BuildChildren, Children, Commands, Entity, EventReader, GlobalTransform, MouseButton,
@@ -19,11 +19,13 @@ use simulator::{
 use crate::{
     get_primary_window_size,
     input::{self, handle_mouse_clicks},
+    notifications::{Notifications, EDIT_WHILE_SIMULATING},
     node_bundles::{
         ConnectorCircleIn, ConnectorCircleOut, CrossingBundle, IONodeBundle, InputCircle,
         OutputCircle, StreetBundle,
     },
     AddStreetStage, StreetLinePosition, CONNECTOR_DISPLAY_RADIUS, calculate_offset_from_crossing_in,
+    GRID_NODE_SPACING, SNAP_GRID_EXTENT,
 };
 use crate::{
     node_bundles::node_render, themes::UITheme, toolbar::ToolType, Camera, NeedsRecolor,
@@ -89,6 +91,57 @@ pub fn mouse_to_world_space(cam: &Transform, mouse_pos: Vec2, windows: &Res<Wind
     (mouse_pos - midpoint_screenspace) * cam.scale.x
 }
 
+/// the inverse of [mouse_to_world_space], the screen position has its origin
+/// in the bottom left corner of the window
+pub fn world_to_screen_space(cam: &Transform, pos: Vec2, window_size: Vec2) -> Vec2 {
+    (pos - Vec2::new(cam.translation.x, cam.translation.y)) / cam.scale.x + window_size / 2.0
+}
+
+/// rounds the position to the nearest point on a grid with the given spacing
+pub fn snap_to_grid(pos: Vec2, spacing: f32) -> Vec2 {
+    (pos / spacing).round() * spacing
+}
+
+/// Marker for the lines of the grid nodes are snapped to
+pub struct GridLine;
+
+/// displays the grid nodes are snapped to, if snapping is enabled
+pub fn display_snap_grid(
+    mut commands: Commands,
+    ui_state: Res<UIState>,
+    theme: Res<UITheme>,
+    grid_lines: Query<Entity, With<GridLine>>,
+) {
+    let is_displayed = grid_lines.iter().next().is_some();
+    // the grid is redrawn if the theme changes
+    let redraw = ui_state.snap_to_grid && (!is_displayed || theme.is_changed());
+    if is_displayed && (redraw || !ui_state.snap_to_grid) {
+        grid_lines.for_each(|line| commands.entity(line).despawn());
+    }
+    if !redraw {
+        return;
+    }
+    let spacing = GRID_NODE_SPACING as f32;
+    let n_lines = (SNAP_GRID_EXTENT / spacing) as i32;
+    for i in -n_lines..=n_lines {
+        let offset = i as f32 * spacing;
+        commands
+            .spawn_bundle(node_render::grid_line(
+                Vec2::new(offset, -SNAP_GRID_EXTENT),
+                Vec2::new(offset, SNAP_GRID_EXTENT),
+                theme.grid,
+            ))
+            .insert(GridLine);
+        commands
+            .spawn_bundle(node_render::grid_line(
+                Vec2::new(-SNAP_GRID_EXTENT, offset),
+                Vec2::new(SNAP_GRID_EXTENT, offset),
+                theme.grid,
+            ))
+            .insert(GridLine);
+    }
+}
+
 /// A marker for crossings currently displaying connectors
 pub struct HasConnectors;
 
@@ -218,6 +271,7 @@ pub fn connector_clicked(
     mut commands: Commands,
     mouse_input: Res<Input<MouseButton>>,
     camera: Query<&Transform, With<Camera>>,
+    mut notifications: ResMut<Notifications>,
 ) {
     let mut mouse_pos = match handle_mouse_clicks(&mouse_input, &windows) {
         Some(p) => p,
@@ -227,6 +281,9 @@ pub fn connector_clicked(
     if let Ok(cam) = camera.single() {
         mouse_pos = mouse_to_world_space(&cam, mouse_pos, &windows);
     }
+    if ui_state.snap_to_grid {
+        mouse_pos = snap_to_grid(mouse_pos, GRID_NODE_SPACING as f32);
+    }
     match *stage {
         AddStreetStage::SelectingOutput => {
             if let Ok((parent_node, ctype)) = out_circles.q0().single() {
@@ -268,7 +325,10 @@ pub fn connector_clicked(
                 let parent_pos = Vec2::new(parent_pos.translation.x, parent_pos.translation.y);
                 let builder = match sim_manager.modify_sim_builder() {
                     Ok(b) => b,
-                    Err(_) => return,
+                    Err(_) => {
+                        notifications.push(EDIT_WHILE_SIMULATING);
+                        return;
+                    }
                 };
                 let new_street = match builder.connect_with_street(
                     (street_info.start_id.0, street_info.out_conn_type.as_dir()),
@@ -277,7 +337,11 @@ pub fn connector_clicked(
                     100.0
                 ) {
                     Ok(s) => s,
-                    Err(e) => panic!("{}", e),
+                    // e.g. the street would end where it starts
+                    Err(e) => {
+                        notifications.push(&e.to_string());
+                        return;
+                    }
                 };
                 // set the correct offset so that streets are parallel
                 let node_start = street_info.start_nbr.0.get();
@@ -347,6 +411,7 @@ pub fn render_new_street(
     windows: Res<Windows>,
     camera: Query<&Transform, With<Camera>>,
     theme: Res<UITheme>,
+    ui_state: Res<UIState>,
 ) {
     let window = windows.get_primary().unwrap();
     let mut mouse_pos = match window.cursor_position() {
@@ -357,6 +422,9 @@ pub fn render_new_street(
     if let Ok(cam) = camera.single() {
         mouse_pos = mouse_to_world_space(&cam, mouse_pos, &windows);
     }
+    if ui_state.snap_to_grid {
+        mouse_pos = snap_to_grid(mouse_pos, GRID_NODE_SPACING as f32);
+    }
     if let Ok((entity, mut line_position)) = street_query.single_mut() {
         *line_position.1 = *mouse_pos;
         let new_shape_bundle =
@@ -375,6 +443,8 @@ pub fn add_crossing_system(
     theme: ResMut<UITheme>,
     windows: Res<Windows>,
     camera: Query<&Transform, With<Camera>>,
+    ui_state: Res<UIState>,
+    mut notifications: ResMut<Notifications>,
 ) {
     let mut mouse_click = match input::handle_mouse_clicks(&mouse_input, &windows) {
         Some(click) => click,
@@ -384,11 +454,15 @@ pub fn add_crossing_system(
     if let Ok(cam) = camera.single() {
         mouse_click = mouse_to_world_space(&cam, mouse_click, &windows);
     }
+    if ui_state.snap_to_grid {
+        mouse_click = snap_to_grid(mouse_click, GRID_NODE_SPACING as f32);
+    }
 
     let simulation_builder = match sim_manager.modify_sim_builder() {
         Ok(builder) => builder,
         Err(_) => {
             eprintln!("Can't modify street builder to add crossing");
+            notifications.push(EDIT_WHILE_SIMULATING);
             return;
         }
     };
@@ -405,6 +479,8 @@ pub fn add_io_node_system(
     theme: ResMut<UITheme>,
     windows: Res<Windows>,
     camera: Query<&Transform, With<Camera>>,
+    ui_state: Res<UIState>,
+    mut notifications: ResMut<Notifications>,
 ) {
     let mut mouse_click = match input::handle_mouse_clicks(&mouse_input, &windows) {
         Some(click) => click,
@@ -414,11 +490,15 @@ pub fn add_io_node_system(
     if let Ok(cam) = camera.single() {
         mouse_click = mouse_to_world_space(&cam, mouse_click, &windows);
     }
+    if ui_state.snap_to_grid {
+        mouse_click = snap_to_grid(mouse_click, GRID_NODE_SPACING as f32);
+    }
 
     let simulation_builder = match sim_manager.modify_sim_builder() {
         Ok(builder) => builder,
         Err(_) => {
             eprintln!("Can't modify street builder to add crossing");
+            notifications.push(EDIT_WHILE_SIMULATING);
             return;
         }
     };
@@ -431,50 +511,81 @@ pub fn add_io_node_system(
 /// Marker for the currently connected node
 pub struct SelectedNode;
 
+/// All currently selected nodes
+///
+/// Every entity in here also has the [SelectedNode] marker
+#[derive(Default)]
+pub struct Selection {
+    pub entities: Vec<Entity>,
+}
+
 pub fn delete_node_system_simple(
     mouse_input: Res<Input<MouseButton>>,
     windows: Res<Windows>,
     mut sim_manager: ResMut<SimManager>,
     nodes: QuerySet<(
-        Query<(Entity, &SimulationID), (With<NodeType>, With<UnderCursor>)>,
+        Query<(Entity, &SimulationID, &NodeType), With<UnderCursor>>,
         Query<(Entity, &SimulationID), (With<NodeType>, Without<UnderCursor>)>,
     )>,
     mut commands: Commands,
+    mut notifications: ResMut<Notifications>,
 ) {
     let mut _mouse_click = match input::handle_mouse_clicks(&mouse_input, &windows) {
         Some(click) => click,
         None => return,
     };
 
-    if let Ok((entity, sim_id)) = nodes.q0().single() {
-        if let Ok(sim_builder) = sim_manager.modify_sim_builder() {
-            commands.entity(entity).despawn();
-            let removed_nodes = sim_builder
-                .remove_node_and_connected_by_id(sim_id.0)
-                .expect("Unable to remove node");
-            let indices_to_remove: Vec<usize> = removed_nodes
-                .iter()
-                .map(|node| node.get().get_id())
-                .collect();
-            for (entity, sim_index) in nodes.q1().iter() {
-                if indices_to_remove.contains(&sim_index.0) {
-                    info!(
-                        "Deleting Node wit id= {} (Entity: {:?})",
-                        sim_index.0, entity
-                    );
+    if let Ok((entity, sim_id, node_type)) = nodes.q0().single() {
+        let sim_builder = match sim_manager.modify_sim_builder() {
+            Ok(builder) => builder,
+            Err(_) => {
+                notifications.push(EDIT_WHILE_SIMULATING);
+                return;
+            }
+        };
+        // only the street itself is removed, its endpoints stay
+        if *node_type == NodeType::STREET {
+            match sim_builder.remove_street_by_id(sim_id.0) {
+                Ok(_) => {
+                    info!("Deleting Street with id= {} (Entity: {:?})", sim_id.0, entity);
                     commands.entity(entity).despawn();
                 }
+                Err(err) => warn!("Unable to remove street: {}", err),
+            }
+            return;
+        }
+        commands.entity(entity).despawn();
+        let removed_nodes = sim_builder
+            .remove_node_and_connected_by_id(sim_id.0)
+            .expect("Unable to remove node");
+        let indices_to_remove: Vec<usize> = removed_nodes
+            .iter()
+            .map(|node| node.get().get_id())
+            .collect();
+        for (entity, sim_index) in nodes.q1().iter() {
+            if indices_to_remove.contains(&sim_index.0) {
+                info!(
+                    "Deleting Node wit id= {} (Entity: {:?})",
+                    sim_index.0, entity
+                );
+                commands.entity(entity).despawn();
             }
         }
     }
 }
 
+/// Selects the node under the cursor
+///
+/// With shift held, the node is added to (or removed from) the selection
+///  instead of replacing it
 pub fn select_node(
     mut commands: Commands,
     mouse_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
     windows: Res<Windows>,
+    mut selection: ResMut<Selection>,
     shapes: QuerySet<(
-        Query<(Entity, &Transform, &NodeType)>,
+        Query<(Entity, &Transform, &NodeType, Option<&StreetLinePosition>)>,
         Query<Entity, With<SelectedNode>>,
     )>,
     camera: Query<&Transform, With<Camera>>,
@@ -488,20 +599,79 @@ pub fn select_node(
             Some(s) => s,
             None => return,
         };
-    if let Ok(prev_selected) = shapes.q1().single() {
-        info!("Unselected previouse node (entity={:?})", prev_selected);
-        commands
-            .entity(prev_selected)
-            .remove::<SelectedNode>()
-            .insert(NeedsRecolor);
+    let add_to_selection =
+        keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+    if add_to_selection {
+        if let Some(i) = selection.entities.iter().position(|e| *e == entity) {
+            info!("Unselecting node (entity={:?})", entity);
+            selection.entities.remove(i);
+            commands
+                .entity(entity)
+                .remove::<SelectedNode>()
+                .insert(NeedsRecolor);
+            return;
+        }
+    } else {
+        for prev_selected in shapes.q1().iter() {
+            info!("Unselected previouse node (entity={:?})", prev_selected);
+            commands
+                .entity(prev_selected)
+                .remove::<SelectedNode>()
+                .insert(NeedsRecolor);
+        }
+        selection.entities.clear();
     }
     info!("Selecting node (entity={:?})", entity);
+    selection.entities.push(entity);
     commands
         .entity(entity)
         .insert(SelectedNode)
         .insert(NeedsRecolor);
 }
 
+/// Removes all selected nodes (and the streets connected to them) when
+///  Delete is pressed
+pub fn delete_selected_nodes(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut sim_manager: ResMut<SimManager>,
+    mut selection: ResMut<Selection>,
+    nodes: Query<(Entity, &SimulationID), With<NodeType>>,
+    mut notifications: ResMut<Notifications>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Delete) || selection.entities.is_empty() {
+        return;
+    }
+    let ids: Vec<usize> = selection
+        .entities
+        .iter()
+        .filter_map(|entity| nodes.get(*entity).ok())
+        .map(|(_entity, sim_id)| sim_id.0)
+        .collect();
+    let sim_builder = match sim_manager.modify_sim_builder() {
+        Ok(builder) => builder,
+        Err(err) => {
+            warn!("Can't delete the selected nodes: {}", err);
+            notifications.push(EDIT_WHILE_SIMULATING);
+            return;
+        }
+    };
+    let removed_ids: Vec<usize> = match sim_builder.remove_nodes_by_ids(&ids) {
+        Ok(removed) => removed.iter().map(|node| node.get().get_id()).collect(),
+        Err(err) => {
+            warn!("Unable to remove the selected nodes: {}", err);
+            return;
+        }
+    };
+    for (entity, sim_id) in nodes.iter() {
+        if removed_ids.contains(&sim_id.0) {
+            info!("Deleting Node wit id= {} (Entity: {:?})", sim_id.0, entity);
+            commands.entity(entity).despawn();
+        }
+    }
+    selection.entities.clear();
+}
+
 pub fn move_node_system(
     mouse_input: Res<Input<MouseButton>>,
     camera: Query<&Transform, With<Camera>>,
@@ -523,3 +693,16 @@ pub fn move_node_system(
     //     trans.translation += vec3(ev.delta.x, ev.delta.y, 0.0);
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::snap_to_grid;
+    use bevy::math::Vec2;
+
+    #[test]
+    fn snapping_rounds_to_nearest_grid_point() {
+        assert_eq!(snap_to_grid(Vec2::new(149.0, -51.0), 100.0), Vec2::new(100.0, -100.0));
+        assert_eq!(snap_to_grid(Vec2::new(151.0, 49.0), 100.0), Vec2::new(200.0, 0.0));
+        assert_eq!(snap_to_grid(Vec2::new(300.0, -200.0), 100.0), Vec2::new(300.0, -200.0));
+    }
+}
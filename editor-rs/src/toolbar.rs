@@ -20,7 +20,9 @@ pub trait Tool: Send + Sync {
         this_index: usize,
         locked: bool,
     ) {
-        if ui.button(self.name()).clicked() {
+        // the selected tool is highlighted
+        let is_selected = *selected_index == Some(this_index);
+        if ui.selectable_label(is_selected, self.name()).clicked() {
             if !locked {
                 *selected_index = Some(this_index)
             }
@@ -59,6 +61,22 @@ impl Toolbar {
         }
     }
 
+    /// selects the first tool of the given type
+    ///
+    /// Returns false if the toolbar is locked or there is no such tool
+    pub fn select_tooltype(&mut self, ttype: ToolType) -> bool {
+        if self.locked {
+            return false;
+        }
+        match self.tools.iter().position(|tool| tool.get_type() == ttype) {
+            Some(i) => {
+                self.selected = Some(i);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn render_tools(&mut self, ui: &mut Ui) {
         for (i, tool) in self.tools.iter().enumerate() {
             tool.render(ui, &mut self.selected, i, self.locked);
@@ -166,7 +184,7 @@ pub struct DeleteNodeTool;
 
 impl Tool for DeleteNodeTool {
     fn name<'a>(&'a self) -> &'a str {
-        "Delete Crossing/IO-Node"
+        "Delete Node/Street"
     }
     fn get_type(&self) -> ToolType {
         ToolType::DeleteNode
@@ -1,26 +1,149 @@
-use std::{collections::HashMap, ops::RangeInclusive, env, fs::File};
-use std::io::{Write, Read};
+use std::{collections::HashMap, error::Error, num::NonZeroUsize, ops::RangeInclusive, time::Duration};
 use bevy::prelude::*;
 use bevy_egui::{
     egui::{self, CollapsingHeader, CtxRef, Ui, Color32},
     EguiContext,
 };
-use simulator::{datastructs::WeakIntMut, nodes::NodeBuilder, SimManager, SimulatorBuilder};
+use simulator::{datastructs::WeakIntMut, nodes::{IONodeRole, InOut, NodeBuilder}, FitnessMode, Recording, Replayer, SimManager, SimulatorBuilder, StopCondition, DEFAULT_SPEED_LIMIT};
 
-use crate::{StreetLinePosition, SimulationID, node_bundles};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::auto_save;
+use crate::notifications::{Notifications, EDIT_WHILE_SIMULATING};
+use crate::{StreetLinePosition, SimulationID, node_bundles, simulation_display::{LivePlayback, LiveStats, Replay, SimBridge}, render_settings::RenderSettings, file_io::{self, PendingLoad}};
 use crate::{
-    tool_systems::SelectedNode, CurrentTheme, NeedsRecolor, NodeBuilderRef, NodeType, UIMode,
-    UIState, themes::UITheme,
+    tool_systems::SelectedNode, Camera, CurrentTheme, NeedsRecolor, NodeBuilderRef, NodeType, UIMode,
+    UIState, themes::{ThemeColors, UITheme},
 };
 
 use art_int::Network;
 use serde::{self, Serialize, Deserialize};
 
+/// the file the street network is saved to (on the web, the name of the download)
+pub const SAVE_FILE_NAME: &str = "StreetSimulation.json";
+/// the file the street network is saved to in the compact binary format
+pub const BIN_SAVE_FILE_NAME: &str = "StreetSimulation.bin";
+/// the file recordings are saved to (see [draw_replay_window])
+const RECORDING_FILE_NAME: &str = "Recording.json";
+/// the title of the window, followed by the name of the loaded map
+const WINDOW_TITLE: &str = "Street Simulation";
+/// the range of steps that can be recorded at once
+const RECORDED_STEPS_RANGE: RangeInclusive<usize> = 1..=5000;
+
 #[derive(Serialize, Deserialize)]
 pub struct FunnyNNBuilderCombi {
     pub builder: SimulatorBuilder,
     pub nn: Option<Vec<Network>>,
-    pub builder_graphics: HashMap<usize, Vec<[f32; 2]>>
+    pub builder_graphics: HashMap<usize, Vec<[f32; 2]>>,
+    /// the view of the camera when saving (missing in older saves)
+    #[serde(default)]
+    pub camera: Option<CameraView>,
+    /// the name and description of the map (missing in older saves)
+    #[serde(default)]
+    pub meta: Option<SaveMeta>,
+}
+
+impl FunnyNNBuilderCombi {
+    /// collects everything that is saved, `nodes` are the nodes displayed in the editor
+    pub fn new<'a>(
+        builder: &SimulatorBuilder,
+        nn: Option<Vec<Network>>,
+        nodes: impl Iterator<Item = (&'a Transform, Option<&'a StreetLinePosition>, &'a SimulationID)>,
+        camera: Option<CameraView>,
+        meta: SaveMeta,
+    ) -> FunnyNNBuilderCombi {
+        FunnyNNBuilderCombi {
+            builder: builder.clone(),
+            nn,
+            builder_graphics: nodes.map(| (transform, street_line_pos, sim_id) | {
+                let id = sim_id.0;
+                match street_line_pos {
+                    Some(pos) => {
+                        let start: [f32; 2] = pos.0.into();
+                        let end: [f32; 2] = pos.1.into();
+                        (id, vec![start, end])
+                    },
+                    None => {
+                        let pos = [transform.translation.x, transform.translation.y];
+                        (id, vec![pos])
+                    },
+                }
+            }).collect(),
+            camera,
+            meta: Some(meta),
+        }
+    }
+    /// serializes it with bincode, which is smaller and faster to parse than JSON
+    pub fn save_bin(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+    /// deserializes a save created with [FunnyNNBuilderCombi::save_bin]
+    pub fn load_bin(bytes: &[u8]) -> Result<FunnyNNBuilderCombi, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+    /// serializes it in the format of the file `name` (see [file_io::is_binary_file])
+    pub fn to_file_contents(&self, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        match file_io::is_binary_file(name) {
+            true => Ok(self.save_bin()?),
+            false => Ok(serde_json::to_vec_pretty(self)?),
+        }
+    }
+    /// deserializes the content of the file `name` (see [file_io::is_binary_file])
+    pub fn from_file_contents(name: &str, contents: &[u8]) -> Result<FunnyNNBuilderCombi, Box<dyn Error>> {
+        match file_io::is_binary_file(name) {
+            true => Ok(FunnyNNBuilderCombi::load_bin(contents)?),
+            false => Ok(serde_json::from_slice(contents)?),
+        }
+    }
+}
+
+/// Information about a saved map, to tell saved maps apart
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SaveMeta {
+    pub name: String,
+    pub description: String,
+    /// the date the map was first saved (YYYY-MM-DD)
+    pub created: String,
+}
+
+impl SaveMeta {
+    /// the title of the window while this map is open
+    pub fn window_title(&self) -> String {
+        match self.name.trim() {
+            "" => WINDOW_TITLE.to_string(),
+            name => format!("{} - {}", WINDOW_TITLE, name),
+        }
+    }
+}
+
+/// shows the name of the current map in the title of the window
+pub fn update_window_title(save_meta: Res<SaveMeta>, mut windows: ResMut<Windows>) {
+    if let Some(window) = windows.get_primary_mut() {
+        let title = save_meta.window_title();
+        if window.title() != title {
+            window.set_title(title);
+        }
+    }
+}
+
+/// The position and zoom of the camera
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CameraView {
+    pub translation: [f32; 3],
+    pub scale: [f32; 3],
+}
+
+impl CameraView {
+    pub fn from_transform(transform: &Transform) -> CameraView {
+        CameraView {
+            translation: transform.translation.into(),
+            scale: transform.scale.into(),
+        }
+    }
+    /// moves and zooms the camera with the given transform to this view
+    pub fn apply_to(&self, transform: &mut Transform) {
+        transform.translation = self.translation.into();
+        transform.scale = self.scale.into();
+    }
 }
 
 
@@ -31,6 +154,145 @@ pub fn update_sim_reports(
     sim_manager.update_reports();
 }
 
+/// The settings of the replay window that aren't part of the [Replay]
+pub struct ReplaySettings {
+    /// how many steps are simulated when recording a run
+    pub steps: usize,
+    /// the content of a recording file that was loaded, but not replayed yet
+    pub pending_load: PendingLoad,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        ReplaySettings {
+            steps: 500,
+            pending_load: PendingLoad::default(),
+        }
+    }
+}
+
+/// Draws a window to record a run of the street network and to step through
+/// the recorded frames (see [simulator::Replayer])
+pub fn draw_replay_window(
+    egui_context: ResMut<EguiContext>,
+    mut sim_manager: ResMut<SimManager>,
+    mut replay: ResMut<Replay>,
+    mut settings: ResMut<ReplaySettings>,
+    mut live_playback: ResMut<LivePlayback>,
+) {
+    // the frames of the last simulation can't be stepped through anymore
+    if !sim_manager.is_simulating() && !live_playback.is_empty() {
+        live_playback.clear();
+    }
+    // the replay is only written to if it changes, as every change redraws the cars
+    if let Some(recording) = sim_manager.take_recording() {
+        replay.replayer = Some(Replayer::new(recording));
+    }
+    if let Some(file) = settings.pending_load.take() {
+        match file.text() {
+            Ok(json) => match Recording::from_json(json) {
+                Ok(recording) => replay.replayer = Some(Replayer::new(recording)),
+                Err(err) => error!("{}", err),
+            },
+            Err(err) => error!("The recording isn't valid text: {}", err),
+        }
+    }
+    egui::Window::new("Replay")
+        .resizable(false)
+        .show(egui_context.ctx(), |ui| {
+            if !sim_manager.is_simulating() {
+                let mut steps = settings.steps;
+                ui.add(
+                    egui::Slider::new(&mut steps, RECORDED_STEPS_RANGE)
+                        .text("Steps to record")
+                        .clamp_to_range(true)
+                );
+                if steps != settings.steps {
+                    settings.steps = steps;
+                }
+                ui.horizontal(|ui| {
+                    // the run is simulated in the background, so the editor doesn't freeze
+                    if sim_manager.is_recording() {
+                        ui.label("Recording...");
+                    } else if ui.button("Record").clicked() {
+                        if let Err(err) = sim_manager.record_run_in_background(settings.steps) {
+                            error!("Unable to record a run: {}", err);
+                        }
+                    }
+                    if ui.button("Load recording").clicked() {
+                        if let Err(err) = file_io::request_load(RECORDING_FILE_NAME, &settings.pending_load) {
+                            error!("Unable to load the recording: {}", err);
+                        }
+                    }
+                });
+            } else if replay.replayer.is_none() {
+                draw_live_playback_controls(ui, &mut live_playback);
+            }
+            let replayer = match &replay.replayer {
+                Some(replayer) if !replayer.is_empty() => replayer,
+                _ => return,
+            };
+            ui.separator();
+            let mut position = replayer.position();
+            let last = replayer.len() - 1;
+            let dt = replayer.recording().dt;
+            let mut save = false;
+            let mut stop = false;
+            ui.add(egui::Slider::new(&mut position, 0..=last).text("Frame"));
+            ui.horizontal(|ui| {
+                if ui.button("<").clicked() {
+                    position = position.saturating_sub(1);
+                }
+                if ui.button(">").clicked() {
+                    position = (position + 1).min(last);
+                }
+                ui.label(format!("{:.1} s", position as f32 * dt));
+            });
+            ui.horizontal(|ui| {
+                save = ui.button("Save recording").clicked();
+                stop = ui.button("Stop replay").clicked();
+            });
+            if save {
+                match replayer.recording().to_json() {
+                    Ok(json) => match file_io::save_file(RECORDING_FILE_NAME, json.as_bytes()) {
+                        Ok(_) => info!("Saved the recording to {}", RECORDING_FILE_NAME),
+                        Err(err) => error!("Unable to save the recording: {}", err),
+                    },
+                    Err(err) => error!("Unable to serialize the recording: {}", err),
+                }
+            }
+            if stop {
+                replay.replayer = None;
+            } else if position != replayer.position() {
+                if let Some(replayer) = replay.replayer.as_mut() {
+                    replayer.seek(position);
+                }
+            }
+        });
+}
+
+/// Pauses the displayed simulation and steps through the frames received from it
+fn draw_live_playback_controls(ui: &mut egui::Ui, live_playback: &mut ResMut<LivePlayback>) {
+    // the playback is only written to if it changes, as every change redraws the cars
+    ui.horizontal(|ui| {
+        if live_playback.is_paused() {
+            if ui.button("Resume").clicked() {
+                live_playback.resume();
+            }
+        } else if ui.button("Pause").clicked() {
+            live_playback.pause();
+        }
+        ui.set_enabled(live_playback.can_step_back());
+        if ui.button("Step back").clicked() {
+            live_playback.step_back();
+        }
+        ui.set_enabled(live_playback.can_step_forward());
+        if ui.button("Step forward").clicked() {
+            live_playback.step_forward();
+        }
+    });
+}
+
 /// Draws the ui
 ///
 /// Nice reference: [Examples](https://github.com/mvlabat/bevy_egui/blob/main/examples/ui.rs)
@@ -43,13 +305,80 @@ pub fn draw_user_interface(
     mut background: ResMut<ClearColor>,
     mut theme: ResMut<UITheme>,
     mut current_theme: ResMut<CurrentTheme>,
+    live_stats: Res<LiveStats>,
+    sim_bridge: Res<SimBridge>,
+    mut render_settings: ResMut<RenderSettings>,
+    pending_load: Res<PendingLoad>,
+    mut save_meta: ResMut<SaveMeta>,
+    mut notifications: ResMut<Notifications>,
     // mut colors: ResMut<Assets<ColorMaterial>>,
-    nodes: QuerySet<(
+    mut nodes: QuerySet<(
         Query<(Entity, &Transform, Option<&StreetLinePosition>, &SimulationID), With<NodeType>>,
         Query<(Entity, &NodeBuilderRef), (With<NodeType>, With<SelectedNode>)>,
+        // the camera is saved with the street network
+        Query<&mut Transform, With<Camera>>,
     )>, //mut crossings: Query<, With<IONodeMarker>>
 ) {
     let mut repaint_necessary = false;
+    // a file chosen with "Load" (on the web it is only read after a few frames)
+    if let Some(file) = pending_load.take() {
+        match sim_manager.modify_sim_builder() {
+            Ok(builder) => {
+                match FunnyNNBuilderCombi::from_file_contents(&file.name, &file.contents) {
+                    Ok(sim_info) => {
+                        let new_builder = sim_info.builder;
+                        *builder = new_builder; 
+                        // despawn old nodes
+                        nodes.q0().iter().for_each(| (entity, _, _, _) | {
+                            commands.entity(entity).despawn_recursive();
+                        });
+                        let ui_info = sim_info.builder_graphics;
+                        ui_info.iter().for_each(| (id, position) | {
+                            let node = builder.get_node(*id).unwrap();
+                            match &*node.get() {
+                                NodeBuilder::IONode(_) => {
+                                    let bundle = node_bundles::IONodeBundle::new(*id, &node, position[0].into(), theme.io_node);
+                                    commands.spawn_bundle(bundle);
+                                },
+                                NodeBuilder::Crossing(_) => {
+                                    let bundle = node_bundles::CrossingBundle::new(*id, &node, position[0].into(), theme.crossing);
+                                    commands.spawn_bundle(bundle);
+                                },
+                                NodeBuilder::Street(_) => {
+                                    let bundle = node_bundles::StreetBundle::new(*id, &node, position[0].into(), position[1].into(), theme.street);
+                                    commands.spawn_bundle(bundle);
+                                },
+                            }
+
+                        });
+                        // older saves don't contain the camera, so it stays where it is
+                        if let (Some(view), Ok(mut camera)) = (sim_info.camera, nodes.q2_mut().single_mut()) {
+                            view.apply_to(&mut camera);
+                        }
+                        // older saves don't have a name, so the title is reset
+                        *save_meta = sim_info.meta.unwrap_or_default();
+                        info!("Loaded Simulation Builder");
+                        // the loaded network is already saved in its file
+                        sim_manager.mark_builder_saved();
+                        // continue the evolution from the saved networks
+                        if let Some(nn) = sim_info.nn {
+                            match sim_manager.seed_population_from(nn) {
+                                Ok(()) => info!("Seeding the next simulation with the saved neural networks"),
+                                Err(err) => error!("Can't seed the next simulation with the saved neural networks: {}", err),
+                            }
+                        }
+                    },
+                    Err(err) => {
+                        error!("Unable to load from file. Error: {}", err);
+                    },
+                }
+            },
+            Err(err) => {
+                error!("Cannot load file because SimBuilder can not be modified: {}", err);
+                notifications.push(EDIT_WHILE_SIMULATING);
+            },
+        }
+    }
     let panel = egui::TopBottomPanel::top("menu_top_panel");
     panel.show(egui_context.ctx(), |ui| {
         ui.horizontal(|ui| {
@@ -57,92 +386,48 @@ pub fn draw_user_interface(
                 ui.menu_button("File", |ui| {
 
                     if !sim_manager.is_simulating() {
+                        ui.horizontal(|ui| {
+                            ui.label("Name:");
+                            ui.text_edit_singleline(&mut save_meta.name);
+                        });
+                        ui.label("Description:");
+                        ui.text_edit_multiline(&mut save_meta.description);
+                        ui.checkbox(&mut ui_state.binary_save, "Compact binary format");
+                        let save_file_name = match ui_state.binary_save {
+                            true => BIN_SAVE_FILE_NAME,
+                            false => SAVE_FILE_NAME,
+                        };
                         if ui.button("Save").clicked() {
+                            if save_meta.created.is_empty() {
+                                save_meta.created = file_io::current_date();
+                            }
                             let report = sim_manager.simulation_report.as_ref().map(| report | report.get_best_nn());
-                            match sim_manager.modify_sim_builder() {
-                                Ok(builder) => {
-                                    let sim_wrapper = FunnyNNBuilderCombi {
-                                        builder: builder.clone(),
-                                        nn: report,
-                                        builder_graphics: nodes.q0().iter().map(| (_, transform, street_line_pos, sim_id) | {
-                                            let id = sim_id.0;
-                                            match street_line_pos {
-                                                Some(pos) => {
-                                                    let start: [f32; 2] = pos.0.into();
-                                                    let end: [f32; 2] = pos.1.into();
-                                                    (id, vec![start, end])
-                                                },
-                                                None => {
-                                                    let pos = [transform.translation.x, transform.translation.y];
-                                                    (id, vec![pos])
-                                                },
-                                            }
-                                        }).collect()
-                                    };
-                                    let json = serde_json::to_string_pretty(&sim_wrapper);
-                                    match json {
-                                        Ok(s) => {
-                                            // Create a temporary file.
-                                            let temp_directory = env::current_dir().unwrap();
-                                            let full_path = temp_directory.as_path();
-                                            let temp_file = temp_directory.join("StreetSimulation.json");
-                                            let mut file = File::create(temp_file).unwrap();
-                                            write!(&mut file, "{}", s).unwrap();
-                                            info!("Saved simulation and street network to {}", full_path.display());
-                                        },
-                                        Err(_) => todo!(),
-                                    }
+                            let camera = nodes.q2_mut().single_mut().ok().map(|camera| CameraView::from_transform(&camera));
+                            let sim_wrapper = FunnyNNBuilderCombi::new(
+                                sim_manager.sim_builder(),
+                                report,
+                                nodes.q0().iter().map(| (_, transform, line, sim_id) | (transform, line, sim_id)),
+                                camera,
+                                save_meta.clone(),
+                            );
+                            match sim_wrapper.to_file_contents(save_file_name) {
+                                Ok(contents) => match file_io::save_file(save_file_name, &contents) {
+                                    Ok(_) => {
+                                        info!("Saved simulation and street network to {}", save_file_name);
+                                        sim_manager.mark_builder_saved();
+                                        // everything is saved, so there is nothing to recover
+                                        #[cfg(not(target_arch = "wasm32"))]
+                                        auto_save::remove_auto_save();
+                                    },
+                                    Err(err) => error!("Unable to save the street network: {}", err),
                                 },
-                                Err(_) => todo!(),
+                                Err(err) => error!("Unable to serialize the street network: {}", err),
                             }
                         }
                         if ui.button("Load").clicked() {
-                            let directory = env::current_dir().unwrap();
-                            let path = directory.join("StreetSimulation.json");
-                            let mut file = File::open(path).unwrap();
-                            let mut json = String::new();
-                            file.read_to_string(&mut json).unwrap();
-                            let sim_wrapper = serde_json::from_str::<FunnyNNBuilderCombi>(&json);
-                            match sim_manager.modify_sim_builder() {
-                                Ok(builder) => {
-                                    match sim_wrapper {
-                                        Ok(sim_info) => {
-                                            let new_builder = sim_info.builder;
-                                            *builder = new_builder; 
-                                            // despawn old nodes
-                                            nodes.q0().iter().for_each(| (entity, _, _, _) | {
-                                                commands.entity(entity).despawn_recursive();
-                                            });
-                                            let ui_info = sim_info.builder_graphics;
-                                            ui_info.iter().for_each(| (id, position) | {
-                                                let node = builder.get_node(*id).unwrap();
-                                                match &*node.get() {
-                                                    NodeBuilder::IONode(_) => {
-                                                        let bundle = node_bundles::IONodeBundle::new(*id, &node, position[0].into(), theme.io_node);
-                                                        commands.spawn_bundle(bundle);
-                                                    },
-                                                    NodeBuilder::Crossing(_) => {
-                                                        let bundle = node_bundles::CrossingBundle::new(*id, &node, position[0].into(), theme.crossing);
-                                                        commands.spawn_bundle(bundle);
-                                                    },
-                                                    NodeBuilder::Street(_) => {
-                                                        let bundle = node_bundles::StreetBundle::new(*id, &node, position[0].into(), position[1].into(), theme.street);
-                                                        commands.spawn_bundle(bundle);
-                                                    },
-                                                }
-
-                                            });
-                                            let nn = sim_info.nn;
-                                            info!("Loaded Simulation Builder");
-                                        },
-                                        Err(err) => {
-                                            error!("Unable to load from file. Error: {}", err);
-                                        },
-                                    }
-                                },
-                                Err(err) => {
-                                    error!("Cannot load file because SimBuilder can not be modified: {}", err)
-                                },
+                            // the file is loaded at the start of the next frames
+                            if let Err(err) = file_io::request_load(save_file_name, &pending_load) {
+                                error!("Unable to load from file. Error: {}", err);
                             }
                         }
                     }
@@ -178,13 +463,23 @@ pub fn draw_user_interface(
                     //   are needed)
                     if let Ok((_entity, selected_node_ref)) = nodes.q1().single() {
                         let selected_node = &selected_node_ref.0;
+                        // the builder is shared with the item editor, changing it
+                        //  while simulating would desync it from the simulations
+                        let editable = !sim_manager.is_simulating();
+                        if !editable {
+                            ui.colored_label(theme.text_color, "(Can't be edited while simulating)");
+                        }
                         let display_conns = |ui: &mut Ui,
                                              conns: &mut HashMap<
                             simulator::nodes::Direction,
-                            WeakIntMut<NodeBuilder>,
+                            Vec<WeakIntMut<NodeBuilder>>,
                         >| {
-                            let mut conns =
-                                conns.iter_mut().collect::<Vec<(
+                            let mut changed = false;
+                            // a side can hold several connections
+                            let mut conns = conns
+                                .iter_mut()
+                                .flat_map(|(d, cs)| cs.iter_mut().map(move |c| (d, c)))
+                                .collect::<Vec<(
                                     &simulator::nodes::Direction,
                                     &mut WeakIntMut<NodeBuilder>,
                                 )>>();
@@ -204,17 +499,17 @@ pub fn draw_user_interface(
                                 if ntype == "Street" {
                                     match &mut *c.upgrade().get() {
                                         NodeBuilder::Street(street) => {
-                                            ui.add(
-                                                egui::Slider::new(&mut street.lanes, 1..=10)
-                                                    .text("lanes")
-                                                    .clamp_to_range(true),
-                                            );
+                                            changed |= lanes_editor(ui, &mut street.lanes, editable);
                                         }
                                         _ => panic!(""),
                                     };
                                 }
                             }
+                            changed
                         };
+                        // the edits below don't go through modify_sim_builder, so
+                        //  the builder is marked as changed for the auto-save
+                        let mut changed = false;
                         match &mut *selected_node.get() {
                             NodeBuilder::IONode(node) => {
                                 ui.horizontal(|ui| {
@@ -225,11 +520,26 @@ pub fn draw_user_interface(
                                     ui.colored_label(theme.text_color,"Node ID: ");
                                     ui.colored_label(theme.text_color,node.id.to_string());
                                 });
-                                ui.add(
-                                    egui::Slider::new(&mut node.spawn_rate, 0.0..=1.0)
-                                        .text("spawn rate")
-                                        .clamp_to_range(true),
-                                );
+                                changed |= spawn_rate_editor(ui, &mut node.spawn_rate, editable);
+                                changed |= ui.horizontal(|ui| {
+                                    ui.set_enabled(editable);
+                                    ui.colored_label(theme.text_color, "Max. cars per step: ");
+                                    let mut max = node.max_spawn_per_step.get();
+                                    let changed = ui.add(egui::DragValue::new(&mut max).clamp_range(1..=100))
+                                        .changed();
+                                    if let Some(max) = NonZeroUsize::new(max) {
+                                        node.max_spawn_per_step = max;
+                                    }
+                                    changed
+                                }).inner;
+                                let role = node.role;
+                                ui.horizontal(|ui| {
+                                    ui.set_enabled(editable);
+                                    ui.radio_value(&mut node.role, IONodeRole::Both, "Source & sink");
+                                    ui.radio_value(&mut node.role, IONodeRole::Source, "Source");
+                                    ui.radio_value(&mut node.role, IONodeRole::Sink, "Sink");
+                                });
+                                changed |= node.role != role;
                                 CollapsingHeader::new(format!(
                                     "Connections ({})",
                                     node.connections_out.len()
@@ -246,14 +556,7 @@ pub fn draw_user_interface(
                                         if ntype == "Street" {
                                             match &mut *c.upgrade().get() {
                                                 NodeBuilder::Street(street) => {
-                                                    ui.add(
-                                                        egui::Slider::new(
-                                                            &mut street.lanes,
-                                                            1..=10,
-                                                        )
-                                                        .text("lanes")
-                                                        .clamp_to_range(true),
-                                                    );
+                                                    changed |= lanes_editor(ui, &mut street.lanes, editable);
                                                 }
                                                 _ => panic!(""),
                                             };
@@ -270,18 +573,31 @@ pub fn draw_user_interface(
                                     ui.colored_label(theme.text_color,"Node ID: ");
                                     ui.colored_label(theme.text_color,node.id.to_string());
                                 });
+                                changed |= crossing_length_editor(ui, &mut node.length, editable);
+                                // the live state of the crossing in the tracked simulation
+                                if let Some(snapshot) = sim_bridge.crossing(node.id).filter(|_| sim_manager.is_simulating()) {
+                                    ui.colored_label(theme.text_color, format!(
+                                        "Traffic light: {:?} (for {:.1}s)",
+                                        snapshot.traffic_light_state,
+                                        snapshot.time_in_current_phase
+                                    ));
+                                    ui.colored_label(theme.text_color, format!(
+                                        "Waiting cars [N, E, S, W]: {:?}",
+                                        snapshot.queue_lengths
+                                    ));
+                                }
                                 CollapsingHeader::new(format!(
                                     "Connections IN ({})",
-                                    node.connections.input.len()
+                                    node.connections.len(InOut::IN)
                                 ))
                                 .default_open(true)
-                                .show(ui, |ui| display_conns(ui, &mut node.connections.input));
+                                .show(ui, |ui| changed |= display_conns(ui, &mut node.connections.input));
                                 CollapsingHeader::new(format!(
                                     "Connections OUT ({})",
-                                    node.connections.output.len()
+                                    node.connections.len(InOut::OUT)
                                 ))
                                 .default_open(true)
-                                .show(ui, |ui| display_conns(ui, &mut node.connections.output));
+                                .show(ui, |ui| changed |= display_conns(ui, &mut node.connections.output));
                             }
                             NodeBuilder::Street(node) => {
                                 ui.horizontal(|ui| {
@@ -292,8 +608,13 @@ pub fn draw_user_interface(
                                     ui.colored_label(theme.text_color,"Node ID: ");
                                     ui.colored_label(theme.text_color,node.id.to_string());
                                 });
+                                changed |= lanes_editor(ui, &mut node.lanes, editable);
+                                changed |= speed_limit_editor(ui, &mut node.speed_limit, editable);
                             }
                         }
+                        if changed {
+                            sim_manager.mark_builder_changed();
+                        }
                     }
                 });
             // Toolbar
@@ -324,23 +645,38 @@ pub fn draw_user_interface(
                 .resizable(false)
                 .show(egui_context.ctx(), |ui| {
                 ui.heading("Simulation Settings");
+                ui.checkbox(&mut ui_state.car_heatmap, "Color cars by speed (heatmap)");
+                ui.checkbox(&mut ui_state.street_car_labels, "Show the number of cars on streets");
+                ui.separator();
                 match sim_manager.is_simulating() {
                     false => {
                         ui.vertical(| ui | {
-                            let builder = sim_manager.modify_sim_builder().expect("Can not modify SimBuilder even though no simulation is running");
+                            // the builder is only modified if a value changed, as
+                            //  that marks it for the auto-save
+                            let builder = sim_manager.sim_builder();
+                            let (mut delay, mut speed_multiplier, mut speed_to_co2, mut dt) =
+                                (builder.delay, builder.speed_multiplier, builder.speed_to_co2, builder.dt);
                             ui.add(
                                 egui::Slider::new(
-                                    &mut builder.delay,
+                                    &mut delay,
                                     0..=1000
                                 )
                                 .text("Simulation delay in ms")
                                 .clamp_to_range(true)
                             );
                             ui.label("(Useful for inspecting the car movement)");
+                            ui.add(
+                                egui::Slider::new(
+                                    &mut speed_multiplier,
+                                    1..=100
+                                )
+                                .text("Playback speed (steps per frame)")
+                                .clamp_to_range(true)
+                            );
                             ui.separator();
                             ui.add(
                                 egui::Slider::new(
-                                    &mut builder.speed_to_co2,
+                                    &mut speed_to_co2,
                                     0.0..=1.0
                                 )
                                 .text("Optimisation Target: (Left: Speed, Right: Co2)")
@@ -349,12 +685,23 @@ pub fn draw_user_interface(
                             ui.separator();
                             ui.add(
                                 egui::Slider::new(
-                                    &mut builder.dt,
+                                    &mut dt,
                                     0.01..=10.0
                                 )
                                 .text("Time step in seconds")
                                 .clamp_to_range(true)
                             );
+                            if (delay, speed_multiplier, speed_to_co2, dt) != (builder.delay, builder.speed_multiplier, builder.speed_to_co2, builder.dt) {
+                                let builder = sim_manager.modify_sim_builder().expect("Can not modify SimBuilder even though no simulation is running");
+                                builder.delay = delay;
+                                builder.speed_multiplier = speed_multiplier;
+                                builder.speed_to_co2 = speed_to_co2;
+                                if dt != builder.dt {
+                                    if let Err(err) = builder.set_dt(dt) {
+                                        notifications.push(err);
+                                    }
+                                }
+                            }
                             ui.separator();
                             ui.add(
                                 egui::Slider::new(
@@ -374,14 +721,44 @@ pub fn draw_user_interface(
                                 .clamp_to_range(true)
                             );
                             ui.separator();
-                            ui.add(
-                                egui::Slider::new(
-                                    &mut sim_manager.stop_iterations,
-                                    1..=10000
-                                )
-                                .text("Iterations to stop")
-                                .clamp_to_range(true)
-                            );
+                            ui.label("Stop each generation after:");
+                            ui.horizontal(| ui | {
+                                let condition = &mut sim_manager.stop_condition;
+                                if ui.radio(matches!(condition, StopCondition::Iterations(_)), "Iterations").clicked() {
+                                    *condition = StopCondition::Iterations(3000);
+                                }
+                                if ui.radio(matches!(condition, StopCondition::SimTime(_)), "Simulated time").clicked() {
+                                    *condition = StopCondition::SimTime(300.0);
+                                }
+                                if ui.radio(matches!(condition, StopCondition::WallClock(_)), "Real time").clicked() {
+                                    *condition = StopCondition::WallClock(Duration::from_secs(10));
+                                }
+                            });
+                            match &mut sim_manager.stop_condition {
+                                StopCondition::Iterations(iterations) => {
+                                    ui.add(
+                                        egui::Slider::new(iterations, 1..=10000)
+                                            .text("Iterations to stop")
+                                            .clamp_to_range(true)
+                                    );
+                                },
+                                StopCondition::SimTime(seconds) => {
+                                    ui.add(
+                                        egui::Slider::new(seconds, 1.0..=3600.0)
+                                            .text("Simulated seconds to stop")
+                                            .clamp_to_range(true)
+                                    );
+                                },
+                                StopCondition::WallClock(duration) => {
+                                    let mut seconds = duration.as_secs_f32();
+                                    ui.add(
+                                        egui::Slider::new(&mut seconds, 1.0..=600.0)
+                                            .text("Real seconds to stop")
+                                            .clamp_to_range(true)
+                                    );
+                                    *duration = Duration::from_secs_f32(seconds);
+                                },
+                            }
                             ui.separator();
                             ui.add(
                                 egui::Slider::new(
@@ -399,6 +776,18 @@ pub fn draw_user_interface(
                                 .text("Mutation Coefficent")
                                 .clamp_to_range(true)
                             );
+                            ui.add(
+                                egui::Slider::new(
+                                    &mut sim_manager.weight_limit,
+                                    1.0..=100.0
+                                )
+                                .text("Maximum absolute weight")
+                                .clamp_to_range(true)
+                            );
+                            ui.label("Fitness:");
+                            ui.radio_value(&mut sim_manager.fitness_mode, FitnessMode::Scalar, "Cost only");
+                            ui.radio_value(&mut sim_manager.fitness_mode, FitnessMode::ParetoCo2Speed, "Pareto (Cost and CO2)");
+                            ui.separator();
                             ui.checkbox(&mut sim_manager.disable_tracking, "Disable tracking in frontend (recommended when not using delay)");
                             ui.separator();
                             ui.heading("Commands");
@@ -419,6 +808,18 @@ pub fn draw_user_interface(
                                     }
                                 }
                             });
+                            // throughput of the best simulation of the last run
+                            if let Some(report) = &sim_manager.simulation_report {
+                                ui.separator();
+                                ui.heading("Throughput");
+                                let mut throughput: Vec<(usize, usize)> = report.get_best_throughput().into_iter().collect();
+                                throughput.sort_unstable();
+                                let total: usize = throughput.iter().map(| (_, absorbed) | absorbed).sum();
+                                ui.label(format!("Cars arrived (total): {}", total));
+                                for (id, absorbed) in throughput {
+                                    ui.label(format!("IONode {}: {}", id, absorbed));
+                                }
+                            }
                         });
                     },
                     true => {
@@ -436,16 +837,36 @@ pub fn draw_user_interface(
                 }
 
             });
+            // live statistics of the tracked simulation
+            if sim_manager.is_simulating() && !sim_manager.disable_tracking {
+                egui::Window::new("Live Statistics")
+                    .resizable(false)
+                    .show(egui_context.ctx(), |ui| {
+                        ui.label(format!("Cost: {:.2}", live_stats.cost[0]));
+                        ui.label(format!("CO2: {:.4} tonnes", live_stats.cost[1]));
+                        ui.label(format!("Cars driving: {}", live_stats.car_count));
+                        ui.label(format!("Cars arrived: {}", live_stats.absorbed_cars));
+                    });
+            }
             egui::TopBottomPanel::bottom("Generation Report").default_height(100.0).resizable(true).show(egui_context.ctx(), | ui | {
                 ui.heading("Generation Report");
-                if let Some(sims) = &sim_manager.simulations {
+                // after a run, the history of the report is shown
+                let reports = match (&sim_manager.simulations, &sim_manager.simulation_report) {
+                    (Some(sims), _) => Some(&sims.generation_reports),
+                    (None, Some(report)) => Some(&report.history),
+                    (None, None) => None,
+                };
+                if let Some(reports) = reports {
                     egui::ScrollArea::vertical().show(ui, |ui| {
-                        for (i, report) in sims.generation_reports.iter().enumerate() {
+                        for (i, report) in reports.iter().enumerate() {
                             ui.horizontal(| ui | {
                                 ui.label(format!("Generation #{}", i) );
                                 ui.separator();
                                 ui.label(format!("Cost: {}", report.cost) );
                                 ui.label(format!("CO2: {} tonnes", report.tonnes_co2) );
+                                ui.label(format!("Iterations: {}", report.iterations) );
+                                ui.label(format!("Longest queue: {}", report.max_queue) );
+                                ui.label(format!("Steps/s: {:.0}", report.steps_per_second) );
                             });
                         }
                     });
@@ -459,20 +880,26 @@ pub fn draw_user_interface(
                     ui.horizontal(| ui | {
                         ui.heading("Simulation Overview");
                     });
+                    let mut to_track = None;
                     egui::ScrollArea::vertical().show(ui, |ui| {
                         if let Ok(stati) = sim_manager.get_sim_status() {
-                            stati.iter_mut().enumerate().for_each( | (i, sim_info) | {
-                                if ui.button(format!("Simulation {}", i)).clicked()  {
-                                    sim_info.displaying = !sim_info.displaying;
-                                }
-                                if sim_info.displaying {
-                                    egui::Window::new(format!("Information for Simulation {}", i)).show( egui_context.ctx(), | ui | {
-                                                
-                                    });
+                            stati.iter().enumerate().for_each( | (i, sim_info) | {
+                                // the tracked simulation is highlighted
+                                if ui.selectable_label(sim_info.displaying, format!("Simulation {}", i)).clicked()  {
+                                    to_track = Some(i);
                                 }
                             });
                         }
                     });
+                    if let Some(i) = to_track.filter(|i| sim_manager.tracked_simulation() != Some(*i)) {
+                        match sim_manager.track_simulation(i) {
+                            Ok(_) => info!("Tracking Simulation index={}", i),
+                            Err(err) => {
+                                warn!("Unable to track Simulation with index={}: {}", i, err);
+                                notifications.push(&err);
+                            },
+                        }
+                    }
                 });
 
         }
@@ -491,11 +918,42 @@ pub fn draw_user_interface(
                     let mut new_theme = (*current_theme).clone();
                     ui.radio_value(&mut new_theme, CurrentTheme::LIGHT, "Light");
                     ui.radio_value(&mut new_theme, CurrentTheme::DRACULA, "Dracula");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut ui_state.theme_path);
+                        if ui.button("Load theme…").clicked() {
+                            match ThemeColors::from_file(&ui_state.theme_path) {
+                                Ok(colors) => new_theme = CurrentTheme::Custom(colors),
+                                Err(err) => warn!("Could not load theme from {}: {}", ui_state.theme_path, err),
+                            }
+                        }
+                    });
                     if new_theme != *current_theme {
                         *current_theme = new_theme;
+                        current_theme.save();
                         *theme = UITheme::from_enum(&new_theme);
                         repaint_necessary = true;
                     }
+                    ui.separator();
+                    ui.checkbox(&mut ui_state.snap_to_grid, "Snap nodes to grid");
+                    #[cfg(not(target_arch = "wasm32"))]
+                    ui.horizontal(|ui| {
+                        ui.colored_label(theme.text_color, "Snapshot file (saved with F12):");
+                        ui.text_edit_singleline(&mut ui_state.snapshot_path);
+                    });
+                    ui.separator();
+                    // the settings are only written back if they changed, as
+                    //  every change redraws all nodes
+                    let mut new_settings = (*render_settings).clone();
+                    ui.add(egui::Slider::new(&mut new_settings.car_size, 0.5..=10.0).text("Car size"));
+                    ui.add(egui::Slider::new(&mut new_settings.car_z, 0.0..=100.0).text("Car z position"));
+                    ui.add(egui::Slider::new(&mut new_settings.street_thickness, 1.0..=20.0).text("Street thickness"));
+                    ui.add(egui::Slider::new(&mut new_settings.crossing_size, 10.0..=60.0).text("Crossing size"));
+                    if ui.button("Reset sizes").clicked() {
+                        new_settings = RenderSettings::default();
+                    }
+                    if new_settings != *render_settings {
+                        *render_settings = new_settings;
+                    }
                 });
             });
         }
@@ -511,6 +969,92 @@ pub fn draw_user_interface(
     }
 }
 
+/// the range of lanes a street can have in the editor
+const LANES_RANGE: RangeInclusive<u8> = 1..=10;
+
+/// A slider for the number of lanes of a street, with a field to type in
+///  the exact value
+///
+/// The widgets are greyed out if not `editable`. Returns whether the value
+///  was changed
+fn lanes_editor(ui: &mut Ui, lanes: &mut u8, editable: bool) -> bool {
+    ui.horizontal(|ui| {
+        ui.set_enabled(editable);
+        let slider = ui.add(
+            egui::Slider::new(&mut *lanes, LANES_RANGE)
+                .text("lanes")
+                .clamp_to_range(true),
+        );
+        let drag = ui.add(egui::DragValue::new(lanes).clamp_range(LANES_RANGE).speed(0.1));
+        slider.changed() || drag.changed()
+    })
+    .inner
+}
+
+/// A slider for the speed limit of a street
+///
+/// The widgets are greyed out if not `editable`. Returns whether the value
+///  was changed
+fn speed_limit_editor(ui: &mut Ui, speed_limit: &mut f32, editable: bool) -> bool {
+    ui.horizontal(|ui| {
+        ui.set_enabled(editable);
+        // the default limit is much higher than the speed of any car
+        ui.add(
+            egui::Slider::new(speed_limit, 0.01..=DEFAULT_SPEED_LIMIT)
+                .logarithmic(true)
+                .text("speed limit")
+                .clamp_to_range(true),
+        )
+        .changed()
+    })
+    .inner
+}
+
+/// the range of lengths a crossing can have in the editor
+const CROSSING_LENGTH_RANGE: RangeInclusive<f32> = 1.0..=100.0;
+
+/// A slider for the length the cars traverse on a crossing
+///
+/// The widgets are greyed out if not `editable`. Returns whether the value
+///  was changed
+fn crossing_length_editor(ui: &mut Ui, length: &mut f32, editable: bool) -> bool {
+    ui.horizontal(|ui| {
+        ui.set_enabled(editable);
+        let slider = ui.add(
+            egui::Slider::new(&mut *length, CROSSING_LENGTH_RANGE)
+                .text("length")
+                .clamp_to_range(true),
+        );
+        let drag = ui.add(egui::DragValue::new(length).clamp_range(CROSSING_LENGTH_RANGE).speed(0.1));
+        slider.changed() || drag.changed()
+    })
+    .inner
+}
+
+/// A slider for the spawn rate of an IONode, with a field to type in the
+///  exact value
+///
+/// The widgets are greyed out if not `editable`. Returns whether the value
+///  was changed
+fn spawn_rate_editor(ui: &mut Ui, spawn_rate: &mut f64, editable: bool) -> bool {
+    ui.horizontal(|ui| {
+        ui.set_enabled(editable);
+        let slider = ui.add(
+            egui::Slider::new(&mut *spawn_rate, 0.0..=1.0)
+                .text("spawn rate")
+                .clamp_to_range(true),
+        );
+        let drag = ui.add(
+            egui::DragValue::new(spawn_rate)
+                .clamp_range(0.0..=1.0)
+                .speed(0.01)
+                .max_decimals(3),
+        );
+        slider.changed() || drag.changed()
+    })
+    .inner
+}
+
 pub fn repaint_ui(
     mut commands: Commands,
     egui_ui: Option<&CtxRef>,
@@ -526,3 +1070,63 @@ pub fn repaint_ui(
         commands.entity(entity).insert(NeedsRecolor);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CameraView, FunnyNNBuilderCombi, SaveMeta, BIN_SAVE_FILE_NAME, SAVE_FILE_NAME, WINDOW_TITLE};
+    use simulator::debug::build_grid_sim;
+    use std::collections::HashMap;
+
+    #[test]
+    fn save_meta_survives_round_trip() {
+        let meta = SaveMeta {
+            name: "Grid".to_string(),
+            description: "3x3 crossings".to_string(),
+            created: "2024-01-01".to_string(),
+        };
+        let save = FunnyNNBuilderCombi {
+            builder: build_grid_sim(3, 100.0),
+            nn: None,
+            builder_graphics: HashMap::new(),
+            camera: None,
+            meta: Some(meta.clone()),
+        };
+        let json = serde_json::to_string(&save).unwrap();
+        let loaded: FunnyNNBuilderCombi = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.meta, Some(meta.clone()));
+        assert_eq!(loaded.meta.unwrap().window_title(), format!("{} - Grid", WINDOW_TITLE));
+        // older saves don't have any metadata
+        let mut old: serde_json::Value = serde_json::from_str(&json).unwrap();
+        old.as_object_mut().unwrap().remove("meta");
+        let loaded: FunnyNNBuilderCombi = serde_json::from_value(old).unwrap();
+        assert_eq!(loaded.meta, None);
+        assert_eq!(SaveMeta::default().window_title(), WINDOW_TITLE);
+    }
+
+    #[test]
+    fn binary_save_matches_json_save() {
+        let mut builder_graphics = HashMap::new();
+        builder_graphics.insert(0, vec![[1.0, 2.0]]);
+        builder_graphics.insert(1, vec![[0.0, 0.0], [10.0, 5.0]]);
+        let save = FunnyNNBuilderCombi {
+            builder: build_grid_sim(3, 100.0),
+            nn: None,
+            builder_graphics,
+            camera: Some(CameraView {
+                translation: [1.0, 2.0, 3.0],
+                scale: [1.0; 3],
+            }),
+            meta: Some(SaveMeta::default()),
+        };
+        let json = save.to_file_contents(SAVE_FILE_NAME).unwrap();
+        let bin = save.to_file_contents(BIN_SAVE_FILE_NAME).unwrap();
+        assert!(bin.len() < json.len());
+        let from_json = FunnyNNBuilderCombi::from_file_contents(SAVE_FILE_NAME, &json).unwrap();
+        let from_bin = FunnyNNBuilderCombi::from_file_contents(BIN_SAVE_FILE_NAME, &bin).unwrap();
+        // the maps aren't ordered, so the saves are compared as json values
+        assert_eq!(
+            serde_json::to_value(&from_bin).unwrap(),
+            serde_json::to_value(&from_json).unwrap()
+        );
+    }
+}
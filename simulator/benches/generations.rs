@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use simulator::{debug::build_grid_sim, SimManager, StopCondition};
+
+/// Simulates many short generations of a tiny population, so the time is
+/// dominated by the overhead of starting each generation
+fn generation_overhead_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generation_overhead_bench");
+    group.sample_size(10);
+    group.bench_function("100 generations", |b| {
+        b.iter(|| {
+            let mut sim_builder = build_grid_sim(4, 100.0);
+            // make sure every simulation has cars, so the costs are never zero
+            for (id, _rate) in sim_builder.io_nodes() {
+                sim_builder.set_spawn_rate(id, 1.0).unwrap();
+            }
+            sim_builder.with_dt(1.0);
+            let mut manager = SimManager::new();
+            *manager.modify_sim_builder().unwrap() = sim_builder;
+            manager.population = 4;
+            manager.generations = 100;
+            manager.stop_condition = StopCondition::Iterations(100);
+            manager.simulate().unwrap();
+            while !manager.simulations.as_ref().unwrap().has_terminated() {
+                std::thread::yield_now();
+            }
+            manager.terminate_sims();
+        })
+    });
+    group.finish()
+}
+
+criterion_group!(benches, generation_overhead_bench);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rand::{rngs::StdRng, SeedableRng};
+use simulator::{
+    debug::build_grid_sim,
+    nodes::{NodeBuilder, NodeBuilderTrait},
+    path::{MovableServer, PathAwareCar},
+};
+
+/// Generates cars for every IONode of a 10x10 grid. Without the cache, a
+/// path has to be found for each car
+fn generate_movable_bench(c: &mut Criterion) {
+    let sim_builder = build_grid_sim(10, 100.0);
+    let io_nodes: Vec<usize> = sim_builder
+        .nodes
+        .iter()
+        .filter_map(|n| match &*n.get() {
+            NodeBuilder::IONode(io_node) => Some(io_node.get_id()),
+            _ => None,
+        })
+        .collect();
+    let mut mv_server = MovableServer::<PathAwareCar>::new();
+    mv_server.register_simulator_builder(&sim_builder);
+    let mut rng = StdRng::seed_from_u64(0);
+    // fill the cache with (almost certainly) all paths
+    let mut cached_server = mv_server.clone();
+    for _ in 0..100 {
+        for id in io_nodes.iter() {
+            cached_server.generate_movable_with_rng(*id, &mut rng).unwrap();
+        }
+    }
+
+    let mut group = c.benchmark_group("generate_movable_bench");
+    group.bench_function("uncached", |b| {
+        b.iter_batched(
+            // clones share the cache, so a new server is needed
+            || {
+                let mut server = MovableServer::<PathAwareCar>::new();
+                server.register_simulator_builder(&sim_builder);
+                (server, StdRng::seed_from_u64(0))
+            },
+            |(mut server, mut rng)| {
+                for id in io_nodes.iter() {
+                    server.generate_movable_with_rng(*id, &mut rng).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("cached", |b| {
+        let mut rng = StdRng::seed_from_u64(0);
+        b.iter(|| {
+            for id in io_nodes.iter() {
+                cached_server.generate_movable_with_rng(*id, &mut rng).unwrap();
+            }
+        })
+    });
+    group.finish()
+}
+
+criterion_group!(benches, generate_movable_bench);
+criterion_main!(benches);
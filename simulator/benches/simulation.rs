@@ -1,35 +1,72 @@
-use art_int::{LayerTopology, ActivationFunc};
+use art_int::{LayerTopology, ActivationFunc, Network};
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, SeedableRng};
 use simulator::{
     datastructs::IntMut,
     debug::build_grid_sim,
+    nodes::Node,
     path::{MovableServer, PathAwareCar},
+    Simulator,
 };
 
+/// builds a grid with side length `size` and the same neural networks each time
+fn build_sim(size: u32) -> Simulator {
+    let mut sim_builder = build_grid_sim(size, 100.0);
+    let mut mv_server = MovableServer::<PathAwareCar>::new();
+    mv_server.register_simulator_builder(&sim_builder);
+    let mut sim = sim_builder.build(&mv_server).unwrap();
+    let topology = [
+        LayerTopology::new(16),
+        LayerTopology::new(8),
+        LayerTopology::new(4).with_activation(ActivationFunc::SoftMax),
+    ];
+    let crossings = sim
+        .nodes
+        .iter()
+        .filter(|n| matches!(&*n.read(), Node::Crossing(_)))
+        .count();
+    let mut rng = StdRng::seed_from_u64(0);
+    sim.set_neural_networks(
+        (0..crossings)
+            .map(|_| Network::random(&mut rng, &topology))
+            .collect(),
+    )
+    .unwrap();
+    sim
+}
+
 fn simulation_performance_bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("simulation_performance_bench");
     let mut size: u32 = 10;
     for _i in 1..5 {
         size *= 2;
-        let mut sim_builder = build_grid_sim(size, 100.0);
-        let mut mv_server = MovableServer::<PathAwareCar>::new();
-        mv_server.register_simulator_builder(&sim_builder);
-        // make sure it works when using the cached value
-        let mut sim = sim_builder.build(&mv_server);
-        sim.init_neural_networks_random(
-            &[
-                    LayerTopology::new(16),
-                    LayerTopology::new(8),
-                    LayerTopology::new(4),
-                    LayerTopology::new(0).with_activation(ActivationFunc::SoftMax),
-                ]
-        );
+        let mut sim = build_sim(size);
+        // the cars are spawned with a seeded rng, so every run simulates the same traffic
+        let mut rng = StdRng::seed_from_u64(0);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &_size| {
+            b.iter(|| sim.update_all_nodes_with_rng(sim.dt.into(), &mut rng))
+        });
+    }
+    group.finish()
+}
+
+/// calculates the cost of a grid after cars have been driving for a while
+fn sim_cost_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sim_cost_bench");
+    let mut size: u32 = 10;
+    for _i in 1..3 {
+        size *= 2;
+        let mut sim = build_sim(size);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            sim.update_all_nodes_with_rng(sim.dt.into(), &mut rng);
+        }
         group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &_size| {
-            b.iter(|| sim.sim_iter())
+            b.iter(|| sim.calculate_sim_cost())
         });
     }
     group.finish()
 }
 
-criterion_group!(benches, simulation_performance_bench);
+criterion_group!(benches, sim_cost_bench, simulation_performance_bench);
 criterion_main!(benches);
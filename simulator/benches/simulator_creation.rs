@@ -13,8 +13,8 @@ fn performance_simulation_creation(c: &mut Criterion) {
         let mut sim_builder = build_grid_sim(size, 100.0);
         let mut mv_server = MovableServer::<PathAwareCar>::new();
         mv_server.register_simulator_builder(&sim_builder);
-        // build once to populate the cache
-        let build = sim_builder.build(&mv_server);
+        // the nodes are built anew each time
+        let build = sim_builder.build(&mv_server).unwrap();
         println!("Build finished with {} nodes", build.nodes.len());
         group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &_size| {
             b.iter(|| sim_builder.build(&mv_server))
@@ -23,5 +23,22 @@ fn performance_simulation_creation(c: &mut Criterion) {
     group.finish()
 }
 
-criterion_group!(benches, performance_simulation_creation);
+/// Builds the simulators of a whole population, like at the start of a simulation
+fn population_creation_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("population_creation_bench");
+    group.sample_size(10);
+    let mut sim_builder = build_grid_sim(4, 100.0);
+    let mut mv_server = MovableServer::<PathAwareCar>::new();
+    mv_server.register_simulator_builder(&sim_builder);
+    group.bench_function("1000 simulators", |b| {
+        b.iter(|| {
+            (0..1000)
+                .map(|_| sim_builder.build(&mv_server).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+    group.finish()
+}
+
+criterion_group!(benches, population_creation_bench, performance_simulation_creation);
 criterion_main!(benches);
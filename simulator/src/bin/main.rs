@@ -1,5 +1,14 @@
-use simulator::{path::PathAwareCar, SimulatorBuilder};
+use simulator::{
+    debug::build_grid_sim,
+    default_nn_topology,
+    path::{MovableServer, PathAwareCar},
+};
 
+/// the number of iterations the example simulation runs for
+const ITERATIONS: usize = 1000;
+
+/// Builds a small grid with [simulator::SimulatorBuilder], simulates it with
+/// random neural networks and prints how many cars got through
 fn main() {
     // NOTE: The logger expects an environment variable called RUST_LOG
     //  for logging the frontend, use RUST_LOG="editor_rs=<level>" (where <level> is either "trace", "debug", "info", "warn" or "error")
@@ -8,6 +17,22 @@ fn main() {
     //  for both: RUST_LOG="editor_rs=<level>,simulator=<level>"
     // pretty_env_logger::init();
     // tracing_subscriber::fmt::init();
-    // let json: &str = r#"{"crossings": [{"traffic_lights": false, "is_io_node": false, "connected": [[1, 15]]}, {"traffic_lights": false, "is_io_node": false, "connected": [[0, 1], [2, 1], [3, 1], [4, 1]]}, {"traffic_lights": false, "is_io_node": false, "connected": [[1, 1], [3, 1], [4, 1], [5, 1]]}, {"traffic_lights": false, "is_io_node": false, "connected": [[2, 1], [1, 1]]}, {"traffic_lights": false, "is_io_node": false, "connected": [[1, 1], [2, 1]]}, {"traffic_lights": false, "is_io_node": true, "connected": [[2, 1]]}]}"#;
-    // let mut _data = SimulatorBuilder::<PathAwareCar>::from_json(json).unwrap();
+    let mut builder = build_grid_sim(3, 10.0);
+    builder.with_dt(1.0);
+    let mut mv_server = MovableServer::<PathAwareCar>::new();
+    mv_server.register_simulator_builder(&builder);
+    let mut sim = builder.build(&mv_server).unwrap();
+    sim.init_neural_networks_random(&default_nn_topology());
+    for _ in 0..ITERATIONS {
+        sim.sim_iter();
+    }
+    let [cost, co2] = sim.calculate_sim_cost();
+    println!(
+        "After {} iterations: {} cars driving, {} cars absorbed, cost {:.2}, co2 {:.2}",
+        ITERATIONS,
+        sim.count_cars(),
+        sim.count_absorbed_cars(),
+        cost,
+        co2
+    );
 }
@@ -121,3 +121,16 @@ pub fn build_grid_sim(grid_side_len: u32, street_len: f32) -> SimulatorBuilder {
     }
     sim
 }
+
+/// Builds a grid (see [build_grid_sim]) in which every IONode spawns a car
+/// each second and every step simulates one second, so the tests quickly
+/// have cars on the streets
+#[cfg(test)]
+pub(crate) fn busy_grid_sim(grid_side_len: u32, street_len: f32) -> SimulatorBuilder {
+    let mut sim = build_grid_sim(grid_side_len, street_len);
+    for (id, _rate) in sim.io_nodes() {
+        sim.set_spawn_rate(id, 1.0).unwrap();
+    }
+    sim.with_dt(1.0);
+    sim
+}
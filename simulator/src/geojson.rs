@@ -0,0 +1,247 @@
+use crate::node_builder::{CrossingBuilder, Direction, IONodeBuilder, NodeBuilder, NodeBuilderTrait};
+use crate::pathfinding::PathAwareCar;
+use crate::simulation_builder::SimulatorBuilder;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use tracing::warn;
+
+/// used to project longitude and latitude onto a plane (in meters)
+const EARTH_RADIUS: f64 = 6_371_000.0;
+/// coordinates are compared after rounding them to this many decimal places
+const COORDINATE_PRECISION: f64 = 1e7;
+
+/// Is returned if a GeoJSON street network can't be imported
+#[derive(Debug, Clone)]
+pub struct GeoJsonError(String);
+
+impl fmt::Display for GeoJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to import GeoJSON: {}", self.0)
+    }
+}
+
+impl Error for GeoJsonError {}
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: Geometry,
+    #[serde(default)]
+    properties: Option<RoadProperties>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    LineString { coordinates: Vec<[f64; 2]> },
+    Point { coordinates: [f64; 2] },
+}
+
+#[derive(Deserialize)]
+struct RoadProperties {
+    #[serde(default = "default_lanes")]
+    lanes: u8,
+    #[serde(default)]
+    oneway: bool,
+}
+
+impl Default for RoadProperties {
+    fn default() -> Self {
+        RoadProperties {
+            lanes: default_lanes(),
+            oneway: false,
+        }
+    }
+}
+
+fn default_lanes() -> u8 {
+    1
+}
+
+/// identifies a position, so roads ending at the same position share a node
+type CoordinateKey = (i64, i64);
+
+fn key(coordinate: &[f64; 2]) -> CoordinateKey {
+    (
+        (coordinate[0] * COORDINATE_PRECISION).round() as i64,
+        (coordinate[1] * COORDINATE_PRECISION).round() as i64,
+    )
+}
+
+/// projects `[longitude, latitude]` onto a plane around the latitude `lat0`
+/// (x points east, y points north, both in meters)
+fn project(coordinate: &[f64; 2], lat0: f64) -> [f64; 2] {
+    [
+        coordinate[0].to_radians() * EARTH_RADIUS * lat0.to_radians().cos(),
+        coordinate[1].to_radians() * EARTH_RADIUS,
+    ]
+}
+
+/// the side of a node a road leaves it at, when going from `from` to `towards`
+fn side(from: &[f64; 2], towards: &[f64; 2]) -> Direction {
+    let dx = towards[0] - from[0];
+    let dy = towards[1] - from[1];
+    if dx.abs() > dy.abs() {
+        if dx > 0.0 {
+            Direction::E
+        } else {
+            Direction::W
+        }
+    } else if dy > 0.0 {
+        Direction::N
+    } else {
+        Direction::S
+    }
+}
+
+impl SimulatorBuilder {
+    /// Imports a street network from a minimal GeoJSON `FeatureCollection`
+    ///
+    /// - `LineString`s are roads with `[longitude, latitude]` coordinates. They
+    ///   have to end at intersections, roads passing through them are not split.
+    ///   The optional properties `lanes` (default 1) and `oneway` (default false,
+    ///   the road can only be driven in the order of its coordinates) are used.
+    /// - `Point`s mark intersections
+    ///
+    /// Crossings are created where roads share an endpoint or where a point
+    /// is, IONodes at the endpoints of only one road. The side of a crossing
+    /// a road is connected to is derived from the bearing of the road.
+    pub fn from_geojson(geojson: &str) -> Result<SimulatorBuilder, GeoJsonError> {
+        let collection: FeatureCollection =
+            serde_json::from_str(geojson).map_err(|err| GeoJsonError(err.to_string()))?;
+        let mut roads = Vec::new();
+        let mut intersections = HashSet::new();
+        for feature in collection.features {
+            match feature.geometry {
+                Geometry::LineString { coordinates } => {
+                    if coordinates.len() < 2 {
+                        return Err(GeoJsonError("a road needs at least 2 coordinates".to_string()));
+                    }
+                    if key(&coordinates[0]) == key(coordinates.last().unwrap()) {
+                        return Err(GeoJsonError("a road can't start and end at the same position".to_string()));
+                    }
+                    roads.push((coordinates, feature.properties.unwrap_or_default()));
+                }
+                Geometry::Point { coordinates } => {
+                    intersections.insert(key(&coordinates));
+                }
+            }
+        }
+        if roads.is_empty() {
+            return Err(GeoJsonError("there are no roads".to_string()));
+        }
+        // project all coordinates around the average latitude
+        let coordinates = roads.iter().flat_map(|(c, _)| c.iter());
+        let lat0 = coordinates.clone().map(|c| c[1]).sum::<f64>() / coordinates.count() as f64;
+        // the number of roads ending at each position
+        let mut degree: HashMap<CoordinateKey, usize> = HashMap::new();
+        for (coordinates, _) in roads.iter() {
+            *degree.entry(key(&coordinates[0])).or_insert(0) += 1;
+            *degree.entry(key(coordinates.last().unwrap())).or_insert(0) += 1;
+        }
+        for point in intersections.iter() {
+            if !degree.contains_key(point) {
+                warn!("Ignoring intersection {:?} that isn't the endpoint of a road", point);
+            }
+        }
+        let mut builder = SimulatorBuilder::<PathAwareCar>::new();
+        let mut node_ids: HashMap<CoordinateKey, usize> = HashMap::new();
+        for (coordinates, properties) in roads.iter() {
+            let projected: Vec<[f64; 2]> = coordinates.iter().map(|c| project(c, lat0)).collect();
+            let n = projected.len();
+            let mut ends = Vec::with_capacity(2);
+            for (coordinate, side) in [
+                (&coordinates[0], side(&projected[0], &projected[1])),
+                (&coordinates[n - 1], side(&projected[n - 1], &projected[n - 2])),
+            ] {
+                let key = key(coordinate);
+                let id = match node_ids.get(&key) {
+                    Some(id) => *id,
+                    None => {
+                        let node = if degree[&key] > 1 || intersections.contains(&key) {
+                            NodeBuilder::Crossing(CrossingBuilder::new())
+                        } else {
+                            NodeBuilder::IONode(IONodeBuilder::new())
+                        };
+                        let id = builder.add_node(node).get().get_id();
+                        node_ids.insert(key, id);
+                        id
+                    }
+                };
+                ends.push((id, side));
+            }
+            let length = projected
+                .windows(2)
+                .map(|w| ((w[1][0] - w[0][0]).powi(2) + (w[1][1] - w[0][1]).powi(2)).sqrt())
+                .sum::<f64>() as f32;
+            let mut connect = |from: (usize, Direction), to: (usize, Direction)| {
+                builder
+                    .connect_with_street(from, to, properties.lanes, length)
+                    .map(|_| ())
+                    .map_err(|err| GeoJsonError(err.to_string()))
+            };
+            connect(ends[0], ends[1])?;
+            if !properties.oneway {
+                connect(ends[1], ends[0])?;
+            }
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_builder::InOut;
+
+    #[test]
+    fn import_two_roads() {
+        // one road going north, turning east at an intersection
+        let geojson = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "geometry": {"type": "LineString", "coordinates": [[16.0, 48.0], [16.0, 48.001]]},
+                    "properties": {"lanes": 2}
+                },
+                {
+                    "type": "Feature",
+                    "geometry": {"type": "LineString", "coordinates": [[16.0, 48.001], [16.0005, 48.001], [16.001, 48.001]]},
+                    "properties": null
+                },
+                {
+                    "type": "Feature",
+                    "geometry": {"type": "Point", "coordinates": [16.0, 48.001]}
+                }
+            ]
+        }"#;
+        let builder = SimulatorBuilder::from_geojson(geojson).unwrap();
+        let count = |f: fn(&NodeBuilder) -> bool| builder.nodes.iter().filter(|n| f(&n.get())).count();
+        assert_eq!(count(|n| matches!(n, NodeBuilder::Crossing(_))), 1);
+        assert_eq!(count(|n| matches!(n, NodeBuilder::IONode(_))), 2);
+        // both roads can be driven in both directions
+        assert_eq!(count(|n| matches!(n, NodeBuilder::Street(_))), 4);
+        let crossing = builder.nodes.iter().find(|n| matches!(&*n.get(), NodeBuilder::Crossing(_))).unwrap();
+        if let NodeBuilder::Crossing(crossing) = &*crossing.get() {
+            // the first road arrives from the south, the second leaves to the east
+            for dir in [Direction::S, Direction::E] {
+                assert!(crossing.has_connection(InOut::IN, dir));
+                assert!(crossing.has_connection(InOut::OUT, dir));
+            }
+        }
+        // about 111 meters per thousandth of a degree of latitude
+        for node in builder.nodes.iter() {
+            if let NodeBuilder::Street(street) = &*node.get() {
+                assert!((street.lane_length - 111.2).abs() < 1.0 || (street.lane_length - 74.4).abs() < 1.0);
+            }
+        }
+        assert!(builder.validate().is_ok());
+    }
+}
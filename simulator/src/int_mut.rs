@@ -1,39 +1,155 @@
 use std::{
     ptr,
-    sync::{Arc, Mutex, MutexGuard, Weak},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, Weak},
 };
+#[cfg(debug_assertions)]
+use std::{
+    any::type_name,
+    thread,
+    time::{Duration, Instant},
+};
+#[cfg(debug_assertions)]
+use tracing::warn;
+
+/// How long [IntMut::get] waits for the lock before reporting a possible deadlock
+/// (only in debug builds)
+#[cfg(debug_assertions)]
+const DEADLOCK_WARN_TIME: Duration = Duration::from_secs(1);
 
 /// This struct implements the interior mutability pattern and
 /// is basically only used to store data and make the access
 /// to it easier
+///
+/// The data is stored in a [RwLock], so any number of threads can
+/// [read](IntMut::read) it at the same time, while [writing](IntMut::write)
+/// requires exclusive access.
 #[derive(Debug)]
 pub struct IntMut<T> {
     /// data
-    data: Arc<Mutex<T>>,
+    data: Arc<RwLock<T>>,
+    /// the id of the node the data belongs to, if any
+    ///
+    /// It is stored outside of the lock, so a possible deadlock can be reported
+    /// with the id of the node (see [IntMut::with_id])
+    id: Option<usize>,
 }
 impl<T> IntMut<T> {
     /// creates a new IntMut
     pub fn new(data: T) -> IntMut<T> {
         IntMut {
-            data: Arc::new(Mutex::new(data)),
+            data: Arc::new(RwLock::new(data)),
+            id: None,
+        }
+    }
+    /// creates a new IntMut for the node with the given id
+    ///
+    /// The id is included in the warning about a possible deadlock in debug builds
+    pub fn with_id(data: T, id: usize) -> IntMut<T> {
+        IntMut {
+            data: Arc::new(RwLock::new(data)),
+            id: Some(id),
+        }
+    }
+    /// returns a [RwLockWriteGuard] to the data (the same as [IntMut::write])
+    ///
+    /// BE CAREFUL: Calling this while already holding a guard to the same data
+    /// deadlocks. In debug builds, a warning is logged if the lock couldn't be
+    /// acquired for a long time.
+    pub fn get(&self) -> RwLockWriteGuard<T> {
+        self.write()
+    }
+    /// returns a [RwLockWriteGuard] to the data, blocking until no other
+    /// guard exists
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        #[cfg(debug_assertions)]
+        if let Some(guard) = self.spin(Self::try_write) {
+            return guard;
+        }
+        (*self.data).write().unwrap()
+    }
+    /// returns a [RwLockReadGuard] to the data, blocking only while a
+    /// [RwLockWriteGuard] exists
+    ///
+    /// Use this for read-only access, so other threads can read at the same time
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        #[cfg(debug_assertions)]
+        if let Some(guard) = self.spin(Self::try_read) {
+            return guard;
+        }
+        (*self.data).read().unwrap()
+    }
+    /// tries to return a [RwLockWriteGuard] to the data without blocking
+    ///
+    /// Returns [None] if the data is currently locked, e.g. because the calling
+    /// code already holds a guard to it and [IntMut::get] would deadlock
+    pub fn try_get(&self) -> Option<RwLockWriteGuard<T>> {
+        self.try_write()
+    }
+    /// tries to return a [RwLockWriteGuard] to the data without blocking
+    /// (see [IntMut::try_get])
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        match (*self.data).try_write() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(err)) => panic!("{}", err),
+        }
+    }
+    /// tries to return a [RwLockReadGuard] to the data without blocking
+    ///
+    /// Returns [None] if a [RwLockWriteGuard] to the data currently exists
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        match (*self.data).try_read() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(err)) => panic!("{}", err),
         }
     }
-    /// returns a [MutexGuard] to the data
-    pub fn get(&self) -> MutexGuard<T> {
-        (*self.data).lock().unwrap()
+    /// retries `try_lock` for [DEADLOCK_WARN_TIME] and logs a warning if the
+    /// lock couldn't be acquired
+    ///
+    /// The lock is usually free, so it is tried once before the clock is read
+    #[cfg(debug_assertions)]
+    fn spin<'a, G>(&'a self, try_lock: impl Fn(&'a Self) -> Option<G>) -> Option<G> {
+        if let Some(guard) = try_lock(self) {
+            return Some(guard);
+        }
+        let start = Instant::now();
+        while start.elapsed() < DEADLOCK_WARN_TIME {
+            match try_lock(self) {
+                Some(guard) => return Some(guard),
+                None => thread::yield_now(),
+            }
+        }
+        match self.id {
+            Some(id) => warn!(
+                "Possible deadlock: waited more than {:?} for the lock of node {}",
+                DEADLOCK_WARN_TIME, id
+            ),
+            None => warn!(
+                "Possible deadlock: waited more than {:?} for IntMut<{}> at {:p}",
+                DEADLOCK_WARN_TIME,
+                type_name::<T>(),
+                Arc::as_ptr(&self.data)
+            ),
+        }
+        None
     }
     /// returns a [WeakIntMut] by calling downgrade on the internal Arc
     pub fn downgrade(&self) -> WeakIntMut<T> {
         WeakIntMut {
             data: Arc::downgrade(&self.data),
+            id: self.id,
         }
     }
 }
 impl<T: Clone> IntMut<T> {
     /// deep copy of the IntMut
     pub fn deep_copy(&self) -> IntMut<T> {
-        let new_data = (*self.get()).clone();
-        IntMut::new(new_data)
+        let new_data = (*self.read()).clone();
+        IntMut {
+            data: Arc::new(RwLock::new(new_data)),
+            id: self.id,
+        }
     }
 }
 impl<T> Clone for IntMut<T> {
@@ -41,6 +157,7 @@ impl<T> Clone for IntMut<T> {
     fn clone(&self) -> Self {
         Self {
             data: Arc::clone(&self.data),
+            id: self.id,
         }
     }
 }
@@ -76,7 +193,9 @@ impl<T> Clone for IntMut<T> {
 #[derive(Debug, Clone)]
 pub struct WeakIntMut<T> {
     /// data
-    data: Weak<Mutex<T>>,
+    data: Weak<RwLock<T>>,
+    /// see [IntMut::id]
+    id: Option<usize>,
 }
 impl<T> WeakIntMut<T> {
     /// Upgrades the reference to a strong referenced IntMut
@@ -86,6 +205,7 @@ impl<T> WeakIntMut<T> {
     pub fn upgrade(&self) -> IntMut<T> {
         IntMut {
             data: self.data.upgrade().unwrap(),
+            id: self.id,
         }
     }
     /// Tries to upgrade the reference to an IntMut
@@ -96,13 +216,14 @@ impl<T> WeakIntMut<T> {
         match self.data.upgrade() {
             Some(upgraded_data) => Some(IntMut {
                 data: upgraded_data,
+                id: self.id,
             }),
             None => None,
         }
     }
 
     /// calls as_ptr on the inner data and returns the result
-    pub fn data_as_ptr(&self) -> *const Mutex<T> {
+    pub fn data_as_ptr(&self) -> *const RwLock<T> {
         self.data.as_ptr()
     }
 }
@@ -162,6 +283,29 @@ mod tests {
         assert_ne!(a, wb);
         assert_ne!(wa, b);
     }
+    #[test]
+    fn try_get_does_not_block() {
+        use super::IntMut;
+        let a = IntMut::new(1);
+        let guard = a.get();
+        // locking again would deadlock
+        assert!(a.try_get().is_none());
+        drop(guard);
+        assert_eq!(*a.try_get().unwrap(), 1);
+    }
+    #[test]
+    fn reads_do_not_block_each_other() {
+        use super::IntMut;
+        let a = IntMut::new(1);
+        let first = a.read();
+        let second = a.try_read();
+        assert_eq!(*second.unwrap(), 1);
+        // writing has to wait for the readers
+        assert!(a.try_write().is_none());
+        drop(first);
+        *a.write() = 2;
+        assert_eq!(*a.read(), 2);
+    }
     //#[test]
     //fn deref_intmut() {
     //    use super::IntMut;
@@ -10,6 +10,8 @@ pub mod debug {
     pub use super::build_grid::*;
 }
 
+/// imports street networks from GeoJSON
+mod geojson;
 /// wrapper for interior mutability
 mod int_mut;
 /// logic for cars and pedestrians
@@ -20,6 +22,8 @@ mod node;
 mod node_builder;
 /// is responsible for calculating paths through the street network
 mod pathfinding;
+/// simple traffic light controllers that can be compared with the neural networks
+mod traffic_controller;
 /// top level struct used for managing Simulation, SimulationManager, MovableServer
 mod sim_manager;
 /// used for simulating a street network
@@ -28,26 +32,50 @@ mod simulation;
 mod simulation_builder;
 /// provides logic to move cars and pedestrians
 mod traversible;
+/// records simulation runs to replay them later
+mod recording;
 // reexport
 pub mod nodes {
     //! importing the different node types
     pub use crate::node::*;
     pub use crate::node_builder::*;
 }
+pub mod controllers {
+    //! Traffic light controllers that can be used instead of a neural network
+    pub use crate::traffic_controller::{FixedTimerController, InvalidPhaseDurationError, RoundRobin};
+    pub use crate::traits::TrafficController;
+}
 pub mod path {
     //! Crate for the Car and Movableserver
-    pub use crate::pathfinding::{MovableServer, PathAwareCar};
+    pub use crate::pathfinding::{MovableServer, PathAwareCar, RoutingWeights};
 }
 
-pub use sim_manager::SimManager;
+pub use sim_manager::{
+    default_nn_topology, validate_nn_topology, FitnessMode, InvalidTopologyError, SimManager, StatusUpdate,
+    StopCondition,
+};
 
 pub mod datastructs {
     //! The most important data saving structs needed
     pub use crate::int_mut::{IntMut, WeakIntMut};
     pub use crate::movable::MovableStatus;
 }
-pub use simulation::Simulator;
-pub use simulation_builder::SimulatorBuilder;
+pub use simulation::{NeuralNetworkError, SimStats, Simulator};
+pub use recording::{Frame, Recorder, Recording, RecordingError, Replayer};
+pub use simulation_builder::{GraphError, SimulatorBuilder, SpawnRateError};
+pub use geojson::GeoJsonError;
 
 /// TODO: Make this unique for different cars
-const CAR_SPACING: f32 = 3.0;
\ No newline at end of file
+const CAR_SPACING: f32 = 3.0;
+/// The speed limit of new streets
+///
+/// It is high enough to not slow down any car
+pub const DEFAULT_SPEED_LIMIT: f32 = 1000.0;
+/// How many cars new IONodes spawn per step at most
+///
+/// Further cars are spawned in the following steps
+pub const DEFAULT_MAX_SPAWN_PER_STEP: usize = 1;
+/// Streets shorter than this are treated as if they had this length
+///
+/// A length of zero would lead to divisions by zero when calculating costs
+const MIN_LANE_LENGTH: f32 = 1.0;
\ No newline at end of file
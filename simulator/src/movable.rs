@@ -2,7 +2,8 @@ use crate::datastructs::IntMut;
 
 use super::{int_mut::WeakIntMut, node::Node, traits::Movable};
 use rand::Rng;
-use std::{error::Error, sync::MutexGuard};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
 
 /// A person that takes turn at random
 #[derive(Debug, Clone)]
@@ -52,12 +53,14 @@ pub struct RandCar {
     current_speed: f32,
     speed: f32,
     id: u32,
+    /// see [Movable::fractional_position]
+    position: f32,
 }
 
 impl RandCar {
     /// returns a car with default speed
     pub fn new() -> RandCar {
-        RandCar { id: 0, speed: 2.0, current_speed: 0.0}
+        RandCar { id: 0, speed: 2.0, current_speed: 0.0, position: 0.0 }
     }
 }
 
@@ -87,24 +90,36 @@ impl Movable for RandCar {
     }
 
     fn new() -> Self {
-        RandCar { speed: 0.0, id: 0, current_speed: 0.0}
+        RandCar { speed: 0.0, id: 0, current_speed: 0.0, position: 0.0 }
     }
 
     fn set_current_speed(&mut self, cs: f32) {
         self.current_speed = cs
     }
+
+    fn fractional_position(&self) -> f32 {
+        self.position
+    }
+
+    fn set_fractional_position(&mut self, position: f32) {
+        self.position = position
+    }
 }
 
 /// This struct encapsulates data for a [Movable] (to render it later)
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MovableStatus {
     /// the Movable's position on the street (crossings and ionodes are not supported yet) as float
-    /// between 0 and 1
+    /// between 0 and 1 (see [Movable::fractional_position])
     pub position: f32,
     /// random index that is used differently by different nodes
     pub lane_index: u8,
     /// each movable has a unique id
     pub movable_id: u32,
     /// should the node be deleted?
-    pub delete: bool
+    pub delete: bool,
+    /// the id of the node the Movable drives to next, if it is known
+    pub target_node_id: Option<usize>,
+    /// the current speed of the Movable (0 if it is waiting)
+    pub speed: f32,
 }
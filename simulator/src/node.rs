@@ -1,18 +1,20 @@
 use super::int_mut::{IntMut, WeakIntMut};
 use super::movable::RandCar;
-use super::node_builder::{CrossingConnections, Direction, InOut};
+use super::node_builder::{CrossingConnections, Direction, IONodeRole, InOut};
 use super::traversible::Traversible;
 use crate::movable::MovableStatus;
 use crate::pathfinding::MovableServer;
 use crate::simulation::calculate_cost;
-use crate::traits::{CarReport, Movable, NodeTrait};
+use crate::traffic_controller::MissingController;
+use crate::traits::{CarReport, Movable, NodeTrait, TrafficController};
 use art_int;
 use rand::Rng;
-use rand::prelude::ThreadRng;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
 use std::error::Error;
 use std::ptr;
@@ -44,14 +46,11 @@ impl<Car: Movable> NodeTrait<Car> for Node<Car> {
             .find(|n| *n == other)
             .is_some()
     }
-    fn update_cars(&mut self, t: f64, mv_server: &mut MovableServer<Car>, rng: &mut ThreadRng) -> Vec<usize> {
+    fn update_cars(&mut self, t: f64, mv_server: &mut MovableServer<Car>, rng: &mut dyn RngCore, cars_at_end: &mut Vec<usize>) {
         match self {
-            Node::Street(street) => street.update_movables(t),
-            Node::IONode(io_node) => io_node.update_cars(t, mv_server, rng),
-            Node::Crossing(crossing) => {
-                crossing.traffic_light_state = crossing.determine_traffic_light_state().expect("Error when determining traffic light state");
-                crossing.car_lane.update_movables(t as f32)
-            },
+            Node::Street(street) => street.update_movables_into(t, cars_at_end),
+            Node::IONode(io_node) => io_node.update_cars(t, mv_server, rng, cars_at_end),
+            Node::Crossing(crossing) => crossing.update_cars(t as f32, cars_at_end),
         }
     }
 
@@ -65,9 +64,28 @@ impl<Car: Movable> NodeTrait<Car> for Node<Car> {
         }
     }
 
+    fn extend_out_connections(&self, out: &mut Vec<WeakIntMut<Node<Car>>>) {
+        match self {
+            Node::Street(street) => out.extend(street.conn_out.iter().cloned()),
+            Node::IONode(io_node) => out.extend(io_node.connections.iter().cloned()),
+            Node::Crossing(crossing) => out.extend(
+                crossing.connections.iter(InOut::OUT).map(|(_dir, c)| c.clone())
+            ),
+        }
+    }
+
     fn add_car(&mut self, car: Car) {
         match self {
-            Node::Street(street) => street.add_movable(car),
+            Node::Street(street) => {
+                // the car only got here by leaving its route
+                if let Err(car) = street.add_movable(car) {
+                    error!(
+                        "No lane of street {} permits the turn of car {}, so it is removed",
+                        street.id,
+                        car.get_id()
+                    );
+                }
+            }
             Node::IONode(io_node) => io_node.add_car(car),
             Node::Crossing(crossing) => crossing.car_lane.add(car),
         }
@@ -122,7 +140,7 @@ impl<Car: Movable> NodeTrait<Car> for Node<Car> {
         }
     }
 
-    fn get_car_by_index(&mut self, i: usize) -> &Car {
+    fn get_car_by_index(&self, i: usize) -> &Car {
         match self {
             Node::Street(street) => street.get_car_by_index(i),
             Node::IONode(ionode) => ionode.cached.get(&i).unwrap(),
@@ -133,15 +151,23 @@ impl<Car: Movable> NodeTrait<Car> for Node<Car> {
     fn reset_cars(&mut self) -> Vec<MovableStatus> {
         match self {
             Node::Street(s) => s.lanes.iter_mut().flat_map(| l | l.reset()).collect(),
-            Node::IONode(node) => {node.cached = HashMap::new(); node.num_cars_spawned = 0; node.total_cost = [0.0; 2]; node.recorded_cars.drain(..).map( | c | {
+            Node::IONode(node) => {node.cached = HashMap::new(); node.num_cars_spawned = 0; node.deferred_spawns = 0; node.absorbed_cars = 0; node.total_cost = [0.0; 2]; node.recorded_cars.drain(..).map( | c | {
                 MovableStatus {
                     position: 0.0,
                     lane_index: 0,
                     movable_id: c.get_id(),
                     delete: true,
+                    target_node_id: None,
+                    speed: 0.0,
                 }
             }).collect()},
-            Node::Crossing(node) => node.car_lane.reset(),
+            Node::Crossing(node) => {
+                node.time_in_current_phase = 0.0;
+                node.clearance_remaining = 0.0;
+                node.phase_switches = 0;
+                node.max_queue_lengths = [0; 4];
+                node.car_lane.reset()
+            },
         }
     }
 
@@ -155,15 +181,28 @@ impl<Car: Movable> NodeTrait<Car> for Node<Car> {
 
     fn get_target_id_of_car_at_end(&self) -> Option<usize> {
         match self {
-            Node::Street(street) => street.lanes[0].get_target_id_of_movable_at_end(),
+            Node::Street(street) => street.lanes.iter().find_map(| l | l.get_target_id_of_movable_at_end()),
             Node::IONode(node) => None,
             Node::Crossing(crossing) => crossing.car_lane.get_target_id_of_movable_at_end(),
         }
     }
 }
 
+/// The number of inputs of the neural network of a crossing
+/// (see [Crossing::calculate_nn_inputs])
+pub const NN_INPUTS: usize = 16;
+/// The number of outputs of the neural network of a crossing, one for each
+/// [TrafficLightState]
+pub const NN_OUTPUTS: usize = 4;
+/// The minimum time (in seconds) a traffic light state is kept before the
+/// neural network may choose another one
+pub const MIN_PHASE_DURATION: f32 = 5.0;
+/// The time (in seconds) after a change of the traffic light state in which
+/// no car may enter the crossing, so it can be cleared
+pub const AMBER_DURATION: f32 = 2.0;
+
 /// The state of a traffic light (ampelstatus)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TrafficLightState {
     /// State 0
     S0,
@@ -174,6 +213,109 @@ pub enum TrafficLightState {
     /// State 3
     S3,
 }
+impl TrafficLightState {
+    /// all states, in the order of the outputs of the neural network
+    pub const ALL: [TrafficLightState; NN_OUTPUTS] = [
+        TrafficLightState::S0,
+        TrafficLightState::S1,
+        TrafficLightState::S2,
+        TrafficLightState::S3,
+    ];
+    /// the input sides of a crossing that are allowed to drive in this state
+    pub fn green_inputs(&self) -> [Direction; 2] {
+        match self {
+            TrafficLightState::S0 | TrafficLightState::S2 => [Direction::N, Direction::S],
+            TrafficLightState::S1 | TrafficLightState::S3 => [Direction::W, Direction::E],
+        }
+    }
+    /// true if cars coming from the side `input_node_dir` may leave the crossing
+    /// on the side `output_node_dir` in this state
+    ///
+    /// (see [Crossing::can_out_node_be_reached] for a drawing of the states)
+    pub fn allows(&self, input_node_dir: Direction, output_node_dir: Direction) -> bool {
+        match self {
+            TrafficLightState::S0 => {
+                if input_node_dir == Direction::N {
+                    if output_node_dir == Direction::S || output_node_dir == Direction::W {
+                        return true;
+                    } else {
+                        return false;
+                    }
+                } else if input_node_dir == Direction::S {
+                    if output_node_dir == Direction::N || output_node_dir == Direction::E {
+                        return true;
+                    } else {
+                        return false;
+                    }
+                } else {
+                    return false;
+                }
+            }
+            TrafficLightState::S1 => {
+                if input_node_dir == Direction::W {
+                    if output_node_dir == Direction::S || output_node_dir == Direction::E {
+                        return true;
+                    } else {
+                        return false;
+                    }
+                } else if input_node_dir == Direction::E {
+                    if output_node_dir == Direction::W || output_node_dir == Direction::N {
+                        return true;
+                    } else {
+                        return false;
+                    }
+                } else {
+                    return false;
+                }
+            }
+            TrafficLightState::S2 => {
+                if input_node_dir == Direction::N {
+                    if output_node_dir == Direction::E {
+                        return true;
+                    } else {
+                        return false;
+                    }
+                } else if input_node_dir == Direction::S {
+                    if output_node_dir == Direction::W {
+                        return true;
+                    } else {
+                        return false;
+                    }
+                } else {
+                    return false;
+                }
+            }
+            TrafficLightState::S3 => {
+                if input_node_dir == Direction::W {
+                    if output_node_dir == Direction::N {
+                        return true;
+                    } else {
+                        return false;
+                    }
+                } else if input_node_dir == Direction::E {
+                    if output_node_dir == Direction::S {
+                        return true;
+                    } else {
+                        return false;
+                    }
+                } else {
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+/// The live state of a crossing, e.g. to display it in the frontend
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossingSnapshot {
+    /// the current traffic light state
+    pub traffic_light_state: TrafficLightState,
+    /// time since the traffic light state last changed
+    pub time_in_current_phase: f32,
+    /// the number of cars currently waiting at each input `[N, E, S, W]`
+    pub queue_lengths: [u32; 4],
+}
 
 /// A simple crossing
 #[derive(Debug, Clone)]
@@ -198,8 +340,25 @@ where
     ///  
     /// for further explanation, look at the method `calculate_nn_inputs`
     pub time_since_input_passable: [f32; 4],
-    /// the NN used to determine the traffic light state at each iteration
-    pub nn: Option<art_int::Network>,
+    /// determines the traffic light state (usually the NN given by the genetic algorithm)
+    pub controller: Box<dyn TrafficController>,
+    /// time since the traffic light state last changed
+    pub time_in_current_phase: f32,
+    /// the remaining time of the amber clearance interval, in which no car
+    /// may enter the crossing
+    pub clearance_remaining: f32,
+    /// how often the traffic light state has changed (used to penalize
+    /// rapid switching in the cost)
+    pub phase_switches: usize,
+    /// the longest queue at each input since the cars were last reset
+    ///
+    /// `[N, E, S, W]`
+    pub max_queue_lengths: [u32; 4],
+    /// if set, the state is sampled from the outputs of the neural network
+    /// (SoftMax with this temperature) instead of choosing the highest output
+    ///
+    /// Other controllers ignore it
+    pub exploration_temperature: Option<f32>,
 }
 impl<Car: Movable> Crossing<Car> {
     /// Returns a new Crossing with no connections and id=0
@@ -210,7 +369,52 @@ impl<Car: Movable> Crossing<Car> {
             id: 0,
             traffic_light_state: TrafficLightState::S0,
             time_since_input_passable: [0.0; 4],
-            nn: None,
+            controller: Box::new(MissingController::new(0)),
+            time_in_current_phase: 0.0,
+            clearance_remaining: 0.0,
+            phase_switches: 0,
+            max_queue_lengths: [0; 4],
+            exploration_temperature: None,
+        }
+    }
+    /// returns the current state of the crossing
+    pub fn snapshot(&self) -> CrossingSnapshot {
+        CrossingSnapshot {
+            traffic_light_state: self.traffic_light_state.clone(),
+            time_in_current_phase: self.time_in_current_phase,
+            queue_lengths: self.queue_lengths(),
+        }
+    }
+    /// returns the number of cars waiting at the end of the input streets of each side
+    ///
+    /// `[N, E, S, W]`, the queues of all input streets of a side are added up.
+    /// Sides without an input street have a queue length of 0
+    pub fn queue_lengths(&self) -> [u32; 4] {
+        let mut queues = [0; 4];
+        for (i, dir) in [Direction::N, Direction::E, Direction::S, Direction::W].iter().enumerate() {
+            for conn in self.connections.get_all(InOut::IN, *dir) {
+                // only read, see `calculate_nn_inputs`
+                if let Node::Street(street) = &*conn.upgrade().read() {
+                    queues[i] += street.get_num_cars_at_end();
+                }
+            }
+        }
+        queues
+    }
+    /// updates the traffic light state and moves the cars over the crossing
+    ///
+    /// The indices of the cars that reached the end are appended to `cars_at_end`
+    pub fn update_cars(&mut self, t: f32, cars_at_end: &mut Vec<usize>) {
+        self.record_queue_lengths();
+        self.update_traffic_light_state(t).expect("Error when determining traffic light state");
+        // usually empty, so nothing is allocated
+        cars_at_end.extend(self.car_lane.update_movables(t))
+    }
+    /// updates `max_queue_lengths` with the current queue lengths
+    pub fn record_queue_lengths(&mut self) {
+        let queues = self.queue_lengths();
+        for (max, queue) in self.max_queue_lengths.iter_mut().zip(queues.iter()) {
+            *max = (*max).max(*queue);
         }
     }
     /// calculates the inputs for the neural network controlling the traffic light state
@@ -220,18 +424,21 @@ impl<Car: Movable> Crossing<Car> {
     /// 3. What direction do the cars want to go to?
     ///
     /// If there is no street, the time and number of cars is set to 0.0
-    pub fn calculate_nn_inputs(&self) -> [f32; 16] {
-        let mut cars_at_end = [0.0f32; 16];
+    pub fn calculate_nn_inputs(&self) -> [f32; NN_INPUTS] {
+        let mut cars_at_end = [0.0f32; NN_INPUTS];
 
         let mut i = 0;
-        let map_output_id_to_dir_index: HashMap<usize, Direction> = self.connections.output.iter().map(| (dir, conn) | {
-            (conn.upgrade().get().id(), *dir)
+        // the connected streets are only read. While the crossings are updated, no
+        //  street is locked for writing (see `Simulator::update_all_nodes_parallel`), so
+        //  this only blocks while another thread checks the type of the street
+        let map_output_id_to_dir_index: HashMap<usize, Direction> = self.connections.iter(InOut::OUT).map(| (dir, conn) | {
+            (conn.upgrade().read().id(), dir)
         }).collect();
         for dir in [Direction::N, Direction::E, Direction::S, Direction::W] {
-            if let Some(conn) = self.connections.input.get(&dir) {
-                let node_id = conn.upgrade().get().get_target_id_of_car_at_end();
-                if let Some(id) = node_id {
-                    let dir_out = map_output_id_to_dir_index[&id];
+            // every input street of the side can have a car waiting
+            for conn in self.connections.get_all(InOut::IN, dir) {
+                let node_id = conn.upgrade().read().get_target_id_of_car_at_end();
+                if let Some(dir_out) = node_id.and_then(|id| map_output_id_to_dir_index.get(&id)) {
                     let offset = match dir_out {
                         Direction::N => 0,
                         Direction::E => 1,
@@ -251,38 +458,97 @@ impl<Car: Movable> Crossing<Car> {
     /// Is used to set the NN given by the genetic algorithm
     pub fn set_neural_network(&mut self, nn: art_int::Network) {
         // make sure the input has the right size
-        assert_eq!(nn.layers[0].neurons[0].weights.len(), 16);
-        self.nn = Some(nn);
-    }
-    /// computes the traffic light state using the neural network
-    pub fn determine_traffic_light_state(&self) -> Result<TrafficLightState, &'static str> {
-        let nn_input = self.calculate_nn_inputs();
-        // the output should be a value between 0 and 1 where 0.25 is state 0, 0.5 is state 1 and so on
-        let nn_output = match &self.nn {
-            Some(nn) => {
-                let out_vec = nn.propagate(nn_input.into());
-                //let out = out_vec.get(0);
-                //out.map(|op| *op).ok_or("NN has no output!")?
-                out_vec
+        assert_eq!(nn.topology()[0].neurons, NN_INPUTS);
+        self.controller = Box::new(nn);
+    }
+    /// replaces the controller that determines the traffic light state
+    pub fn set_controller(&mut self, controller: Box<dyn TrafficController>) {
+        self.controller = controller;
+    }
+    /// asks the controller for the next traffic light state
+    pub fn determine_traffic_light_state(&mut self) -> Result<TrafficLightState, &'static str> {
+        let inputs = self.calculate_nn_inputs();
+        // crossings with missing sides (e.g. T-junctions) only choose states that let
+        //  cars drive between connected sides. If no state does, any may be chosen
+        let available: Vec<usize> = (0..NN_OUTPUTS)
+            .filter(| i | self.serves_connected_sides(&TrafficLightState::ALL[*i]))
+            .collect();
+        let i = match (self.exploration_temperature, self.controller.network()) {
+            (Some(temperature), Some(nn)) => {
+                Self::sample_state(nn, inputs.to_vec(), temperature, &available, &mut rand::thread_rng())
             }
-            None => return Err("cannot determine traffic state without NeuralNetwork"),
+            _ => self.controller.next_available_state(&inputs, NN_OUTPUTS, &available),
         };
-        let i = nn_output.iter().enumerate().max_by(| (_, a), (_, b) | a.partial_cmp(b).unwrap_or(Ordering::Equal)).unwrap().0;
-        Ok(
-            match i {
-                0 => TrafficLightState::S0,
-                1 => TrafficLightState::S1,
-                2 => TrafficLightState::S2,
-                3 => TrafficLightState::S3,
-                _ => {warn!("NN returned strange index ({})", i); return Err("Weird index")},
+        match TrafficLightState::ALL.get(i) {
+            Some(state) => Ok(state.clone()),
+            None => {warn!("Controller returned strange index ({})", i); Err("Weird index")},
+        }
+    }
+
+    /// samples an available state proportionally to the outputs of `nn` with the
+    /// given SoftMax temperature
+    fn sample_state<R: Rng + ?Sized>(
+        nn: &art_int::Network,
+        inputs: Vec<f32>,
+        temperature: f32,
+        available: &[usize],
+        rng: &mut R,
+    ) -> usize {
+        let mut probabilities = nn.propagate_with_temperature(inputs, temperature);
+        probabilities.truncate(NN_OUTPUTS);
+        for (i, p) in probabilities.iter_mut().enumerate() {
+            if !available.is_empty() && !available.contains(&i) {
+                *p = 0.0;
             }
-        )
+        }
+        match WeightedIndex::new(&probabilities) {
+            Ok(dist) => dist.sample(rng),
+            // all available states are too unlikely to be represented as f32
+            Err(_) => available.first().copied().unwrap_or(0),
+        }
+    }
+
+    /// advances the phase timers by `dt` and lets the controller choose a
+    /// new traffic light state once [MIN_PHASE_DURATION] has passed
+    ///
+    /// Changing the state starts an amber clearance interval of [AMBER_DURATION]
+    pub fn update_traffic_light_state(&mut self, dt: f32) -> Result<(), &'static str> {
+        self.time_in_current_phase += dt;
+        self.clearance_remaining = (self.clearance_remaining - dt).max(0.0);
+        self.controller.advance(dt);
+        if self.time_in_current_phase < MIN_PHASE_DURATION {
+            return Ok(());
+        }
+        let state = self.determine_traffic_light_state()?;
+        if state != self.traffic_light_state {
+            self.traffic_light_state = state;
+            self.time_in_current_phase = 0.0;
+            self.clearance_remaining = AMBER_DURATION;
+            self.phase_switches += 1;
+        }
+        Ok(())
+    }
+
+    /// true if the state lets cars drive from at least one connected input to
+    /// a connected output
+    pub fn serves_connected_sides(&self, state: &TrafficLightState) -> bool {
+        self.connections.iter(InOut::IN).any(| (dir_in, _) | {
+            self.connections
+                .iter(InOut::OUT)
+                .any(| (dir_out, _) | state.allows(dir_in, dir_out))
+        })
     }
 
     /// removes the neural network and returns it
+    ///
+    /// The crossing falls back to cycling through the states until it gets a
+    /// new network (see [crate::controllers::RoundRobin])
     pub fn remove_neural_network(&mut self) -> Result<art_int::Network, &'static str> {
-        let nn = self.nn.take();
-        nn.ok_or("No neural network to remove!")
+        if self.controller.network().is_none() {
+            return Err("No neural network to remove!");
+        }
+        let controller = std::mem::replace(&mut self.controller, Box::new(MissingController::new(self.id)));
+        controller.into_network().ok_or("No neural network to remove!")
     }
 
     /// Returns a list of only OUTPUT connecitons
@@ -290,9 +556,8 @@ impl<Car: Movable> Crossing<Car> {
     /// This function is deprecated and will be removed soon
     pub fn get_out_connections(&self) -> Vec<WeakIntMut<Node<Car>>> {
         self.connections
-            .output
-            .values()
-            .map(|c| c.clone())
+            .iter(InOut::OUT)
+            .map(|(_dir, c)| c.clone())
             .collect()
     }
     /// Tries to add a connections at the specified position and raises
@@ -353,6 +618,8 @@ impl<Car: Movable> Crossing<Car> {
     ///       v
     ///       S
     /// ```
+    ///
+    /// During the amber clearance interval no out node can be reached
     pub fn can_out_node_be_reached(
         &self,
         in_node: &IntMut<Node<Car>>,
@@ -366,77 +633,7 @@ impl<Car: Movable> Crossing<Car> {
             .connections
             .get_direction_for_item(InOut::OUT, out_node)
             .expect("Crossing doesn't seem to be connected with street (output)");
-        // funky stuff here
-        match self.traffic_light_state {
-            TrafficLightState::S0 => {
-                if input_node_dir == Direction::N {
-                    if output_node_dir == Direction::S || output_node_dir == Direction::W {
-                        return true;
-                    } else {
-                        return false;
-                    }
-                } else if input_node_dir == Direction::S {
-                    if output_node_dir == Direction::N || output_node_dir == Direction::E {
-                        return true;
-                    } else {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-            TrafficLightState::S1 => {
-                if input_node_dir == Direction::W {
-                    if output_node_dir == Direction::S || output_node_dir == Direction::E {
-                        return true;
-                    } else {
-                        return false;
-                    }
-                } else if input_node_dir == Direction::E {
-                    if output_node_dir == Direction::W || output_node_dir == Direction::N {
-                        return true;
-                    } else {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-            TrafficLightState::S2 => {
-                if input_node_dir == Direction::N {
-                    if output_node_dir == Direction::E {
-                        return true;
-                    } else {
-                        return false;
-                    }
-                } else if input_node_dir == Direction::S {
-                    if output_node_dir == Direction::W {
-                        return true;
-                    } else {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-            TrafficLightState::S3 => {
-                if input_node_dir == Direction::W {
-                    if output_node_dir == Direction::N {
-                        return true;
-                    } else {
-                        return false;
-                    }
-                } else if input_node_dir == Direction::E {
-                    if output_node_dir == Direction::S {
-                        return true;
-                    } else {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-        }
+        self.clearance_remaining <= 0.0 && self.traffic_light_state.allows(input_node_dir, output_node_dir)
     }
 }
 
@@ -459,6 +656,13 @@ where
     pub connections: Vec<WeakIntMut<Node<Car>>>,
     /// new Cars/Second
     pub spawn_rate: f64,
+    /// whether the node spawns cars, absorbs them or both
+    pub role: IONodeRole,
+    /// how many cars are spawned in a single step at most
+    pub max_spawn_per_step: usize,
+    /// cars that should have been spawned already, but were deferred because
+    /// of `max_spawn_per_step`
+    pub deferred_spawns: usize,
     /// parameters for calculating the cost
     pub cost_calc_params: CostCalcParameters,
     /// Tracks how many cars have reached their destination in this node
@@ -474,9 +678,15 @@ where
     /// delete them if get_car_status is called
     pub record: bool,
     pub num_cars_spawned: usize,
-    /// the cars that have been recorded
-    pub recorded_cars: Vec<Car>
+    /// the cars that have been recorded, at most [MAX_RECORDED_CARS]
+    pub recorded_cars: VecDeque<Car>,
+    /// the number of recorded cars that were dropped, because get_car_status
+    /// wasn't called often enough
+    pub dropped_recorded_cars: usize,
 }
+
+/// the number of absorbed cars an [IONode] keeps while recording, older ones are dropped
+pub const MAX_RECORDED_CARS: usize = 1000;
 impl<Car> IONode<Car>
 where
     Car: Movable,
@@ -486,6 +696,9 @@ where
         Self {
             connections: Vec::new(),
             spawn_rate: 0.01,
+            role: IONodeRole::default(),
+            max_spawn_per_step: crate::DEFAULT_MAX_SPAWN_PER_STEP,
+            deferred_spawns: 0,
             absorbed_cars: 0,
             total_cost: [0.0, 0.0],
             id: 0,
@@ -494,7 +707,8 @@ where
                 speed_to_co2: 0.5,
             },
             record: false,
-            recorded_cars: Vec::new(),
+            recorded_cars: VecDeque::new(),
+            dropped_recorded_cars: 0,
             num_cars_spawned: 0
         }
     }
@@ -511,6 +725,8 @@ where
                 lane_index: 0,
                 movable_id: car.get_id(),
                 delete: true,
+                target_node_id: None,
+                speed: 0.0,
             }
         }).collect()
     }
@@ -522,25 +738,40 @@ where
         self.total_cost[0] += cost;
         self.total_cost[1] += co2;
         if self.record {
-            self.recorded_cars.push(car);
+            if self.recorded_cars.len() >= MAX_RECORDED_CARS {
+                self.recorded_cars.pop_front();
+                self.dropped_recorded_cars += 1;
+            }
+            self.recorded_cars.push_back(car);
         }
     }
 
-    /// sets self.record
+    /// sets self.record, the recorded cars are dropped if recording is disabled
     pub fn set_car_recording(&mut self, record: bool) {
         self.record = record;
+        if !record {
+            self.recorded_cars.clear();
+        }
     }
 
     /// is responsible for spawning new cars if a time is reached
-    pub fn update_cars(&mut self, dt: f64, mv_server: &mut MovableServer<Car>, rng: &mut ThreadRng) -> Vec<usize> {
-        // create new car
-        let mut new_cars = Vec::<usize>::new();
-        // TODO: rework spawn rate
-        if rng.gen_bool(self.spawn_rate*dt) {
-            // TODO: Remove and replace with proper request to
-            //  the movable server
-            // new_cars.push(Car::new())
-            let car_result = mv_server.generate_movable(self.id);
+    ///
+    /// On average `spawn_rate * dt` cars are due each step, but at most
+    /// `max_spawn_per_step` are spawned, the rest is deferred to the following steps
+    ///
+    /// The indices of the new cars are appended to `new_cars`
+    pub fn update_cars(&mut self, dt: f64, mv_server: &mut MovableServer<Car>, rng: &mut dyn RngCore, new_cars: &mut Vec<usize>) {
+        if !self.role.spawns() {
+            return;
+        }
+        // the whole part of the expected cars is always spawned, the
+        //  fractional part with the according probability
+        let expected = (self.spawn_rate * dt).max(0.0);
+        self.deferred_spawns += expected.trunc() as usize + rng.gen_bool(expected.fract()) as usize;
+        let to_spawn = self.deferred_spawns.min(self.max_spawn_per_step);
+        self.deferred_spawns -= to_spawn;
+        for _ in 0..to_spawn {
+            let car_result = mv_server.generate_movable_with_rng(self.id, rng);
             match car_result {
                 Ok(car) => {
                     self.cached.insert(self.num_cars_spawned, car);
@@ -552,7 +783,6 @@ where
                 },
             }
         }
-        new_cars
     }
 }
 
@@ -574,6 +804,10 @@ where
     pub lanes: Vec<Traversible<Car>>,
     /// The index in the simulation
     pub id: usize,
+    /// the outputs of the next crossing each lane may feed
+    ///
+    /// `None` means that any lane can be used for any turn
+    pub lane_turns: Option<Vec<HashSet<Direction>>>,
 }
 
 impl<Car: Movable> Street<Car> {
@@ -584,6 +818,7 @@ impl<Car: Movable> Street<Car> {
             conn_in: None,
             lanes: vec![Traversible::<Car>::new(100.0)],
             id: 0,
+            lane_turns: None,
         }
     }
     /// Connects a node at the specifed position. If a node is already
@@ -625,15 +860,77 @@ impl<Car: Movable> Street<Car> {
     /// * Step 3: 2 - 4 < 0, so the offset is the number of movables on the previous two lanes
     ///  and the movable is on this lane (lane 2). The index in the lane is 2
     pub fn update_movables(&mut self, t: f64) -> Vec<usize> {
-        let mut offset = 0;
         let mut movables = Vec::new();
+        self.update_movables_into(t, &mut movables);
+        movables
+    }
+    /// Same as [Street::update_movables], but appends the indices to `movables`
+    pub fn update_movables_into(&mut self, t: f64, movables: &mut Vec<usize>) {
+        self.change_lanes();
+        let mut offset = 0;
         for traversible in self.lanes.iter_mut() {
             for m in traversible.update_movables(t as f32) {
                 movables.push(m + offset)
             }
             offset += traversible.num_movables();
         }
-        movables
+    }
+    /// Lets cars that are queued behind another car move to an adjacent lane
+    ///
+    /// A car changes the lane if there is a gap next to it, fewer cars are in
+    /// front of it on the other lane and the other lane permits the turn it
+    /// wants to take at the next crossing. Each lane takes part in at most one
+    /// lane change per step
+    fn change_lanes(&mut self) {
+        if self.lanes.len() < 2 {
+            return;
+        }
+        let mut changed = vec![false; self.lanes.len()];
+        for from in 0..self.lanes.len() {
+            if changed[from] {
+                continue;
+            }
+            let lane = &self.lanes[from];
+            // try the car closest to the end first
+            let lane_change = lane.queued_movables().rev().find_map(|i| {
+                let pos = lane.get_position(i);
+                let ahead = lane.num_movables_ahead(pos);
+                from.checked_sub(1)
+                    .into_iter()
+                    .chain(Some(from + 1))
+                    .filter(|to| {
+                        *to < self.lanes.len()
+                            && !changed[*to]
+                            && self.lanes[*to].has_gap_at(pos)
+                            && self.lanes[*to].num_movables_ahead(pos) < ahead
+                    })
+                    // looking up the direction needs the next crossing, so it is done last
+                    .find(|to| {
+                        self.lane_permits(*to, || self.get_desired_direction(lane.get_movable_by_index(i)))
+                    })
+                    .map(|to| (i, to))
+            });
+            if let Some((i, to)) = lane_change {
+                let (mut car, pos) = self.lanes[from].take_movable(i);
+                car.set_allowed_turns(self.lane_turns.as_ref().and_then(|turns| turns.get(to).cloned()));
+                self.lanes[to].insert_at(car, pos);
+                changed[from] = true;
+                changed[to] = true;
+            }
+        }
+    }
+    /// checks if the lane with index `lane` can be used to leave the next
+    /// crossing in the direction returned by `dir`
+    fn lane_permits(&self, lane: usize, dir: impl FnOnce() -> Option<Direction>) -> bool {
+        match &self.lane_turns {
+            Some(lane_turns) => match dir() {
+                Some(dir) => lane_turns
+                    .get(lane)
+                    .map_or(false, |turns| turns.contains(&dir)),
+                None => true,
+            },
+            None => true,
+        }
     }
     /// returns the number of cars waiting at the end
     pub fn get_num_cars_at_end(&self) -> u32 {
@@ -657,12 +954,12 @@ impl<Car: Movable> Street<Car> {
         panic!("Invalid Index!")
     }
     /// returns a reference to the Car with index i
-    fn get_car_by_index(&mut self, i: usize) -> &Car {
+    fn get_car_by_index(&self, i: usize) -> &Car {
         let mut element_index = i as isize;
         for lane in self.lanes.iter() {
             let num_m = lane.num_movables() as isize;
             if element_index - num_m < 0 {
-                return lane.get_movable_by_index(i);
+                return lane.get_movable_by_index(element_index as usize);
             }
             element_index -= num_m;
         }
@@ -670,22 +967,47 @@ impl<Car: Movable> Street<Car> {
     }
 
     /// Adds a movable to the street
-    pub fn add_movable(&mut self, movable: Car) {
+    ///
+    /// The movable is put on the lane with the least movables. If the street has
+    /// turn lanes, only the lanes that permit the turn it wants to take at the
+    /// next crossing are considered.
+    ///
+    /// Returns the movable, if no lane permits its turn, as it could never
+    /// leave the street (the routes avoid these turns, see [MovableServer])
+    pub fn add_movable(&mut self, mut movable: Car) -> Result<(), Car> {
         info!("Adding movable to dstreet");
-        // get the index of the lane with the least movables on it
-        // let trav_most_movables = self
-        //     .lanes
-        //     .iter()
-        //     .enumerate()
-        //     .min_by_key(|(_i, traversible)| traversible.num_movables());
-        // let i = match trav_most_movables {
-        //     Some((i, _)) => i,
-        //     None => {
-        //         warn!("Can not determine lane with minimum number of cars.");
-        //         return;
-        //     }
-        // };
-        self.lanes[0].add(movable)
+        let dir = self.get_desired_direction(&movable);
+        let permits_turn = |i: usize| match (&self.lane_turns, dir) {
+            (Some(lane_turns), Some(dir)) => lane_turns.get(i).map_or(false, |turns| turns.contains(&dir)),
+            _ => true,
+        };
+        let least_movables = |permitted: &dyn Fn(usize) -> bool| {
+            (0..self.lanes.len())
+                .filter(|i| permitted(*i))
+                .min_by_key(|i| self.lanes[*i].num_movables())
+        };
+        let i = match least_movables(&permits_turn) {
+            Some(i) => i,
+            None => return Err(movable),
+        };
+        movable.set_allowed_turns(self.lane_turns.as_ref().and_then(|turns| turns.get(i).cloned()));
+        self.lanes[i].add(movable);
+        Ok(())
+    }
+    /// returns the direction in which the movable wants to leave the crossing at
+    /// the end of the street (or None if the street doesn't end in a crossing)
+    fn get_desired_direction(&self, movable: &Car) -> Option<Direction> {
+        let overnext_id = movable.overnext_node_id()?;
+        let conn_out = self.conn_out.as_ref()?.try_upgrade()?;
+        let guard = conn_out.read();
+        match &*guard {
+            Node::Crossing(crossing) => crossing
+                .connections
+                .iter(InOut::OUT)
+                .find(|(_dir, c)| c.upgrade().read().id() == overnext_id)
+                .map(|(dir, _c)| dir),
+            _ => None,
+        }
     }
     /// gets car status
     pub fn get_car_status(&self) -> Vec<MovableStatus> {
@@ -700,3 +1022,359 @@ impl<Car: Movable> Street<Car> {
         car_status
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a street without connections with two lanes of length 100
+    fn two_lane_street() -> Street<RandCar> {
+        Street {
+            conn_out: None,
+            conn_in: None,
+            lanes: vec![Traversible::<RandCar>::new(100.0), Traversible::<RandCar>::new(100.0)],
+            id: 0,
+            lane_turns: None,
+        }
+    }
+
+    /// a topology without hidden layers for the networks of the crossings
+    fn single_layer_topology() -> [art_int::LayerTopology; 2] {
+        use art_int::{ActivationFunc, LayerTopology};
+        [
+            LayerTopology::new(NN_INPUTS),
+            LayerTopology::new(NN_OUTPUTS).with_activation(ActivationFunc::SoftMax),
+        ]
+    }
+
+    #[test]
+    fn recorded_cars_are_bounded() {
+        let mut node = IONode::<RandCar>::new();
+        node.set_car_recording(true);
+        for _ in 0..MAX_RECORDED_CARS + 10 {
+            node.add_car(RandCar::new());
+        }
+        assert_eq!(node.recorded_cars.len(), MAX_RECORDED_CARS);
+        assert_eq!(node.dropped_recorded_cars, 10);
+        assert_eq!(node.absorbed_cars, MAX_RECORDED_CARS + 10);
+        assert_eq!(node.get_car_status().len(), MAX_RECORDED_CARS);
+        assert!(node.recorded_cars.is_empty());
+        node.add_car(RandCar::new());
+        node.set_car_recording(false);
+        assert!(node.recorded_cars.is_empty());
+    }
+
+    #[test]
+    fn longer_crossings_take_longer_to_traverse() {
+        use crate::node_builder::{CrossingBuilder, NodeBuilderTrait};
+        use crate::pathfinding::PathAwareCar;
+        // the number of steps and the time spent until the car reaches the end
+        let traverse = |length: f32| {
+            let mut crossing = match CrossingBuilder::new().with_length(length).build::<PathAwareCar>() {
+                Node::Crossing(crossing) => crossing,
+                _ => panic!("A CrossingBuilder has to build a Crossing"),
+            };
+            let mut car = PathAwareCar::new();
+            car.set_speed(1.0);
+            crossing.car_lane.add(car);
+            let mut cars_at_end = Vec::new();
+            let mut steps = 0;
+            while cars_at_end.is_empty() {
+                crossing.update_cars(1.0, &mut cars_at_end);
+                steps += 1;
+            }
+            (steps as f32, crossing.car_lane.get_movable_by_index(0).get_report().time_taken)
+        };
+        let (short_steps, short_time) = traverse(50.0);
+        let (long_steps, long_time) = traverse(100.0);
+        let ratio = long_steps / short_steps;
+        assert!(ratio > 1.9 && ratio < 2.1, "ratio: {}", ratio);
+        assert!(long_time > short_time);
+    }
+
+    #[test]
+    fn car_halfway_along_street_is_at_half() {
+        use crate::node_builder::{NodeBuilderTrait, StreetBuilder};
+        let mut street = match StreetBuilder::new().with_length(100.0).with_speed_limit(10.0).build::<RandCar>() {
+            Node::Street(street) => street,
+            _ => panic!("A StreetBuilder has to build a Street"),
+        };
+        let mut car = RandCar::new();
+        car.set_speed(1000.0);
+        street.lanes[0].add(car);
+        assert_eq!(street.lanes[0].get_movable_by_index(0).fractional_position(), 0.0);
+        // the car drives at the speed limit, so 10 per step
+        for _ in 0..5 {
+            street.update_movables(1.0);
+        }
+        let position = street.lanes[0].get_movable_by_index(0).fractional_position();
+        assert!((position - 0.5).abs() < 1e-5, "position: {}", position);
+        assert_eq!(street.lanes[0].get_movable_status()[0].position, position);
+    }
+
+    #[test]
+    fn car_on_crossing_of_length_zero_has_a_position() {
+        use crate::node_builder::{CrossingBuilder, NodeBuilderTrait};
+        let mut crossing = match CrossingBuilder::new().with_length(0.0).build::<RandCar>() {
+            Node::Crossing(crossing) => crossing,
+            _ => panic!("A CrossingBuilder has to build a Crossing"),
+        };
+        crossing.car_lane.insert_at(RandCar::new(), 0.0);
+        assert_eq!(crossing.car_lane.get_movable_by_index(0).fractional_position(), 0.0);
+        crossing.car_lane.update_movables(1.0);
+        assert!(!crossing.car_lane.get_movable_by_index(0).fractional_position().is_nan());
+    }
+
+    #[test]
+    fn cars_are_spread_over_unrestricted_lanes() {
+        let mut street = two_lane_street();
+        for _ in 0..4 {
+            assert!(street.add_movable(RandCar::new()).is_ok());
+        }
+        assert_eq!(street.lanes[0].num_movables(), 2);
+        assert_eq!(street.lanes[1].num_movables(), 2);
+    }
+
+    #[test]
+    fn car_is_rejected_if_no_lane_permits_its_turn() {
+        use crate::node_builder::{CrossingBuilder, IONodeBuilder, NodeBuilder};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut builder = SimulatorBuilder::<PathAwareCar>::new();
+        builder.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        builder.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        builder.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        builder.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        // street 4 leads into the crossing, 5 goes straight on and 6 turns right
+        builder.connect_with_street((0, Direction::E), (1, Direction::W), 2, 100.0).unwrap();
+        builder.connect_with_street((1, Direction::E), (2, Direction::W), 1, 100.0).unwrap();
+        builder.connect_with_street((1, Direction::S), (3, Direction::N), 1, 100.0).unwrap();
+        // both lanes only go straight on
+        if let NodeBuilder::Street(street) = &mut *builder.get_node(4).unwrap().get() {
+            street.lane_turns = Some(vec![HashSet::from([Direction::E]), HashSet::from([Direction::E])]);
+        }
+        let sim = builder.build(&MovableServer::<PathAwareCar>::new()).unwrap();
+        let street = sim.nodes.iter().find(|n| n.get().id() == 4).unwrap().clone();
+        let mut street_guard = street.get();
+        let street = match &mut *street_guard {
+            Node::Street(street) => street,
+            _ => panic!("Node 4 should be a street"),
+        };
+        // a car that wants to turn right onto street 6
+        let mut car = PathAwareCar::new();
+        car.set_path(vec![3, 6, 1]);
+        assert!(street.add_movable(car).is_err());
+        assert_eq!(street.lanes.iter().map(|lane| lane.num_movables()).sum::<usize>(), 0);
+        // going straight on is fine
+        let mut car = PathAwareCar::new();
+        car.set_path(vec![2, 5, 1]);
+        assert!(street.add_movable(car).is_ok());
+    }
+
+    #[test]
+    fn spawn_bursts_are_deferred() {
+        use crate::node_builder::{IONodeBuilder, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut builder = SimulatorBuilder::<PathAwareCar>::new();
+        let mut io_node = IONodeBuilder::new();
+        io_node.spawn_rate(10.0).max_spawn_per_step(std::num::NonZeroUsize::new(3).unwrap());
+        builder.add_node(NodeBuilder::IONode(io_node.clone()));
+        builder.add_node(NodeBuilder::IONode(io_node));
+        builder
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0)
+            .unwrap();
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut node = match builder.get_node(0).unwrap().get().build::<PathAwareCar>() {
+            Node::IONode(node) => node,
+            _ => panic!("An IONodeBuilder has to build an IONode"),
+        };
+        let mut rng = rand::thread_rng();
+        let mut new_cars = Vec::new();
+        let mut spawned = 0;
+        // 10 cars are due each step, but only 3 may be spawned
+        for _ in 0..100 {
+            node.update_cars(1.0, &mut mv_server, &mut rng, &mut new_cars);
+            assert!(new_cars.len() <= 3);
+            spawned += new_cars.drain(..).count();
+        }
+        assert_eq!(spawned, 300);
+        // the deferred cars are spawned once the rate drops
+        node.spawn_rate = 0.0;
+        while node.deferred_spawns > 0 {
+            node.update_cars(1.0, &mut mv_server, &mut rng, &mut new_cars);
+            assert!(new_cars.len() <= 3);
+            spawned += new_cars.drain(..).count();
+        }
+        assert_eq!(spawned, 1000);
+    }
+
+    #[test]
+    fn cars_dont_exceed_speed_limit() {
+        use crate::node_builder::{NodeBuilderTrait, StreetBuilder};
+        let limit = 0.1;
+        let mut street = match StreetBuilder::new().with_speed_limit(limit).build::<RandCar>() {
+            Node::Street(street) => street,
+            _ => panic!("A StreetBuilder has to build a Street"),
+        };
+        let mut car = RandCar::new();
+        car.set_speed(10.0);
+        street.lanes[0].add(car);
+        let mut last_pos = 0.0;
+        for _ in 0..20 {
+            street.update_movables(1.0);
+            let pos = street.lanes[0].get_position(0);
+            // allow for rounding errors of the position
+            assert!(pos - last_pos <= limit + 1e-5);
+            assert!(street.lanes[0].get_movable_by_index(0).get_speed()[0] <= limit);
+            last_pos = pos;
+        }
+        assert!(last_pos > 0.0);
+    }
+
+    #[test]
+    fn blocked_car_changes_to_free_lane() {
+        let mut street = two_lane_street();
+        // the lead car waits at the end, the other one is right behind it
+        street.lanes[0].insert_at(RandCar::new(), 100.0);
+        street.lanes[0].insert_at(RandCar::new(), 98.0);
+        street.update_movables(1.0);
+        assert_eq!(street.lanes[0].queued_movables().len(), 1);
+        street.update_movables(1.0);
+        assert_eq!(street.lanes[0].num_movables(), 1);
+        assert_eq!(street.lanes[1].num_movables(), 1);
+    }
+
+    #[test]
+    fn removed_cars_are_no_longer_waiting() {
+        let mut street = two_lane_street();
+        street.lanes[0].insert_at(RandCar::new(), 100.0);
+        street.lanes[0].insert_at(RandCar::new(), 98.0);
+        street.update_movables(1.0);
+        assert_eq!(street.lanes[0].num_movables_waiting(), 2);
+        // the car at the end drives onto the next node
+        let lane = &mut street.lanes[0];
+        lane.remove_movable(lane.num_movables() - 1);
+        assert_eq!(lane.num_movables_waiting(), 1);
+        assert_eq!(lane.get_movable_status()[0].speed, 0.0);
+        // a car put further back doesn't wait, one put at the end does
+        lane.insert_at(RandCar::new(), 50.0);
+        assert_eq!(lane.num_movables_waiting(), 1);
+        lane.insert_at(RandCar::new(), 100.0);
+        assert_eq!(lane.num_movables_waiting(), 2);
+    }
+
+    #[test]
+    fn car_stays_if_adjacent_lane_is_not_better() {
+        let mut street = two_lane_street();
+        for lane in street.lanes.iter_mut() {
+            lane.insert_at(RandCar::new(), 100.0);
+            lane.insert_at(RandCar::new(), 98.0);
+        }
+        street.update_movables(1.0);
+        street.update_movables(1.0);
+        assert_eq!(street.lanes[0].num_movables(), 2);
+        assert_eq!(street.lanes[1].num_movables(), 2);
+    }
+
+    #[test]
+    fn exploration_temperature_samples_states() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let topology = single_layer_topology();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut crossing = Crossing::<RandCar>::new();
+        for _ in 0..20 {
+            crossing.set_neural_network(art_int::Network::random(&mut rng, &topology));
+            crossing.exploration_temperature = None;
+            let best = crossing.determine_traffic_light_state().unwrap();
+            // without a temperature, the highest output is chosen like before
+            crossing.exploration_temperature = Some(0.0);
+            for _ in 0..10 {
+                assert_eq!(crossing.determine_traffic_light_state().unwrap(), best);
+            }
+        }
+        // a high temperature makes all states almost equally likely
+        crossing.exploration_temperature = Some(1000.0);
+        let states: Vec<TrafficLightState> = (0..200)
+            .map(|_| crossing.determine_traffic_light_state().unwrap())
+            .collect();
+        assert!(TrafficLightState::ALL.iter().all(|state| states.contains(state)));
+    }
+
+    #[test]
+    fn phase_is_kept_for_min_duration() {
+        let topology = single_layer_topology();
+        let mut crossing = Crossing::<RandCar>::new();
+        // gives the crossing a network that ignores its inputs and always chooses
+        //  the state after `state`
+        let nn_not_choosing = |crossing: &mut Crossing, state: TrafficLightState| {
+            let chosen = (TrafficLightState::ALL.iter().position(|s| *s == state).unwrap() + 1) % NN_OUTPUTS;
+            // every neuron has a bias followed by its weights
+            let weights = (0..NN_OUTPUTS)
+                .flat_map(|i| std::iter::once((i == chosen) as u8 as f32).chain([0.0; NN_INPUTS]));
+            crossing.set_neural_network(art_int::Network::from_weights(&topology, weights));
+        };
+        nn_not_choosing(&mut crossing, TrafficLightState::S0);
+        let dt = MIN_PHASE_DURATION / 10.0;
+        for _ in 0..9 {
+            crossing.update_traffic_light_state(dt).unwrap();
+            assert_eq!(crossing.traffic_light_state, TrafficLightState::S0);
+        }
+        crossing.update_traffic_light_state(dt).unwrap();
+        let state = crossing.traffic_light_state.clone();
+        assert_ne!(state, TrafficLightState::S0);
+        assert_eq!(crossing.phase_switches, 1);
+        assert!(crossing.clearance_remaining > 0.0);
+        // the new state is kept even if the network wants to change it again
+        nn_not_choosing(&mut crossing, state.clone());
+        for _ in 0..9 {
+            crossing.update_traffic_light_state(dt).unwrap();
+            assert_eq!(crossing.traffic_light_state, state);
+        }
+        assert_eq!(crossing.clearance_remaining, 0.0);
+        crossing.update_traffic_light_state(dt).unwrap();
+        assert_ne!(crossing.traffic_light_state, state);
+        assert_eq!(crossing.phase_switches, 2);
+    }
+
+    #[test]
+    fn t_junction_never_serves_missing_leg() {
+        use crate::node_builder::{CrossingBuilder, IONodeBuilder, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::simulation_builder::SimulatorBuilder;
+        // the crossing has no southern leg and cars can only leave to the north
+        //
+        //       N
+        //       ^
+        //  W <->C<-> E
+        let mut builder = SimulatorBuilder::<PathAwareCar>::new();
+        let crossing = builder.add_node(NodeBuilder::Crossing(CrossingBuilder::new())).get().get_id();
+        let north = builder.add_node(NodeBuilder::IONode(IONodeBuilder::new())).get().get_id();
+        let east = builder.add_node(NodeBuilder::IONode(IONodeBuilder::new())).get().get_id();
+        let west = builder.add_node(NodeBuilder::IONode(IONodeBuilder::new())).get().get_id();
+        builder.connect_with_street((crossing, Direction::N), (north, Direction::S), 1, 10.0).unwrap();
+        for (io_node, side, io_side) in [(east, Direction::E, Direction::W), (west, Direction::W, Direction::E)] {
+            builder.connect_with_street((crossing, side), (io_node, io_side), 1, 10.0).unwrap();
+            builder.connect_with_street((io_node, io_side), (crossing, side), 1, 10.0).unwrap();
+        }
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let sim = builder.build(&mv_server).unwrap();
+        let crossing = sim.nodes.iter().find(|n| n.read().id() == crossing).unwrap();
+        let mut crossing = crossing.get();
+        let crossing = match &mut *crossing {
+            Node::Crossing(crossing) => crossing,
+            _ => panic!("node is not a crossing"),
+        };
+        // the states 0 and 2 only let cars from the north and south drive
+        assert!(!crossing.serves_connected_sides(&TrafficLightState::S0));
+        assert!(!crossing.serves_connected_sides(&TrafficLightState::S2));
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            crossing.set_neural_network(art_int::Network::random(&mut rng, &single_layer_topology()));
+            let state = crossing.determine_traffic_light_state().unwrap();
+            assert!(matches!(state, TrafficLightState::S1 | TrafficLightState::S3));
+        }
+    }
+}
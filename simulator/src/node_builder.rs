@@ -1,7 +1,9 @@
-use std::{collections::HashMap, error::Error, fmt::Debug, hash::Hash};
+use std::{collections::{HashMap, HashSet, VecDeque}, error::Error, fmt::Debug, hash::Hash, num::NonZeroUsize};
 
 use crate::node::{CostCalcParameters, TrafficLightState};
+use crate::traffic_controller::MissingController;
 use crate::traits::Movable;
+use crate::{DEFAULT_MAX_SPAWN_PER_STEP, DEFAULT_SPEED_LIMIT, MIN_LANE_LENGTH};
 
 use super::int_mut::{IntMut, WeakIntMut};
 use super::{
@@ -150,14 +152,30 @@ pub struct StreetBuilder {
     pub lane_length: f32,
     /// the unique id of a street
     pub id: usize,
+    /// the outputs of the next crossing each lane may feed
+    ///
+    /// `None` means that any lane can be used for any turn
+    pub lane_turns: Option<Vec<HashSet<Direction>>>,
+    /// the maximum speed of the cars on the street
+    pub speed_limit: f32,
 }
 impl NodeBuilderTrait for StreetBuilder {
     fn build<Car: Movable>(&self) -> Node<Car> {
+        let num_lanes = match &self.lane_turns {
+            Some(turns) => turns.len(),
+            None => self.lanes as usize,
+        };
         Node::Street(Street {
-            lanes: vec![Traversible::<RandCar>::new(self.lane_length)],
+            lanes: (0..num_lanes.max(1))
+                .map(|_| {
+                    Traversible::<RandCar>::new(self.get_node_dist())
+                        .with_speed_limit(self.speed_limit)
+                })
+                .collect(),
             conn_in: None,
             conn_out: None,
             id: self.id,
+            lane_turns: self.lane_turns.clone(),
         })
     }
     fn get_out_connections<'a>(&'a self) -> Vec<WeakIntMut<NodeBuilder>> {
@@ -210,7 +228,7 @@ impl NodeBuilderTrait for StreetBuilder {
     }
 
     fn get_node_dist(&self) -> f32 {
-        self.lane_length
+        self.lane_length.max(MIN_LANE_LENGTH)
     }
 }
 impl StreetBuilder {
@@ -236,11 +254,24 @@ impl StreetBuilder {
         self.lane_length = length;
         self
     }
+    /// sets the maximum speed of the cars on the street
+    pub fn with_speed_limit(mut self, speed_limit: f32) -> Self {
+        self.speed_limit = speed_limit;
+        self
+    }
     /// sets the number of lanes
     pub fn with_lanes(mut self, lanes: u8) -> Self {
         self.lanes = lanes;
         self
     }
+    /// restricts the outputs of the next crossing each lane may feed
+    ///
+    /// The number of lanes is set to the number of entries in `lane_turns`
+    pub fn with_lane_turns(mut self, lane_turns: Vec<HashSet<Direction>>) -> Self {
+        self.lanes = lane_turns.len() as u8;
+        self.lane_turns = Some(lane_turns);
+        self
+    }
     /// returns a new [StreetBuilder] that is connected to nothing
     pub fn new() -> Self {
         Self {
@@ -249,10 +280,34 @@ impl StreetBuilder {
             lanes: 1,
             lane_length: 100.0,
             id: 0,
+            lane_turns: None,
+            speed_limit: DEFAULT_SPEED_LIMIT,
         }
     }
 }
 
+/// Whether an [IONode] spawns cars, absorbs them or both
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IONodeRole {
+    /// the node only spawns cars
+    Source,
+    /// the node is only chosen as the destination of cars
+    Sink,
+    /// the node spawns cars and is chosen as a destination
+    #[default]
+    Both,
+}
+impl IONodeRole {
+    /// true if nodes with this role spawn cars
+    pub fn spawns(&self) -> bool {
+        matches!(self, IONodeRole::Source | IONodeRole::Both)
+    }
+    /// true if nodes with this role can be the destination of a car
+    pub fn absorbs(&self) -> bool {
+        matches!(self, IONodeRole::Sink | IONodeRole::Both)
+    }
+}
+
 /// [IONode]s represent either an input or an output of the simulation
 ///
 /// # Usage
@@ -268,13 +323,28 @@ pub struct IONodeBuilder {
     /// the unique id of a IONode
     pub id: usize,
     ///
-    pub speed_to_co2: f32
+    pub speed_to_co2: f32,
+    /// whether the node spawns cars, absorbs them or both
+    pub role: IONodeRole,
+    /// how many cars are spawned in a single step at most
+    ///
+    /// If more cars should be spawned, e.g. because of a high spawn rate and a
+    /// large time step, the others are spawned in the following steps, so the
+    /// first street isn't flooded
+    pub max_spawn_per_step: NonZeroUsize,
+}
+/// [DEFAULT_MAX_SPAWN_PER_STEP] as the spawn limit of an [IONodeBuilder]
+pub(crate) fn default_max_spawn_per_step() -> NonZeroUsize {
+    NonZeroUsize::new(DEFAULT_MAX_SPAWN_PER_STEP).expect("The default spawn limit can't be 0")
 }
 impl NodeBuilderTrait for IONodeBuilder {
     fn build<Car: Movable>(&self) -> Node<Car> {
         Node::IONode(IONode {
             connections: Vec::new(),
             spawn_rate: self.spawn_rate,
+            role: self.role,
+            max_spawn_per_step: self.max_spawn_per_step.get(),
+            deferred_spawns: 0,
             absorbed_cars: 0,
             id: self.id,
             cached: HashMap::new(),
@@ -283,7 +353,8 @@ impl NodeBuilderTrait for IONodeBuilder {
                 speed_to_co2: self.speed_to_co2,
             },
             record: false,
-            recorded_cars: Vec::new(),
+            recorded_cars: VecDeque::new(),
+            dropped_recorded_cars: 0,
             num_cars_spawned: 0,
         })
     }
@@ -329,6 +400,8 @@ impl IONodeBuilder {
             spawn_rate: 0.001,
             id: 0,
             speed_to_co2: 0.5,
+            role: IONodeRole::default(),
+            max_spawn_per_step: default_max_spawn_per_step(),
         }
     }
     /// set spawn rate in cars / second
@@ -336,6 +409,13 @@ impl IONodeBuilder {
         self.spawn_rate = rate;
         self
     }
+    /// set how many cars are spawned in a single step at most
+    ///
+    /// It can't be 0, as the node would never spawn a car
+    pub fn max_spawn_per_step(&mut self, max: NonZeroUsize) -> &mut Self {
+        self.max_spawn_per_step = max;
+        self
+    }
     /// connects to other nodes. An IONode can have an indefinite amount of connections
     pub fn connect(&mut self, in_out: InOut, n: &IntMut<NodeBuilder>) {
         match in_out {
@@ -358,8 +438,61 @@ pub enum Direction {
     W,
 }
 
+/// The sides of an angled crossing: the four sides of [Direction] and the
+/// diagonals between them
+///
+/// Only used by [AngledCrossingBuilder], regular crossings keep to the four
+/// sides of [Direction], as the NN inputs and traffic light phases are laid out
+/// for them
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+pub enum Bearing {
+    ///
+    N,
+    ///
+    NE,
+    ///
+    E,
+    ///
+    SE,
+    ///
+    S,
+    ///
+    SW,
+    ///
+    W,
+    ///
+    NW,
+}
+
+impl Bearing {
+    /// the angle of the side in degrees, clockwise from north
+    pub fn degrees(&self) -> f32 {
+        match self {
+            Bearing::N => 0.0,
+            Bearing::NE => 45.0,
+            Bearing::E => 90.0,
+            Bearing::SE => 135.0,
+            Bearing::S => 180.0,
+            Bearing::SW => 225.0,
+            Bearing::W => 270.0,
+            Bearing::NW => 315.0,
+        }
+    }
+}
+
+impl From<Direction> for Bearing {
+    fn from(dir: Direction) -> Bearing {
+        match dir {
+            Direction::N => Bearing::N,
+            Direction::E => Bearing::E,
+            Direction::S => Bearing::S,
+            Direction::W => Bearing::W,
+        }
+    }
+}
+
 /// Used to define wether connections are an input or output
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InOut {
     /// Input
     IN,
@@ -371,21 +504,44 @@ pub enum InOut {
 /// Crossings can be connected to streets or other nodes
 ///
 /// The idea is that a Crossing has a square shape of which each
-/// side can connect to one input street and one output street.
+/// side can connect to input streets and output streets. A side can
+/// hold several connections of the same type (e.g. two outgoing roads).
+///
+/// The sides are [Direction]s by default, angled crossings use [Bearing]s
 #[derive(Clone, Debug)]
-pub struct CrossingConnections<T = NodeBuilder> {
-    /// output streets by direction
-    pub input: HashMap<Direction, WeakIntMut<T>>,
+pub struct CrossingConnections<T = NodeBuilder, D = Direction> {
+    /// input streets by direction
+    pub input: HashMap<D, Vec<WeakIntMut<T>>>,
     /// output streets by direction
-    pub output: HashMap<Direction, WeakIntMut<T>>,
+    pub output: HashMap<D, Vec<WeakIntMut<T>>>,
+}
+
+impl<T, D: Copy + Eq + Hash + Debug> Default for CrossingConnections<T, D> {
+    fn default() -> Self {
+        CrossingConnections::new()
+    }
 }
 
-impl<T> CrossingConnections<T> {
+impl<T, D: Copy + Eq + Hash + Debug> CrossingConnections<T, D> {
     /// Creates a new [CrossingConnections] holding connections of type `T`
-    pub fn new() -> CrossingConnections<T> {
+    pub fn new() -> CrossingConnections<T, D> {
         CrossingConnections {
-            input: HashMap::<Direction, WeakIntMut<T>>::new(),
-            output: HashMap::<Direction, WeakIntMut<T>>::new(),
+            input: HashMap::<D, Vec<WeakIntMut<T>>>::new(),
+            output: HashMap::<D, Vec<WeakIntMut<T>>>::new(),
+        }
+    }
+    /// returns the connections of the given type
+    fn by_type(&self, conn_type: InOut) -> &HashMap<D, Vec<WeakIntMut<T>>> {
+        match conn_type {
+            InOut::IN => &self.input,
+            InOut::OUT => &self.output,
+        }
+    }
+    /// returns the connections of the given type mutably
+    fn by_type_mut(&mut self, conn_type: InOut) -> &mut HashMap<D, Vec<WeakIntMut<T>>> {
+        match conn_type {
+            InOut::IN => &mut self.input,
+            InOut::OUT => &mut self.output,
         }
     }
     /// Adds a new connection at the specified position
@@ -393,89 +549,80 @@ impl<T> CrossingConnections<T> {
     /// Returns an error if the connection already exsists
     pub fn add(
         &mut self,
-        dir: Direction,
+        dir: D,
         conn_type: InOut,
         conn: &IntMut<T>,
     ) -> Result<(), String> {
-        let connection: &mut HashMap<Direction, WeakIntMut<T>>;
         match conn_type {
-            InOut::IN => {
-                assert!(!self.is_connected(InOut::OUT, conn));
-                connection = &mut self.input
-            }
-            InOut::OUT => {
-                assert!(!self.is_connected(InOut::IN, conn));
-                connection = &mut self.output
-            }
+            InOut::IN => assert!(!self.is_connected(InOut::OUT, conn)),
+            InOut::OUT => assert!(!self.is_connected(InOut::IN, conn)),
         }
-        match connection.get(&dir) {
-            Some(_value) => {
-                return Err(format!(
-                    "Connection at ({:?}, {:?}) already exists",
-                    conn_type, dir
-                ))
-            }
-            None => {
-                connection.insert(dir, conn.downgrade());
-            }
+        let connections = self.by_type_mut(conn_type).entry(dir).or_default();
+        if connections.iter().any(|c| c == conn) {
+            return Err(format!(
+                "Connection at ({:?}, {:?}) already exists",
+                conn_type, dir
+            ));
         }
+        connections.push(conn.downgrade());
         Ok(())
     }
-    /// Removes the specified connection and returns Some(connections) if it exists
-    /// or None if there is no such connection
-    pub fn pop(&mut self, dir: Direction, conn_type: InOut) -> Option<WeakIntMut<T>> {
-        let connection: &mut HashMap<Direction, WeakIntMut<T>>;
-        match conn_type {
-            InOut::IN => connection = &mut self.input,
-            InOut::OUT => connection = &mut self.output,
+    /// Removes the last connection added at the specified position and returns
+    /// Some(connection) if it exists or None if there is no such connection
+    pub fn pop(&mut self, dir: D, conn_type: InOut) -> Option<WeakIntMut<T>> {
+        let connection = self.by_type_mut(conn_type);
+        let popped = connection.get_mut(&dir)?.pop();
+        // don't keep empty sides around, as they would count as connected
+        if connection.get(&dir).map_or(false, |c| c.is_empty()) {
+            connection.remove(&dir);
         }
-        connection.remove(&dir)
+        popped
     }
 
     /// removes all connections that point to `conn`
     pub fn remove_connection(&mut self, conn_type: InOut, conn: &WeakIntMut<T>) {
-        let connection: &mut HashMap<Direction, WeakIntMut<T>>;
-        match conn_type {
-            InOut::IN => connection = &mut self.input,
-            InOut::OUT => connection = &mut self.output,
-        }
+        let connection = self.by_type_mut(conn_type);
         // remove all connections that point to the same object as `conn`
-        connection.retain(|_k, v| !(v == conn));
+        connection.values_mut().for_each(|v| v.retain(|c| !(c == conn)));
+        connection.retain(|_k, v| !v.is_empty());
     }
     /// returns true if the connection at the given position exists
     pub fn is_connected(&self, conn_type: InOut, node: &IntMut<T>) -> bool {
-        let connection: &HashMap<Direction, WeakIntMut<T>>;
-        match conn_type {
-            InOut::IN => connection = &self.input,
-            InOut::OUT => connection = &self.output,
-        }
-        connection.values().find(|v| *v == node).is_some()
+        self.iter(conn_type).any(|(_dir, c)| c == node)
     }
-    /// Returns `Some(Direction)` for an item if it is saved in the connections
-    pub fn get_direction_for_item(&self, conn_type: InOut, item: &IntMut<T>) -> Option<Direction> {
-        let connection: &HashMap<Direction, WeakIntMut<T>>;
-        match conn_type {
-            InOut::IN => connection = &self.input,
-            InOut::OUT => connection = &self.output,
-        }
-        let search_results = connection.iter().find(|&(_k, v)| {
+    /// Returns `Some(direction)` for an item if it is saved in the connections
+    pub fn get_direction_for_item(&self, conn_type: InOut, item: &IntMut<T>) -> Option<D> {
+        self.iter(conn_type)
             // Both point to the same internal T
-            v == item
-        });
-        // Transform the results to match the function signature
-        match search_results {
-            Some((k, _v)) => Some(k.clone()),
-            None => None,
-        }
+            .find(|(_dir, c)| *c == item)
+            .map(|(dir, _c)| dir)
     }
     /// Returns true, if there is a conneciton at the specified position
-    pub fn has_connection(&self, conn_type: InOut, dir: Direction) -> bool {
-        let connection: &HashMap<Direction, WeakIntMut<T>>;
-        match conn_type {
-            InOut::IN => connection = &self.input,
-            InOut::OUT => connection = &self.output,
+    pub fn has_connection(&self, conn_type: InOut, dir: D) -> bool {
+        self.by_type(conn_type).contains_key(&dir)
+    }
+    /// Returns the first connection at the specified position
+    ///
+    /// This is convenient if a side is known to only hold a single connection
+    pub fn get(&self, conn_type: InOut, dir: D) -> Option<&WeakIntMut<T>> {
+        self.by_type(conn_type).get(&dir).and_then(|c| c.first())
+    }
+    /// Returns all connections at the specified position
+    pub fn get_all(&self, conn_type: InOut, dir: D) -> &[WeakIntMut<T>] {
+        match self.by_type(conn_type).get(&dir) {
+            Some(c) => c,
+            None => &[],
         }
-        connection.contains_key(&dir)
+    }
+    /// Iterates over all connections of the given type together with their direction
+    pub fn iter(&self, conn_type: InOut) -> impl Iterator<Item = (D, &WeakIntMut<T>)> {
+        self.by_type(conn_type)
+            .iter()
+            .flat_map(|(dir, c)| c.iter().map(move |c| (*dir, c)))
+    }
+    /// the number of connections of the given type
+    pub fn len(&self, conn_type: InOut) -> usize {
+        self.by_type(conn_type).values().map(|c| c.len()).sum()
     }
 }
 
@@ -486,6 +633,9 @@ pub struct CrossingBuilder {
     pub connections: CrossingConnections,
     /// The length a car has to traverse when traversing
     /// the crossing
+    ///
+    /// The longer the crossing, the longer the cars spend on it, which
+    /// increases the cost of the simulation
     pub length: f32,
     /// the id of a crossing builder in the simulation
     pub id: usize,
@@ -498,24 +648,28 @@ impl NodeBuilderTrait for CrossingBuilder {
             id: self.id,
             traffic_light_state: TrafficLightState::S0,
             time_since_input_passable: [0.0; 4],
-            nn: None, // Will be set later with the `set_neural_network` function to keep the function signature consistent
+            // the NN will be set later with the `set_neural_network` function to keep the function signature consistent
+            controller: Box::new(MissingController::new(self.id)),
+            time_in_current_phase: 0.0,
+            clearance_remaining: 0.0,
+            phase_switches: 0,
+            max_queue_lengths: [0; 4],
+            exploration_temperature: None,
         })
     }
     fn get_out_connections(&self) -> Vec<WeakIntMut<NodeBuilder>> {
         self.connections
-            .output
-            .values()
-            .map(|c| c.clone())
+            .iter(InOut::OUT)
+            .map(|(_dir, c)| c.clone())
             .collect()
     }
     fn get_all_connections(&self) -> Vec<WeakIntMut<NodeBuilder>> {
         let mut cout: Vec<WeakIntMut<NodeBuilder>> = self
             .connections
-            .output
-            .values()
-            .map(|c| c.clone())
+            .iter(InOut::OUT)
+            .map(|(_dir, c)| c.clone())
             .collect();
-        let mut cin = self.connections.input.values().map(|c| c.clone()).collect();
+        let mut cin = self.connections.iter(InOut::IN).map(|(_dir, c)| c.clone()).collect();
         cout.append(&mut cin);
         cout
     }
@@ -577,3 +731,75 @@ impl CrossingBuilder {
         self.connections.get_direction_for_item(conn_type, item)
     }
 }
+
+/// The settings of a crossing whose streets can also meet it at the diagonal
+/// sides (see [Bearing])
+///
+/// This only covers the connection model of angled crossings. They are not a
+/// [NodeBuilder] variant, so they can't be added to a simulation, saved or
+/// drawn in the editor: the NN inputs and phase tables of [Crossing] and the
+/// street offsets of the editor only know about the four sides of [Direction]
+#[derive(Debug, Clone)]
+pub struct AngledCrossingBuilder {
+    /// the connections by the side of the crossing they meet it at
+    pub connections: CrossingConnections<NodeBuilder, Bearing>,
+    /// The length a car has to traverse when traversing the crossing
+    pub length: f32,
+    /// the id of the crossing builder in the simulation
+    pub id: usize,
+}
+
+impl Default for AngledCrossingBuilder {
+    fn default() -> Self {
+        AngledCrossingBuilder::new()
+    }
+}
+
+impl AngledCrossingBuilder {
+    /// Constructs a new [AngledCrossingBuilder] with id=0
+    pub fn new() -> AngledCrossingBuilder {
+        AngledCrossingBuilder {
+            connections: CrossingConnections::new(),
+            length: 10.0,
+            id: 0,
+        }
+    }
+    /// set the length a car has to traverse when traversing the crossing
+    pub fn with_length(mut self, length: f32) -> AngledCrossingBuilder {
+        self.length = length;
+        self
+    }
+    /// connects to node at the side `bearing`
+    pub fn connect(
+        &mut self,
+        bearing: Bearing,
+        conn_type: InOut,
+        other: &IntMut<NodeBuilder>,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        self.connections.add(bearing, conn_type, other)?;
+        Ok(self)
+    }
+    /// returns true, if there a connection is present at the specified side
+    pub fn has_connection(&self, conn_type: InOut, bearing: Bearing) -> bool {
+        self.connections.has_connection(conn_type, bearing)
+    }
+    /// returns the side the item is connected at
+    pub fn get_direction_for_item(&self, conn_type: InOut, item: &IntMut<NodeBuilder>) -> Option<Bearing> {
+        self.connections.get_direction_for_item(conn_type, item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn angled_crossing_stores_diagonal_side() {
+        use crate::datastructs::IntMut;
+        use crate::node_builder::{AngledCrossingBuilder, Bearing, InOut, NodeBuilder, StreetBuilder};
+        let mut crossing = AngledCrossingBuilder::default();
+        let street = IntMut::new(NodeBuilder::Street(StreetBuilder::new()));
+        crossing.connect(Bearing::NE, InOut::OUT, &street).unwrap();
+        assert!(crossing.has_connection(InOut::OUT, Bearing::NE));
+        assert!(!crossing.has_connection(InOut::OUT, Bearing::N));
+        assert_eq!(crossing.get_direction_for_item(InOut::OUT, &street), Some(Bearing::NE));
+    }
+}
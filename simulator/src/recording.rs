@@ -0,0 +1,211 @@
+use crate::movable::MovableStatus;
+use crate::node::TrafficLightState;
+use crate::simulation::Simulator;
+use crate::traits::Movable;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// The state of a simulation after one step, as far as it is needed to display it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    /// the status of all cars, the key is the node id (see [Simulator::get_car_status])
+    pub cars: HashMap<usize, Vec<MovableStatus>>,
+    /// the traffic light state of all crossings, the key is the node id
+    pub traffic_lights: HashMap<usize, TrafficLightState>,
+}
+
+impl Frame {
+    /// captures the current state of `sim`
+    pub fn capture<Car: Movable>(sim: &mut Simulator<Car>) -> Frame {
+        Frame {
+            cars: sim.get_car_status(),
+            traffic_lights: sim.get_traffic_light_states(),
+        }
+    }
+}
+
+/// A recorded simulation run that can be saved and replayed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    /// the time between two frames in seconds
+    pub dt: f32,
+    /// one frame for every simulated step
+    pub frames: Vec<Frame>,
+}
+
+/// Is returned if a recording can't be read
+#[derive(Debug)]
+pub struct RecordingError(String);
+
+impl Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unable to read the recording: {}", self.0)
+    }
+}
+
+impl Error for RecordingError {}
+
+impl Recording {
+    /// serializes the recording, so it can be saved to a file
+    pub fn to_json(&self) -> Result<String, RecordingError> {
+        serde_json::to_string(self).map_err(|err| RecordingError(err.to_string()))
+    }
+    /// reads a recording saved with [Recording::to_json]
+    pub fn from_json(json: &str) -> Result<Recording, RecordingError> {
+        serde_json::from_str(json).map_err(|err| RecordingError(err.to_string()))
+    }
+}
+
+/// Collects the frames of a simulation while it runs
+///
+/// Use [Simulator::start_recording] to record every step of `sim_iter`
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    recording: Recording,
+}
+
+impl Recorder {
+    /// a recorder for a simulation that is advanced `dt` seconds each step
+    pub fn new(dt: f32) -> Recorder {
+        Recorder {
+            recording: Recording {
+                dt,
+                frames: Vec::new(),
+            },
+        }
+    }
+    /// appends the current state of `sim` as a new frame
+    pub fn record<Car: Movable>(&mut self, sim: &mut Simulator<Car>) {
+        self.push(Frame::capture(sim));
+    }
+    /// appends a frame
+    pub fn push(&mut self, frame: Frame) {
+        self.recording.frames.push(frame);
+    }
+    /// the frames recorded so far, the oldest first
+    pub fn frames(&self) -> &[Frame] {
+        &self.recording.frames
+    }
+    /// removes the `n` oldest frames (e.g. to limit the memory used while recording)
+    pub fn drop_oldest(&mut self, n: usize) {
+        let n = n.min(self.recording.frames.len());
+        self.recording.frames.drain(..n);
+    }
+    /// stops recording and returns the recording
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+/// Steps through a [Recording] frame by frame, without simulating anything
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    recording: Recording,
+    /// the index of the current frame
+    position: usize,
+}
+
+impl Replayer {
+    /// starts at the first frame of `recording`
+    pub fn new(recording: Recording) -> Replayer {
+        Replayer {
+            recording,
+            position: 0,
+        }
+    }
+    /// the recording that is replayed
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+    /// the number of frames
+    pub fn len(&self) -> usize {
+        self.recording.frames.len()
+    }
+    /// true if the recording doesn't have any frames
+    pub fn is_empty(&self) -> bool {
+        self.recording.frames.is_empty()
+    }
+    /// the index of the current frame
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    /// the current frame, None if the recording is empty
+    pub fn current(&self) -> Option<&Frame> {
+        self.recording.frames.get(self.position)
+    }
+    /// jumps to the frame with the given index (clamped to the last frame)
+    pub fn seek(&mut self, position: usize) {
+        self.position = position.min(self.len().saturating_sub(1));
+    }
+    /// moves to the next frame, returns false if the current frame is the last one
+    pub fn step_forward(&mut self) -> bool {
+        let old = self.position;
+        self.seek(old + 1);
+        self.position != old
+    }
+    /// moves to the previous frame, returns false if the current frame is the first one
+    pub fn step_back(&mut self) -> bool {
+        let old = self.position;
+        self.seek(old.saturating_sub(1));
+        self.position != old
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Frame, Recorder, Recording, Replayer};
+    use std::collections::HashMap;
+    use crate::debug::build_grid_sim;
+    use crate::pathfinding::{MovableServer, PathAwareCar};
+    use crate::sim_manager::default_nn_topology;
+
+    #[test]
+    fn recorded_run_replays_after_saving() {
+        let mut builder = build_grid_sim(3, 20.0);
+        builder.with_dt(1.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim = builder.build(&mv_server).unwrap();
+        sim.init_neural_networks_random(&default_nn_topology());
+        sim.start_recording();
+        for _ in 0..500 {
+            sim.sim_iter();
+        }
+        let recording = sim.stop_recording().unwrap();
+        assert_eq!(recording.frames.len(), 500);
+        assert_eq!(recording.dt, 1.0);
+        // the last frame is the current state
+        assert_eq!(recording.frames[499].traffic_lights, sim.get_traffic_light_states());
+        assert!(sim.stop_recording().is_none());
+
+        let loaded = Recording::from_json(&recording.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, recording);
+
+        let mut replayer = Replayer::new(loaded);
+        assert_eq!(replayer.current(), Some(&recording.frames[0]));
+        assert!(!replayer.step_back());
+        assert!(replayer.step_forward());
+        assert_eq!(replayer.current(), Some(&recording.frames[1]));
+        replayer.seek(10_000);
+        assert_eq!(replayer.position(), 499);
+        assert!(!replayer.step_forward());
+        assert!(replayer.step_back());
+        assert_eq!(replayer.current(), Some(&recording.frames[498]));
+    }
+
+    #[test]
+    fn recorder_drops_oldest_frames() {
+        let frame = |id: usize| Frame {
+            cars: HashMap::new(),
+            traffic_lights: vec![(id, crate::node::TrafficLightState::S0)].into_iter().collect(),
+        };
+        let mut recorder = Recorder::new(1.0);
+        (0..5).for_each(|i| recorder.push(frame(i)));
+        recorder.drop_oldest(2);
+        assert_eq!(recorder.frames(), &[frame(2), frame(3), frame(4)]);
+        recorder.drop_oldest(10);
+        assert!(recorder.frames().is_empty());
+    }
+}
@@ -1,27 +1,33 @@
 use crate::datastructs::{IntMut, MovableStatus};
+use crate::nodes::{CrossingSnapshot, NodeBuilder, TrafficLightState, NN_INPUTS, NN_OUTPUTS};
 use crate::path::MovableServer;
 use crate::pathfinding::PathAwareCar;
-use crate::{SimulatorBuilder, Simulator};
-use art_int::genetics::{crossover_sim_nns, mutate_sim_nns};
+use crate::traits::TrafficController;
+use crate::{NeuralNetworkError, SimulatorBuilder, Simulator};
+use crate::simulation_builder::ValidationError;
+use crate::recording::Recording;
+use art_int::genetics::{crossover_sim_nns, mutate_sim_nns, DEFAULT_WEIGHT_LIMIT};
 use art_int::{LayerTopology, ActivationFunc, Network};
 use pathfinding::num_traits::Pow;
 use tracing::{info_span, span, Level};
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 use rand::prelude::SliceRandom;
+use rand::prelude::ThreadRng;
 use rand::thread_rng;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
 use std::panic;
-use std::sync::{mpsc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use rayon::prelude::*;
 
 
 /// Useful for displaying information about each Simulation in the frontend
 pub struct SimulationStatus { 
+    /// true if the simulation is tracked (see [SimManager::track_simulation])
     pub displaying: bool    
 }
 impl SimulationStatus {
@@ -32,9 +38,234 @@ impl SimulationStatus {
     }
 }
 
+/// The information sent to the frontend for displaying a tracked simulation
+pub struct StatusUpdate {
+    /// the status of all cars, the key is the node index
+    pub cars: HashMap<usize, Vec<MovableStatus>>,
+    /// the traffic light state of all crossings, the key is the node index
+    pub traffic_lights: HashMap<usize, TrafficLightState>,
+    /// the state of all crossings, the key is the node index
+    pub crossings: HashMap<usize, CrossingSnapshot>,
+    /// the current cost of the simulation (see [Simulator::calculate_sim_cost])
+    pub cost: [f64; 2],
+    /// the number of cars currently driving
+    pub car_count: usize,
+    /// the number of cars that reached their destination
+    pub absorbed_cars: usize,
+}
+
+impl StatusUpdate {
+    /// collects the current state of the simulator, `cars` are the car statuses
+    /// to send (either the current status or the cars that were deleted)
+    fn new(simulator: &mut Simulator, cars: HashMap<usize, Vec<MovableStatus>>) -> StatusUpdate {
+        StatusUpdate {
+            cars,
+            traffic_lights: simulator.get_traffic_light_states(),
+            crossings: simulator.crossing_snapshots(),
+            cost: simulator.calculate_sim_cost(),
+            car_count: simulator.count_cars(),
+            absorbed_cars: simulator.count_absorbed_cars(),
+        }
+    }
+}
+
+/// the number of status updates that are buffered before the oldest ones are dropped
+const STATUS_CHANNEL_CAPACITY: usize = 4;
+/// how long (in simulated seconds) cars may drive without any of them reaching
+/// its destination before the simulation is considered deadlocked
+const DEADLOCK_TIME: f32 = 600.0;
+/// the cost of a deadlocked simulation, for both objectives
+///
+/// It is finite, so the selection weights stay valid, it just makes the
+/// simulation very unlikely to become a parent
+const DEADLOCK_COST: f64 = 1e9;
+/// the costs are clamped to at least this value before being inverted for the
+/// selection weights, so a cost of 0 (e.g. no cars were spawned) doesn't give
+/// an infinite weight
+const MIN_SELECTION_COST: f64 = 1e-6;
+
+/// The sending half of the channel transmitting [StatusUpdate]s to the frontend
+///
+/// The channel is bounded. If the simulation produces updates faster than the
+/// frontend can display them, the oldest update is dropped, so the latest state
+/// is always delivered without the buffer growing indefinitely.
+#[derive(Clone)]
+pub struct StatusSender {
+    tx: mpsc::SyncSender<StatusUpdate>,
+    /// used to remove the oldest update if the channel is full
+    rx: Arc<Mutex<mpsc::Receiver<StatusUpdate>>>,
+    dropped_frames: IntMut<usize>,
+}
+
+impl StatusSender {
+    /// Creates a new bounded channel
+    ///
+    /// Returns the sender, the receiver (which is shared with the sender) and
+    /// the counter of dropped frames
+    pub fn new(capacity: usize) -> (StatusSender, Arc<Mutex<mpsc::Receiver<StatusUpdate>>>, IntMut<usize>) {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let rx = Arc::new(Mutex::new(rx));
+        let dropped_frames = IntMut::new(0);
+        let sender = StatusSender {
+            tx,
+            rx: rx.clone(),
+            dropped_frames: dropped_frames.clone(),
+        };
+        (sender, rx, dropped_frames)
+    }
+
+    /// Sends the update, dropping the oldest update in the channel if it is full
+    pub fn send(&self, mut update: StatusUpdate) -> Result<(), mpsc::TrySendError<StatusUpdate>> {
+        loop {
+            match self.tx.try_send(update) {
+                Ok(()) => return Ok(()),
+                Err(mpsc::TrySendError::Full(rejected)) => {
+                    // make room for the new update
+                    if self.rx.lock().unwrap().try_recv().is_ok() {
+                        *self.dropped_frames.get() += 1;
+                    }
+                    update = rejected;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Summary of a single generation
+#[derive(Debug, Clone)]
 pub struct GenerationReport {
+    /// the lowest cost of all simulations in the generation
     pub cost: f64,
-    pub tonnes_co2: f64
+    /// the CO2 of the simulation with the lowest cost
+    pub tonnes_co2: f64,
+    /// the most iterations any simulation ran in this generation
+    pub iterations: u32,
+    /// the longest queue at any crossing in any simulation of this generation
+    pub max_queue: u32,
+    /// how many steps all simulations of the generation ran per second of real time
+    pub steps_per_second: f64,
+}
+
+/// Determines when the simulations of a generation stop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopCondition {
+    /// stop after the number of iterations
+    Iterations(u32),
+    /// stop after the simulated time in seconds (the sum of all `dt`)
+    SimTime(f32),
+    /// stop after the real time passed, regardless of how fast the machine is
+    WallClock(Duration),
+}
+
+impl Default for StopCondition {
+    fn default() -> Self {
+        StopCondition::Iterations(3000)
+    }
+}
+
+impl StopCondition {
+    /// true if a simulation that ran for the given iterations, simulated
+    /// time and real time should stop
+    pub fn is_met(&self, iterations: u32, sim_time: f32, elapsed: Duration) -> bool {
+        match self {
+            StopCondition::Iterations(max) => iterations >= *max,
+            StopCondition::SimTime(max) => sim_time >= *max,
+            StopCondition::WallClock(max) => elapsed >= *max,
+        }
+    }
+    /// the number of steps left until the condition is met, or None if that
+    /// can't be told in advance
+    fn remaining_steps(&self, iterations: u32, sim_time: f32, dt: f32) -> Option<usize> {
+        match self {
+            StopCondition::Iterations(max) => Some(max.saturating_sub(iterations) as usize),
+            StopCondition::SimTime(max) => Some(((*max - sim_time) / dt).ceil().max(0.0) as usize),
+            StopCondition::WallClock(_) => None,
+        }
+    }
+}
+
+/// Determines how the simulations of a generation are compared when
+/// choosing the parents of the next generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitnessMode {
+    /// only the blended cost (see [Simulator::calculate_sim_cost]) is used
+    #[default]
+    Scalar,
+    /// the blended cost and the CO2 are treated as two objectives and the
+    /// simulations are ranked by non-dominated sorting
+    ParetoCo2Speed,
+}
+
+impl FitnessMode {
+    /// the weight of each simulation when choosing parents (higher is better)
+    ///
+    /// A NaN cost gets a weight of 0. If no simulation has a positive weight,
+    /// all of them get the same weight, so the parents are chosen uniformly
+    fn selection_weights(&self, costs: &[[f64; 2]]) -> Vec<f64> {
+        let weights: Vec<f64> = match self {
+            FitnessMode::Scalar => costs
+                .iter()
+                .map(|cost| match cost[0].is_nan() {
+                    true => 0.0,
+                    false => (1.0 / cost[0].max(MIN_SELECTION_COST)).pow(2),
+                })
+                .collect(),
+            FitnessMode::ParetoCo2Speed => pareto_fronts(costs)
+                .iter()
+                .map(|front| (1.0 / (*front + 1) as f64).pow(2))
+                .collect(),
+        };
+        if weights.iter().all(|weight| *weight <= 0.0) {
+            warn!("No simulation has a positive selection weight, choosing the parents uniformly");
+            return vec![1.0; weights.len()];
+        }
+        weights
+    }
+}
+
+/// true if `a` is at least as good as `b` in both objectives and better in
+/// at least one (lower is better)
+fn dominates(a: &[f64; 2], b: &[f64; 2]) -> bool {
+    a[0] <= b[0] && a[1] <= b[1] && (a[0] < b[0] || a[1] < b[1])
+}
+
+/// Sorts the costs into Pareto fronts and returns the front of every cost
+///
+/// Front 0 is the non-dominated front, front 1 is dominated only by costs in
+/// front 0 and so on.
+pub fn pareto_fronts(costs: &[[f64; 2]]) -> Vec<usize> {
+    let n = costs.len();
+    // the indices each cost dominates and the number of costs dominating it
+    let mut dominated: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut domination_count = vec![0; n];
+    for i in 0..n {
+        for j in 0..n {
+            if dominates(&costs[i], &costs[j]) {
+                dominated[i].push(j);
+            } else if dominates(&costs[j], &costs[i]) {
+                domination_count[i] += 1;
+            }
+        }
+    }
+    let mut fronts = vec![0; n];
+    let mut current: Vec<usize> = (0..n).filter(|i| domination_count[*i] == 0).collect();
+    let mut front = 0;
+    while !current.is_empty() {
+        let mut next = Vec::new();
+        for &i in current.iter() {
+            fronts[i] = front;
+            for &j in dominated[i].iter() {
+                domination_count[j] -= 1;
+                if domination_count[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        current = next;
+        front += 1;
+    }
+    fronts
 }
 
 
@@ -45,8 +276,15 @@ pub struct Simulating {
     /// set to report updates with `report_updates`
     ///
     /// Unfortunatly, this field has to be wrapped  in a Mutex so it implements the
-    /// [Sync] trait. (Which is required by bevy)
-    pub car_updates: Mutex<mpsc::Receiver<HashMap<usize, Vec<MovableStatus>>>>,
+    /// [Sync] trait. (Which is required by bevy) An [IntMut] doesn't work here, because
+    /// its [std::sync::RwLock] is only [Sync] if the receiver is.
+    ///
+    /// It is shared with the [StatusSender]s, which remove the oldest update if the
+    /// channel is full
+    pub car_updates: Arc<Mutex<mpsc::Receiver<StatusUpdate>>>,
+    /// the number of status updates that were dropped because the frontend
+    /// didn't receive them fast enough
+    dropped_frames: IntMut<usize>,
     /// if this bool is set to true, the Simulators will terminate. This is forceful termination
     pub terminate: IntMut<bool>,
     /// this bool is set by the thread executing the simulations and reports if all simulation has ended
@@ -66,52 +304,194 @@ pub struct Simulating {
 /// used to encapsulate data used when creating a Simulator
 pub struct SimData {
     pub simulator: Simulator,
-    pub channel: Mutex<mpsc::Sender<HashMap<usize, Vec<MovableStatus>>>>,
+    pub channel: StatusSender,
     pub report_updates:  IntMut<bool>,
     pub terminate: IntMut<bool>,
     pub terminate_generation: IntMut<bool>,
     pub id: usize,
+    /// set if the simulation stopped early in the last generation because no
+    /// car reached its destination for [DEADLOCK_TIME]
+    pub deadlocked: bool,
 }
    
+/// Builds the simulations of the first generation
+///
+/// Without a seed, the neural networks are initialised randomly. With a seed,
+/// the first simulation gets an exact copy of it and the others get mutated
+/// copies, so the evolution continues from the seed.
+fn new_population(
+    sim_builder: &mut SimulatorBuilder,
+    mv_server: &MovableServer,
+    population: usize,
+    seed: Option<&Vec<Network>>,
+    mutation_coeff: f32,
+    weight_limit: f32,
+    nn_topology: &[LayerTopology],
+) -> Vec<Simulator> {
+    let mut rng = thread_rng();
+    (0..population).map( | i | {
+        let mut sim = sim_builder
+            .build(mv_server)
+            .expect("The SimulatorBuilder has to be validated before simulating");
+        match seed {
+            Some(nns) => {
+                let mut nns = nns.clone();
+                if i > 0 {
+                    mutate_sim_nns(&mut rng, &mut nns, 1.0, mutation_coeff, weight_limit);
+                }
+                if let Err(err) = sim.set_neural_networks(nns) {
+                    error!("Can't use the seed: {}, using random neural networks", err);
+                    sim.init_neural_networks_random(nn_topology);
+                }
+            }
+            None => sim.init_neural_networks_random(nn_topology),
+        }
+        sim
+    }).collect()
+}
+
+impl SimData {
+    /// Simulates until the stop condition is met, the generation is terminated
+    /// or the simulation is deadlocked (see [SimData::deadlocked])
+    ///
+    /// Returns the number of iterations and the simulated time in seconds
+    fn simulate_until(&mut self, stop_condition: StopCondition, rng: &mut ThreadRng) -> (u32, f32) {
+        let mut iterations = 0;
+        let mut sim_time = 0.0;
+        let start = Instant::now();
+        self.deadlocked = false;
+        // the simulated time a car last reached its destination (or no car was driving)
+        let mut last_progress = 0.0;
+        let mut absorbed_cars = self.simulator.count_absorbed_cars();
+        // the number of steps simulated before each report to the frontend
+        let max_steps = self.simulator.speed_multiplier.max(1);
+        while !*self.terminate_generation.get() && !*self.terminate.get() {
+            if stop_condition.is_met(iterations, sim_time, start.elapsed()) {
+                break
+            }
+            // the last chunk must not overshoot the stop condition
+            let steps = stop_condition
+                .remaining_steps(iterations, sim_time, self.simulator.dt)
+                .map_or(max_steps, |remaining| max_steps.min(remaining.max(1)));
+            self.simulator.sim_steps_with_rng(steps, rng);
+            iterations += steps as u32;
+            sim_time += steps as f32 * self.simulator.dt;
+            let absorbed = self.simulator.count_absorbed_cars();
+            if absorbed > absorbed_cars || self.simulator.count_cars() == 0 {
+                absorbed_cars = absorbed;
+                last_progress = sim_time;
+            } else if sim_time - last_progress >= DEADLOCK_TIME {
+                warn!("Simulation {} is deadlocked, stopping it after {} iterations", self.id, iterations);
+                self.deadlocked = true;
+                break
+            }
+            let report_updates = *self.report_updates.get();
+            self.simulator.set_car_recording(report_updates);
+            if report_updates {
+                let cars = self.simulator.get_car_status();
+                let updates = StatusUpdate::new(&mut self.simulator, cars);
+                self.channel.send(updates).expect("Unable to send car status updates, even though report_updates is set to true");
+            }
+        }
+        (iterations, sim_time)
+    }
+
+    /// the cost of the simulation, [DEADLOCK_COST] if it is deadlocked
+    fn cost(&self) -> [f64; 2] {
+        match self.deadlocked {
+            true => [DEADLOCK_COST; 2],
+            false => self.simulator.calculate_sim_cost(),
+        }
+    }
+}
+
+/// Simulates a generation, every simulation until the stop condition is met
+///
+/// Each simulation stops on its own (e.g. when it is deadlocked), the others
+/// continue. Returns the simulations and the iterations each of them ran.
+fn simulate_generation(sims: Vec<SimData>, stop_condition: StopCondition, min_len: usize, generation: usize) -> (Vec<SimData>, Vec<u32>) {
+    sims.into_par_iter()
+        .with_min_len(min_len)
+        // every worker gets one rng that is used for all the simulations it handles
+        .map_init(thread_rng, move | sim_rng, mut data | {
+            // delete old cars
+            let deleted_cars = data.simulator.reset_cars();
+            let status_updates = StatusUpdate::new(&mut data.simulator, deleted_cars);
+            if *data.report_updates.get() {
+                data.channel.send(status_updates).expect("Unable to send car status updates, even though report_updates is set to true");
+            }
+            let span = span!(Level::TRACE, "simulation", sim_index=generation);
+            let _enter = span.enter();
+            info!("starting Simulation thread");
+            panic::set_hook(Box::new(|e| {
+                error!("Simulation panicked! Backtrace: {}", e);
+            }));
+            let (iterations, _sim_time) = data.simulate_until(stop_condition, sim_rng);
+            (data, iterations)
+        }).unzip()
+}
+
+/// The parameters of the evolution run by [Simulating]
+///
+/// See the fields of [SimManager] with the same names
+#[derive(Debug, Clone)]
+pub struct EvolutionSettings {
+    pub population: usize,
+    pub generations: usize,
+    pub mutation_chance: f32,
+    pub mutation_coeff: f32,
+    pub weight_limit: f32,
+    pub stop_condition: StopCondition,
+    pub fitness_mode: FitnessMode,
+    pub nn_topology: Vec<LayerTopology>,
+    pub exploration_temperature: Option<f32>,
+}
+
 impl Simulating {
     /// Creates new simulations and runs them in different threads using the rayon crate
+    ///
+    /// Without a seed, the neural networks of the first generation are random
+    /// (see [SimManager::seed_population_from])
     pub fn new(
         sim_builder: &mut SimulatorBuilder,
         mv_server: &MovableServer,
-        population: usize,
-        generations: usize,
-        mutation_chance: f32,
-        mutation_coeff: f32,
-        stop_iterations: u32
+        settings: EvolutionSettings,
+        seed: Option<Vec<Network>>,
     ) -> Simulating {
         debug!("creating new Simulating");
+        let EvolutionSettings {
+            population,
+            generations,
+            mutation_chance,
+            mutation_coeff,
+            weight_limit,
+            stop_condition,
+            fitness_mode,
+            nn_topology,
+            exploration_temperature,
+        } = settings;
         // create all the necessary variables for the simulation thread to later use them in a
         // parallel iterator
         let terminate_generation = IntMut::new(false);
         let report_updates = (0..population).map( | _ | IntMut::new(false)).collect::<Vec<IntMut<bool>>>();
-        let (car_tx, car_rx) = mpsc::channel();
+        let (car_tx, car_rx, dropped_frames) = StatusSender::new(STATUS_CHANNEL_CAPACITY);
         let (report_tx, report_rx) = mpsc::channel();
         let terminate = IntMut::new(false);
         let mut simulation_information = Vec::with_capacity(population);
-        let simulation_data: Vec<SimData> =  (0..population).map( | i | {
-            let mut sim = sim_builder.build(mv_server);
-            sim.init_neural_networks_random(
-            &[
-                    LayerTopology::new(16),
-                    LayerTopology::new(14),
-                    LayerTopology::new(8),
-                    LayerTopology::new(4),
-                    LayerTopology::new(0).with_activation(ActivationFunc::SoftMax),
-                ]
-            );
+        let simulation_data: Vec<SimData> = new_population(sim_builder, mv_server, population, seed.as_ref(), mutation_coeff, weight_limit, &nn_topology)
+            .into_iter()
+            .enumerate()
+            .map( | (i, mut sim) | {
+            sim.set_exploration_temperature(exploration_temperature);
             simulation_information.push(SimulationStatus::new());
             SimData {
                 simulator: sim,
-                channel: Mutex::new(car_tx.clone()),
+                channel: car_tx.clone(),
                 report_updates: report_updates[i].clone(),
                 terminate: terminate.clone(),
                 terminate_generation: terminate_generation.clone(),
                 id: i,
+                deadlocked: false,
             }
         }).collect();
         // drop the inital transmitter to prevent having a transmitter that does nothing
@@ -127,61 +507,64 @@ impl Simulating {
             let mut rng = thread_rng();
             let mut terminated_sims: Vec<SimData> = simulation_data;
             let cpus = num_cpus::get();
-            let min_num = (population as f32 / cpus as f32).ceil() as usize;
+            let min_num = ((population as f32 / cpus as f32).ceil() as usize).max(1);
+            // the pool lives as long as the thread, so the workers are reused
+            // for every generation
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(cpus)
+                .build()
+                .expect("Unable to create thread pool for the simulations");
             for generation in 0..generations {
-                terminated_sims = terminated_sims.into_par_iter()
-                // .with_min_len(min_num)
-                 .map( move | mut data | {
-                    // delete old cars
-                    let status_updates = data.simulator.reset_cars();
-                    if *data.report_updates.get() {
-                        data.channel.lock().unwrap().send(status_updates).expect("Unable to send car status updates, even though report_updates is set to true");
-                    }
-                    let span = span!(Level::TRACE, "simulation", sim_index=generation);
-                    let _enter = span.enter();
-                    info!("starting Simulation thread");
-                    panic::set_hook(Box::new(|e| {
-                        error!("Simulation panicked! Backtrace: {}", e);
-                    }));
-                    let mut i = 0;
-                    let mut previous_tracking_setting = false;
-                    while !*data.terminate_generation.get() &&  !*data.terminate.get() {
-                        i += 1;
-                        if i > stop_iterations {
-                            break
-                        }
-                        data.simulator.sim_iter();
-                        let report_updates = *data.report_updates.get();
-                        data.simulator.set_car_recording(report_updates);
-                        if report_updates {
-                            let updates = data.simulator.get_car_status();
-                            data.channel.lock().unwrap().send(updates).expect("Unable to send car status updates, even though report_updates is set to true");
-                        }
-                    }
-                    // println!("Number of cars in Simulation {}: {} ({})", data.id, data.simulator.count_cars(), i);
-                    data
-                }).collect();
+                let generation_start = Instant::now();
+                let (sims, iterations) = pool.install(|| simulate_generation(terminated_sims, stop_condition, min_num, generation));
+                terminated_sims = sims;
+                let elapsed = generation_start.elapsed();
+                let steps: u64 = iterations.iter().map(| i | *i as u64).sum();
                 if !*terminate_thread.get() {
+                    let max_queue = terminated_sims.iter().map(| s | s.simulator.max_queue()).max().unwrap_or(0);
                         // TODO: Maybe make this more efficient
                     let old_nns_and_costs: Vec<([f64; 2], Vec<Network>)> = terminated_sims.iter_mut().map(
-                        | s | (s.simulator.calculate_sim_cost(), s.simulator.remove_all_neural_networks())
+                        | s | (s.cost(), s.simulator.remove_all_neural_networks())
                     ).collect();
                     let min_cost = old_nns_and_costs.iter().fold( [f64::INFINITY; 2], | [a1, a2], ([b1, b2], _) | if a1 < *b1 {[a1, a2]} else {[*b1, *b2]});
                     report_tx.send(GenerationReport {
                         cost: min_cost[0],
                         tonnes_co2: min_cost[1],
+                        iterations: iterations.iter().copied().max().unwrap_or(0),
+                        max_queue,
+                        steps_per_second: steps as f64 / elapsed.as_secs_f64(),
                     }).unwrap();
                     old_nns_and_costs.iter().for_each(| ([c, _], _) | {
                         if *c == f64::INFINITY || (1.0_f64 / *c).is_nan()  {
                             println!("Oh Shit!")
                         }
                     });
-                    terminated_sims.iter_mut().for_each( | s | {
-                        let parent_a = &old_nns_and_costs.choose_weighted(&mut rng, | (cost, _nns) | (1.0/(cost[0]) as f64).pow(2)).expect("Empty population").1;
-                        let parent_b = &old_nns_and_costs.choose_weighted(&mut rng, | (cost, _nns) | (1.0/(cost[0])as f64).pow(2)).expect("Empty population").1;
-                        let mut crossed = crossover_sim_nns(parent_a, parent_b, &mut rng);
-                        mutate_sim_nns(&mut rng, &mut crossed, mutation_chance, mutation_coeff);
-                        s.simulator.set_neural_networks(crossed);
+                    let costs: Vec<[f64; 2]> = old_nns_and_costs.iter().map(| (cost, _) | *cost).collect();
+                    let weighted_nns: Vec<(f64, &Vec<Network>)> = fitness_mode
+                        .selection_weights(&costs)
+                        .into_iter()
+                        .zip(old_nns_and_costs.iter().map(| (_, nns) | nns))
+                        .collect();
+                    terminated_sims.iter_mut().zip(old_nns_and_costs.iter()).for_each( | (s, (_, old_nns)) | {
+                        let parent_a = weighted_nns.choose_weighted(&mut rng, | (weight, _nns) | *weight).expect("Empty population").1;
+                        let parent_b = weighted_nns.choose_weighted(&mut rng, | (weight, _nns) | *weight).expect("Empty population").1;
+                        let mut crossed = crossover_sim_nns(parent_a, parent_b, &mut rng, weight_limit)
+                            .unwrap_or_else(| err | {
+                                // all simulations are built from the same builder, so this shouldn't happen
+                                error!("{}, keeping the nns of the first parent", err);
+                                parent_a.clone()
+                            });
+                        let sanitized = mutate_sim_nns(&mut rng, &mut crossed, mutation_chance, mutation_coeff, weight_limit);
+                        if sanitized > 0 {
+                            debug!("Clamped {} weights that left the allowed range after mutation", sanitized);
+                        }
+                        if let Err(err) = s.simulator.set_neural_networks(crossed) {
+                            error!("{}, keeping the nns of the last generation", err);
+                            // they were removed from the simulation above
+                            if let Err(err) = s.simulator.set_neural_networks(old_nns.clone()) {
+                                error!("Unable to restore the nns of the last generation: {}", err);
+                            }
+                        }
                     });
 
                 }
@@ -190,7 +573,8 @@ impl Simulating {
             terminated_sims
         });
         Simulating {
-            car_updates: Mutex::new(car_rx),
+            car_updates: car_rx,
+            dropped_frames,
             terminate,
             terminated,
             current_generation: IntMut::new(0),
@@ -206,6 +590,11 @@ impl Simulating {
     pub fn has_terminated(&self) -> bool {
         *self.terminated.get()
     }
+    /// The number of status updates that were dropped, because the frontend
+    /// didn't receive them fast enough
+    pub fn dropped_frames(&self) -> usize {
+        *self.dropped_frames.get()
+    }
     /// tracks the specified simulation if it exists
     ///  (and untracks all other simulations)
     pub fn track_simulation(&mut self, i: usize) -> Result<(), String> {
@@ -216,47 +605,85 @@ impl Simulating {
         self.report_updates.iter_mut().enumerate().for_each( | (j, do_report) | {
             *do_report.get() = i == j
         });
+        self.simulation_information.iter_mut().enumerate().for_each( | (j, status) | {
+            status.displaying = i == j
+        });
+        // the buffered updates are from the previously tracked simulation
+        self.car_updates.lock().unwrap().try_iter().for_each(drop);
         Ok(())
     }
+    /// the index of the tracked simulation, if there is one
+    pub fn tracked_simulation(&self) -> Option<usize> {
+        self.report_updates.iter().position( | do_report | *do_report.get())
+    }
     pub fn terminate(&mut self) -> Result<SimulationReport, String> {
         *self.terminate.get() = true;
         if let Some(handle) = self.generation_thread_handle.take() {
             match handle.join() {
                 Ok(sim_data) => {
                     info!("Terminated thread handling Simulations");
-                    return Ok(SimulationReport::new(sim_data))
+                    self.collect_generation_reports();
+                    let history = std::mem::take(&mut self.generation_reports);
+                    return Ok(SimulationReport::new(sim_data, history))
                 },
                 Err(err) => return Err(format!("Could not terminate thread handling Simulations: {:?}", err)),
             }
         }
         Err("No thread to terminate".to_string())
     }
+    /// moves the reports of finished generations from the channel to
+    /// `generation_reports`
+    pub fn collect_generation_reports(&mut self) {
+        for report in self.reports_channel.lock().unwrap().try_iter() {
+            self.generation_reports.push(report);
+        }
+    }
+    /// Terminates all simulations and waits for the thread handling them to finish
+    ///
+    /// Unlike [Simulating::terminate], no report is generated. This function can
+    /// safely be called multiple times, even if the thread has already been joined.
+    pub fn shutdown(&mut self) {
+        *self.terminate.get() = true;
+        if let Some(handle) = self.generation_thread_handle.take() {
+            match handle.join() {
+                Ok(_) => info!("Terminated thread handling Simulations"),
+                Err(err) => error!("Could not terminate thread handling Simulations: {:?}", err),
+            }
+        }
+    }
 }
 
 impl Drop for Simulating {
     /// Terminate all simulation and wait for them to finish
     fn drop(&mut self) {
-        self.terminate();
+        self.shutdown();
     }
 }
     
 
     
 pub struct SimulationReport {
-    pub sims: Vec<(f64, SimData)>
+    pub sims: Vec<(f64, SimData)>,
+    /// the reports of all generations that finished, in order
+    pub history: Vec<GenerationReport>,
 }
 
 impl SimulationReport {
-    pub fn new(mut sims: Vec<SimData>) -> SimulationReport {
-        let mut sims: Vec<(f64, SimData)> = sims.drain(..).map( | s | (s.simulator.calculate_sim_cost()[0], s)).collect();
+    pub fn new(mut sims: Vec<SimData>, history: Vec<GenerationReport>) -> SimulationReport {
+        let mut sims: Vec<(f64, SimData)> = sims.drain(..).map( | s | (s.cost()[0], s)).collect();
         sims.sort_by(| a, b | a.0.partial_cmp(&b.0).unwrap());
         SimulationReport {
             sims: sims,
+            history,
         }
     }
     pub fn get_best_nn(&self) -> Vec<Network> {
         self.sims[0].1.simulator.get_all_neural_networks()
     }
+    /// the cars absorbed per IONode by the best simulation
+    pub fn get_best_throughput(&self) -> HashMap<usize, usize> {
+        self.sims[0].1.simulator.throughput_report()
+    }
 }
 
 /// This struct saves a list of currently simulating Simulators
@@ -268,16 +695,21 @@ pub struct SimManager {
     /// the sim builder generates new simulations and can be used to
     /// configure them (before simulating)
     sim_builder: SimulatorBuilder, 
+    /// whether the sim builder was handed out for modification since it was
+    /// last saved
+    builder_changed: bool,
     /// A list of currently running Simulators
     pub simulations: Option<Simulating>,
     /// how likely the nn is to mutate
     pub mutation_chance: f32,
     /// how strongly it mutates if it mutates
     pub mutation_coeff: f32,
+    /// the maximum absolute value of the weights after crossover and mutation
+    pub weight_limit: f32,
     /// 
     is_simulating: bool,
-    ///
-    pub stop_iterations: u32,
+    /// when the simulations of a generation stop
+    pub stop_condition: StopCondition,
     /// the number of generations that should be simulated
     pub generations: usize,
     /// the size of each population in a generation
@@ -285,7 +717,19 @@ pub struct SimManager {
     /// saves the status report of the last simulation
     pub simulation_report: Option<SimulationReport>,
     /// 
-    pub disable_tracking: bool
+    pub disable_tracking: bool,
+    /// how the simulations are compared when choosing parents
+    pub fitness_mode: FitnessMode,
+    /// the neural networks the next run starts from (see [SimManager::seed_population_from])
+    seed: Option<Vec<Network>>,
+    /// the topology of the randomly initialised neural networks
+    /// (see [validate_nn_topology])
+    pub nn_topology: Vec<LayerTopology>,
+    /// if set, the crossings sample their states with this SoftMax temperature
+    /// instead of always choosing the highest output (see [Simulator::set_exploration_temperature])
+    pub exploration_temperature: Option<f32>,
+    /// receives the run recorded in the background (see [SimManager::record_run_in_background])
+    recording: Option<Mutex<mpsc::Receiver<Recording>>>,
 }
 
 /// This error is returned if one tries to modify the SimulatorBuilder while a Simulation is running
@@ -318,6 +762,98 @@ impl Display for SimulationDoesNotExistError {
     }
 }
 
+/// This error is returned if a neural network topology can't be used
+/// for the crossings
+#[derive(Debug)]
+pub struct InvalidTopologyError {
+    /// why the topology can't be used
+    pub msg: String,
+}
+
+impl Display for InvalidTopologyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid neural network topology: {}", self.msg)
+    }
+}
+
+impl Error for InvalidTopologyError {}
+
+/// The topology used for the neural networks of the crossings by default
+pub fn default_nn_topology() -> Vec<LayerTopology> {
+    vec![
+        LayerTopology::new(NN_INPUTS),
+        LayerTopology::new(14),
+        LayerTopology::new(8),
+        LayerTopology::new(NN_OUTPUTS).with_activation(ActivationFunc::SoftMax),
+    ]
+}
+
+/// Checks that the topology can be used for the neural networks of the crossings
+///
+/// The first layer has to take the inputs of [crate::nodes::Crossing::calculate_nn_inputs]
+/// and the network has to output one value per traffic light state.
+pub fn validate_nn_topology(topology: &[LayerTopology]) -> Result<(), InvalidTopologyError> {
+    if topology.len() < 2 {
+        return Err(InvalidTopologyError {
+            msg: format!("expected at least 2 layers, got {}", topology.len()),
+        });
+    }
+    if topology[0].neurons != NN_INPUTS {
+        return Err(InvalidTopologyError {
+            msg: format!("the first layer needs {} neurons, got {}", NN_INPUTS, topology[0].neurons),
+        });
+    }
+    if topology.iter().any(|layer| layer.neurons == 0) {
+        return Err(InvalidTopologyError {
+            msg: "every layer needs at least one neuron".to_string(),
+        });
+    }
+    let width = topology[topology.len() - 1].neurons;
+    if width != NN_OUTPUTS {
+        return Err(InvalidTopologyError {
+            msg: format!("the network needs {} outputs, got {}", NN_OUTPUTS, width),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the network can be used for a crossing
+///
+/// Unlike [validate_nn_topology], the weights of the neurons are checked as well,
+/// e.g. a network with an empty layer is rejected (see [Network::check_layers])
+pub(crate) fn validate_nn(nn: &Network) -> Result<(), InvalidTopologyError> {
+    nn.check_layers()
+        .map_err(|err| InvalidTopologyError { msg: err.msg })?;
+    validate_nn_topology(&nn.topology())
+}
+
+/// simulates `steps` steps and returns the recording of all of them
+fn record_steps(sim: &mut Simulator, steps: usize) -> Recording {
+    sim.start_recording();
+    sim.sim_steps(steps);
+    sim.stop_recording().unwrap_or_default()
+}
+
+/// This error is returned if the seed for the population doesn't have one
+/// neural network for every crossing
+#[derive(Debug)]
+pub struct SeedMismatchError {
+    pub networks: usize,
+    pub crossings: usize,
+}
+
+impl Display for SeedMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The seed has {} neural networks, but the street network has {} crossings",
+            self.networks, self.crossings
+        )
+    }
+}
+
+impl Error for SeedMismatchError {}
+
 impl SimManager {
     /// creates a new SimManager with an empty SimulationBuilder
     pub fn new() -> SimManager {
@@ -326,15 +862,22 @@ impl SimManager {
         SimManager {
             movable_server,
             sim_builder: sim_builder,
+            builder_changed: false,
             simulations: None,
             mutation_chance: 0.0001,
             mutation_coeff: 0.01,
+            weight_limit: DEFAULT_WEIGHT_LIMIT,
             is_simulating: false,
             population: 1000,
             generations: 100,
             simulation_report: None,
-            stop_iterations: 3000,
+            stop_condition: StopCondition::default(),
             disable_tracking: true,
+            fitness_mode: FitnessMode::default(),
+            seed: None,
+            nn_topology: default_nn_topology(),
+            exploration_temperature: None,
+            recording: None,
         }
     }
     /// Returns a mutable reference to the SimulatorBuilder, if no Simulation
@@ -347,8 +890,29 @@ impl SimManager {
                 msg: "Cannot modify SimulatorBuilder, as Simulations are running.",
             });
         }
+        self.builder_changed = true;
         return Ok(&mut self.sim_builder);
     }
+    /// Returns the SimulatorBuilder without marking it as changed, e.g. for
+    /// saving it
+    pub fn sim_builder(&self) -> &SimulatorBuilder {
+        &self.sim_builder
+    }
+    /// Whether the SimulatorBuilder might have changed since
+    /// [SimManager::mark_builder_saved] was last called
+    pub fn builder_changed(&self) -> bool {
+        self.builder_changed
+    }
+    /// Marks the SimulatorBuilder as changed, for modifications that didn't
+    /// go through [SimManager::modify_sim_builder]
+    pub fn mark_builder_changed(&mut self) {
+        self.builder_changed = true;
+    }
+    /// Marks the SimulatorBuilder as saved, so that it only counts as
+    /// changed after the next modification
+    pub fn mark_builder_saved(&mut self) {
+        self.builder_changed = false;
+    }
     /// Starts simulating 
     pub fn simulate(&mut self) -> Result<(), Box<dyn Error>> {
         // are any simulations still running?
@@ -358,33 +922,177 @@ impl SimManager {
                 msg: "Can not start new simulations while old ones are still running.",
             }));
         }
+        // make sure building the simulations won't fail
+        self.sim_builder.validate().map_err(ValidationError)?;
+        validate_nn_topology(&self.nn_topology)?;
+        if let Some(seed) = &self.seed {
+            let crossings = self.sim_builder.nodes.iter().filter(| n | matches!(&*n.get(), NodeBuilder::Crossing(_))).count();
+            if seed.len() != crossings {
+                return Err(Box::new(SeedMismatchError {
+                    networks: seed.len(),
+                    crossings,
+                }));
+            }
+        }
         // index nodes
         self.movable_server
             .register_simulator_builder(&self.sim_builder);
+        for (start, end) in self.movable_server.check_all_io_pairs() {
+            warn!("There is no path from IONode {} to IONode {}, no cars will drive between them", start, end);
+        }
         self.simulations = Some(
             Simulating::new(
                 &mut self.sim_builder,
                 &self.movable_server,
-                self.population, 
-                self.generations,
-                self.mutation_chance,
-                self.mutation_coeff,
-                self.stop_iterations
+                EvolutionSettings {
+                    population: self.population,
+                    generations: self.generations,
+                    mutation_chance: self.mutation_chance,
+                    mutation_coeff: self.mutation_coeff,
+                    weight_limit: self.weight_limit,
+                    stop_condition: self.stop_condition,
+                    fitness_mode: self.fitness_mode,
+                    nn_topology: self.nn_topology.clone(),
+                    exploration_temperature: self.exploration_temperature,
+                },
+                self.seed.take(),
             )
         );
         self.is_simulating = true;
         Ok(())
     }
 
+    /// Uses the given neural networks (one per crossing, e.g. the best networks of
+    /// a saved simulation) as the initial population of the next run instead of
+    /// random networks
+    ///
+    /// Returns an error if a network can't be used for a crossing (see [validate_nn]),
+    /// e.g. because a layer of a loaded network is empty. Whether there is one network
+    /// per crossing is checked when the simulation starts.
+    pub fn seed_population_from(&mut self, nns: Vec<Network>) -> Result<(), NeuralNetworkError> {
+        for (i, nn) in nns.iter().enumerate() {
+            validate_nn(nn).map_err(|err| NeuralNetworkError::InvalidTopology(i, err))?;
+        }
+        self.seed = Some(nns);
+        Ok(())
+    }
+
+    /// Simulates the street network once with `controller` at every crossing
+    /// (e.g. a [crate::controllers::FixedTimerController]) until the stop
+    /// condition is met and returns the cost
+    ///
+    /// Nothing evolves, so this is a baseline for the costs of the neural networks
+    pub fn evaluate_controller(
+        &mut self,
+        controller: &(dyn TrafficController + 'static),
+    ) -> Result<[f64; 2], Box<dyn Error>> {
+        if self.simulations.iter().any(|s| !s.has_terminated()) {
+            return Err(Box::new(SimulationRunningError {
+                msg: "Can not evaluate a controller while simulations are running.",
+            }));
+        }
+        self.sim_builder.validate().map_err(ValidationError)?;
+        self.movable_server
+            .register_simulator_builder(&self.sim_builder);
+        let mut sim = self.sim_builder.build(&self.movable_server)?;
+        sim.set_traffic_controllers(controller);
+        let mut rng = thread_rng();
+        let mut iterations = 0;
+        let mut sim_time = 0.0;
+        let start = Instant::now();
+        while !self.stop_condition.is_met(iterations, sim_time, start.elapsed()) {
+            sim.update_all_nodes_with_rng(sim.dt.into(), &mut rng);
+            iterations += 1;
+            sim_time += sim.dt;
+        }
+        Ok(sim.calculate_sim_cost())
+    }
+
+    /// Simulates the street network once for `steps` steps and records every step
+    /// (see [crate::Recording])
+    ///
+    /// The crossings use the best neural networks of the last run, or random ones
+    /// if there was no run yet or the networks don't fit the current street network
+    pub fn record_run(&mut self, steps: usize) -> Result<Recording, Box<dyn Error>> {
+        let mut sim = self.build_recording_sim()?;
+        Ok(record_steps(&mut sim, steps))
+    }
+
+    /// Like [SimManager::record_run], but the steps are simulated on the thread
+    /// pool, so the caller isn't blocked
+    ///
+    /// The recording can be taken with [SimManager::take_recording] once it is done.
+    /// A recording that is still running is dropped
+    pub fn record_run_in_background(&mut self, steps: usize) -> Result<(), Box<dyn Error>> {
+        let mut sim = self.build_recording_sim()?;
+        let (tx, rx) = mpsc::channel();
+        rayon::spawn(move || {
+            // the receiver is gone if the recording has been dropped in the meantime
+            let _ = tx.send(record_steps(&mut sim, steps));
+        });
+        self.recording = Some(Mutex::new(rx));
+        Ok(())
+    }
+
+    /// Is a run being recorded in the background (see [SimManager::record_run_in_background])?
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// returns the recording started with [SimManager::record_run_in_background],
+    /// if it is done
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        let recording = match self.recording.as_ref()?.lock().unwrap().try_recv() {
+            Ok(recording) => Some(recording),
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                error!("The recording thread stopped without a recording");
+                None
+            }
+        };
+        self.recording = None;
+        recording
+    }
+
+    /// builds the simulation that is recorded by [SimManager::record_run]
+    fn build_recording_sim(&mut self) -> Result<Simulator, Box<dyn Error>> {
+        if self.simulations.iter().any(|s| !s.has_terminated()) {
+            return Err(Box::new(SimulationRunningError {
+                msg: "Can not record a run while simulations are running.",
+            }));
+        }
+        self.sim_builder.validate().map_err(ValidationError)?;
+        self.movable_server
+            .register_simulator_builder(&self.sim_builder);
+        let mut sim = self.sim_builder.build(&self.movable_server)?;
+        // the street network or the topology may have changed since the last run
+        let best_nns = match &self.simulation_report {
+            Some(report) => match sim.set_neural_networks(report.get_best_nn()) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("The networks of the last run can't be recorded, using random ones: {}", err);
+                    false
+                }
+            },
+            None => false,
+        };
+        if !best_nns {
+            validate_nn_topology(&self.nn_topology)?;
+            sim.init_neural_networks_random(&self.nn_topology);
+        }
+        sim.set_exploration_temperature(self.exploration_temperature);
+        // the delay is only meant for watching live simulations
+        sim.delay = 0;
+        Ok(sim)
+    }
+
     /// Are Simulations currently running?
     pub fn is_simulating(&self) -> bool {
         self.is_simulating
     }
     pub fn update_reports(&mut self) {
         if let Some(sim) = &mut self.simulations {
-            for report in sim.reports_channel.lock().unwrap().try_iter() {
-                sim.generation_reports.push(report);
-            }
+            sim.collect_generation_reports();
         }
     }
 
@@ -411,12 +1119,11 @@ impl SimManager {
     /// returns a status update, if it is found in the channel, else
     /// None is returned. None is also returned, if no Simulation is tracked
     ///
-    /// TODO: Add some way of handling the case where the Simulation computes
-    ///  status updates faster than the UI can display it (This could cause the
-    ///  Receiver to fill up.)
-    pub fn get_status_updates(&self) -> Option<HashMap<usize, Vec<MovableStatus>>> {
+    /// If the Simulation computes status updates faster than the UI can display
+    /// them, the oldest updates are dropped (see [SimManager::dropped_frames])
+    pub fn get_status_updates(&self) -> Option<StatusUpdate> {
         if let Some(sim) = &self.simulations {
-            if let Ok(value) = sim.car_updates.lock().expect("Unable to aquire lock on Car Update Receiver")
+            if let Ok(value) = sim.car_updates.lock().unwrap()
             .recv_timeout(Duration::from_millis(2))
                 {
                     return Some(value)
@@ -425,6 +1132,12 @@ impl SimManager {
         None
     }
 
+    /// the number of status updates dropped in the current simulation, because they
+    /// were not received fast enough
+    pub fn dropped_frames(&self) -> usize {
+        self.simulations.as_ref().map_or(0, |sim| sim.dropped_frames())
+    }
+
     /// tracks the car_updates of the simulation with the given index#
     /// raises an error, if no simulation with the given index exists
     pub fn track_simulation(&mut self, i: usize) -> Result<(), String> {
@@ -434,7 +1147,10 @@ impl SimManager {
         }
     }
 
-    ///
+    /// the index of the simulation whose car_updates are tracked, if there is one
+    pub fn tracked_simulation(&self) -> Option<usize> {
+        self.simulations.as_ref().and_then(|sim| sim.tracked_simulation())
+    }
 
     /// returns the simulation information of the current simulating
     pub fn get_sim_status(&mut self) -> Result<&mut Vec<SimulationStatus>, String> {
@@ -444,3 +1160,393 @@ impl SimManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flooding_status_channel_keeps_latest() {
+        let (tx, rx, dropped_frames) = StatusSender::new(STATUS_CHANNEL_CAPACITY);
+        let n_updates = 1000;
+        for i in 0..n_updates {
+            let mut cars = HashMap::new();
+            cars.insert(i, Vec::new());
+            tx.send(StatusUpdate {
+                cars,
+                traffic_lights: HashMap::new(),
+                crossings: HashMap::new(),
+                cost: [0.0; 2],
+                car_count: 0,
+                absorbed_cars: 0,
+            })
+            .unwrap();
+        }
+        let received: Vec<StatusUpdate> = rx.lock().unwrap().try_iter().collect();
+        assert_eq!(received.len(), STATUS_CHANNEL_CAPACITY);
+        assert_eq!(*dropped_frames.get(), n_updates - STATUS_CHANNEL_CAPACITY);
+        // the updates arrive in order and the last one is the latest
+        assert!(received.last().unwrap().cars.contains_key(&(n_updates - 1)));
+    }
+
+    #[test]
+    fn pareto_fronts_identify_non_dominated_costs() {
+        let costs = [
+            [1.0, 5.0],
+            [2.0, 2.0],
+            [5.0, 1.0],
+            [3.0, 3.0],
+            [6.0, 6.0],
+            [2.0, 2.0],
+        ];
+        let fronts = pareto_fronts(&costs);
+        assert_eq!(fronts, vec![0, 0, 0, 1, 2, 0]);
+        // the dominated simulations are less likely to become parents
+        let weights = FitnessMode::ParetoCo2Speed.selection_weights(&costs);
+        assert!(weights[0] > weights[3] && weights[3] > weights[4]);
+    }
+
+    #[test]
+    fn selection_weights_are_finite() {
+        // a cost of 0 mustn't give an infinite weight
+        let weights = FitnessMode::Scalar.selection_weights(&[[0.0, 0.0], [1.0, 1.0], [f64::NAN, 0.0]]);
+        assert!(weights.iter().all(|weight| weight.is_finite()));
+        assert!(weights[0] > weights[1]);
+        assert_eq!(weights[2], 0.0);
+        // without any positive weight, the parents are chosen uniformly
+        let weights = FitnessMode::Scalar.selection_weights(&[[f64::INFINITY, 0.0], [f64::NAN, 0.0]]);
+        assert_eq!(weights, vec![1.0, 1.0]);
+    }
+
+    /// a simulation of a 4x4 grid with `dt = 1` and random neural networks
+    fn grid_sim_data() -> SimData {
+        let mut builder = crate::build_grid::build_grid_sim(4, 100.0);
+        builder.with_dt(1.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let (channel, _rx, _dropped_frames) = StatusSender::new(STATUS_CHANNEL_CAPACITY);
+        let mut data = SimData {
+            simulator: builder.build(&mv_server).unwrap(),
+            channel,
+            report_updates: IntMut::new(false),
+            terminate: IntMut::new(false),
+            terminate_generation: IntMut::new(false),
+            id: 0,
+            deadlocked: false,
+        };
+        data.simulator.init_neural_networks_random(&[
+            LayerTopology::new(16),
+            LayerTopology::new(4).with_activation(ActivationFunc::SoftMax),
+        ]);
+        data
+    }
+
+    #[test]
+    fn sim_time_stops_once_limit_is_reached() {
+        let mut data = grid_sim_data();
+        let limit = 10.5;
+        let (_iterations, sim_time) = data.simulate_until(StopCondition::SimTime(limit), &mut thread_rng());
+        // the last step crossed the limit
+        assert!(sim_time >= limit);
+        assert!(sim_time - data.simulator.dt < limit);
+    }
+
+    #[test]
+    fn last_chunk_doesnt_overshoot_the_stop_condition() {
+        let mut data = grid_sim_data();
+        // 10 isn't a multiple of the steps per report
+        data.simulator.speed_multiplier = 4;
+        let (iterations, _sim_time) = data.simulate_until(StopCondition::Iterations(10), &mut thread_rng());
+        assert_eq!(iterations, 10);
+        let limit = 10.5;
+        let (_iterations, sim_time) = data.simulate_until(StopCondition::SimTime(limit), &mut thread_rng());
+        assert!(sim_time >= limit);
+        assert!(sim_time - data.simulator.dt < limit);
+    }
+
+    #[test]
+    fn deadlocked_simulation_stops_without_stopping_the_others() {
+        use crate::controllers::FixedTimerController;
+        let (channel, _rx, _dropped_frames) = StatusSender::new(STATUS_CHANNEL_CAPACITY);
+        let sims = (0..3).map(|id| {
+            let mut builder = crate::build_grid::busy_grid_sim(3, 10.0);
+            for node in builder.nodes.iter() {
+                // the cars of the first simulation can't move
+                if let NodeBuilder::Street(street) = &mut *node.get() {
+                    if id == 0 {
+                        street.speed_limit = 0.0;
+                    }
+                }
+            }
+            let mut mv_server = MovableServer::<PathAwareCar>::new();
+            mv_server.register_simulator_builder(&builder);
+            let mut simulator = builder.build(&mv_server).unwrap();
+            simulator.set_traffic_controllers(&FixedTimerController::new(vec![10.0; NN_OUTPUTS]).unwrap());
+            SimData {
+                simulator,
+                channel: channel.clone(),
+                report_updates: IntMut::new(false),
+                terminate: IntMut::new(false),
+                terminate_generation: IntMut::new(false),
+                id,
+                deadlocked: false,
+            }
+        }).collect();
+        let max_iterations = 2 * DEADLOCK_TIME as u32;
+        let (sims, iterations) = simulate_generation(sims, StopCondition::Iterations(max_iterations), 1, 0);
+        assert!(sims[0].deadlocked);
+        assert!(iterations[0] < max_iterations);
+        assert_eq!(sims[0].cost(), [DEADLOCK_COST; 2]);
+        for (sim, iterations) in sims.iter().zip(iterations).skip(1) {
+            assert!(!sim.deadlocked);
+            assert_eq!(iterations, max_iterations);
+            assert!(sim.cost().iter().all(|cost| cost.is_finite() && *cost < DEADLOCK_COST));
+        }
+    }
+
+    #[test]
+    fn fixed_timer_baseline_has_a_cost() {
+        use crate::controllers::FixedTimerController;
+        let mut manager = SimManager::new();
+        let builder = manager.modify_sim_builder().unwrap();
+        *builder = crate::build_grid::busy_grid_sim(3, 10.0);
+        manager.stop_condition = StopCondition::Iterations(100);
+        let controller = FixedTimerController::new(vec![10.0; NN_OUTPUTS]).unwrap();
+        let [cost, co2] = manager.evaluate_controller(&controller).unwrap();
+        assert!(cost.is_finite() && cost > 0.0);
+        assert!(co2.is_finite());
+    }
+
+    #[test]
+    fn builder_cant_be_modified_while_simulating() {
+        let mut manager = SimManager::new();
+        *manager.modify_sim_builder().unwrap() = crate::build_grid::build_grid_sim(3, 10.0);
+        manager.population = 2;
+        manager.generations = 1;
+        // the simulations only stop once they are terminated
+        manager.stop_condition = StopCondition::Iterations(1_000_000_000);
+        manager.simulate().unwrap();
+        assert!(manager.is_simulating());
+        assert!(manager.modify_sim_builder().is_err());
+        manager.terminate_sims();
+        assert!(!manager.is_simulating());
+        assert!(manager.modify_sim_builder().is_ok());
+    }
+
+    #[test]
+    fn builder_is_changed_until_it_is_saved() {
+        let mut manager = SimManager::new();
+        assert!(!manager.builder_changed());
+        *manager.modify_sim_builder().unwrap() = crate::build_grid::build_grid_sim(3, 10.0);
+        assert!(manager.builder_changed());
+        manager.mark_builder_saved();
+        // reading the builder doesn't change it
+        assert!(!manager.sim_builder().nodes.is_empty());
+        assert!(!manager.builder_changed());
+        manager.mark_builder_changed();
+        assert!(manager.builder_changed());
+    }
+
+    #[test]
+    fn seeded_population_derives_from_seed() {
+        let mut builder = crate::build_grid::build_grid_sim(4, 100.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let seed = new_population(&mut builder, &mv_server, 1, None, 0.0, DEFAULT_WEIGHT_LIMIT, &default_nn_topology())[0].get_all_neural_networks();
+        let weights = |nns: &Vec<Network>| -> Vec<f32> { nns.iter().flat_map(|nn| nn.weights()).collect() };
+        let population = new_population(&mut builder, &mv_server, 4, Some(&seed), 0.01, DEFAULT_WEIGHT_LIMIT, &default_nn_topology());
+        // the first simulation is an exact copy of the seed
+        assert_eq!(weights(&population[0].get_all_neural_networks()), weights(&seed));
+        for sim in population.iter().skip(1) {
+            let nns = sim.get_all_neural_networks();
+            assert_eq!(nns.len(), seed.len());
+            // the others are only slightly mutated
+            for (w, seed_w) in weights(&nns).iter().zip(weights(&seed)) {
+                assert!((w - seed_w).abs() <= 0.01 + f32::EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn seed_with_wrong_number_of_networks_is_rejected() {
+        let mut manager = SimManager::new();
+        *manager.modify_sim_builder().unwrap() = crate::build_grid::build_grid_sim(4, 100.0);
+        manager.seed_population_from(Vec::new()).unwrap();
+        assert!(manager.simulate().is_err());
+        assert!(!manager.is_simulating());
+    }
+
+    #[test]
+    fn seed_with_invalid_topology_is_rejected() {
+        use art_int::Layer;
+        let mut manager = SimManager::new();
+        let mut rng = thread_rng();
+        let valid = Network::random(&mut rng, &default_nn_topology());
+        let too_few_inputs = Network::random(&mut rng, &[LayerTopology::new(3), LayerTopology::new(NN_OUTPUTS)]);
+        let result = manager.seed_population_from(vec![valid.clone(), too_few_inputs]);
+        assert!(matches!(result, Err(NeuralNetworkError::InvalidTopology(1, _))));
+        assert!(manager.seed.is_none());
+        // e.g. networks saved with an empty output layer
+        let mut empty_layer = valid.clone();
+        empty_layer.layers.push(Layer {
+            neurons: Vec::new(),
+            activation: ActivationFunc::SoftMax,
+        });
+        let result = manager.seed_population_from(vec![valid.clone(), empty_layer]);
+        assert!(matches!(result, Err(NeuralNetworkError::InvalidTopology(1, _))));
+        let result = manager.seed_population_from(vec![Network::new(Vec::new())]);
+        assert!(matches!(result, Err(NeuralNetworkError::InvalidTopology(0, _))));
+        assert!(manager.seed.is_none());
+        manager.seed_population_from(vec![valid]).unwrap();
+        assert!(manager.seed.is_some());
+    }
+
+    #[test]
+    fn custom_topology_is_used_for_every_crossing() {
+        let mut builder = crate::build_grid::build_grid_sim(4, 100.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let topology = vec![
+            LayerTopology::new(NN_INPUTS),
+            LayerTopology::new(6),
+            LayerTopology::new(NN_OUTPUTS).with_activation(ActivationFunc::SoftMax),
+        ];
+        validate_nn_topology(&topology).unwrap();
+        let population = new_population(&mut builder, &mv_server, 2, None, 0.0, DEFAULT_WEIGHT_LIMIT, &topology);
+        for sim in population.iter() {
+            let nns = sim.get_all_neural_networks();
+            // the grid has 4 crossings
+            assert_eq!(nns.len(), 4);
+            for nn in nns {
+                assert_eq!(nn.topology(), topology);
+            }
+        }
+    }
+
+    #[test]
+    fn topology_with_wrong_width_is_rejected() {
+        let mut too_few_inputs = default_nn_topology();
+        too_few_inputs[0] = LayerTopology::new(NN_INPUTS - 1);
+        assert!(validate_nn_topology(&too_few_inputs).is_err());
+        let wrong_outputs = vec![
+            LayerTopology::new(NN_INPUTS),
+            LayerTopology::new(NN_OUTPUTS + 1).with_activation(ActivationFunc::SoftMax),
+        ];
+        assert!(validate_nn_topology(&wrong_outputs).is_err());
+        let empty_layer = vec![
+            LayerTopology::new(NN_INPUTS),
+            LayerTopology::new(0),
+            LayerTopology::new(NN_OUTPUTS).with_activation(ActivationFunc::SoftMax),
+        ];
+        assert!(validate_nn_topology(&empty_layer).is_err());
+        assert!(validate_nn_topology(&default_nn_topology()).is_ok());
+    }
+
+    #[test]
+    fn report_contains_history_of_all_generations() {
+        // make sure every simulation has cars, so the costs are never zero
+        let mut builder = crate::build_grid::busy_grid_sim(4, 100.0);
+        let mut manager = SimManager::new();
+        *manager.modify_sim_builder().unwrap() = builder;
+        manager.population = 2;
+        manager.generations = 3;
+        manager.stop_condition = StopCondition::Iterations(100);
+        manager.simulate().unwrap();
+        while !manager.simulations.as_ref().unwrap().has_terminated() {
+            thread::yield_now();
+        }
+        manager.terminate_sims();
+        let history = &manager.simulation_report.as_ref().unwrap().history;
+        assert_eq!(history.len(), 3);
+        assert!(history.iter().all(|report| report.iterations == 100));
+        assert!(history.iter().all(|report| report.steps_per_second > 0.0));
+    }
+
+    #[test]
+    fn recording_uses_random_networks_if_the_last_ones_dont_fit() {
+        let mut manager = SimManager::new();
+        *manager.modify_sim_builder().unwrap() = crate::build_grid::build_grid_sim(3, 10.0);
+        manager.population = 2;
+        manager.generations = 1;
+        manager.stop_condition = StopCondition::Iterations(10);
+        manager.simulate().unwrap();
+        while !manager.simulations.as_ref().unwrap().has_terminated() {
+            thread::yield_now();
+        }
+        manager.terminate_sims();
+        assert!(manager.simulation_report.is_some());
+        // the bigger grid has more crossings than the last run had networks
+        *manager.modify_sim_builder().unwrap() = crate::build_grid::build_grid_sim(4, 10.0);
+        manager.record_run_in_background(20).unwrap();
+        assert!(manager.is_recording());
+        let recording = loop {
+            match manager.take_recording() {
+                Some(recording) => break recording,
+                None => thread::yield_now(),
+            }
+        };
+        assert_eq!(recording.frames.len(), 20);
+        assert!(!manager.is_recording());
+    }
+
+    /// starts simulations that only stop when they are terminated
+    fn start_endless_simulation() -> SimManager {
+        let mut manager = SimManager::new();
+        *manager.modify_sim_builder().unwrap() = crate::build_grid::build_grid_sim(4, 100.0);
+        manager.population = 2;
+        manager.generations = 1;
+        manager.stop_condition = StopCondition::Iterations(u32::MAX);
+        manager.simulate().unwrap();
+        manager
+    }
+
+    #[test]
+    fn only_existing_simulations_can_be_tracked() {
+        let mut manager = start_endless_simulation();
+        assert_eq!(manager.tracked_simulation(), None);
+        manager.track_simulation(1).unwrap();
+        assert_eq!(manager.tracked_simulation(), Some(1));
+        // the population only has 2 simulations
+        assert!(manager.track_simulation(2).is_err());
+        assert_eq!(manager.tracked_simulation(), Some(1));
+        let displaying: Vec<bool> = manager.get_sim_status().unwrap().iter().map(|s| s.displaying).collect();
+        assert_eq!(displaying, vec![false, true]);
+        manager.terminate_sims();
+    }
+
+    #[test]
+    fn switching_tracks_reports_only_the_tracked_simulation() {
+        let mut manager = start_endless_simulation();
+        let reporting = |manager: &SimManager| -> Vec<bool> {
+            let sims = manager.simulations.as_ref().unwrap();
+            sims.report_updates.iter().map(|r| *r.get()).collect()
+        };
+        assert_eq!(reporting(&manager), vec![false, false]);
+        manager.track_simulation(0).unwrap();
+        assert_eq!(reporting(&manager), vec![true, false]);
+        manager.track_simulation(1).unwrap();
+        assert_eq!(reporting(&manager), vec![false, true]);
+        manager.terminate_sims();
+    }
+
+    #[test]
+    fn thread_exits_after_terminate_sims() {
+        let mut manager = start_endless_simulation();
+        let terminated = manager.simulations.as_ref().unwrap().terminated.clone();
+        manager.terminate_sims();
+        drop(manager);
+        assert!(*terminated.get());
+    }
+
+    #[test]
+    fn shutdown_is_idempotent() {
+        let mut manager = start_endless_simulation();
+        let mut simulating = manager.simulations.take().unwrap();
+        let terminated = simulating.terminated.clone();
+        simulating.shutdown();
+        simulating.shutdown();
+        assert!(simulating.generation_thread_handle.is_none());
+        drop(simulating);
+        assert!(*terminated.get());
+    }
+}
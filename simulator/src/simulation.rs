@@ -1,10 +1,14 @@
 use crate::movable::MovableStatus;
 use crate::movable::RandCar;
 use crate::node::CostCalcParameters;
+use crate::node::{CrossingSnapshot, TrafficLightState};
 use crate::pathfinding::MovableServer;
 use crate::pathfinding::PathAwareCar;
+use crate::recording::{Recorder, Recording};
+use crate::sim_manager::{validate_nn, InvalidTopologyError};
 use crate::traits::CarReport;
 use crate::traits::Movable;
+use crate::traits::TrafficController;
 use crate::traits::NodeTrait;
 use std::collections::HashMap;
 use std::error::Error;
@@ -15,7 +19,8 @@ use std::{cmp, ptr, thread};
 use super::int_mut::{IntMut, WeakIntMut};
 use super::node::Node;
 use art_int::LayerTopology;
-use rand::prelude::ThreadRng;
+use rand::RngCore;
+use rayon::prelude::*;
 use tracing::event;
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
@@ -31,6 +36,51 @@ impl Display for NodeDoesntExistError {
     }
 }
 
+/// This error is returned if neural networks can't be used for the crossings
+/// of a simulation (see [Simulator::set_neural_networks])
+#[derive(Debug)]
+pub enum NeuralNetworkError {
+    /// there has to be exactly one neural network per crossing
+    WrongCount { networks: usize, crossings: usize },
+    /// the neural network with the index has an unusable topology
+    InvalidTopology(usize, InvalidTopologyError),
+}
+impl Error for NeuralNetworkError {}
+impl Display for NeuralNetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NeuralNetworkError::WrongCount { networks, crossings } => write!(
+                f,
+                "Got {} neural networks, but the simulation has {} crossings",
+                networks, crossings
+            ),
+            NeuralNetworkError::InvalidTopology(index, err) => {
+                write!(f, "Neural network {}: {}", index, err)
+            }
+        }
+    }
+}
+
+/// checks that `dt` can be used as the time step of a simulation
+///
+/// Non-positive time steps are rejected, as the cars wouldn't move and the
+/// costs would be divided by zero. A warning is logged if an IONode would have
+/// to spawn more cars per step than its `max_spawn_per_step` on average, as the
+/// deferred cars would pile up. `max_spawn_rate` is the highest spawn rate
+/// of an IONode divided by its `max_spawn_per_step`.
+pub(crate) fn check_dt(dt: f32, max_spawn_rate: f64) -> Result<(), &'static str> {
+    if dt.is_nan() || dt <= 0.0 {
+        return Err("The time step has to be greater than 0");
+    }
+    if max_spawn_rate * dt as f64 > 1.0 {
+        warn!(
+            "With a time step of {}s, some IONodes should spawn more cars per step than they may, so more and more cars are deferred",
+            dt
+        );
+    }
+    Ok(())
+}
+
 /// source: https://www.econologie.de/Emissions-co2-Liter-Kraftstoff-Benzin-oder-Diesel-gpl/
 pub fn fuel_to_tonnesco2(liters: f32) -> f32 {
     2.6 * liters / 1000.0
@@ -53,10 +103,19 @@ pub fn average_speed_to_fuel(speed: f64) -> f64 {
     + 15.954046194749404
 }
 
+/// the smallest time a car can have taken, used to avoid divisions by zero
+const MIN_TIME_TAKEN: f32 = 0.001;
+/// the cost returned if the real cost can't be represented as a finite number
+const MAX_CAR_COST: f64 = 1.0e12;
+/// is added to the cost for every change of a traffic light state
+const PHASE_SWITCH_COST: f64 = 10.0;
+
 /// calculates the cost of a car
+///
+/// The cost is always finite, invalid reports result in [MAX_CAR_COST]
 pub fn calculate_cost(report: CarReport, params: &CostCalcParameters) -> [f64; 2] {
     // is in m/s
-    let average_speed = report.distance_traversed / report.time_taken;
+    let average_speed = report.distance_traversed / report.time_taken.max(MIN_TIME_TAKEN);
     // distance that the car has yet to traverse
     let dist_remaining = report.total_dist - report.distance_traversed;
     let dist_penalty = dist_remaining.powf(2.0);
@@ -65,12 +124,29 @@ pub fn calculate_cost(report: CarReport, params: &CostCalcParameters) -> [f64; 2
     let fuel_consumption = average_speed_to_fuel(average_speed.into());
     let tonnes_co2 = fuel_to_tonnesco2(fuel_consumption as f32);
     // lerp
+    let cost = average_speed as f64 * (1.0 - params.speed_to_co2) as f64 + tonnes_co2 as f64* params.speed_to_co2 as f64+ dist_penalty as f64;
+    let tonnes_co2 = tonnes_co2 as f64;
     [
-        average_speed as f64 * (1.0 - params.speed_to_co2) as f64 + tonnes_co2 as f64* params.speed_to_co2 as f64+ dist_penalty as f64,
-        tonnes_co2 as f64
+        if cost.is_finite() { cost } else { MAX_CAR_COST },
+        if tonnes_co2.is_finite() { tonnes_co2 } else { MAX_CAR_COST },
     ]
 }
 
+/// Statistics about the iterations simulated with [Simulator::run_for]
+#[derive(Debug, Clone)]
+pub struct SimStats {
+    /// the number of iterations that were simulated
+    pub iterations: usize,
+    /// how often a car moved from one node to the next
+    pub cars_moved: usize,
+    /// the number of cars spawned by the IONodes
+    pub cars_spawned: usize,
+    /// the number of cars that reached their destination
+    pub cars_absorbed: usize,
+    /// the cost of the simulation after the last iteration (see [Simulator::calculate_sim_cost])
+    pub cost: [f64; 2],
+}
+
 /// A struct representing the street network
 ///
 /// implementing functions for simulating the traffic
@@ -96,86 +172,237 @@ where
     pub delay: u64,
     /// how much the simulation is advanced each step
     pub dt: f32,
+    /// how many steps are simulated per displayed frame (see [Simulator::sim_steps])
+    pub speed_multiplier: usize,
     /// The parameters used for cost calculation
     pub calc_params: CostCalcParameters,
     /// Movables servlsaöe
-    pub mv_server: MovableServer<Car>
+    pub mv_server: MovableServer<Car>,
+    /// the cars that reached the end of each node and where they go
+    /// (see [Simulator::update_all_nodes_with_rng])
+    ///
+    /// Stored here so the memory can be reused for every step
+    pub(crate) node_moves: Vec<NodeMoves<Car>>,
+    /// records a frame after every step, if set (see [Simulator::start_recording])
+    pub(crate) recorder: Option<Recorder>,
+}
+
+/// The cars at the end of a node and the nodes they decided to move to
+#[derive(Debug)]
+pub(crate) struct NodeMoves<Car: Movable> {
+    /// the indices of the cars at the end, in ascending order once they decided
+    cars_at_end: Vec<usize>,
+    /// the node each car in `cars_at_end` moves to, if it can move
+    next_nodes: Vec<Option<WeakIntMut<Node<Car>>>>,
+    /// the out connections of the node the cars can choose from
+    options: Vec<WeakIntMut<Node<Car>>>,
+}
+
+impl<Car: Movable> Default for NodeMoves<Car> {
+    fn default() -> Self {
+        NodeMoves {
+            cars_at_end: Vec::new(),
+            next_nodes: Vec::new(),
+            options: Vec::new(),
+        }
+    }
+}
+
+/// calls `f` for every node and its [NodeMoves], in parallel if `parallel` is set
+fn for_each_node<Car, F>(nodes: &[IntMut<Node<Car>>], moves: &mut [NodeMoves<Car>], parallel: bool, f: F)
+where
+    Car: Movable,
+    F: Fn(&IntMut<Node<Car>>, &mut NodeMoves<Car>) + Sync + Send,
+{
+    match parallel {
+        true => nodes.par_iter().zip(moves.par_iter_mut()).for_each(|(node, moves)| f(node, moves)),
+        false => nodes.iter().zip(moves.iter_mut()).for_each(|(node, moves)| f(node, moves)),
+    }
 }
 
 /// The simulator, the top level struct that is instaniated to simulate traffic
 impl<Car: Movable> Simulator<Car> {
     /// Update all nodes moving the cars and people to the next
     /// nodes
+    ///
+    /// Returns how many cars moved to the next node
     #[tracing::instrument(skip(self))]
-    pub fn update_all_nodes(&mut self, dt: f64) {
-        let mut rng = ThreadRng::default();
-        for i in 0..self.nodes.len() {
-            let node = &self.nodes[i];
-            let options = node.get().get_out_connections();
-            let mut cars_at_end = node.get().update_cars(dt, &mut self.mv_server, &mut rng);
-            // make sure that the rightmost elements get removed first to avoid
-            // the indices becoming invalid
-            cars_at_end.sort();
-            // cars_at_end.reverse();
-            // TODO: Use something more efficient than cloning the whole Vec here
-            for j in (0..cars_at_end.len()).rev() {
-                let next: Result<Option<WeakIntMut<Node<Car>>>, Box<dyn Error>> = node
-                    .get()
-                    .get_car_by_index(cars_at_end[j])
-                    .decide_next(&options, node);
-                match next {
+    pub fn update_all_nodes(&mut self, dt: f64) -> usize {
+        self.update_all_nodes_with_rng(dt, &mut thread_rng())
+    }
+
+    /// Same as `update_all_nodes`, but reuses the given random number generator
+    ///
+    /// Cars move between nodes, so the update is split into phases:
+    /// 1. the crossings and then the streets advance their cars
+    /// 2. the IONodes spawn new cars
+    /// 3. the cars at the end of the nodes decide where to go (only reading the nodes)
+    /// 4. the cars are moved to the next nodes
+    ///
+    /// A car moved to a node is only advanced on it in the next step.
+    ///
+    /// Returns how many cars moved to the next node
+    pub fn update_all_nodes_with_rng(&mut self, dt: f64, rng: &mut dyn RngCore) -> usize {
+        self.update_nodes_in_phases(dt, rng, false)
+    }
+    /// Same as `update_all_nodes_with_rng`, but the nodes are updated in
+    /// parallel, which is faster for large street networks
+    ///
+    /// The phases 1 and 3 run in parallel, the IONodes share the movable server
+    /// and moving the cars changes other nodes, so the phases 2 and 4 run one
+    /// node after the other. The results are the same as with
+    /// `update_all_nodes_with_rng`.
+    ///
+    /// Returns how many cars moved to the next node
+    pub fn update_all_nodes_parallel(&mut self, dt: f64, rng: &mut dyn RngCore) -> usize {
+        self.update_nodes_in_phases(dt, rng, true)
+    }
+    /// updates the nodes in the phases described in [Simulator::update_all_nodes_with_rng]
+    fn update_nodes_in_phases(&mut self, dt: f64, rng: &mut dyn RngCore, parallel: bool) -> usize {
+        let nodes = &self.nodes;
+        let node_moves = &mut self.node_moves;
+        node_moves.resize_with(nodes.len(), NodeMoves::default);
+        // the crossings only read the streets, so the streets are updated afterwards.
+        //  The other nodes are only locked for reading, so the crossings can read them
+        for_each_node(nodes, node_moves, parallel, |node, moves| {
+            moves.cars_at_end.clear();
+            moves.next_nodes.clear();
+            if !matches!(&*node.read(), Node::Crossing(_)) {
+                return;
+            }
+            if let Node::Crossing(crossing) = &mut *node.get() {
+                crossing.update_cars(dt as f32, &mut moves.cars_at_end);
+            }
+        });
+        for_each_node(nodes, node_moves, parallel, |node, moves| {
+            if !matches!(&*node.read(), Node::Street(_)) {
+                return;
+            }
+            if let Node::Street(street) = &mut *node.get() {
+                street.update_movables_into(dt, &mut moves.cars_at_end);
+            }
+        });
+        for (node, moves) in nodes.iter().zip(node_moves.iter_mut()) {
+            if let Node::IONode(io_node) = &mut *node.get() {
+                io_node.update_cars(dt, &mut self.mv_server, rng, &mut moves.cars_at_end);
+            }
+        }
+        // from here on, the nodes are only read, so they can't block each other
+        for_each_node(nodes, node_moves, parallel, |node, moves| {
+            if moves.cars_at_end.is_empty() {
+                return;
+            }
+            moves.cars_at_end.sort_unstable();
+            let inner = node.read();
+            moves.options.clear();
+            inner.extend_out_connections(&mut moves.options);
+            for i in moves.cars_at_end.iter() {
+                let next = match inner.get_car_by_index(*i).decide_next(&moves.options, node) {
+                    Ok(next) => next,
                     Err(err) => {
                         warn!(
                             "Unable to decide next node for car with index {} at node {}. Error: {}",
-                            j, i, err
+                            i, inner.id(), err
                         );
+                        None
                     }
-                    Ok(next_node) => {
-                        match next_node {
-                            Some(nn) => {
-                                let mut car = node.get().remove_car(cars_at_end[j]);
-                                car.advance();
-                                nn.upgrade()
-                                    .get()
-                                    .add_car(car);
-                                // println!("{:?}", nn.try_upgrade().expect("asdof").get())
-                            }
-                            None => {
-                                // Nothing to do here. If car can not be moved, we will not move it
-                                debug!("aösldk")
-                            }
-                        }
-                    }
+                };
+                moves.next_nodes.push(next);
+            }
+        });
+        // all cars are removed before any are added, as adding cars changes the indices
+        let mut moving_cars = Vec::new();
+        for (node, moves) in nodes.iter().zip(node_moves.iter()) {
+            if moves.next_nodes.is_empty() {
+                continue;
+            }
+            let mut inner = node.get();
+            // the rightmost cars are removed first, so the indices stay valid
+            for (i, next) in moves.cars_at_end.iter().zip(moves.next_nodes.iter()).rev() {
+                if let Some(next) = next {
+                    moving_cars.push((inner.remove_car(*i), next.clone()));
                 }
             }
         }
+        let cars_moved = moving_cars.len();
+        for (mut car, next) in moving_cars {
+            car.advance();
+            next.upgrade().get().add_car(car);
+        }
+        cars_moved
+    }
+    /// returns the traffic light state of every crossing
+    ///
+    /// the key of the HashMap is the node index
+    pub fn get_traffic_light_states(&self) -> HashMap<usize, TrafficLightState> {
+        self.nodes
+            .iter()
+            .filter_map(|n| match &*n.read() {
+                Node::Crossing(crossing) => Some((crossing.id, crossing.traffic_light_state.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+    /// returns the current state of every crossing
+    ///
+    /// the key of the HashMap is the node index
+    pub fn crossing_snapshots(&self) -> HashMap<usize, CrossingSnapshot> {
+        self.nodes
+            .iter()
+            .filter_map(|n| match &*n.read() {
+                Node::Crossing(crossing) => Some((crossing.id, crossing.snapshot())),
+                _ => None,
+            })
+            .collect()
     }
     /// resets all cars
+    ///
+    /// The ids of the cars spawned afterwards start at 0 again, so they are
+    /// comparable between generations
     pub fn reset_cars(&mut self) -> HashMap<usize, Vec<MovableStatus>> {
+        self.mv_server.reset_car_count();
         self.nodes.iter().map(| n| {
             let inner_node = &mut *n.get();
             (inner_node.id(), inner_node.reset_cars())
         }).collect()
     }
     /// used the output from the genetic algorithm to set the neural networks
-    pub fn set_neural_networks(&mut self, mut nns: Vec<art_int::Network>) {
+    ///
+    /// Returns an error without changing any crossing if there isn't exactly one
+    /// network per crossing or a network can't be used for a crossing (see [crate::sim_manager::validate_nn])
+    pub fn set_neural_networks(&mut self, mut nns: Vec<art_int::Network>) -> Result<(), NeuralNetworkError> {
+        let crossings = self
+            .nodes
+            .iter()
+            .filter(|n| matches!(&*n.read(), Node::Crossing(_)))
+            .count();
+        if nns.len() != crossings {
+            return Err(NeuralNetworkError::WrongCount {
+                networks: nns.len(),
+                crossings,
+            });
+        }
+        for (i, nn) in nns.iter().enumerate() {
+            validate_nn(nn).map_err(|err| NeuralNetworkError::InvalidTopology(i, err))?;
+        }
         nns.reverse();
         self.nodes.iter_mut().for_each(|n| match &mut *n.get() {
             Node::Crossing(crossing) => crossing.set_neural_network(
                 nns.pop()
-                    .expect("Cannot set neural network because there are too few nns as input"),
+                    .expect("The number of nns has been checked"),
             ),
             _ => {}
         });
+        Ok(())
     }
 
     /// returns a copy of all nns in the simulation
     pub fn get_all_neural_networks(&self) -> Vec<art_int::Network> {
         let mut nns = Vec::new();
         self.nodes.iter().for_each(|n| {
-            match &*n.get() {
+            match &*n.read() {
                 Node::Crossing(crossing) => {
-                    if let Some(nn) = &crossing.nn {
+                    if let Some(nn) = crossing.controller.network() {
                         nns.push(nn.clone());
                     } else {
                         warn!("Removing all neural networks but crossing doesn't have a neural network")
@@ -217,13 +444,33 @@ impl<Car: Movable> Simulator<Car> {
         });
     }
     
+    /// lets every crossing sample its state from the outputs of its neural network
+    /// with the given SoftMax temperature (None always chooses the highest output)
+    ///
+    /// See [crate::nodes::Crossing::exploration_temperature]
+    pub fn set_exploration_temperature(&mut self, temperature: Option<f32>) {
+        self.nodes.iter_mut().for_each(|n| match &mut *n.get() {
+            Node::Crossing(crossing) => crossing.exploration_temperature = temperature,
+            _ => {}
+        });
+    }
+
+    /// gives every crossing a copy of `controller` (e.g. to compare a simple
+    /// baseline with the neural networks)
+    pub fn set_traffic_controllers(&mut self, controller: &(dyn TrafficController + 'static)) {
+        self.nodes.iter_mut().for_each(|n| match &mut *n.get() {
+            Node::Crossing(crossing) => crossing.set_controller(dyn_clone::clone_box(controller)),
+            _ => {}
+        });
+    }
+
     /// returns the total cost of all the cars in the simulation 
     /// (including those that have already been destroyed)
     #[tracing::instrument(skip(self))]
     pub fn calculate_sim_cost(&self) -> [f64; 2] {
         self.nodes
             .iter()
-            .map(|n| match &*n.get() {
+            .map(|n| match &*n.read() {
                 Node::Street(s) => s
                     .lanes
                     .iter()
@@ -235,7 +482,10 @@ impl<Car: Movable> Simulator<Car> {
                         ]
                     }),
                 Node::IONode(n) => n.total_cost,
-                Node::Crossing(c) => c.car_lane.calculate_cost_of_movables(&self.calc_params),
+                Node::Crossing(c) => {
+                    let [cost, co2] = c.car_lane.calculate_cost_of_movables(&self.calc_params);
+                    [cost + c.phase_switches as f64 * PHASE_SWITCH_COST, co2]
+                }
             })
             .fold([0.0, 0.0], | [sumcost, sumco2], [cost, co2] | {
                 [
@@ -277,7 +527,7 @@ impl<Car: Movable> Simulator<Car> {
     /// counts all cars in the simulation
     pub fn count_cars(&mut self) -> usize {
         self.nodes.iter().map( | n | {
-            match &*n.get() {
+            match &*n.read() {
                 Node::Street(street) => street.lanes.iter().map(| l | l.num_movables()).sum(),
                 Node::IONode(node) => 0,
                 Node::Crossing(cross) => cross.car_lane.num_movables(),
@@ -285,15 +535,149 @@ impl<Car: Movable> Simulator<Car> {
         }).sum()
     }
 
+    /// the number of cars that reached their destination (summed over all IONodes)
+    pub fn count_absorbed_cars(&self) -> usize {
+        self.throughput_report().values().sum()
+    }
+
+    /// the number of cars that reached each IONode
+    ///
+    /// the key is the id of the IONode
+    pub fn throughput_report(&self) -> HashMap<usize, usize> {
+        self.nodes
+            .iter()
+            .filter_map(|n| match &*n.read() {
+                Node::IONode(node) => Some((node.id, node.absorbed_cars)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Simulates `steps` iterations without waiting for the delay and
+    /// returns statistics about them
+    ///
+    /// This makes it possible to test traffic light policies without a [crate::SimManager]
+    pub fn run_for(&mut self, steps: usize) -> SimStats {
+        let spawned_before = self.count_spawned_cars();
+        let absorbed_before = self.count_absorbed_cars();
+        let mut rng = thread_rng();
+        let cars_moved = (0..steps)
+            .map(|_| {
+                let moved = self.update_all_nodes_with_rng(self.dt.into(), &mut rng);
+                self.record_frame();
+                moved
+            })
+            .sum();
+        SimStats {
+            iterations: steps,
+            cars_moved,
+            cars_spawned: self.count_spawned_cars() - spawned_before,
+            cars_absorbed: self.count_absorbed_cars() - absorbed_before,
+            cost: self.calculate_sim_cost(),
+        }
+    }
+
+    /// the number of cars spawned (summed over all IONodes)
+    pub fn count_spawned_cars(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|n| match &*n.read() {
+                Node::IONode(node) => node.num_cars_spawned,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// the longest queue at each input of each crossing since the cars were last reset
+    ///
+    /// the key is the id of the crossing, the queues are ordered `[N, E, S, W]`
+    pub fn max_queue_report(&self) -> HashMap<usize, [u32; 4]> {
+        self.nodes
+            .iter()
+            .filter_map(|n| match &*n.read() {
+                Node::Crossing(crossing) => Some((crossing.id, crossing.max_queue_lengths)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// the longest queue at any input of any crossing since the cars were last reset
+    pub fn max_queue(&self) -> u32 {
+        self.max_queue_report()
+            .values()
+            .flat_map(|queues| queues.iter().copied())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// sets how much the simulation is advanced each step
+    ///
+    /// Non-positive time steps are rejected, as the cars wouldn't move and the
+    /// costs would be divided by zero
+    pub fn set_dt(&mut self, dt: f32) -> Result<(), &'static str> {
+        let max_spawn_rate = self
+            .nodes
+            .iter()
+            .filter_map(|n| match &*n.read() {
+                Node::IONode(node) => Some(node.spawn_rate / node.max_spawn_per_step as f64),
+                _ => None,
+            })
+            .fold(0.0, f64::max);
+        check_dt(dt, max_spawn_rate)?;
+        self.dt = dt;
+        Ok(())
+    }
+
     /// a single iteration
     #[tracing::instrument(skip(self))]
     pub fn sim_iter(&mut self) {
         // At the moment all nodes are updated
         // error!("{}", self.delay);
         self.update_all_nodes(self.dt.into());
+        self.record_frame();
+        thread::sleep(Duration::from_millis(self.delay));
+    }
+
+    /// simulates `n` iterations at once and only waits for the delay afterwards
+    ///
+    /// This way, the playback speed can be changed without changing `dt`
+    #[tracing::instrument(skip(self))]
+    pub fn sim_steps(&mut self, n: usize) {
+        self.sim_steps_with_rng(n, &mut thread_rng());
+    }
+
+    /// Same as `sim_steps`, but reuses the given random number generator
+    ///
+    /// This avoids getting a new handle to the thread local generator for every step
+    pub fn sim_steps_with_rng(&mut self, n: usize, rng: &mut dyn RngCore) {
+        for _ in 0..n {
+            self.update_all_nodes_with_rng(self.dt.into(), rng);
+            self.record_frame();
+        }
         thread::sleep(Duration::from_millis(self.delay));
     }
 
+    /// records a frame after every following step of [Simulator::sim_iter],
+    /// [Simulator::sim_steps] and [Simulator::run_for], until [Simulator::stop_recording] is called
+    ///
+    /// A previous recording is dropped
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(Recorder::new(self.dt));
+    }
+
+    /// stops recording and returns the frames recorded since [Simulator::start_recording]
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recorder.take().map(Recorder::finish)
+    }
+
+    /// adds the current state to the recording, if there is one
+    fn record_frame(&mut self) {
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.record(self);
+            self.recorder = Some(recorder);
+        }
+    }
+
     /// returns status information for all of the cars in the simulation
     ///
     /// the key of the HashMap is the node index
@@ -372,6 +756,292 @@ impl Display for Simulator {
 }
 
 mod tests {
+    #[test]
+    fn non_positive_dt_is_rejected() {
+        let mut simulator = crate::build_grid::build_grid_sim(3, 10.0)
+            .build(&crate::pathfinding::MovableServer::<crate::pathfinding::PathAwareCar>::new())
+            .unwrap();
+        assert!(simulator.set_dt(0.0).is_err());
+        assert!(simulator.set_dt(-1.0).is_err());
+        assert!(simulator.set_dt(f32::NAN).is_err());
+        assert!(simulator.set_dt(0.5).is_ok());
+        assert_eq!(simulator.dt, 0.5);
+    }
+    #[test]
+    fn unusable_neural_networks_are_rejected() {
+        use super::NeuralNetworkError;
+        use art_int::{LayerTopology, Network};
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut simulator = crate::build_grid::build_grid_sim(3, 10.0)
+            .build(&crate::pathfinding::MovableServer::<crate::pathfinding::PathAwareCar>::new())
+            .unwrap();
+        let crossings = simulator
+            .nodes
+            .iter()
+            .filter(|n| matches!(&*n.read(), super::Node::Crossing(_)))
+            .count();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut random_nns = |topology: &[LayerTopology], n: usize| -> Vec<Network> {
+            (0..n).map(|_| Network::random(&mut rng, topology)).collect()
+        };
+        let too_few = random_nns(&crate::default_nn_topology(), crossings - 1);
+        assert!(matches!(
+            simulator.set_neural_networks(too_few),
+            Err(NeuralNetworkError::WrongCount { .. })
+        ));
+        let wrong_inputs = random_nns(&[LayerTopology::new(3), LayerTopology::new(4)], crossings);
+        assert!(matches!(
+            simulator.set_neural_networks(wrong_inputs),
+            Err(NeuralNetworkError::InvalidTopology(0, _))
+        ));
+        assert!(simulator.get_all_neural_networks().is_empty());
+        let nns = random_nns(&crate::default_nn_topology(), crossings);
+        simulator.set_neural_networks(nns).unwrap();
+        assert_eq!(simulator.get_all_neural_networks().len(), crossings);
+    }
+    #[test]
+    fn cost_is_finite_for_zero_time() {
+        use super::calculate_cost;
+        use crate::node::CostCalcParameters;
+        use crate::traits::CarReport;
+        let params = CostCalcParameters { speed_to_co2: 0.5 };
+        let report = CarReport {
+            distance_traversed: 10.0,
+            total_dist: 20.0,
+            time_taken: 0.0,
+        };
+        let [cost, co2] = calculate_cost(report, &params);
+        assert!(cost.is_finite());
+        assert!(co2.is_finite());
+    }
+    #[test]
+    fn cars_only_drive_from_sources_to_sinks() {
+        use crate::node::Node;
+        use crate::node_builder::{CrossingBuilder, Direction, IONodeBuilder, IONodeRole, NodeBuilder};
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut builder = SimulatorBuilder::<PathAwareCar>::new();
+        for role in [IONodeRole::Source, IONodeRole::Sink] {
+            let mut io_node = IONodeBuilder::new();
+            io_node.spawn_rate = 1.0;
+            io_node.role = role;
+            builder.add_node(NodeBuilder::IONode(io_node));
+        }
+        builder.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        // source (0) and sink (1) are connected in both directions
+        //  over the crossing (2)
+        builder.connect_with_street((0, Direction::E), (2, Direction::W), 1, 10.0).unwrap();
+        builder.connect_with_street((2, Direction::W), (0, Direction::E), 1, 10.0).unwrap();
+        builder.connect_with_street((2, Direction::E), (1, Direction::W), 1, 10.0).unwrap();
+        builder.connect_with_street((1, Direction::W), (2, Direction::E), 1, 10.0).unwrap();
+        builder.with_dt(1.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim = builder.build(&mv_server).unwrap();
+        for _ in 0..200 {
+            sim.sim_iter();
+        }
+        let (source, sink) = match (&*sim.nodes[0].read(), &*sim.nodes[1].read()) {
+            (Node::IONode(source), Node::IONode(sink)) => {
+                ((source.num_cars_spawned, source.absorbed_cars), (sink.num_cars_spawned, sink.absorbed_cars))
+            }
+            _ => panic!("The first two nodes have to be IONodes"),
+        };
+        assert!(source.0 > 0);
+        assert_eq!(source.1, 0);
+        assert_eq!(sink.0, 0);
+        assert!(sink.1 > 0);
+    }
+    #[test]
+    fn throughput_is_bounded_by_spawned_cars() {
+        use crate::build_grid::busy_grid_sim;
+        use crate::node::Node;
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use art_int::{ActivationFunc, LayerTopology, Network};
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut builder = busy_grid_sim(4, 10.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim = builder.build(&mv_server).unwrap();
+        // a random policy can jam every crossing, so the networks and the
+        //  spawned cars are seeded
+        let mut rng = StdRng::seed_from_u64(1);
+        let topology = [
+            LayerTopology::new(16),
+            LayerTopology::new(4).with_activation(ActivationFunc::SoftMax),
+        ];
+        let crossings = sim.nodes.iter().filter(|n| matches!(&*n.read(), Node::Crossing(_))).count();
+        sim.set_neural_networks((0..crossings).map(|_| Network::random(&mut rng, &topology)).collect())
+            .unwrap();
+        for _ in 0..1000 {
+            sim.update_all_nodes_with_rng(sim.dt.into(), &mut rng);
+        }
+        let spawned: usize = sim
+            .nodes
+            .iter()
+            .map(|n| match &*n.read() {
+                Node::IONode(node) => node.num_cars_spawned,
+                _ => 0,
+            })
+            .sum();
+        let absorbed: usize = sim.throughput_report().values().sum();
+        assert_eq!(absorbed, sim.count_absorbed_cars());
+        assert!(absorbed > 0);
+        assert!(absorbed <= spawned);
+    }
+    #[test]
+    fn run_for_collects_stats() {
+        use crate::build_grid::busy_grid_sim;
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        let mut builder = busy_grid_sim(3, 10.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim = builder.build(&mv_server).unwrap();
+        sim.init_neural_networks_random(&crate::default_nn_topology());
+        let stats = sim.run_for(100);
+        assert_eq!(stats.iterations, 100);
+        assert_eq!(stats.cars_spawned, sim.count_spawned_cars());
+        assert_eq!(stats.cars_absorbed, sim.count_absorbed_cars());
+        // every spawned car moves onto a street
+        assert!(stats.cars_moved >= stats.cars_spawned);
+        assert!(stats.cars_spawned > 0);
+        assert!(stats.cost.iter().all(|c| c.is_finite()));
+        // the next call only counts its own iterations
+        sim.start_recording();
+        let stats = sim.run_for(10);
+        assert!(stats.cars_spawned <= sim.count_spawned_cars());
+        // every step is recorded
+        assert_eq!(sim.stop_recording().unwrap().frames.len(), 10);
+    }
+    #[test]
+    fn queue_report_counts_waiting_cars() {
+        use crate::node::{Node, TrafficLightState};
+        use crate::node_builder::{CrossingBuilder, Direction, IONodeBuilder, IONodeRole, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::simulation_builder::SimulatorBuilder;
+        //  W -> C -> E
+        let mut builder = SimulatorBuilder::<PathAwareCar>::new();
+        let crossing = builder.add_node(NodeBuilder::Crossing(CrossingBuilder::new())).get().get_id();
+        let west = builder.add_node(NodeBuilder::IONode(IONodeBuilder::new())).get().get_id();
+        let east = builder.add_node(NodeBuilder::IONode(IONodeBuilder::new())).get().get_id();
+        let street = builder.connect_with_street((west, Direction::E), (crossing, Direction::W), 1, 100.0).unwrap().get().get_id();
+        builder.connect_with_street((crossing, Direction::E), (east, Direction::W), 1, 100.0).unwrap();
+        // a second street enters the crossing from the west
+        let west2 = builder.add_node(NodeBuilder::IONode(IONodeBuilder::new())).get().get_id();
+        let street2 = builder.connect_with_street((west2, Direction::E), (crossing, Direction::W), 1, 100.0).unwrap().get().get_id();
+        // the destinations are weighted by the spawn rates. No cars are
+        //  spawned anyway, as no time passes
+        for node in builder.nodes.iter() {
+            if let NodeBuilder::IONode(io_node) = &mut *node.get() {
+                io_node.spawn_rate = 1.0;
+                // all cars drive east
+                io_node.role = match io_node.id == east {
+                    true => IONodeRole::Sink,
+                    false => IONodeRole::Source,
+                };
+            }
+        }
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim = builder.build(&mv_server).unwrap();
+        // three cars are backed up at the end of the street. State 0 only lets
+        //  cars from the north and south drive
+        for node in sim.nodes.iter() {
+            match &mut *node.get() {
+                Node::Street(s) if s.id == street => {
+                    for pos in [100.0, 98.0, 96.0] {
+                        s.lanes[0].insert_at(mv_server.generate_movable(west).unwrap(), pos);
+                    }
+                }
+                Node::Street(s) if s.id == street2 => {
+                    for pos in [100.0, 98.0] {
+                        s.lanes[0].insert_at(mv_server.generate_movable(west2).unwrap(), pos);
+                    }
+                }
+                Node::Crossing(c) => assert_eq!(c.traffic_light_state, TrafficLightState::S0),
+                _ => {}
+            }
+        }
+        // the streets have to be updated once before the cars count as waiting
+        sim.update_all_nodes(0.0);
+        sim.update_all_nodes(0.0);
+        // the queues of both streets in the west are added up
+        assert_eq!(sim.max_queue_report()[&crossing], [0, 0, 0, 5]);
+        assert_eq!(sim.max_queue(), 5);
+        let snapshot = &sim.crossing_snapshots()[&crossing];
+        assert_eq!(snapshot.queue_lengths, [0, 0, 0, 5]);
+        assert_eq!(snapshot.traffic_light_state, TrafficLightState::S0);
+        sim.reset_cars();
+        assert_eq!(sim.max_queue(), 0);
+    }
+    #[test]
+    fn parallel_update_matches_update_all_nodes() {
+        use crate::build_grid::build_grid_sim;
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use rand::{rngs::StdRng, SeedableRng};
+        // the traffic is light, so the cars aren't stuck in a jam
+        let mut builder = build_grid_sim(4, 10.0);
+        for (id, _rate) in builder.io_nodes() {
+            builder.set_spawn_rate(id, 0.1).unwrap();
+        }
+        builder.with_dt(1.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut serial = builder.build(&mv_server).unwrap();
+        let mut parallel = builder.build(&mv_server).unwrap();
+        // only the IONodes use the rng, so both spawn the same cars
+        let mut serial_rng = StdRng::seed_from_u64(1);
+        let mut parallel_rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let moved = serial.update_all_nodes_with_rng(1.0, &mut serial_rng);
+            assert_eq!(parallel.update_all_nodes_parallel(1.0, &mut parallel_rng), moved);
+        }
+        let spawned = serial.count_spawned_cars();
+        assert_eq!(parallel.count_spawned_cars(), spawned);
+        assert!(serial.count_absorbed_cars() > 0);
+        assert_eq!(parallel.count_cars(), serial.count_cars());
+        assert_eq!(parallel.count_absorbed_cars(), serial.count_absorbed_cars());
+        // no car is lost or duplicated
+        assert_eq!(serial.count_cars() + serial.count_absorbed_cars(), spawned);
+    }
+    #[test]
+    fn sim_steps_equals_sim_iter() {
+        use crate::node_builder::{Direction, IONodeBuilder, NodeBuilder};
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut builder = SimulatorBuilder::<PathAwareCar>::new();
+        // the nodes spawn a car each iteration, but only the first one
+        //  has a path to the other
+        let mut io_node = IONodeBuilder::new();
+        io_node.spawn_rate = 1.0;
+        builder.add_node(NodeBuilder::IONode(io_node.clone()));
+        builder.add_node(NodeBuilder::IONode(io_node));
+        builder
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 1000.0)
+            .unwrap();
+        builder.with_dt(1.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut stepped = builder.build(&mv_server).unwrap();
+        let mut iterated = builder.build(&mv_server).unwrap();
+        stepped.sim_steps(5);
+        for _ in 0..5 {
+            iterated.sim_iter();
+        }
+        let positions = |sim: &mut super::Simulator| {
+            let mut positions: Vec<(u32, f32)> = sim
+                .get_car_status()
+                .values()
+                .flatten()
+                .map(|s| (s.movable_id, s.position))
+                .collect();
+            positions.sort_by_key(|(id, _)| *id);
+            positions
+        };
+        assert_eq!(stepped.count_cars(), iterated.count_cars());
+        assert!(stepped.count_cars() > 0);
+        assert_eq!(positions(&mut stepped), positions(&mut iterated));
+    }
     #[test]
     fn test_simloop() {
         use super::super::simulation_builder::SimulatorBuilder;
@@ -384,7 +1054,7 @@ mod tests {
         // mv_server.register_simulator_builder(&sim_builder);
         // let mv_server = IntMut::new(mv_server);
         // sim_builder.with_delay(1).with_max_iter(Some(1000));
-        // let mut sim = sim_builder.build(&mv_server);
+        // let mut sim = sim_builder.build(&mv_server).unwrap();
         // sim.simulation_loop().unwrap();
     }
 }
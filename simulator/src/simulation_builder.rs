@@ -2,28 +2,90 @@ use crate::node::CostCalcParameters;
 use crate::node_builder::InOut;
 use crate::pathfinding::{MovableServer, PathAwareCar};
 use crate::traits::{Movable, NodeTrait};
+use crate::DEFAULT_SPEED_LIMIT;
 
-use super::int_mut::IntMut;
+use super::int_mut::{IntMut, WeakIntMut};
 use super::node::Node;
-use super::node_builder::{CrossingBuilder, IONodeBuilder, NodeBuilder, StreetBuilder};
+use super::node_builder::{default_max_spawn_per_step, CrossingBuilder, IONodeBuilder, IONodeRole, NodeBuilder, StreetBuilder};
 use super::node_builder::{Direction, NodeBuilderTrait};
-use super::simulation::Simulator;
-use std::collections::HashMap;
+use super::simulation::{check_dt, Simulator};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{self};
+use std::marker::PhantomData;
+use std::num::NonZeroUsize;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+use std::sync::RwLockWriteGuard;
 
 use serde::{Deserialize, Serialize};
 
 
+/// The ids of the nodes connected to one side of a crossing
+///
+/// A single id is saved as a plain number, so files from before a side
+/// could hold multiple connections can still be read
+///
+/// Binary formats (e.g. bincode) can't tell a number from a list, so they
+/// always store a list
+#[derive(Debug)]
+enum JsonConnectionIds {
+    Single(usize),
+    Multiple(Vec<usize>),
+}
+impl Serialize for JsonConnectionIds {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer {
+        match self {
+            JsonConnectionIds::Single(id) if serializer.is_human_readable() => id.serialize(serializer),
+            _ => self.ids().serialize(serializer),
+        }
+    }
+}
+impl<'de> Deserialize<'de> for JsonConnectionIds {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de> {
+        if !deserializer.is_human_readable() {
+            return Vec::deserialize(deserializer).map(JsonConnectionIds::Multiple);
+        }
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Ids {
+            Single(usize),
+            Multiple(Vec<usize>),
+        }
+        Ok(match Ids::deserialize(deserializer)? {
+            Ids::Single(id) => JsonConnectionIds::Single(id),
+            Ids::Multiple(ids) => JsonConnectionIds::Multiple(ids),
+        })
+    }
+}
+impl JsonConnectionIds {
+    fn from_ids(mut ids: Vec<usize>) -> JsonConnectionIds {
+        match ids.len() {
+            1 => JsonConnectionIds::Single(ids.pop().unwrap()),
+            _ => JsonConnectionIds::Multiple(ids),
+        }
+    }
+    fn ids(&self) -> Vec<usize> {
+        match self {
+            JsonConnectionIds::Single(id) => vec![*id],
+            JsonConnectionIds::Multiple(ids) => ids.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct JsonCrossingConnections {
-    pub input: HashMap<Direction, usize>,
-    pub output: HashMap<Direction, usize>
+    pub input: HashMap<Direction, JsonConnectionIds>,
+    pub output: HashMap<Direction, JsonConnectionIds>
 }
 
-/// This is just used to deserialize the JSON File t    input: HashMap<Direction, u32>o
+/// This is just used to deserialize the JSON File to
 /// an object that can be conveniently used in
-/// `StreetData::from_json`
+/// `SimulatorBuilder::deserialize`
 ///
 #[derive(Debug, Deserialize, Serialize)]
 struct JsonCrossing {
@@ -36,7 +98,13 @@ struct JsonIONode {
     pub connected_in: Vec<usize>,
     pub connected_out: Vec<usize>,
     pub spawn_rate: f64,
-    pub id: usize
+    pub id: usize,
+    #[serde(default)]
+    pub role: IONodeRole,
+    /// files saved before IONodes had a spawn limit don't contain it, 0 is
+    /// rejected, as the node would never spawn a car
+    #[serde(default = "default_max_spawn_per_step")]
+    pub max_spawn_per_step: NonZeroUsize,
 }
 #[derive(Debug, Deserialize, Serialize)]
 struct JsonStreet {
@@ -44,7 +112,15 @@ struct JsonStreet {
     pub conn_out: Option<usize>,
     pub lanes: u8,
     pub length: f32,
-    pub id: usize
+    pub id: usize,
+    #[serde(default)]
+    pub lane_turns: Option<Vec<HashSet<Direction>>>,
+    #[serde(default = "default_speed_limit")]
+    pub speed_limit: f32,
+}
+/// files saved before streets had speed limits don't contain them
+fn default_speed_limit() -> f32 {
+    DEFAULT_SPEED_LIMIT
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -66,13 +142,17 @@ impl JsonNode {
             JsonNode::IONode(ionode) => {
                 let mut ionodeb = IONodeBuilder::new();
                 ionodeb.spawn_rate = ionode.spawn_rate;
+                ionodeb.role = ionode.role;
+                ionodeb.max_spawn_per_step = ionode.max_spawn_per_step;
                 ionodeb.set_id(ionode.id);
                 NodeBuilder::IONode(ionodeb)
             },
             JsonNode::Street(jstreet) => {
                 let mut street = StreetBuilder::new()
                     .with_lanes(jstreet.lanes)
-                    .with_length(jstreet.length);
+                    .with_length(jstreet.length)
+                    .with_speed_limit(jstreet.speed_limit);
+                street.lane_turns = jstreet.lane_turns.clone();
                 street.set_id(jstreet.id);
                 // println!("Creating STreet with id: {}", street.get_id());
                 NodeBuilder::Street(street)
@@ -89,20 +169,95 @@ struct JsonRepresentation {
     pub dt: f32,
     pub delay: u64
 }
+/// connects a crossing loaded from json to `node`, `ids` are the ids of the
+/// nodes `(from, to)` for the errors
+fn connect_loaded_crossing(
+    crossing: &mut CrossingBuilder,
+    dir: Direction,
+    conn_type: InOut,
+    node: &IntMut<NodeBuilder>,
+    (from, to): (usize, usize),
+) -> Result<(), GraphError> {
+    if crossing.connections.get_all(conn_type, dir).iter().any(|c| c == node) {
+        return Err(GraphError::DuplicateConnection { from, to });
+    }
+    let other_type = match conn_type {
+        InOut::IN => InOut::OUT,
+        InOut::OUT => InOut::IN,
+    };
+    if crossing.connections.is_connected(other_type, node) {
+        return Err(GraphError::InvalidConnection {
+            from,
+            to,
+            msg: "the node is both an input and an output of the crossing".to_string(),
+        });
+    }
+    crossing
+        .connect(dir, conn_type, node)
+        .map_err(|err| GraphError::InvalidConnection { from, to, msg: err.to_string() })?;
+    Ok(())
+}
+
 /// Is raised when the conversion `JSON` -> `Simulator` fails
-#[derive(Debug, Clone)]
-pub struct JsonError(String);
-impl fmt::Display for JsonError {
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphError {
+    /// The text isn't valid json or doesn't describe a street network
+    InvalidJson(String),
+    /// A connection refers to a node id that doesn't exist
+    NodeIndexOutOfRange {
+        /// the id that doesn't belong to a node
+        index: usize,
+    },
+    /// Two nodes are connected more than once in the same direction
+    DuplicateConnection {
+        /// the id of the node the connections start at
+        from: usize,
+        /// the id of the node the connections end at
+        to: usize,
+    },
+    /// A street that is missing its start or end node
+    DanglingStreet {
+        /// the id of the street
+        id: usize,
+    },
+    /// A connection that can't be made for another reason, e.g. a node that is
+    /// both an input and an output of the same crossing
+    InvalidConnection {
+        /// the id of the node the connection starts at
+        from: usize,
+        /// the id of the node the connection ends at
+        to: usize,
+        /// why the connection can't be made
+        msg: String,
+    },
+}
+impl fmt::Display for GraphError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        match self {
+            GraphError::InvalidJson(msg) => write!(f, "Invalid json: {}", msg),
+            GraphError::NodeIndexOutOfRange { index } => {
+                write!(f, "There is no node with the id {}", index)
+            }
+            GraphError::DuplicateConnection { from, to } => {
+                write!(f, "Node (id={}) is connected to node (id={}) more than once", from, to)
+            }
+            GraphError::DanglingStreet { id } => {
+                write!(f, "Street (id={}) is missing a start or end node", id)
+            }
+            GraphError::InvalidConnection { from, to, msg } => {
+                write!(f, "Node (id={}) can't be connected to node (id={}): {}", from, to, msg)
+            }
+        }
     }
 }
-impl Error for JsonError {}
+impl Error for GraphError {}
 
 /// An error that is raised when the simulation fails to connect nodes
 #[derive(Debug, Clone)]
 pub struct ConnectionError {
+    /// the id of the node the street should start at
     start: usize,
+    /// the id of the node the street should end at
     end: usize,
     msg: Option<String>,
 }
@@ -133,6 +288,79 @@ impl fmt::Display for IndexError {
 
 impl Error for IndexError {}
 
+/// An error that is returned if the spawn rate of a node can't be set
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpawnRateError {
+    /// The rate is negative or NaN
+    InvalidRate(f64),
+    /// There is no node with the id
+    NodeDoesntExist(usize),
+    /// The node with the id isn't an IONode
+    NotAnIONode(usize),
+}
+impl fmt::Display for SpawnRateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpawnRateError::InvalidRate(rate) => {
+                write!(f, "The spawn rate has to be a number that isn't negative, got {}", rate)
+            }
+            SpawnRateError::NodeDoesntExist(id) => write!(f, "There is no node with the id {}", id),
+            SpawnRateError::NotAnIONode(id) => write!(f, "Node (id={}) is not an IONode", id),
+        }
+    }
+}
+impl Error for SpawnRateError {}
+
+/// A problem in the street network that prevents it from being simulated
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    /// A street that is missing its start or end node
+    DanglingStreet(usize),
+    /// A crossing cars can drive into, but not out of
+    CrossingWithoutOutputs(usize),
+    /// An IONode that can't be reached from and can't reach any other IONode
+    UnreachableIONode(usize),
+}
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::DanglingStreet(id) => {
+                write!(f, "Street (id={}) is missing a start or end node", id)
+            }
+            ValidationIssue::CrossingWithoutOutputs(id) => {
+                write!(f, "Crossing (id={}) has inputs, but no outputs", id)
+            }
+            ValidationIssue::UnreachableIONode(id) => {
+                write!(f, "IONode (id={}) is not connected to any other IONode", id)
+            }
+        }
+    }
+}
+
+/// Is raised when a [SimulatorBuilder] with an invalid street network is built
+#[derive(Debug, Clone)]
+pub struct ValidationError(pub Vec<ValidationIssue>);
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The street network is invalid:")?;
+        for issue in self.0.iter() {
+            write!(f, " {};", issue)?;
+        }
+        Ok(())
+    }
+}
+impl Error for ValidationError {}
+
+/// locks a node connected to the node with the id `start_id`, while the lock on
+/// that node is already held
+///
+/// Panics instead of deadlocking if the node is connected to itself
+fn lock_connected<T>(node: &IntMut<T>, start_id: usize) -> RwLockWriteGuard<T> {
+    node.try_get().unwrap_or_else(|| {
+        panic!("Node (id={}) is connected to itself or locked elsewhere", start_id)
+    })
+}
+
 /// A struct for creating simulators
 ///
 /// To seperate simulation creation from actual simulation logic,
@@ -144,7 +372,8 @@ where
     /// A list of all the nodes
     pub nodes: Vec<IntMut<NodeBuilder>>,
     max_iter: Option<usize>,
-    cache: Option<Vec<IntMut<Node<Car>>>>,
+    /// the type of the cars in the built simulators
+    car_type: PhantomData<Car>,
     /// public so it can be more easily changed in the front end
     pub delay: u64,
     /// The id of the next node. This is necessary, as the length of the nodes
@@ -153,7 +382,11 @@ where
     /// how much a simulation is advanced each step
     pub dt: f32,
     ///
-    pub speed_to_co2: f32
+    pub speed_to_co2: f32,
+    /// how many steps are simulated between two reports to the frontend
+    ///
+    /// public so it can be more easily changed in the front end
+    pub speed_multiplier: usize,
 }
 
 impl<Car: Movable> SimulatorBuilder<Car> {
@@ -162,11 +395,12 @@ impl<Car: Movable> SimulatorBuilder<Car> {
         SimulatorBuilder {
             nodes: Vec::new(),
             max_iter: None,
+            car_type: PhantomData,
             delay: 0,
-            cache: None,
             next_id: 0,
             dt: 0.1,
             speed_to_co2: 0.5,
+            speed_multiplier: 1,
         }
     }
 
@@ -174,6 +408,10 @@ impl<Car: Movable> SimulatorBuilder<Car> {
 
  
     /// Connects two nodes, ONE WAY ONLY, adding a street in between
+    ///
+    /// The lanes of the new street are `street_length` long. A node can't be
+    /// connected to itself and the length has to be positive, as the cars
+    /// could never leave such a street.
     pub fn connect_with_street(
         &mut self,
         node_info1: (usize, Direction),
@@ -183,6 +421,20 @@ impl<Car: Movable> SimulatorBuilder<Car> {
     ) -> Result<&IntMut<NodeBuilder>, Box<dyn Error>> {
         let (idnode1, dir1) = node_info1;
         let (idnode2, dir2) = node_info2;
+        if idnode1 == idnode2 {
+            return Err(Box::new(ConnectionError {
+                start: idnode1,
+                end: idnode2,
+                msg: Some("A node can't be connected to itself".to_string()),
+            }));
+        }
+        if street_length.is_nan() || street_length <= 0.0 {
+            return Err(Box::new(ConnectionError {
+                start: idnode1,
+                end: idnode2,
+                msg: Some(format!("The street has to be longer than 0 (is {})", street_length)),
+            }));
+        }
         // make sure the second nodes actually exist and get their indices
         let mut inode1: Option<usize> = None;
         let mut inode2: Option<usize> = None;
@@ -204,17 +456,31 @@ impl<Car: Movable> SimulatorBuilder<Car> {
 
         let node1 = &self.nodes[inode1];
         let node2 = &self.nodes[inode2];
+        // check this before anything is connected, so no half built street
+        //  is left behind
+        let already_connected = node1.get().get_out_connections().iter().any(|street| {
+            street
+                .try_upgrade()
+                .map_or(false, |street| street.get().get_out_connections().iter().any(|n| n == node2))
+        });
+        if already_connected {
+            return Err(Box::new(ConnectionError {
+                start: idnode1,
+                end: idnode2,
+                msg: Some("The nodes are already connected in this direction".to_string()),
+            }));
+        }
         // create a new street to connect them
         let mut new_street = StreetBuilder::new().with_lanes(lanes);
         new_street.lane_length = street_length;
         new_street
             .connect(InOut::IN, node1)
             .connect(InOut::OUT, node2);
+        // the id is only used up once the street is connected
         new_street.set_id(self.next_id);
-        self.next_id += 1;
 
         // wrap the street (this is how it is stored internally)
-        let new_street = IntMut::new(NodeBuilder::Street(new_street));
+        let new_street = IntMut::with_id(NodeBuilder::Street(new_street), self.next_id);
         // add the connection the the street in the nodes
         match &mut *node1.get() {
             NodeBuilder::IONode(inner) => {
@@ -223,8 +489,8 @@ impl<Car: Movable> SimulatorBuilder<Car> {
             NodeBuilder::Crossing(inner) => {
                 inner.connect(dir1, InOut::OUT, &new_street).map_err(|er| {
                     Box::new(ConnectionError {
-                        start: inode1,
-                        end: inode2,
+                        start: idnode1,
+                        end: idnode2,
                         msg: Some(format!(
                             "Unable to connect OUT -> IN. (Failed at OUT): {}",
                             er
@@ -232,44 +498,185 @@ impl<Car: Movable> SimulatorBuilder<Car> {
                     })
                 })?;
             }
-            NodeBuilder::Street(_) => panic!("Can't connect street with street"),
+            NodeBuilder::Street(_) => {
+                return Err(Box::new(ConnectionError {
+                    start: idnode1,
+                    end: idnode2,
+                    msg: Some("Can't connect street with street (Failed at OUT)".to_string()),
+                }))
+            }
         }
-        match &mut *node2.get() {
+        // node1 is already connected, so every failure from here on has to
+        //  undo that before returning
+        let connected = match &mut *node2.get() {
             NodeBuilder::IONode(inner) => {
-                inner.connect(InOut::OUT, &new_street);
+                inner.connect(InOut::IN, &new_street);
+                Ok(())
             }
-            NodeBuilder::Crossing(inner) => {
-                inner.connect(dir2, InOut::IN, &new_street).map_err(|er| {
-                    Box::new(ConnectionError {
-                        start: inode1,
-                        end: inode2,
-                        msg: Some(format!(
-                            "Unable to connect OUT -> IN. (Failed at IN): {}",
-                            er
-                        )),
-                    })
-                })?;
-            }
-            NodeBuilder::Street(_) => panic!("Can't connect street with street"),
+            NodeBuilder::Crossing(inner) => inner
+                .connect(dir2, InOut::IN, &new_street)
+                .map(|_| ())
+                .map_err(|er| ConnectionError {
+                    start: idnode1,
+                    end: idnode2,
+                    msg: Some(format!(
+                        "Unable to connect OUT -> IN. (Failed at IN): {}",
+                        er
+                    )),
+                }),
+            NodeBuilder::Street(_) => Err(ConnectionError {
+                start: idnode1,
+                end: idnode2,
+                msg: Some("Can't connect street with street (Failed at IN)".to_string()),
+            }),
+        };
+        if let Err(err) = connected {
+            // the first node would otherwise point to a street that is dropped
+            node1.get().remove_connection(&new_street.downgrade());
+            return Err(Box::new(err));
         }
+        self.next_id += 1;
         // println!("Connecting: {}->{}", inode1, inode2);
         self.nodes.push(new_street);
         Ok(self.nodes.last().unwrap())
     }
 
+    /// Checks the street network for problems that would make building or
+    /// simulating it fail, without building it
+    pub fn validate(&self) -> Result<(), Vec<ValidationIssue>> {
+        let mut issues = self.connection_issues();
+        issues.append(&mut self.unreachable_io_nodes());
+        match issues.is_empty() {
+            true => Ok(()),
+            false => Err(issues),
+        }
+    }
+    /// the issues of single nodes that would make building the network fail
+    ///
+    /// Unlike the reachability of the IONodes, this is cheap enough to be
+    /// checked on every [SimulatorBuilder::build]
+    fn connection_issues(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        for node in self.nodes.iter() {
+            let node = node.get();
+            match &*node {
+                NodeBuilder::Street(street) => {
+                    let has_start = street.conn_in.as_ref().and_then(|c| c.try_upgrade()).is_some();
+                    let has_end = street.conn_out.as_ref().and_then(|c| c.try_upgrade()).is_some();
+                    if !has_start || !has_end {
+                        issues.push(ValidationIssue::DanglingStreet(street.id));
+                    }
+                }
+                NodeBuilder::Crossing(crossing) => {
+                    if crossing.connections.len(InOut::IN) > 0
+                        && crossing.connections.len(InOut::OUT) == 0
+                    {
+                        issues.push(ValidationIssue::CrossingWithoutOutputs(crossing.id));
+                    }
+                }
+                NodeBuilder::IONode(_) => {}
+            }
+        }
+        issues
+    }
+    /// the IONodes that can't be reached from and can't reach any other IONode
+    fn unreachable_io_nodes(&self) -> Vec<ValidationIssue> {
+        let io_nodes: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter_map(|n| match &*n.get() {
+                NodeBuilder::IONode(io_node) => Some(io_node.id),
+                _ => None,
+            })
+            .collect();
+        // the IONodes every IONode can drive to
+        let reachable: HashMap<usize, HashSet<usize>> = io_nodes
+            .iter()
+            .map(|id| (*id, self.reachable_from(*id)))
+            .collect();
+        io_nodes
+            .iter()
+            .filter(|id| {
+                let reaches_other = io_nodes
+                    .iter()
+                    .any(|other| other != *id && reachable[*id].contains(other));
+                let reached_by_other = io_nodes
+                    .iter()
+                    .any(|other| other != *id && reachable[other].contains(*id));
+                !reaches_other && !reached_by_other
+            })
+            .map(|id| ValidationIssue::UnreachableIONode(*id))
+            .collect()
+    }
+    /// returns the ids of all nodes that can be reached from the node with the given id
+    fn reachable_from(&self, id: usize) -> HashSet<usize> {
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![id];
+        while let Some(current) = to_visit.pop() {
+            if !visited.insert(current) {
+                continue;
+            }
+            if let Some(node) = self.get_node(current) {
+                let connections = node.get().get_out_connections();
+                for conn in connections {
+                    if let Some(next) = conn.try_upgrade() {
+                        to_visit.push(next.get().get_id());
+                    }
+                }
+            }
+        }
+        visited
+    }
+    /// Exports the street network as a GraphViz DOT graph
+    ///
+    /// Crossings are drawn as boxes, IONodes as ellipses and every street is
+    /// an edge labeled with its number of lanes
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph street_network {\n");
+        let mut edges = Vec::new();
+        for node in self.nodes.iter() {
+            let node = node.read();
+            match &*node {
+                NodeBuilder::Crossing(crossing) => {
+                    dot.push_str(&format!("    {} [label=\"Crossing {}\", shape=box];\n", crossing.id, crossing.id));
+                }
+                NodeBuilder::IONode(io_node) => {
+                    dot.push_str(&format!("    {} [label=\"IONode {}\", shape=ellipse];\n", io_node.id, io_node.id));
+                }
+                NodeBuilder::Street(street) => {
+                    let start = street.conn_in.as_ref().and_then(|c| c.try_upgrade());
+                    let end = street.conn_out.as_ref().and_then(|c| c.try_upgrade());
+                    // dangling streets can't be drawn as edges
+                    if let (Some(start), Some(end)) = (start, end) {
+                        edges.push(format!(
+                            "    {} -> {} [label=\"{}\"];\n",
+                            start.read().get_id(),
+                            end.read().get_id(),
+                            street.lanes
+                        ));
+                    }
+                }
+            }
+        }
+        for edge in edges {
+            dot.push_str(&edge);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
     /// Creates a new simulator from the templates
-    pub fn build(&mut self, mv_server: &MovableServer<Car>) -> Simulator<Car> {
-        if let Some(cache) = &self.cache {
-            return Simulator {
-                nodes: cache.iter().map(|n| n.deep_copy()).collect(),
-                max_iter: self.max_iter,
-                delay: self.delay,
-                dt: self.dt,
-                calc_params: CostCalcParameters {
-                    speed_to_co2: self.speed_to_co2
-                },
-                mv_server: mv_server.clone()
-            };
+    ///
+    /// Each call builds new nodes, so simulators built from the same builder
+    /// don't share any state and can run in parallel.
+    ///
+    /// Returns an error if a street is missing an endpoint or a crossing has no
+    /// outputs. The more expensive checks of [SimulatorBuilder::validate] aren't
+    /// run, as a simulator is built for every simulation of a generation
+    pub fn build(&mut self, mv_server: &MovableServer<Car>) -> Result<Simulator<Car>, ValidationError> {
+        let issues = self.connection_issues();
+        if !issues.is_empty() {
+            return Err(ValidationError(issues));
         }
         // create the nodes
         let sim_nodes: Vec<IntMut<Node<Car>>> = self
@@ -277,7 +684,8 @@ impl<Car: Movable> SimulatorBuilder<Car> {
             .iter()
             .map(|n| {
                 let mut new_node = n.get().build();
-                IntMut::new(new_node)
+                let id = new_node.id();
+                IntMut::with_id(new_node, id)
             })
             .collect();
         // create the connections
@@ -291,10 +699,23 @@ impl<Car: Movable> SimulatorBuilder<Car> {
                     // get strong reference to get the id
                     let end_node_builder_int_mut = &*c;
                     let end_node_builder = &*end_node_builder_int_mut;
+                    // connections to nodes that were removed are skipped
+                    let end_id = match end_node_builder.try_upgrade() {
+                        Some(end) => lock_connected(&end, start_id).get_id(),
+                        None => {
+                            warn!("Node (id={}) is connected to a node that doesn't exist anymore", start_id);
+                            return;
+                        }
+                    };
                     // find the node with the correct id
-                    let end_id = end_node_builder.upgrade().get().get_id();
                     let starting_node = sim_nodes.iter().find( | n | n.get().id() == start_id).unwrap();
-                    let end_node = sim_nodes.iter().find( | n | n.get().id() == end_id).unwrap();
+                    let end_node = match sim_nodes.iter().find( | n | n.get().id() == end_id) {
+                        Some(end_node) => end_node,
+                        None => {
+                            warn!("Node (id={}) is connected to node (id={}), which isn't part of the simulation", start_id, end_id);
+                            return;
+                        }
+                    };
                     let starting_node_unwrapped = &mut *starting_node.get();
                     // we will connect using the out connections and set the in connections
                     // at the same time
@@ -309,7 +730,7 @@ impl<Car: Movable> SimulatorBuilder<Car> {
                             // set out connection
                             street.connect(InOut::OUT, end_node);
                             // set in connection of target node
-                            match &mut *end_node.get() {
+                            match &mut *lock_connected(end_node, start_id) {
                                 Node::Street(target) => {
                                     target.connect(InOut::IN, &starting_node);
                                 },
@@ -318,7 +739,7 @@ impl<Car: Movable> SimulatorBuilder<Car> {
                                 },
                                 Node::Crossing(target) => {
                                     let end_node_builder = end_node_builder.upgrade();
-                                    let data = end_node_builder.get();
+                                    let data = lock_connected(&end_node_builder, start_id);
                                     let crossing_builder= match &*data {
                                         NodeBuilder::Street(_) => panic!("NodeBuilders and Nodes not in same position in list."),
                                         NodeBuilder::IONode(_) => panic!("NodeBuilders and Nodes not in same position in list."),
@@ -359,7 +780,7 @@ impl<Car: Movable> SimulatorBuilder<Car> {
                             let direction = crossing_builder.connections.get_direction_for_item(InOut::OUT, &end_node_builder_int_mut.upgrade()
                                                                                                 ).unwrap();
                             crossing.connect(direction, InOut::OUT, end_node).unwrap();
-                            match &mut *end_node.get() {
+                            match &mut *lock_connected(end_node, start_id) {
                                 Node::Street(street) => {street.connect(InOut::IN, starting_node);},
                                 Node::IONode(_) => panic!("NodeBuilders and Nodes not in same position in list."),
                                 Node::Crossing(_) => panic!("NodeBuilders and Nodes not in same position in list."),
@@ -368,35 +789,62 @@ impl<Car: Movable> SimulatorBuilder<Car> {
                     }
                 });
         });
-        // self.cache = Some(sim_nodes.iter().map(|n| n.deep_copy()).collect());
-        Simulator {
+        Ok(Simulator {
             nodes: sim_nodes,
             max_iter: self.max_iter,
             delay: self.delay,
             dt: self.dt,
+            speed_multiplier: self.speed_multiplier,
             calc_params: CostCalcParameters {
                 speed_to_co2: self.speed_to_co2,
             },
             mv_server: mv_server.clone(),
-        }
-    }
-    /// Drops the internal node cache
-    pub fn drop_cache(&mut self) {
-        self.cache = None
+            node_moves: Vec::new(),
+            recorder: None,
+        })
     }
-
     /// adds a node to the Simulation and sets the correct id
     pub fn add_node(&mut self, mut node: NodeBuilder) -> &IntMut<NodeBuilder> {
-        // the cache cannot be used if
-        // the internals change
-        self.drop_cache();
         // set the internal id. Is later used for calculating paths
         node.set_id(self.next_id);
-        self.next_id += 1;
         let new_node_index = self.nodes.len();
-        self.nodes.push(IntMut::new(node));
+        self.nodes.push(IntMut::with_id(node, self.next_id));
+        self.next_id += 1;
         &self.nodes[new_node_index]
     }
+    /// adds multiple nodes at once and returns their ids
+    ///
+    /// The ids are assigned in sequence
+    pub fn add_nodes(&mut self, nodes: impl IntoIterator<Item = NodeBuilder>) -> Vec<usize> {
+        nodes
+            .into_iter()
+            .map(|mut node| {
+                let id = self.next_id;
+                node.set_id(id);
+                self.next_id += 1;
+                self.nodes.push(IntMut::with_id(node, id));
+                id
+            })
+            .collect()
+    }
+    /// connects multiple pairs of nodes with streets of the default length
+    ///
+    /// Each entry consists of the start node, the end node and the number of
+    /// lanes (see [SimulatorBuilder::connect_with_street]). Returns the ids of
+    /// the new streets.
+    pub fn connect_many(
+        &mut self,
+        connections: &[((usize, Direction), (usize, Direction), u8)],
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        let street_length = StreetBuilder::new().lane_length;
+        connections
+            .iter()
+            .map(|(node_info1, node_info2, lanes)| {
+                self.connect_with_street(*node_info1, *node_info2, *lanes, street_length)
+                    .map(|street| street.get().get_id())
+            })
+            .collect()
+    }
     /// an optional delay between each iteration
     pub fn with_delay(&mut self, value: u64) -> &mut Self {
         self.delay = value;
@@ -407,6 +855,25 @@ impl<Car: Movable> SimulatorBuilder<Car> {
         self.dt = value;
         self
     }
+    /// sets the time step, if it is valid (see [crate::Simulator::set_dt])
+    pub fn set_dt(&mut self, dt: f32) -> Result<(), &'static str> {
+        let max_spawn_rate = self
+            .nodes
+            .iter()
+            .filter_map(|n| match &*n.read() {
+                NodeBuilder::IONode(node) => Some(node.spawn_rate / node.max_spawn_per_step.get() as f64),
+                _ => None,
+            })
+            .fold(0.0, f64::max);
+        check_dt(dt, max_spawn_rate)?;
+        self.dt = dt;
+        Ok(())
+    }
+    /// sets how many steps are simulated per displayed frame
+    pub fn with_speed_multiplier(&mut self, value: usize) -> &mut Self {
+        self.speed_multiplier = value;
+        self
+    }
     /// Makes the simulation stop after `value` iterations
     pub fn with_max_iter(&mut self, value: Option<usize>) -> &mut Self {
         self.max_iter = value;
@@ -422,6 +889,74 @@ impl<Car: Movable> SimulatorBuilder<Car> {
             n.get().get_id() == i
         })
     }
+    /// returns the ids of the nodes connected to the node with id `id`, the side
+    /// of the node they are connected to and whether they are an input or output
+    ///
+    /// Only crossings have sides, for the other nodes the direction is `None`.
+    /// If there is no node with the id, the list is empty.
+    pub fn neighbors(&self, id: usize) -> Vec<(usize, Option<Direction>, InOut)> {
+        let node = match self.get_node(id) {
+            Some(node) => node.read(),
+            None => return Vec::new(),
+        };
+        let connections: Vec<(WeakIntMut<NodeBuilder>, Option<Direction>, InOut)> = match &*node {
+            NodeBuilder::Crossing(crossing) => [InOut::IN, InOut::OUT]
+                .iter()
+                .flat_map(|in_out| {
+                    crossing
+                        .connections
+                        .iter(*in_out)
+                        .map(move |(dir, c)| (c.clone(), Some(dir), *in_out))
+                })
+                .collect(),
+            NodeBuilder::Street(street) => street
+                .conn_in
+                .iter()
+                .map(|c| (c.clone(), None, InOut::IN))
+                .chain(street.conn_out.iter().map(|c| (c.clone(), None, InOut::OUT)))
+                .collect(),
+            NodeBuilder::IONode(io_node) => io_node
+                .connections_in
+                .iter()
+                .map(|c| (c.clone(), None, InOut::IN))
+                .chain(io_node.connections_out.iter().map(|c| (c.clone(), None, InOut::OUT)))
+                .collect(),
+        };
+        connections
+            .into_iter()
+            // the connection may already be removed (see [ValidationIssue::DanglingStreet])
+            .filter_map(|(c, dir, in_out)| Some((c.try_upgrade()?.read().get_id(), dir, in_out)))
+            .collect()
+    }
+    /// returns the id and the spawn rate of every IONode, ordered by id
+    pub fn io_nodes(&self) -> Vec<(usize, f64)> {
+        let mut io_nodes: Vec<(usize, f64)> = self
+            .nodes
+            .iter()
+            .filter_map(|n| match &*n.read() {
+                NodeBuilder::IONode(io_node) => Some((io_node.id, io_node.spawn_rate)),
+                _ => None,
+            })
+            .collect();
+        io_nodes.sort_by_key(|(id, _rate)| *id);
+        io_nodes
+    }
+    /// sets the spawn rate (in cars / second) of the IONode with the id `id`
+    ///
+    /// Returns an error if there is no such IONode or the rate is negative or NaN
+    pub fn set_spawn_rate(&mut self, id: usize, rate: f64) -> Result<(), SpawnRateError> {
+        if rate.is_nan() || rate < 0.0 {
+            return Err(SpawnRateError::InvalidRate(rate));
+        }
+        let node = self.get_node(id).ok_or(SpawnRateError::NodeDoesntExist(id))?;
+        match &mut *node.get() {
+            NodeBuilder::IONode(io_node) => {
+                io_node.spawn_rate(rate);
+                Ok(())
+            }
+            _ => Err(SpawnRateError::NotAnIONode(id)),
+        }
+    }
     /// removes a node by it's id
     ///
     /// For IONodes and Crossings, the connected Streets are removed as well, for
@@ -442,6 +977,61 @@ impl<Car: Movable> SimulatorBuilder<Car> {
         };
         self.remove_node(i, true)
     }
+    /// removes several nodes by their ids at once
+    ///
+    /// Like [SimulatorBuilder::remove_node_and_connected_by_id], the streets connected
+    /// to IONodes and Crossings are removed as well, while streets are only detached
+    /// from their endpoints. Nothing is removed if one of the ids doesn't exist.
+    pub fn remove_nodes_by_ids(
+        &mut self,
+        ids: &[usize],
+    ) -> Result<Vec<IntMut<NodeBuilder>>, &'static str> {
+        if ids.iter().any(|id| self.get_node(*id).is_none()) {
+            return Err("Specified node does not exist");
+        }
+        let mut removed = Vec::new();
+        for id in ids {
+            // the node may already be gone, e.g. a street that was connected
+            //  to a crossing removed before
+            let is_street = match self.get_node(*id) {
+                Some(node) => matches!(&*node.get(), NodeBuilder::Street(_)),
+                None => continue,
+            };
+            if is_street {
+                removed.push(self.remove_street_by_id(*id)?);
+            } else {
+                removed.append(&mut self.remove_node_and_connected_by_id(*id)?);
+            }
+        }
+        Ok(removed)
+    }
+    /// removes a single street by it's id without removing its endpoints
+    ///
+    /// The street is detached from the nodes it connects and then removed
+    /// from the list of nodes.
+    pub fn remove_street_by_id(&mut self, id: usize) -> Result<IntMut<NodeBuilder>, &'static str> {
+        let i = match self
+            .nodes
+            .iter()
+            .enumerate()
+            .find(|(_i, n)| n.get().get_id() == id)
+        {
+            Some((i, _n)) => i,
+            None => return Err("Specified street does not exist"),
+        };
+        let connections = match &*self.nodes[i].get() {
+            NodeBuilder::Street(street) => street.get_all_connections(),
+            _ => return Err("Specified node is not a street"),
+        };
+        let street = self.nodes.swap_remove(i);
+        let weak_street = street.downgrade();
+        for connection in connections {
+            if let Some(node) = connection.try_upgrade() {
+                node.get().remove_connection(&weak_street);
+            }
+        }
+        Ok(street)
+    }
     /// removes a node using the internal node index
     ///
     /// This is used by the functions `remove_node_by_id` and `remove_node_by_ref`
@@ -456,9 +1046,9 @@ impl<Car: Movable> SimulatorBuilder<Car> {
         if node_index >= self.nodes.len() {
             return Err("Node index out of bounds");
         }
-        // TODO: Replace with swap_remove for better performance (check if this
-        // doesn't break anything)
-        let node = self.nodes.remove(node_index);
+        // The position of the nodes doesn't matter, as all lookups (`build`,
+        // the MovableServer, the frontend) go through the ids of the nodes
+        let node = self.nodes.swap_remove(node_index);
         if !remove_connections {
             return Ok(vec![node]);
         }
@@ -485,11 +1075,9 @@ impl<Car: Movable> SimulatorBuilder<Car> {
                         // the nodes that are connected to it, to avoid having dead references
                         if remove {
                             for connection in rnode.get().get_all_connections() {
-                                if connection != node {
-                                    connection
-                                        .upgrade()
-                                        .get()
-                                        .remove_connection(&rnode.downgrade());
+                                // the other end may have been removed already
+                                if let Some(other) = connection.try_upgrade().filter(|c| *c != node) {
+                                    other.get().remove_connection(&rnode.downgrade());
                                 }
                             }
                         }
@@ -519,11 +1107,9 @@ impl<Car: Movable> SimulatorBuilder<Car> {
                         if remove {
                             // println!("{}", rnode.get().get_id());
                             for connection in rnode.get().get_all_connections() {
-                                if connection != node {
-                                    connection
-                                        .upgrade()
-                                        .get()
-                                        .remove_connection(&rnode.downgrade());
+                                // the other end may have been removed already
+                                if let Some(other) = connection.try_upgrade().filter(|c| *c != node) {
+                                    other.get().remove_connection(&rnode.downgrade());
                                 }
                             }
                         }
@@ -538,31 +1124,38 @@ impl<Car: Movable> SimulatorBuilder<Car> {
         removed_nodes.push(node);
         // Make sure the elements that are the rightmost get removed first
         // this is CRUCIAL to ensure that the right elements are removed
-        // (when removing, the index of all the elements on the right gets one
-        // lower, making all the other saved indicis invalid)
+        // (swap_remove moves the last element to the removed position, which
+        // is only safe if no index to the right of it is still needed)
         to_remove.sort();
         for index in to_remove.iter().rev() {
-            removed_nodes.push(self.nodes.remove(*index));
+            removed_nodes.push(self.nodes.swap_remove(*index));
         }
         return Ok(removed_nodes);
     }
 }
 
-impl<'de> Deserialize<'de> for SimulatorBuilder {
-    /// creates a `SimulatorBuilder` object from a `&str` formatted in a json-like way
-    ///
-    /// to see how the json must be formatted, look at the fields of
-    /// `JsonCrossing` and `JsonRepresentation`
+impl SimulatorBuilder {
+    /// creates a `SimulatorBuilder` from a `&str` formatted in a json-like way
+    /// (see [SimulatorBuilder::deserialize])
     ///
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de> {
-        // Generate object holding all the data, still formatted in json way
-        let json_representation: JsonRepresentation = JsonRepresentation::deserialize(deserializer)?;
+    /// Unlike `serde_json::from_str`, the returned [GraphError] tells what is wrong
+    pub fn from_json(json: &str) -> Result<SimulatorBuilder, GraphError> {
+        let json_representation: JsonRepresentation =
+            serde_json::from_str(json).map_err(|err| GraphError::InvalidJson(err.to_string()))?;
+        SimulatorBuilder::from_json_representation(json_representation)
+    }
+
+    /// connects the nodes of the deserialized json, making sure every
+    /// connection points to an existing node
+    fn from_json_representation(
+        json_representation: JsonRepresentation,
+    ) -> Result<SimulatorBuilder, GraphError> {
         let mut nodes: Vec<IntMut<NodeBuilder>> = Vec::new();
         // generate all crossings
         for json_node in json_representation.nodes.iter() {
-            nodes.push(IntMut::new(json_node.to_unfinished_builder()));
+            let node = json_node.to_unfinished_builder();
+            let id = node.get_id();
+            nodes.push(IntMut::with_id(node, id));
         }
         let mut builder = SimulatorBuilder::<PathAwareCar>::new();
         builder.with_delay(json_representation.delay)
@@ -570,56 +1163,66 @@ impl<'de> Deserialize<'de> for SimulatorBuilder {
         builder.next_id = json_representation.next_id;
 
         builder.nodes = nodes;
+        let find = |nodes: &[IntMut<NodeBuilder>], id: usize| {
+            nodes
+                .iter()
+                .find(|n| n.get().get_id() == id)
+                .cloned()
+                .ok_or(GraphError::NodeIndexOutOfRange { index: id })
+        };
+        // the start and end of every street, to find streets connecting the same nodes
+        let mut street_ends = HashSet::new();
         // info!("nodes: {:#?}", builder.nodes);
         // connect the crossings with streets
         for (i, node) in json_representation.nodes.iter().enumerate() {
             match node {
                 JsonNode::Crossing(jcrossing) => {
-                        for (dir, n_id) in jcrossing.connected.input.iter() {
-                            let node = builder.nodes.iter().find( | n | {
-                                n.get().get_id() == *n_id
-                            }).unwrap();
+                    for (dir, n_ids) in jcrossing.connected.input.iter() {
+                        for n_id in n_ids.ids() {
+                            let node = find(&builder.nodes, n_id)?;
                             if let NodeBuilder::Crossing(crossing) = &mut *builder.nodes[i].get() {
-                                crossing.connect(*dir, InOut::IN, node).unwrap();
+                                connect_loaded_crossing(crossing, *dir, InOut::IN, &node, (n_id, jcrossing.id))?;
                             } else {panic!()}
                         }
-                        for (dir, n_id) in jcrossing.connected.output.iter() {
-                            let node = builder.nodes.iter().find( | n | {
-                                n.get().get_id() == *n_id
-                            }).unwrap();
+                    }
+                    for (dir, n_ids) in jcrossing.connected.output.iter() {
+                        for n_id in n_ids.ids() {
+                            let node = find(&builder.nodes, n_id)?;
                             if let NodeBuilder::Crossing(crossing) = &mut *builder.nodes[i].get() {
-                                crossing.connect(*dir, InOut::OUT, node).unwrap();
+                                connect_loaded_crossing(crossing, *dir, InOut::OUT, &node, (jcrossing.id, n_id))?;
                             } else {panic!()}
                         }
+                    }
                 },
                 JsonNode::IONode(jio_node) => {
                     for id_in in jio_node.connected_in.iter() {
-                        let target = builder.get_node(*id_in).unwrap();
+                        let target = find(&builder.nodes, *id_in)?;
                         if let NodeBuilder::IONode(io_node) = &mut *builder.nodes[i].get() {
-                            io_node.connect(InOut::IN, target);
+                            io_node.connect(InOut::IN, &target);
                         } else {panic!()}
                     }
                     for id_out in jio_node.connected_out.iter() {
                         // info!("Parsing info for node with id: {}, conn_id: {}", jio_node.id, id_out);
-                        let target = builder.nodes.iter().find( | n | n.get().get_id() == *id_out).unwrap();
+                        let target = find(&builder.nodes, *id_out)?;
                         if let NodeBuilder::IONode(io_node) = &mut *builder.nodes[i].get() {
-                            io_node.connect(InOut::OUT, target);
+                            io_node.connect(InOut::OUT, &target);
                         } else {panic!()}
                     }
                 },
                 JsonNode::Street(jstreet) => {
-                    if let Some(id_in) = jstreet.conn_in {
-                        let target = builder.nodes.iter().find( | n | n.get().get_id() == id_in).unwrap();
-                        if let NodeBuilder::Street(street) = &mut *builder.nodes[i].get() {
-                            street.connect(InOut::IN, target);
-                        } else {panic!()}
-                    }
-                    if let Some(id_out) = jstreet.conn_out {
-                        let target = builder.nodes.iter().find( | n | n.get().get_id() == id_out).unwrap();
-                        if let NodeBuilder::Street(street) = &mut *builder.nodes[i].get() {
-                            street.connect(InOut::OUT, target);
-                        } else {panic!()}
+                    let (id_in, id_out) = match (jstreet.conn_in, jstreet.conn_out) {
+                        (Some(id_in), Some(id_out)) => (id_in, id_out),
+                        _ => return Err(GraphError::DanglingStreet { id: jstreet.id }),
+                    };
+                    if !street_ends.insert((id_in, id_out)) {
+                        return Err(GraphError::DuplicateConnection { from: id_in, to: id_out });
                     }
+                    let start = find(&builder.nodes, id_in)?;
+                    let end = find(&builder.nodes, id_out)?;
+                    if let NodeBuilder::Street(street) = &mut *builder.nodes[i].get() {
+                        street.connect(InOut::IN, &start);
+                        street.connect(InOut::OUT, &end);
+                    } else {panic!()}
                 },
             }
         }
@@ -627,6 +1230,22 @@ impl<'de> Deserialize<'de> for SimulatorBuilder {
     }
 }
 
+impl<'de> Deserialize<'de> for SimulatorBuilder {
+    /// creates a `SimulatorBuilder` object from a `&str` formatted in a json-like way
+    ///
+    /// to see how the json must be formatted, look at the fields of
+    /// `JsonCrossing` and `JsonRepresentation`
+    ///
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de> {
+        // Generate object holding all the data, still formatted in json way
+        let json_representation: JsonRepresentation = JsonRepresentation::deserialize(deserializer)?;
+        SimulatorBuilder::from_json_representation(json_representation)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 
 impl Serialize for SimulatorBuilder {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -643,13 +1262,21 @@ impl Serialize for SimulatorBuilder {
                             connected_out: n.connections_out.iter().map( | c | c.upgrade().get().get_id()).collect(),
                             spawn_rate: n.spawn_rate,
                             id,
+                            role: n.role,
+                            max_spawn_per_step: n.max_spawn_per_step,
                         }
                     )
                 },
                 NodeBuilder::Crossing(n) => {
                     let json_conns = JsonCrossingConnections {
-                        input: n.connections.input.iter().map( | (dir, c) | (*dir, c.upgrade().get().get_id()) ).collect(),
-                        output : n.connections.output.iter().map( | (dir, c) | (*dir, c.upgrade().get().get_id()) ).collect(),
+                        input: n.connections.input.iter().map( | (dir, c) | (
+                            *dir,
+                            JsonConnectionIds::from_ids(c.iter().map(| c | c.upgrade().get().get_id()).collect())
+                        )).collect(),
+                        output : n.connections.output.iter().map( | (dir, c) | (
+                            *dir,
+                            JsonConnectionIds::from_ids(c.iter().map(| c | c.upgrade().get().get_id()).collect())
+                        )).collect(),
                     };
                     JsonNode::Crossing(
                         JsonCrossing {
@@ -667,6 +1294,8 @@ impl Serialize for SimulatorBuilder {
                             lanes: n.lanes,
                             length: n.lane_length,
                             id,
+                            lane_turns: n.lane_turns.clone(),
+                            speed_limit: n.speed_limit,
                         }
                     )
                 },
@@ -683,6 +1312,460 @@ impl Serialize for SimulatorBuilder {
 }
 
 mod tests {
+    #[test]
+    fn self_loops_and_empty_streets_are_rejected() {
+        use crate::node_builder::{CrossingBuilder, Direction, IONodeBuilder, NodeBuilder};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        simulator.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        assert!(simulator.connect_with_street((0, Direction::E), (0, Direction::W), 1, 100.0).is_err());
+        assert!(simulator.connect_with_street((0, Direction::E), (1, Direction::W), 1, 0.0).is_err());
+        // nothing was added
+        assert_eq!(simulator.nodes.len(), 2);
+        assert!(simulator.neighbors(0).is_empty());
+        assert!(simulator.connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0).is_ok());
+    }
+    #[test]
+    fn crossing_neighbors_have_their_directions() {
+        use crate::node_builder::{CrossingBuilder, Direction, IONodeBuilder, InOut, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        simulator.add_nodes((0..4).map(|_| NodeBuilder::IONode(IONodeBuilder::new())));
+        let mut street = |from: (usize, Direction), to: (usize, Direction)| {
+            simulator.connect_with_street(from, to, 1, 100.0).unwrap().get().get_id()
+        };
+        let north = street((1, Direction::S), (0, Direction::N));
+        let east = street((0, Direction::E), (2, Direction::W));
+        let south = street((3, Direction::N), (0, Direction::S));
+        let west = street((0, Direction::W), (4, Direction::E));
+        let neighbors = simulator.neighbors(0);
+        assert_eq!(neighbors.len(), 4);
+        for expected in [
+            (north, Some(Direction::N), InOut::IN),
+            (east, Some(Direction::E), InOut::OUT),
+            (south, Some(Direction::S), InOut::IN),
+            (west, Some(Direction::W), InOut::OUT),
+        ] {
+            assert!(neighbors.contains(&expected), "{:?} is missing", expected);
+        }
+        // a street knows its endpoints, but not their sides
+        assert!(simulator.neighbors(north).contains(&(0, None, InOut::OUT)));
+        assert!(simulator.neighbors(north).contains(&(1, None, InOut::IN)));
+        assert!(simulator.neighbors(42).is_empty());
+    }
+    #[test]
+    fn io_node_neighbors_know_the_street_direction() {
+        use crate::node_builder::{CrossingBuilder, Direction, IONodeBuilder, InOut, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        simulator.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        let into_io_node = simulator
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0)
+            .unwrap()
+            .get()
+            .get_id();
+        let out_of_io_node = simulator
+            .connect_with_street((1, Direction::W), (0, Direction::E), 1, 100.0)
+            .unwrap()
+            .get()
+            .get_id();
+        let neighbors = simulator.neighbors(1);
+        assert_eq!(neighbors.len(), 2);
+        // the street ends at the IONode, so it is one of its inputs
+        assert!(neighbors.contains(&(into_io_node, None, InOut::IN)));
+        assert!(neighbors.contains(&(out_of_io_node, None, InOut::OUT)));
+    }
+    #[test]
+    fn spawn_rates_can_be_set_by_id() {
+        use crate::node_builder::{CrossingBuilder, IONodeBuilder, NodeBuilder};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::{SimulatorBuilder, SpawnRateError};
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        simulator.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        let default_rate = IONodeBuilder::new().spawn_rate;
+        assert_eq!(simulator.io_nodes(), vec![(0, default_rate), (2, default_rate)]);
+        simulator.set_spawn_rate(2, 0.5).unwrap();
+        assert_eq!(simulator.io_nodes(), vec![(0, default_rate), (2, 0.5)]);
+        // only IONodes have a spawn rate
+        assert_eq!(simulator.set_spawn_rate(1, 0.5), Err(SpawnRateError::NotAnIONode(1)));
+        assert_eq!(simulator.set_spawn_rate(42, 0.5), Err(SpawnRateError::NodeDoesntExist(42)));
+        assert_eq!(simulator.set_spawn_rate(0, -1.0), Err(SpawnRateError::InvalidRate(-1.0)));
+        assert!(simulator.set_spawn_rate(0, f64::NAN).is_err());
+        assert_eq!(simulator.io_nodes()[0], (0, default_rate));
+    }
+    #[test]
+    fn dot_export_has_an_edge_per_street() {
+        use crate::build_grid::build_grid_sim;
+        use crate::node_builder::NodeBuilder;
+        let simulator = build_grid_sim(3, 100.0);
+        let streets = simulator
+            .nodes
+            .iter()
+            .filter(|n| matches!(&*n.read(), NodeBuilder::Street(_)))
+            .count();
+        let dot = simulator.to_dot();
+        assert!(dot.starts_with("digraph"));
+        assert_eq!(dot.lines().filter(|l| l.contains("->")).count(), streets);
+        assert_eq!(
+            dot.lines().filter(|l| l.contains("shape=")).count(),
+            simulator.nodes.len() - streets
+        );
+    }
+    #[test]
+    fn dangling_street_is_reported() {
+        use crate::node_builder::{Direction, IONodeBuilder, NodeBuilder};
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::simulation_builder::{SimulatorBuilder, ValidationIssue};
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_nodes(vec![
+            NodeBuilder::IONode(IONodeBuilder::new()),
+            NodeBuilder::IONode(IONodeBuilder::new()),
+        ]);
+        simulator
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0)
+            .unwrap();
+        assert!(simulator.validate().is_ok());
+        // remove the end node without removing the street
+        let end = simulator.nodes.remove(1);
+        drop(end);
+        let issues = simulator.validate().unwrap_err();
+        assert!(issues.contains(&ValidationIssue::DanglingStreet(2)));
+        let mv_server = MovableServer::<PathAwareCar>::new();
+        assert!(simulator.build(&mv_server).is_err());
+    }
+    #[test]
+    fn unreachable_io_node_is_only_reported_by_validate() {
+        use crate::node_builder::{Direction, IONodeBuilder, NodeBuilder};
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::simulation_builder::{SimulatorBuilder, ValidationIssue};
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_nodes(vec![
+            NodeBuilder::IONode(IONodeBuilder::new()),
+            NodeBuilder::IONode(IONodeBuilder::new()),
+            NodeBuilder::IONode(IONodeBuilder::new()),
+        ]);
+        simulator
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0)
+            .unwrap();
+        let issues = simulator.validate().unwrap_err();
+        assert_eq!(issues, vec![ValidationIssue::UnreachableIONode(2)]);
+        // reachability isn't checked on every build
+        let mv_server = MovableServer::<PathAwareCar>::new();
+        assert!(simulator.build(&mv_server).is_ok());
+    }
+    #[test]
+    fn removing_keeps_ids_and_connections() {
+        use crate::node_builder::{CrossingBuilder, Direction, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        use std::collections::HashMap;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        let ids = simulator.add_nodes((0..50).map(|_| NodeBuilder::Crossing(CrossingBuilder::new())));
+        // chain all crossings
+        let chain: Vec<_> = ids
+            .windows(2)
+            .map(|w| ((w[0], Direction::E), (w[1], Direction::W), 1))
+            .collect();
+        simulator.connect_many(&chain).unwrap();
+        let connection_ids = |sim: &SimulatorBuilder| -> HashMap<usize, Vec<usize>> {
+            sim.nodes
+                .iter()
+                .map(|n| {
+                    let n = n.get();
+                    let mut conns: Vec<usize> = n
+                        .get_all_connections()
+                        .iter()
+                        .map(|c| c.upgrade().get().get_id())
+                        .collect();
+                    conns.sort();
+                    (n.get_id(), conns)
+                })
+                .collect()
+        };
+        let before = connection_ids(&simulator);
+        let removed: Vec<usize> = simulator
+            .remove_node_and_connected_by_id(25)
+            .unwrap()
+            .iter()
+            .map(|n| n.get().get_id())
+            .collect();
+        // the crossing and the two streets connected to it
+        assert_eq!(removed.len(), 3);
+        let after = connection_ids(&simulator);
+        assert_eq!(after.len(), before.len() - 3);
+        for (id, conns) in after.iter() {
+            let expected: Vec<usize> = before[id]
+                .iter()
+                .filter(|c| !removed.contains(c))
+                .copied()
+                .collect();
+            assert_eq!(*conns, expected);
+        }
+    }
+    #[test]
+    fn add_many_nodes() {
+        use crate::node_builder::{CrossingBuilder, Direction, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        let ids = simulator.add_nodes((0..100).map(|_| NodeBuilder::Crossing(CrossingBuilder::new())));
+        assert_eq!(ids, (0..100).collect::<Vec<usize>>());
+        assert!(simulator.nodes.iter().zip(ids.iter()).all(|(n, id)| n.get().get_id() == *id));
+        let street_ids = simulator
+            .connect_many(&[
+                ((0, Direction::E), (1, Direction::W), 1),
+                ((1, Direction::E), (2, Direction::W), 2),
+            ])
+            .unwrap();
+        assert_eq!(street_ids, vec![100, 101]);
+        // the next node continues after the streets
+        assert_eq!(simulator.add_nodes(vec![NodeBuilder::Crossing(CrossingBuilder::new())]), vec![102]);
+    }
+    #[test]
+    fn street_length_is_set_when_connecting() {
+        use crate::node_builder::{Direction, IONodeBuilder, NodeBuilder};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_nodes(vec![
+            NodeBuilder::IONode(IONodeBuilder::new()),
+            NodeBuilder::IONode(IONodeBuilder::new()),
+        ]);
+        let street = simulator
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 250.0)
+            .unwrap();
+        match &*street.get() {
+            NodeBuilder::Street(street) => assert_eq!(street.lane_length, 250.0),
+            _ => panic!("connect_with_street didn't return a street"),
+        };
+    }
+    #[test]
+    fn multiple_connections_per_side() {
+        use crate::datastructs::IntMut;
+        use crate::node_builder::{CrossingConnections, Direction, InOut, NodeBuilder, StreetBuilder};
+        let mut connections = CrossingConnections::<NodeBuilder>::new();
+        let street_a = IntMut::new(NodeBuilder::Street(StreetBuilder::new()));
+        let street_b = IntMut::new(NodeBuilder::Street(StreetBuilder::new()));
+        connections.add(Direction::N, InOut::OUT, &street_a).unwrap();
+        connections.add(Direction::N, InOut::OUT, &street_b).unwrap();
+        // the same street can't be added twice
+        assert!(connections.add(Direction::N, InOut::OUT, &street_a).is_err());
+        let outputs = connections.get_all(InOut::OUT, Direction::N);
+        assert_eq!(outputs.len(), 2);
+        assert!(outputs[0] == street_a);
+        assert!(outputs[1] == street_b);
+        assert!(*connections.get(InOut::OUT, Direction::N).unwrap() == street_a);
+        assert_eq!(connections.get_direction_for_item(InOut::OUT, &street_b), Some(Direction::N));
+        connections.remove_connection(InOut::OUT, &street_a.downgrade());
+        assert_eq!(connections.len(InOut::OUT), 1);
+    }
+    #[test]
+    fn duplicate_street_is_rejected() {
+        use crate::node_builder::Direction;
+        use crate::node_builder::{CrossingBuilder, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        simulator
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0)
+            .unwrap();
+        assert!(simulator
+            .connect_with_street((0, Direction::N), (1, Direction::S), 1, 100.0)
+            .is_err());
+        assert_eq!(simulator.nodes.len(), 3);
+        assert_eq!(simulator.get_node(0).unwrap().get().get_all_connections().len(), 1);
+        assert_eq!(simulator.get_node(1).unwrap().get().get_all_connections().len(), 1);
+        // the other direction is a different street
+        simulator
+            .connect_with_street((1, Direction::N), (0, Direction::S), 1, 100.0)
+            .unwrap();
+        assert_eq!(simulator.nodes.len(), 4);
+        assert!(simulator.validate().is_ok());
+    }
+    #[test]
+    fn connection_error_contains_node_ids() {
+        use crate::node_builder::Direction;
+        use crate::node_builder::{CrossingBuilder, NodeBuilder};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_nodes((0..4).map(|_| NodeBuilder::Crossing(CrossingBuilder::new())));
+        // the ids of the remaining nodes don't match their indices anymore
+        simulator.remove_node_and_connected_by_id(0).unwrap();
+        simulator
+            .connect_with_street((2, Direction::E), (3, Direction::W), 1, 100.0)
+            .unwrap();
+        let err = simulator
+            .connect_with_street((2, Direction::N), (3, Direction::S), 1, 100.0)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("ConnectionError: 2 -> 3"));
+    }
+    #[test]
+    fn failed_connection_leaves_nodes_unchanged() {
+        use crate::node_builder::Direction;
+        use crate::node_builder::{CrossingBuilder, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        let street_id = simulator
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0)
+            .unwrap()
+            .get()
+            .get_id();
+        let connections_before = simulator.get_node(0).unwrap().get().get_all_connections();
+        // the OUT side can be connected, but a street can't be the IN side
+        assert!(simulator
+            .connect_with_street((0, Direction::N), (street_id, Direction::S), 1, 100.0)
+            .is_err());
+        assert_eq!(simulator.nodes.len(), 3);
+        assert_eq!(
+            simulator.get_node(0).unwrap().get().get_all_connections(),
+            connections_before
+        );
+        // the id of the failed street isn't used up
+        let next_street_id = simulator
+            .connect_with_street((1, Direction::N), (0, Direction::S), 1, 100.0)
+            .unwrap()
+            .get()
+            .get_id();
+        assert_eq!(next_street_id, street_id + 1);
+    }
+    #[test]
+    fn removing_crossing_with_dead_street_end_doesnt_panic() {
+        use crate::node_builder::{CrossingBuilder, Direction, IONodeBuilder, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut builder = SimulatorBuilder::<PathAwareCar>::new();
+        builder.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        builder.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        builder.add_node(NodeBuilder::IONode(IONodeBuilder::new()));
+        builder
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0)
+            .unwrap();
+        builder
+            .connect_with_street((1, Direction::E), (2, Direction::W), 1, 100.0)
+            .unwrap();
+        // drop the second IONode without detaching the street leading to it
+        builder.nodes.retain(|n| n.get().get_id() != 2);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        assert!(builder.build(&mv_server).is_err());
+        let removed = builder.remove_node_and_connected_by_id(1).unwrap();
+        assert_eq!(removed.len(), 3);
+        assert_eq!(builder.nodes.len(), 1);
+        assert!(builder.get_node(0).unwrap().get().get_all_connections().is_empty());
+    }
+    #[test]
+    fn remove_several_nodes_at_once() {
+        use crate::build_grid::build_grid_sim;
+        use crate::node_builder::{NodeBuilder, NodeBuilderTrait};
+        let mut simulator = build_grid_sim(3, 100.0);
+        let crossings: Vec<usize> = simulator
+            .nodes
+            .iter()
+            .filter(|n| matches!(&*n.read(), NodeBuilder::Crossing(_)))
+            .map(|n| n.get().get_id())
+            .take(2)
+            .collect();
+        let street = simulator
+            .nodes
+            .iter()
+            .find(|n| matches!(&*n.read(), NodeBuilder::Street(_)))
+            .map(|n| n.get().get_id())
+            .unwrap();
+        let mut ids = crossings.clone();
+        ids.push(street);
+        let removed: Vec<usize> = simulator
+            .remove_nodes_by_ids(&ids)
+            .unwrap()
+            .iter()
+            .map(|n| n.get().get_id())
+            .collect();
+        for id in ids.iter() {
+            assert!(removed.contains(id));
+            assert!(simulator.get_node(*id).is_none());
+        }
+        // no remaining node points to a removed one
+        for node in simulator.nodes.iter() {
+            for connection in node.get().get_all_connections() {
+                assert!(simulator.nodes.iter().any(|n| *n == connection));
+            }
+        }
+        // unknown ids don't remove anything
+        let num_nodes = simulator.nodes.len();
+        let remaining = simulator.nodes[0].get().get_id();
+        assert!(simulator.remove_nodes_by_ids(&[remaining, street]).is_err());
+        assert_eq!(simulator.nodes.len(), num_nodes);
+    }
+    #[test]
+    fn built_simulators_are_independent() {
+        use crate::build_grid::busy_grid_sim;
+        use crate::pathfinding::{MovableServer, PathAwareCar};
+        use crate::node::Node;
+        use crate::sim_manager::default_nn_topology;
+        use crate::traits::NodeTrait;
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut builder = busy_grid_sim(3, 10.0);
+        let mut mv_server = MovableServer::<PathAwareCar>::new();
+        mv_server.register_simulator_builder(&builder);
+        let mut sim1 = builder.build(&mv_server).unwrap();
+        let mut sim2 = builder.build(&mv_server).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let crossings = sim1.nodes.iter().filter(|n| matches!(&*n.read(), Node::Crossing(_))).count();
+        sim1.set_neural_networks(
+            (0..crossings)
+                .map(|_| art_int::Network::random(&mut rng, &default_nn_topology()))
+                .collect(),
+        )
+        .unwrap();
+        for _ in 0..10 {
+            sim1.update_all_nodes_with_rng(sim1.dt.into(), &mut rng);
+        }
+        assert!(sim1.count_cars() > 0);
+        // the cars of the first simulator don't show up in the second one
+        assert_eq!(sim2.count_cars(), 0);
+        // not even the connections point to nodes of the other simulator
+        for node in sim2.nodes.iter() {
+            assert!(sim1.nodes.iter().all(|n| n != node));
+            for connection in node.get().get_out_connections() {
+                assert!(sim2.nodes.iter().any(|n| *n == connection));
+            }
+        }
+    }
+    #[test]
+    fn remove_street_keeps_endpoints() {
+        use crate::node_builder::Direction;
+        use crate::node_builder::{CrossingBuilder, NodeBuilder, NodeBuilderTrait};
+        use crate::pathfinding::PathAwareCar;
+        use crate::simulation_builder::SimulatorBuilder;
+        let mut simulator = SimulatorBuilder::<PathAwareCar>::new();
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        simulator.add_node(NodeBuilder::Crossing(CrossingBuilder::new()));
+        let street_id = simulator
+            .connect_with_street((0, Direction::E), (1, Direction::W), 1, 100.0)
+            .unwrap()
+            .get()
+            .get_id();
+        simulator.remove_street_by_id(street_id).unwrap();
+        assert_eq!(simulator.nodes.len(), 2);
+        assert!(simulator.get_node(street_id).is_none());
+        assert!(simulator.get_node(0).unwrap().get().get_all_connections().is_empty());
+        assert!(simulator.get_node(1).unwrap().get().get_all_connections().is_empty());
+        // crossings can't be removed this way
+        assert!(simulator.remove_street_by_id(0).is_err());
+    }
     #[test]
     fn connect_with_streets() {
         use crate::node_builder::Direction;
@@ -699,4 +1782,108 @@ mod tests {
             .connect_with_street((1, Direction::S), (0, Direction::N), 3, 100.0)
             .unwrap();
     }
+    #[test]
+    fn invalid_json_graphs_return_typed_errors() {
+        use crate::build_grid::build_grid_sim;
+        use crate::simulation_builder::{GraphError, SimulatorBuilder};
+        use serde_json::Value;
+        let json: Value = serde_json::to_value(&build_grid_sim(3, 100.0)).unwrap();
+        let load = |json: &Value| SimulatorBuilder::from_json(&json.to_string());
+        assert!(load(&json).is_ok());
+        let street_index = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .position(|n| n.get("Street").is_some())
+            .unwrap();
+        let street = json["nodes"][street_index]["Street"].clone();
+        let street_id = street["id"].as_u64().unwrap() as usize;
+
+        assert!(matches!(load(&Value::from("{")), Err(GraphError::InvalidJson(_))));
+
+        let mut missing_node = json.clone();
+        missing_node["nodes"][street_index]["Street"]["conn_out"] = Value::from(9999);
+        assert_eq!(
+            load(&missing_node).unwrap_err(),
+            GraphError::NodeIndexOutOfRange { index: 9999 }
+        );
+
+        let mut dangling = json.clone();
+        dangling["nodes"][street_index]["Street"]["conn_in"] = Value::Null;
+        assert_eq!(load(&dangling).unwrap_err(), GraphError::DanglingStreet { id: street_id });
+
+        // a second street between the same nodes
+        let mut duplicate = json.clone();
+        let mut copy = street.clone();
+        copy["id"] = Value::from(10_000);
+        duplicate["nodes"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({ "Street": copy }));
+        match load(&duplicate) {
+            Err(GraphError::DuplicateConnection { from, to }) => {
+                assert_eq!(from, street["conn_in"].as_u64().unwrap() as usize);
+                assert_eq!(to, street["conn_out"].as_u64().unwrap() as usize);
+            }
+            other => panic!("expected a duplicate connection, got {:?}", other.map(|_| ())),
+        }
+
+        // an input street of a crossing that is also listed as its output
+        let crossing_index = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .position(|n| n.get("Crossing").is_some())
+            .unwrap();
+        let crossing = &json["nodes"][crossing_index]["Crossing"];
+        let crossing_id = crossing["id"].as_u64().unwrap() as usize;
+        let (dir, input) = crossing["connected"]["input"].as_object().unwrap().iter().next().unwrap();
+        let input_id = match input {
+            Value::Array(ids) => ids[0].clone(),
+            id => id.clone(),
+        };
+        let mut in_and_out = json.clone();
+        in_and_out["nodes"][crossing_index]["Crossing"]["connected"]["output"][dir] = input_id.clone();
+        match load(&in_and_out) {
+            Err(GraphError::InvalidConnection { from, to, .. }) => {
+                assert_eq!(from, crossing_id);
+                assert_eq!(to, input_id.as_u64().unwrap() as usize);
+            }
+            other => panic!("expected an invalid connection, got {:?}", other.map(|_| ())),
+        }
+    }
+    #[test]
+    fn a_spawn_limit_of_zero_is_rejected_when_loading() {
+        use crate::build_grid::build_grid_sim;
+        use crate::simulation_builder::{GraphError, SimulatorBuilder};
+        use serde_json::Value;
+        let json: Value = serde_json::to_value(&build_grid_sim(3, 100.0)).unwrap();
+        let io_index = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .position(|n| n.get("IONode").is_some())
+            .unwrap();
+        let with_limit = |limit: u64| {
+            let mut json = json.clone();
+            json["nodes"][io_index]["IONode"]["max_spawn_per_step"] = Value::from(limit);
+            SimulatorBuilder::from_json(&json.to_string())
+        };
+        assert!(matches!(with_limit(0), Err(GraphError::InvalidJson(_))));
+        assert!(with_limit(2).is_ok());
+    }
+    #[test]
+    fn builder_survives_bincode_round_trip() {
+        use crate::build_grid::build_grid_sim;
+        use crate::simulation_builder::SimulatorBuilder;
+        let builder = build_grid_sim(3, 100.0);
+        let bytes = bincode::serialize(&builder).unwrap();
+        let from_bincode: SimulatorBuilder = bincode::deserialize(&bytes).unwrap();
+        let from_json: SimulatorBuilder = serde_json::from_str(&serde_json::to_string(&builder).unwrap()).unwrap();
+        // the maps of the crossings aren't ordered, so the builders are compared as json values
+        assert_eq!(
+            serde_json::to_value(&from_bincode).unwrap(),
+            serde_json::to_value(&from_json).unwrap()
+        );
+    }
 }
@@ -0,0 +1,251 @@
+use crate::node::MIN_PHASE_DURATION;
+use crate::traits::TrafficController;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{self, Display};
+use tracing::warn;
+
+impl TrafficController for art_int::Network {
+    /// chooses the state with the highest output
+    fn next_state(&mut self, inputs: &[f32], phases: usize) -> usize {
+        self.next_available_state(inputs, phases, &[])
+    }
+    /// chooses the available state with the highest output
+    fn next_available_state(&mut self, inputs: &[f32], phases: usize, available: &[usize]) -> usize {
+        self.propagate(inputs.to_vec())
+            .iter()
+            .take(phases)
+            .enumerate()
+            .filter(|(i, _)| available.is_empty() || available.contains(i))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+    fn network(&self) -> Option<&art_int::Network> {
+        Some(self)
+    }
+    fn into_network(self: Box<Self>) -> Option<art_int::Network> {
+        Some(*self)
+    }
+}
+
+/// Switches to the next state every time it is asked
+///
+/// As crossings keep a state for at least [crate::nodes::MIN_PHASE_DURATION],
+/// every state is green for that long
+#[derive(Debug, Clone, Default)]
+pub struct RoundRobin {
+    /// the last state that was returned
+    state: usize,
+}
+
+impl RoundRobin {
+    /// returns a controller that starts with the state after state 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TrafficController for RoundRobin {
+    fn next_state(&mut self, _inputs: &[f32], phases: usize) -> usize {
+        self.state = (self.state + 1) % phases;
+        self.state
+    }
+    /// skips the states that aren't available
+    fn next_available_state(&mut self, inputs: &[f32], phases: usize, available: &[usize]) -> usize {
+        for _ in 0..phases {
+            let state = self.next_state(inputs, phases);
+            if available.is_empty() || available.contains(&state) {
+                return state;
+            }
+        }
+        self.state
+    }
+}
+
+/// The controller of a crossing that hasn't been given a neural network or
+/// another controller (yet)
+///
+/// It behaves like [RoundRobin], but warns the first time it is asked for a
+/// state, as the crossing most likely should have gotten a neural network
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MissingController {
+    /// the id of the crossing, for the warning
+    crossing: usize,
+    round_robin: RoundRobin,
+    warned: bool,
+}
+
+impl MissingController {
+    pub(crate) fn new(crossing: usize) -> Self {
+        Self {
+            crossing,
+            ..Self::default()
+        }
+    }
+    fn warn_once(&mut self) {
+        if !self.warned {
+            warn!(
+                "Crossing (id={}) has no neural network, cycling through the states instead",
+                self.crossing
+            );
+            self.warned = true;
+        }
+    }
+}
+
+impl TrafficController for MissingController {
+    fn next_state(&mut self, inputs: &[f32], phases: usize) -> usize {
+        self.warn_once();
+        self.round_robin.next_state(inputs, phases)
+    }
+    fn next_available_state(&mut self, inputs: &[f32], phases: usize, available: &[usize]) -> usize {
+        self.warn_once();
+        self.round_robin.next_available_state(inputs, phases, available)
+    }
+}
+
+/// This error is returned if a phase duration of a [FixedTimerController]
+/// isn't a positive, finite number
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidPhaseDurationError {
+    /// the index of the phase
+    pub phase: usize,
+    /// the rejected duration in seconds
+    pub duration: f32,
+}
+
+impl Display for InvalidPhaseDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The duration of phase {} has to be positive and finite, got {}",
+            self.phase, self.duration
+        )
+    }
+}
+
+impl Error for InvalidPhaseDurationError {}
+
+/// Cycles through the states on a fixed schedule, regardless of the traffic
+///
+/// This is the naive timing the evolved neural networks are compared with
+#[derive(Debug, Clone)]
+pub struct FixedTimerController {
+    /// how long each state is kept (in seconds), in the order of the states
+    phase_durations: Vec<f32>,
+    /// the time since the current state started
+    elapsed: f32,
+    /// the index of the current state in `phase_durations`
+    state: usize,
+}
+
+impl FixedTimerController {
+    /// returns a controller that starts with state 0
+    ///
+    /// Durations shorter than [MIN_PHASE_DURATION] are extended to it, as a
+    /// crossing can't change its state earlier. Returns an error if a duration
+    /// isn't a positive, finite number, as the schedule couldn't advance
+    pub fn new(phase_durations: Vec<f32>) -> Result<Self, InvalidPhaseDurationError> {
+        if let Some((phase, duration)) = phase_durations
+            .iter()
+            .enumerate()
+            .find(|(_, d)| !(d.is_finite() && **d > 0.0))
+        {
+            return Err(InvalidPhaseDurationError {
+                phase,
+                duration: *duration,
+            });
+        }
+        Ok(Self {
+            phase_durations: phase_durations
+                .into_iter()
+                .map(|d| d.max(MIN_PHASE_DURATION))
+                .collect(),
+            elapsed: 0.0,
+            state: 0,
+        })
+    }
+    /// how long each state is kept (in seconds), in the order of the states
+    pub fn phase_durations(&self) -> &[f32] {
+        &self.phase_durations
+    }
+}
+
+impl TrafficController for FixedTimerController {
+    fn advance(&mut self, dt: f32) {
+        self.elapsed += dt;
+    }
+    fn next_state(&mut self, _inputs: &[f32], phases: usize) -> usize {
+        if self.phase_durations.is_empty() {
+            return 0;
+        }
+        // the remaining time is kept, so the schedule doesn't drift
+        while self.elapsed >= self.phase_durations[self.state] {
+            self.elapsed -= self.phase_durations[self.state];
+            self.state = (self.state + 1) % self.phase_durations.len();
+        }
+        self.state % phases
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movable::RandCar;
+    use crate::node::{Crossing, TrafficLightState};
+
+    #[test]
+    fn round_robin_cycles_through_states() {
+        let mut crossing = Crossing::<RandCar>::new();
+        crossing.set_controller(Box::new(RoundRobin::new()));
+        let mut states = Vec::new();
+        for _ in 0..8 {
+            crossing.update_traffic_light_state(MIN_PHASE_DURATION).unwrap();
+            states.push(crossing.traffic_light_state.clone());
+        }
+        let cycle = [
+            TrafficLightState::S1,
+            TrafficLightState::S2,
+            TrafficLightState::S3,
+            TrafficLightState::S0,
+        ];
+        assert_eq!(states, [cycle.clone(), cycle].concat());
+        assert_eq!(crossing.phase_switches, 8);
+    }
+
+    #[test]
+    fn fixed_timer_switches_when_duration_elapsed() {
+        let mut crossing = Crossing::<RandCar>::new();
+        crossing.set_controller(Box::new(FixedTimerController::new(vec![6.0, 10.0, 8.0, 12.0]).unwrap()));
+        let mut switches = Vec::new();
+        for step in 1..=42 {
+            let before = crossing.traffic_light_state.clone();
+            crossing.update_traffic_light_state(1.0).unwrap();
+            if crossing.traffic_light_state != before {
+                switches.push((step, crossing.traffic_light_state.clone()));
+            }
+        }
+        assert_eq!(
+            switches,
+            [
+                (6, TrafficLightState::S1),
+                (16, TrafficLightState::S2),
+                (24, TrafficLightState::S3),
+                (36, TrafficLightState::S0),
+                (42, TrafficLightState::S1),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixed_timer_rejects_non_positive_durations() {
+        for duration in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let err = FixedTimerController::new(vec![10.0, duration]).unwrap_err();
+            assert_eq!(err.phase, 1);
+        }
+        // short durations are extended to the minimum
+        let controller = FixedTimerController::new(vec![0.1; 4]).unwrap();
+        assert_eq!(controller.phase_durations(), [MIN_PHASE_DURATION; 4]);
+    }
+}
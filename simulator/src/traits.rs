@@ -1,12 +1,12 @@
 use crate::int_mut::{IntMut, WeakIntMut};
 use crate::node::Node;
+use crate::node_builder::Direction;
 use crate::pathfinding::MovableServer;
 use dyn_clone::DynClone;
-use rand::prelude::ThreadRng;
-use std::collections::HashMap;
+use rand::RngCore;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::Debug;
-use std::sync::MutexGuard;
 
 use crate::movable::{MovableStatus, RandCar};
 
@@ -21,10 +21,12 @@ where
 {
     /// returns true, if the given node is connected
     fn is_connected(&self, other: &IntMut<Node<Car>>) -> bool;
-    /// advances the car position and returns the index of all cars at the end
-    fn update_cars(&mut self, t: f64, mv_server: &mut MovableServer<Car>, rng: &mut ThreadRng) -> Vec<usize>;
+    /// advances the car position and appends the index of all cars at the end to `cars_at_end`
+    fn update_cars(&mut self, t: f64, mv_server: &mut MovableServer<Car>, rng: &mut dyn RngCore, cars_at_end: &mut Vec<usize>);
     /// returns a list of all the other nodes connected to the node
     fn get_out_connections(&self) -> Vec<WeakIntMut<Node<Car>>>;
+    /// appends all the other nodes connected to the node to `out`, so the Vec can be reused
+    fn extend_out_connections(&self, out: &mut Vec<WeakIntMut<Node<Car>>>);
     /// adds a new car to the beginning of the node
     fn add_car(&mut self, car: Car);
     /// a unique node id
@@ -41,7 +43,7 @@ where
     /// removes a car with its index
     fn remove_car(&mut self, i: usize) -> Car;
     /// car_ref from id
-    fn get_car_by_index(&mut self, i: usize) -> &Car;
+    fn get_car_by_index(&self, i: usize) -> &Car;
     /// resets all cars (removes them from the simulation)
     fn reset_cars(&mut self) -> Vec<MovableStatus> {Vec::new()}
     /// 
@@ -77,10 +79,25 @@ pub trait Movable: Debug + Clone + Send + Sync + DynClone {
     fn new() -> Self;
     /// advances the time
     fn update(&mut self, t: f32);
+    /// resets the time spent and the distance traversed, but keeps the path
+    ///
+    /// Is called on cached movables before they are handed out again
+    fn reset_for_reuse(&mut self) {}
     /// for recording the internal position
     fn add_to_dist(&mut self, dist: f32) {}
+    /// how far the movable is along the node it is currently on, from 0 (at the
+    /// start) to 1 (at the end)
+    fn fractional_position(&self) -> f32 {
+        0.0
+    }
+    /// is called by the lane the movable is on whenever it moves
+    fn set_fractional_position(&mut self, _position: f32) {}
     /// sets the path. (Only used in PathAwareCar)
     fn set_path(&mut self, P: Vec<usize>) {}
+    /// sets the turns the lane the movable is on permits at the next crossing
+    ///
+    /// `None` means that any turn is allowed
+    fn set_allowed_turns(&mut self, _turns: Option<HashSet<Direction>>) {}
     /// Decides the next node for the movable to move to
     ///
     /// It can very well happen that the next node can't be determined
@@ -107,6 +124,10 @@ pub trait Movable: Debug + Clone + Send + Sync + DynClone {
             total_dist: 0.0,
         }
     }
+    /// the id of the node the movable drives to next
+    fn next_node_id(&self) -> Option<usize> {
+        None
+    }
     ///
     fn overnext_node_id(&self) -> Option<usize> {
         None
@@ -115,3 +136,41 @@ pub trait Movable: Debug + Clone + Send + Sync + DynClone {
 
 // make it possible to derive Clone for structs with Box<dyn Movable>
 // dyn_clone::clone_trait_object!(Movable);
+
+/// Decides which [crate::nodes::TrafficLightState] a crossing switches to
+///
+/// The neural networks evolved by the genetic algorithm implement this trait,
+/// but simpler controllers (see [crate::controllers]) can be used as baselines
+pub trait TrafficController: Debug + Sync + Send + DynClone {
+    /// returns the index of the next state (in `0..phases`)
+    ///
+    /// `inputs` are calculated by [crate::nodes::Crossing::calculate_nn_inputs]
+    fn next_state(&mut self, inputs: &[f32], phases: usize) -> usize;
+    /// is called in every step with the simulated time `dt`, also while the
+    /// crossing doesn't ask for a new state
+    fn advance(&mut self, _dt: f32) {}
+    /// same as `next_state`, but the returned state has to be one of
+    /// `available`, unless it is empty
+    ///
+    /// This way, crossings with missing sides skip states that can't serve any
+    /// of their sides. By default, the first available state after the one
+    /// returned by `next_state` is used
+    fn next_available_state(&mut self, inputs: &[f32], phases: usize, available: &[usize]) -> usize {
+        let state = self.next_state(inputs, phases);
+        (0..phases)
+            .map(|offset| (state + offset) % phases)
+            .find(|s| available.is_empty() || available.contains(s))
+            .unwrap_or(state)
+    }
+    /// returns the neural network, if the controller is one
+    fn network(&self) -> Option<&art_int::Network> {
+        None
+    }
+    /// converts the controller into a neural network, if it is one
+    fn into_network(self: Box<Self>) -> Option<art_int::Network> {
+        None
+    }
+}
+
+// make it possible to derive Clone for structs with Box<dyn TrafficController>
+dyn_clone::clone_trait_object!(TrafficController);
@@ -1,6 +1,6 @@
 use std::{ptr, collections::{VecDeque, HashMap}};
 
-use crate::{movable::MovableStatus, node::CostCalcParameters, simulation::calculate_cost, CAR_SPACING, node_builder::Direction};
+use crate::{movable::MovableStatus, node::CostCalcParameters, simulation::calculate_cost, CAR_SPACING, DEFAULT_SPEED_LIMIT, node_builder::Direction};
 
 use super::{movable::RandCar, traits::Movable};
 #[allow(unused_imports)]
@@ -18,6 +18,20 @@ where
     length: f32,
     /// the number of movables that are waiting at the end to go on a crossing
     movables_waiting: u32,
+    /// the maximum speed of the movables on the traversible
+    speed_limit: f32,
+}
+
+/// how far `pos` is along a traversible of length `length`, from 0 to 1
+///
+/// A traversible of length 0 (e.g. a crossing) is already traversed at its
+/// start, but it is reported as 0 instead of NaN
+fn fraction_of(pos: f32, length: f32) -> f32 {
+    if length > 0.0 {
+        pos.min(length) / length
+    } else {
+        0.0
+    }
 }
 
 impl<T: Movable> Traversible<T> {
@@ -27,14 +41,21 @@ impl<T: Movable> Traversible<T> {
             movables: VecDeque::new(),
             length,
             movables_waiting: 0,
+            speed_limit: DEFAULT_SPEED_LIMIT,
         }
     }
+    /// sets the maximum speed of the movables on the traversible
+    pub fn with_speed_limit(mut self, speed_limit: f32) -> Self {
+        self.speed_limit = speed_limit;
+        self
+    }
     /// update all the movables by timestep `t` and return the index of all that have reached the end
     pub fn update_movables(&mut self, t: f32) -> Vec<usize> {
         // let mut out = Vec::<&mut T>::new();
         // for i in 0..self.movables.len() {
         let mut out = Vec::new();
         let l = self.length;
+        let speed_limit = self.speed_limit;
         let mut part_of_waiting = false;
         let mut dist_last = 0.0;
         let mut movables_waiting = 0;
@@ -45,8 +66,9 @@ impl<T: Movable> Traversible<T> {
             }
             m.update(t);
             let speed = m.get_speed();
-            let pos_delta = t as f32 * (speed[1] - speed[0])*0.3;
-            m.set_current_speed((speed[1] - speed[0])*0.3);
+            let current_speed = ((speed[1] - speed[0])*0.3).min(speed_limit);
+            let pos_delta = t as f32 * current_speed;
+            m.set_current_speed(current_speed);
             m.add_to_dist(pos_delta);
             if is_at_end || (part_of_waiting && (dist_last - (*dist + pos_delta)) <= CAR_SPACING) {
                 part_of_waiting = true;
@@ -55,6 +77,7 @@ impl<T: Movable> Traversible<T> {
                 *dist += pos_delta;
                 part_of_waiting = false;
             }
+            m.set_fractional_position(fraction_of(*dist, l));
             dist_last = *dist;
         });
         self.movables_waiting = movables_waiting;
@@ -94,14 +117,12 @@ impl<T: Movable> Traversible<T> {
             Some((i, _)) => i,
             None => return Err("Invalid reference passed to rm_movable_by_ref"),
         };
-        if self.movables_waiting > 0 {
-            self.movables_waiting -= 1;
-        }
-        Ok(self.movables.remove(index).unwrap().0)
+        Ok(self.take_movable(index).0)
     }
 
     /// puts a movable on the beginning of the road
-    pub fn add(&mut self, movable: T) {
+    pub fn add(&mut self, mut movable: T) {
+        movable.set_fractional_position(0.0);
         self.movables.push_front((movable, 0.0));
     }
 
@@ -109,16 +130,78 @@ impl<T: Movable> Traversible<T> {
     pub fn num_movables(&self) -> usize {
         self.movables.len()
     }
+    /// returns the indices of the movables waiting behind another movable
+    ///
+    /// The movable at the very end isn't included, as it is only waiting for
+    /// the crossing
+    pub fn queued_movables(&self) -> std::ops::Range<usize> {
+        let first_waiting = self.first_waiting();
+        first_waiting..self.movables.len().saturating_sub(1).max(first_waiting)
+    }
+    /// returns the index of the first waiting movable (the waiting movables are
+    /// at the back of the queue)
+    fn first_waiting(&self) -> usize {
+        debug_assert!(
+            self.movables_waiting as usize <= self.movables.len(),
+            "{} movables are waiting, but only {} are on the traversible",
+            self.movables_waiting,
+            self.movables.len()
+        );
+        self.movables.len() - self.movables_waiting as usize
+    }
+    /// returns the distance the movable with index `i` has traveled on the traversible
+    pub fn get_position(&self, i: usize) -> f32 {
+        self.movables[i].1
+    }
+    /// returns the number of movables that are further along than `pos`
+    pub fn num_movables_ahead(&self, pos: f32) -> usize {
+        self.movables.iter().filter(|(_m, dist)| *dist > pos).count()
+    }
+    /// checks if a movable could be put at `pos` without getting closer
+    /// than `CAR_SPACING` to another movable
+    pub fn has_gap_at(&self, pos: f32) -> bool {
+        self.movables
+            .iter()
+            .all(|(_m, dist)| (dist - pos).abs() > CAR_SPACING)
+    }
+    /// removes the movable with index `i` and returns it together with its position
+    pub fn take_movable(&mut self, i: usize) -> (T, f32) {
+        if i >= self.first_waiting() {
+            self.movables_waiting -= 1;
+        }
+        self.movables.remove(i).unwrap()
+    }
+    /// puts a movable at position `pos`, behind all movables that are further along
+    pub fn insert_at(&mut self, mut movable: T, pos: f32) {
+        movable.set_fractional_position(fraction_of(pos, self.length));
+        let i = self
+            .movables
+            .iter()
+            .position(|(_m, dist)| *dist > pos)
+            .unwrap_or(self.movables.len());
+        // a movable put between waiting movables has to wait as well
+        if i > self.first_waiting() {
+            self.movables_waiting += 1;
+        }
+        self.movables.insert(i, (movable, pos));
+    }
     /// generates a status object for all of the movables on the
     /// traversable. All lane indices are set to 0
     pub fn get_movable_status(&self) -> Vec<MovableStatus> {
+        let first_waiting = self.first_waiting();
         self.movables
             .iter()
-            .map(|(m, t)| MovableStatus {
-                position: t.min(self.length) / self.length,
+            .enumerate()
+            .map(|(i, (m, _t))| MovableStatus {
+                position: m.fractional_position(),
                 lane_index: 0,
                 movable_id: m.get_id(),
                 delete: false,
+                target_node_id: m.next_node_id(),
+                speed: match i >= first_waiting {
+                    true => 0.0,
+                    false => m.get_speed()[0],
+                },
             })
             .collect()
     }
@@ -134,7 +217,7 @@ impl<T: Movable> Traversible<T> {
     }
 
     pub fn remove_movable(&mut self, i: usize) -> T {
-        self.movables.remove(i).unwrap().0
+        self.take_movable(i).0
     }
 
     pub fn get_movable_by_index<'a>(&'a self, i: usize) -> &'a T {
@@ -160,6 +243,8 @@ impl<T: Movable> Traversible<T> {
                 lane_index: 0,
                 movable_id: m.get_id(),
                 delete: true,
+                target_node_id: None,
+                speed: 0.0,
             }
         }).collect();
         self.movables = VecDeque::new();
@@ -0,0 +1,65 @@
+//! Simulates a small grid end to end (spawning, routing and absorbing cars)
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use simulator::debug::build_grid_sim;
+use simulator::nodes::Node;
+use simulator::path::{MovableServer, PathAwareCar};
+use simulator::{default_nn_topology, Simulator};
+
+const ITERATIONS: usize = 1000;
+/// the seed of the neural networks and the spawned cars
+const SEED: u64 = 0;
+
+/// builds a 3x3 grid in which every IONode spawns a car each second
+fn build_sim(rng: &mut StdRng) -> Simulator {
+    let mut builder = build_grid_sim(3, 10.0);
+    for (id, _rate) in builder.io_nodes() {
+        builder.set_spawn_rate(id, 1.0).unwrap();
+    }
+    builder.with_dt(1.0);
+    let mut mv_server = MovableServer::<PathAwareCar>::new();
+    mv_server.register_simulator_builder(&builder);
+    let mut sim = builder.build(&mv_server).unwrap();
+    let crossings = sim
+        .nodes
+        .iter()
+        .filter(|n| matches!(&*n.read(), Node::Crossing(_)))
+        .count();
+    let topology = default_nn_topology();
+    sim.set_neural_networks(
+        (0..crossings)
+            .map(|_| art_int::Network::random(rng, &topology))
+            .collect(),
+    )
+    .unwrap();
+    sim
+}
+
+#[test]
+fn grid_spawns_routes_and_absorbs_cars() {
+    // the spawning and the destinations of the cars are random, and a random
+    //  policy can jam the grid, so everything is seeded
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut sim = build_sim(&mut rng);
+    for _ in 0..ITERATIONS {
+        sim.update_all_nodes_with_rng(sim.dt.into(), &mut rng);
+    }
+    let spawned: usize = sim
+        .nodes
+        .iter()
+        .map(|n| match &*n.read() {
+            Node::IONode(node) => node.num_cars_spawned,
+            _ => 0,
+        })
+        .sum();
+    let on_streets = sim.count_cars();
+    let absorbed = sim.count_absorbed_cars();
+    assert!(spawned > 0);
+    assert!(absorbed > 0);
+    assert_eq!(absorbed, sim.throughput_report().values().sum::<usize>());
+    // every spawned car is either still driving or has been absorbed
+    assert!(on_streets + absorbed <= spawned);
+    let [cost, co2] = sim.calculate_sim_cost();
+    assert!(cost.is_finite() && cost >= 0.0);
+    assert!(co2.is_finite() && co2 >= 0.0);
+}